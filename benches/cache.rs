@@ -0,0 +1,65 @@
+//! Compares the read throughput of `IpldCache` and `ConcurrentIpldCache`.
+//!
+//! Run with `cargo bench --features concurrent`.
+use ipld_block_builder::{Cache, Codec, ConcurrentIpldCache, IpldCache};
+use libipld::cid::Cid;
+use libipld::ipld::Ipld;
+use libipld::mem::MemStore;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const VALUES: u64 = 1024;
+const TASKS: usize = 16;
+const READS: usize = 100_000;
+
+async fn populate<K: Cache<Codec, Ipld>>(cache: &K) -> Vec<Cid> {
+    let mut cids = vec![];
+    for i in 0..VALUES {
+        cids.push(cache.insert(Ipld::Integer(i.into())).await.unwrap());
+    }
+    cids
+}
+
+async fn bench<K>(cache: K) -> Duration
+where
+    K: Cache<Codec, Ipld> + Send + Sync + 'static,
+{
+    let cids = Arc::new(populate(&cache).await);
+    let cache = Arc::new(cache);
+    let start = Instant::now();
+    let tasks: Vec<_> = (0..TASKS)
+        .map(|task| {
+            let cache = cache.clone();
+            let cids = cids.clone();
+            async_std::task::spawn(async move {
+                for i in 0..READS {
+                    let cid = &cids[(i * 7 + task) % cids.len()];
+                    cache.get(cid).await.unwrap();
+                }
+            })
+        })
+        .collect();
+    for task in tasks {
+        task.await;
+    }
+    start.elapsed()
+}
+
+fn report(name: &str, elapsed: Duration) {
+    let reads = (TASKS * READS) as f64;
+    println!(
+        "{:<20} {:>10.2?} {:>12.0} reads/s",
+        name,
+        elapsed,
+        reads / elapsed.as_secs_f64()
+    );
+}
+
+#[async_std::main]
+async fn main() {
+    let size = VALUES as usize;
+    let cache = IpldCache::new(MemStore::default(), Codec::new(), size);
+    report("IpldCache", bench(cache).await);
+    let cache = ConcurrentIpldCache::new(MemStore::default(), Codec::new(), size);
+    report("ConcurrentIpldCache", bench(cache).await);
+}
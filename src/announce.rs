@@ -0,0 +1,224 @@
+use crate::builder::BlockBuilder;
+use crate::error::{Error, Result};
+use futures::stream::{Stream, StreamExt};
+use libipld::cbor::DagCborCodec;
+use libipld::cid::Cid;
+use libipld::codec::Codec;
+use libipld::ipld::Ipld;
+use libipld::store::AliasStore;
+use std::collections::BTreeMap;
+
+fn invalid(msg: impl Into<String>) -> Error {
+    Error::InvalidAnnouncement(msg.into())
+}
+
+/// Signs head announcements.
+pub trait Signer: Send + Sync {
+    /// Returns the signature of `msg`.
+    fn sign(&self, msg: &[u8]) -> Vec<u8>;
+}
+
+/// Verifies head announcements.
+pub trait Verifier: Send + Sync {
+    /// Returns if `signature` is a valid signature of `msg`.
+    fn verify(&self, msg: &[u8], signature: &[u8]) -> bool;
+}
+
+/// Signed update of an alias from `prev` to `cid`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct HeadUpdate {
+    /// The alias.
+    pub alias: Vec<u8>,
+    /// The head the update replaces.
+    pub prev: Option<Cid>,
+    /// The new head.
+    pub cid: Cid,
+    /// Signature of the alias, previous and new head.
+    pub signature: Vec<u8>,
+}
+
+fn fields(alias: &[u8], prev: Option<&Cid>, cid: &Cid) -> BTreeMap<String, Ipld> {
+    let mut map = BTreeMap::new();
+    map.insert("alias".to_string(), Ipld::Bytes(alias.to_vec()));
+    let prev = prev.cloned().map(Ipld::Link).unwrap_or(Ipld::Null);
+    map.insert("prev".to_string(), prev);
+    map.insert("cid".to_string(), Ipld::Link(cid.clone()));
+    map
+}
+
+fn encode(map: BTreeMap<String, Ipld>) -> Result<Box<[u8]>> {
+    DagCborCodec::encode(&Ipld::Map(map)).map_err(|err| invalid(err.to_string()))
+}
+
+impl HeadUpdate {
+    /// Creates a signed update.
+    pub fn new<G: Signer>(alias: &[u8], prev: Option<Cid>, cid: Cid, signer: &G) -> Result<Self> {
+        let msg = encode(fields(alias, prev.as_ref(), &cid))?;
+        Ok(Self {
+            alias: alias.to_vec(),
+            prev,
+            cid,
+            signature: signer.sign(&msg),
+        })
+    }
+
+    /// Returns if the signature is valid.
+    pub fn verify<V: Verifier>(&self, verifier: &V) -> Result<bool> {
+        let msg = encode(fields(&self.alias, self.prev.as_ref(), &self.cid))?;
+        Ok(verifier.verify(&msg, &self.signature))
+    }
+
+    /// Encodes the update as a dag-cbor message.
+    pub fn encode(&self) -> Result<Box<[u8]>> {
+        let mut map = fields(&self.alias, self.prev.as_ref(), &self.cid);
+        map.insert("signature".to_string(), Ipld::Bytes(self.signature.clone()));
+        encode(map)
+    }
+
+    /// Decodes a message.
+    pub fn decode(msg: &[u8]) -> Result<Self> {
+        let ipld: Ipld = DagCborCodec::decode(msg).map_err(|err| invalid(err.to_string()))?;
+        let alias = match ipld.get("alias") {
+            Ok(Ipld::Bytes(alias)) => alias.clone(),
+            _ => return Err(invalid("missing alias")),
+        };
+        let prev = match ipld.get("prev") {
+            Ok(Ipld::Link(prev)) => Some(prev.clone()),
+            Ok(Ipld::Null) => None,
+            _ => return Err(invalid("missing prev")),
+        };
+        let cid = match ipld.get("cid") {
+            Ok(Ipld::Link(cid)) => cid.clone(),
+            _ => return Err(invalid("missing cid")),
+        };
+        let signature = match ipld.get("signature") {
+            Ok(Ipld::Bytes(signature)) => signature.clone(),
+            _ => return Err(invalid("missing signature")),
+        };
+        Ok(Self {
+            alias,
+            prev,
+            cid,
+            signature,
+        })
+    }
+}
+
+impl<S: AliasStore, C> BlockBuilder<S, C> {
+    /// Points an alias to `cid` and returns the signed message announcing
+    /// the new head, to be published with a pubsub transport.
+    pub async fn announce<G: Signer>(
+        &self,
+        alias: &[u8],
+        cid: &Cid,
+        signer: &G,
+    ) -> Result<Box<[u8]>> {
+        let prev = self.swap_alias(alias, cid).await?;
+        HeadUpdate::new(alias, prev, cid.clone(), signer)?.encode()
+    }
+
+    /// Applies an announced head with `alias_cas`, returning if the alias
+    /// was updated.
+    ///
+    /// Updates that don't replace the current head of the alias are
+    /// ignored, resolving the conflict is left to the application.
+    pub async fn apply_head<V: Verifier>(&self, msg: &[u8], verifier: &V) -> Result<bool> {
+        let update = HeadUpdate::decode(msg)?;
+        if !update.verify(verifier)? {
+            return Err(invalid("invalid signature"));
+        }
+        if update.prev.as_ref() == Some(&update.cid) {
+            return Ok(false);
+        }
+        self.alias_cas(&update.alias, update.prev.as_ref(), &update.cid)
+            .await
+    }
+
+    /// Applies the announcements received from a pubsub transport until the
+    /// stream ends.
+    ///
+    /// Invalid messages are skipped, store errors are returned.
+    pub async fn subscribe_heads<M, V>(&self, mut messages: M, verifier: &V) -> Result<()>
+    where
+        M: Stream + Unpin,
+        M::Item: AsRef<[u8]>,
+        V: Verifier,
+    {
+        while let Some(msg) = messages.next().await {
+            match self.apply_head(msg.as_ref(), verifier).await {
+                Ok(_) => {}
+                Err(Error::InvalidAnnouncement(_err)) => {
+                    #[cfg(feature = "tracing")]
+                    tracing::warn!("ignoring head announcement: {}", _err);
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Codec;
+    use libipld::ipld;
+    use libipld::mem::MemStore;
+    use libipld::multihash::Blake2b256;
+
+    struct Secret(&'static [u8]);
+
+    impl Secret {
+        fn mac(&self, msg: &[u8]) -> Vec<u8> {
+            Blake2b256::digest(&[self.0, msg].concat())
+                .as_ref()
+                .to_vec()
+        }
+    }
+
+    impl Signer for Secret {
+        fn sign(&self, msg: &[u8]) -> Vec<u8> {
+            self.mac(msg)
+        }
+    }
+
+    impl Verifier for Secret {
+        fn verify(&self, msg: &[u8], signature: &[u8]) -> bool {
+            self.mac(msg) == signature
+        }
+    }
+
+    #[async_std::test]
+    async fn test_announce() {
+        let secret = Secret(b"secret");
+        let a = BlockBuilder::new(MemStore::default(), Codec::new());
+        let b = BlockBuilder::new(MemStore::default(), Codec::new());
+        let v1 = a.insert(&ipld!(1)).await.unwrap();
+        let v2 = a.insert(&ipld!(2)).await.unwrap();
+
+        let m1 = a.announce(b"head", &v1, &secret).await.unwrap();
+        let m2 = a.announce(b"head", &v2, &secret).await.unwrap();
+        assert_eq!(a.resolve(b"head").await.unwrap(), Some(v2.clone()));
+
+        // out of order updates don't apply
+        assert!(!b.apply_head(&m2, &secret).await.unwrap());
+        assert!(b.apply_head(&m1, &secret).await.unwrap());
+        assert!(!b.apply_head(&m1, &secret).await.unwrap());
+
+        let forged = a.announce(b"head", &v1, &Secret(b"forged")).await.unwrap();
+        let messages = futures::stream::iter(vec![forged, b"garbage".to_vec().into(), m2]);
+        b.subscribe_heads(messages, &secret).await.unwrap();
+        assert_eq!(b.resolve(b"head").await.unwrap(), Some(v2));
+    }
+
+    #[async_std::test]
+    async fn test_alias_cas() {
+        let builder = BlockBuilder::new(MemStore::default(), Codec::new());
+        let v1 = builder.insert(&ipld!(1)).await.unwrap();
+        let v2 = builder.insert(&ipld!(2)).await.unwrap();
+        assert!(builder.alias_cas(b"a", None, &v1).await.unwrap());
+        assert!(!builder.alias_cas(b"a", None, &v2).await.unwrap());
+        assert!(builder.alias_cas(b"a", Some(&v1), &v2).await.unwrap());
+        assert_eq!(builder.resolve(b"a").await.unwrap(), Some(v2));
+    }
+}
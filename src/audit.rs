@@ -0,0 +1,355 @@
+use crate::collections::Log;
+use crate::error::{Error, Result};
+use crate::rt::{SystemTime, UNIX_EPOCH};
+use crate::Codec;
+use core::convert::TryFrom;
+use futures::stream::TryStreamExt;
+use libipld::block::Block;
+use libipld::cid::Cid;
+use libipld::error::StoreError;
+use libipld::ipld::Ipld;
+use libipld::store::{AliasStore, ReadonlyStore, Store, StoreResult, Visibility};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+/// Name of the log recording the operations.
+pub const AUDIT_LOG: &str = "audit";
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+fn invalid(msg: impl Into<String>) -> Error {
+    Error::InvalidAuditEntry(msg.into())
+}
+
+/// Mutating operation recorded in the audit log.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum AuditOp {
+    /// A block or a batch was inserted.
+    Insert,
+    /// A block was unpinned.
+    Unpin,
+    /// An alias was set.
+    Alias,
+    /// An alias was removed.
+    Unalias,
+}
+
+impl AuditOp {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Insert => "insert",
+            Self::Unpin => "unpin",
+            Self::Alias => "alias",
+            Self::Unalias => "unalias",
+        }
+    }
+
+    fn from_str(op: &str) -> Result<Self> {
+        match op {
+            "insert" => Ok(Self::Insert),
+            "unpin" => Ok(Self::Unpin),
+            "alias" => Ok(Self::Alias),
+            "unalias" => Ok(Self::Unalias),
+            _ => Err(invalid(format!("unknown op {}", op))),
+        }
+    }
+}
+
+/// Entry of the audit log.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AuditEntry {
+    /// Actor that performed the operation.
+    pub actor: String,
+    /// Time of the operation in seconds since the unix epoch.
+    pub time: u64,
+    /// Operation.
+    pub op: AuditOp,
+    /// Inserted or unpinned block, or the target of an alias. For batches
+    /// the last block.
+    pub cid: Option<Cid>,
+    /// Alias that was set or removed.
+    pub alias: Option<Vec<u8>>,
+}
+
+impl AuditEntry {
+    /// Encodes the entry as ipld.
+    ///
+    /// The cid is encoded as bytes, not as a link, so recording an operation
+    /// doesn't keep the block from being garbage collected.
+    pub fn to_ipld(&self) -> Ipld {
+        let mut map = BTreeMap::new();
+        map.insert("actor".to_string(), Ipld::String(self.actor.clone()));
+        map.insert("time".to_string(), Ipld::Integer(self.time.into()));
+        map.insert("op".to_string(), Ipld::String(self.op.as_str().to_string()));
+        if let Some(cid) = &self.cid {
+            map.insert("cid".to_string(), Ipld::Bytes(cid.to_bytes()));
+        }
+        if let Some(alias) = &self.alias {
+            map.insert("alias".to_string(), Ipld::Bytes(alias.clone()));
+        }
+        Ipld::Map(map)
+    }
+
+    /// Decodes an entry returned by the audit log.
+    pub fn from_ipld(ipld: &Ipld) -> Result<Self> {
+        let actor = match ipld.get("actor") {
+            Ok(Ipld::String(actor)) => actor.clone(),
+            _ => return Err(invalid("missing actor")),
+        };
+        let time = match ipld.get("time") {
+            Ok(Ipld::Integer(time)) => u64::try_from(*time).map_err(|_| invalid("invalid time"))?,
+            _ => return Err(invalid("missing time")),
+        };
+        let op = match ipld.get("op") {
+            Ok(Ipld::String(op)) => AuditOp::from_str(op)?,
+            _ => return Err(invalid("missing op")),
+        };
+        let cid = match ipld.get("cid") {
+            Ok(Ipld::Bytes(cid)) => {
+                Some(Cid::try_from(&cid[..]).map_err(|_| invalid("invalid cid"))?)
+            }
+            Ok(_) => return Err(invalid("invalid cid")),
+            Err(_) => None,
+        };
+        let alias = match ipld.get("alias") {
+            Ok(Ipld::Bytes(alias)) => Some(alias.clone()),
+            Ok(_) => return Err(invalid("invalid alias")),
+            Err(_) => None,
+        };
+        Ok(Self {
+            actor,
+            time,
+            op,
+            cid,
+            alias,
+        })
+    }
+}
+
+/// A store recording every mutating operation in an audit log.
+///
+/// The entries are appended to the log `AUDIT_LOG` in the wrapped store
+/// after the operation succeeded. The log nodes are linked by their cids,
+/// so the head of the log commits to the whole trail. Writes of the log
+/// itself are not recorded.
+#[derive(Clone)]
+pub struct AuditStore<S> {
+    store: S,
+    actor: String,
+    log: Arc<Log<S, Codec, Ipld>>,
+}
+
+impl<S: Clone> AuditStore<S> {
+    /// Wraps `store`, recording operations as performed by `actor`.
+    pub fn new(store: S, actor: &str) -> Self {
+        Self {
+            log: Arc::new(Log::new(store.clone(), Codec::new(), AUDIT_LOG)),
+            store,
+            actor: actor.to_string(),
+        }
+    }
+
+    /// Returns a store sharing the audit log, recording operations as
+    /// performed by `actor`.
+    pub fn with_actor(&self, actor: &str) -> Self {
+        Self {
+            store: self.store.clone(),
+            actor: actor.to_string(),
+            log: self.log.clone(),
+        }
+    }
+}
+
+impl<S> AuditStore<S> {
+    /// Returns the wrapped store.
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    /// Returns the audit log.
+    pub fn log(&self) -> &Log<S, Codec, Ipld> {
+        &self.log
+    }
+}
+
+impl<S: Store + AliasStore> AuditStore<S> {
+    async fn record(
+        &self,
+        op: AuditOp,
+        cid: Option<&Cid>,
+        alias: Option<&[u8]>,
+    ) -> core::result::Result<(), StoreError> {
+        let entry = AuditEntry {
+            actor: self.actor.clone(),
+            time: now(),
+            op,
+            cid: cid.cloned(),
+            alias: alias.map(|alias| alias.to_vec()),
+        };
+        match self.log.append(&entry.to_ipld()).await {
+            Ok(_) => Ok(()),
+            Err(Error::Store(err)) => Err(err),
+            Err(err) => Err(StoreError::Other(Box::new(err))),
+        }
+    }
+
+    /// Returns the recorded entries, starting with the most recent.
+    ///
+    /// Fails if an entry is malformed, the trail is not silently truncated.
+    pub async fn entries(&self) -> Result<Vec<AuditEntry>> {
+        self.log
+            .iter_from_head()
+            .and_then(|(_, ipld)| async move { AuditEntry::from_ipld(&ipld) })
+            .try_collect()
+            .await
+    }
+}
+
+impl<S: ReadonlyStore> ReadonlyStore for AuditStore<S> {
+    fn get<'a>(&'a self, cid: &'a Cid) -> StoreResult<'a, Box<[u8]>> {
+        self.store.get(cid)
+    }
+}
+
+impl<S: Store + AliasStore + Send + Sync> Store for AuditStore<S> {
+    fn insert<'a>(
+        &'a self,
+        cid: &'a Cid,
+        data: Box<[u8]>,
+        visibility: Visibility,
+    ) -> StoreResult<'a, ()> {
+        Box::pin(async move {
+            self.store.insert(cid, data, visibility).await?;
+            self.record(AuditOp::Insert, Some(cid), None).await
+        })
+    }
+
+    fn insert_batch<'a>(
+        &'a self,
+        batch: Vec<Block>,
+        visibility: Visibility,
+    ) -> StoreResult<'a, Cid> {
+        Box::pin(async move {
+            let cid = self.store.insert_batch(batch, visibility).await?;
+            self.record(AuditOp::Insert, Some(&cid), None).await?;
+            Ok(cid)
+        })
+    }
+
+    fn flush(&self) -> StoreResult<'_, ()> {
+        self.store.flush()
+    }
+
+    fn unpin<'a>(&'a self, cid: &'a Cid) -> StoreResult<'a, ()> {
+        Box::pin(async move {
+            self.store.unpin(cid).await?;
+            self.record(AuditOp::Unpin, Some(cid), None).await
+        })
+    }
+}
+
+impl<S: Store + AliasStore + Send + Sync> AliasStore for AuditStore<S> {
+    fn alias<'a>(
+        &'a self,
+        alias: &'a [u8],
+        cid: &'a Cid,
+        visibility: Visibility,
+    ) -> StoreResult<'a, ()> {
+        Box::pin(async move {
+            self.store.alias(alias, cid, visibility).await?;
+            self.record(AuditOp::Alias, Some(cid), Some(alias)).await
+        })
+    }
+
+    fn unalias<'a>(&'a self, alias: &'a [u8]) -> StoreResult<'a, ()> {
+        Box::pin(async move {
+            self.store.unalias(alias).await?;
+            self.record(AuditOp::Unalias, None, Some(alias)).await
+        })
+    }
+
+    fn resolve<'a>(&'a self, alias: &'a [u8]) -> StoreResult<'a, Option<Cid>> {
+        self.store.resolve(alias)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BlockBuilder, Encoder};
+    use libipld::ipld;
+    use libipld::mem::MemStore;
+
+    #[async_std::test]
+    async fn test_audit_store() {
+        let store = AuditStore::new(MemStore::default(), "alice");
+        let alice = BlockBuilder::new(store.clone(), Codec::new());
+        let bob = BlockBuilder::new(store.with_actor("bob"), Codec::new());
+        let cid = alice.insert(&ipld!({ "a": 1 })).await.unwrap();
+        bob.alias(b"head", &cid).await.unwrap();
+        bob.unalias(b"head").await.unwrap();
+        alice.unpin(&cid).await.unwrap();
+
+        let entries = store.entries().await.unwrap();
+        let ops: Vec<_> = entries
+            .iter()
+            .map(|entry| (entry.actor.as_str(), entry.op))
+            .collect();
+        assert_eq!(
+            ops,
+            vec![
+                ("alice", AuditOp::Unpin),
+                ("bob", AuditOp::Unalias),
+                ("bob", AuditOp::Alias),
+                ("alice", AuditOp::Insert),
+            ]
+        );
+        assert_eq!(entries[3].cid, Some(cid.clone()));
+        assert_eq!(entries[2].alias.as_deref(), Some(&b"head"[..]));
+        assert_eq!(store.log().len().await.unwrap(), 4);
+        let head = store.log().head().await.unwrap();
+        assert_eq!(
+            store.store().resolve(store.log().alias()).await.unwrap(),
+            head
+        );
+    }
+
+    #[async_std::test]
+    async fn test_audit_gc() {
+        let store = AuditStore::new(MemStore::default(), "alice");
+        let block = Codec::new().encode(&ipld!({ "a": 1 })).unwrap();
+        let cid = block.cid.clone();
+        store
+            .insert(&cid, block.data, Visibility::Public)
+            .await
+            .unwrap();
+        store.unpin(&cid).await.unwrap();
+        assert_eq!(store.entries().await.unwrap()[1].cid, Some(cid.clone()));
+        // the log doesn't keep unpinned blocks alive
+        assert!(matches!(
+            store.get(&cid).await,
+            Err(StoreError::BlockNotFound(_))
+        ));
+    }
+
+    #[async_std::test]
+    async fn test_audit_malformed_entry() {
+        let store = AuditStore::new(MemStore::default(), "alice");
+        let builder = BlockBuilder::new(store.clone(), Codec::new());
+        builder.insert(&ipld!({ "a": 1 })).await.unwrap();
+        store
+            .log()
+            .append(&ipld!({ "actor": "mallory", "op": "insert" }))
+            .await
+            .unwrap();
+        assert!(matches!(
+            store.entries().await,
+            Err(Error::InvalidAuditEntry(_))
+        ));
+    }
+}
@@ -1,8 +1,9 @@
 use crate::codec::Encoder;
+use crate::error::Result;
 use libipld::block::Block;
 use libipld::cid::Cid;
 use libipld::codec::Encode;
-use libipld::error::Result;
+use libipld::ipld::Ipld;
 
 /// Batch of blocks to insert atomically.
 pub struct Batch<C> {
@@ -27,6 +28,50 @@ impl<C> Batch<C> {
         }
     }
 
+    /// Adds an encoded block to the batch.
+    pub(crate) fn push(&mut self, block: Block) {
+        self.blocks.push(block);
+    }
+
+    /// Returns the encoded blocks.
+    pub(crate) fn blocks(&self) -> &[Block] {
+        &self.blocks
+    }
+
+    /// Returns the cids of the blocks in insertion order.
+    pub fn cids(&self) -> impl Iterator<Item = &Cid> {
+        self.blocks.iter().map(|block| &block.cid)
+    }
+
+    /// Returns the size of the encoded blocks in bytes.
+    pub fn bytes(&self) -> usize {
+        self.blocks.iter().map(|block| block.data.len()).sum()
+    }
+
+    /// Returns the cid of the last block, which is pinned on insertion.
+    ///
+    /// Nothing is written, the cid can be compared or announced before the
+    /// batch is inserted.
+    pub fn root_cid(&self) -> Option<&Cid> {
+        self.blocks.last().map(|block| &block.cid)
+    }
+
+    /// Sorts the blocks by their cid and removes duplicates, keeping the
+    /// last block in place.
+    ///
+    /// Blocks are encoded deterministically, so batches built from the same
+    /// blocks in any order are byte-identical after canonicalization.
+    pub fn canonicalize(&mut self) {
+        let root = match self.blocks.pop() {
+            Some(root) => root,
+            None => return,
+        };
+        self.blocks.retain(|block| block.cid != root.cid);
+        self.blocks.sort_by_cached_key(|block| block.cid.to_bytes());
+        self.blocks.dedup_by(|a, b| a.cid == b.cid);
+        self.blocks.push(root);
+    }
+
     /// Returns an iterator of `Block`.
     pub fn into_vec(self) -> Vec<Block> {
         self.blocks
@@ -40,4 +85,70 @@ impl<C: Encoder> Batch<C> {
         self.blocks.push(block);
         Ok(&self.blocks.last().unwrap().cid)
     }
+
+    /// Inserts an untyped block into the batch.
+    pub fn insert_ipld(&mut self, ipld: &Ipld) -> Result<&Cid>
+    where
+        Ipld: Encode<C::Codec>,
+    {
+        self.insert(ipld)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Codec;
+    use libipld::ipld;
+    use std::collections::BTreeMap;
+
+    fn batch(values: &[Ipld]) -> Batch<Codec> {
+        let mut batch = Batch::new(Codec::new());
+        for value in values {
+            batch.insert(value).unwrap();
+        }
+        batch
+    }
+
+    fn encoded(batch: &Batch<Codec>) -> Vec<(Cid, Box<[u8]>)> {
+        batch
+            .blocks()
+            .iter()
+            .map(|block| (block.cid.clone(), block.data.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn test_reproducible_root() {
+        let mut a = BTreeMap::new();
+        a.insert("b".to_string(), Ipld::Integer(1));
+        a.insert("a".to_string(), Ipld::Float(0.5));
+        let mut b = BTreeMap::new();
+        b.insert("a".to_string(), Ipld::Float(0.5));
+        b.insert("b".to_string(), Ipld::Integer(1));
+        let root = batch(&[Ipld::Map(a)]).root_cid().cloned().unwrap();
+        assert_eq!(batch(&[Ipld::Map(b)]).root_cid(), Some(&root));
+        // cids must not change across runs and platforms
+        assert_eq!(
+            root.to_string(),
+            "bafy2bzacecldlngxgbls67jhmxa2v62zezwuvchjwqpvnsschgho6md2gn326"
+        );
+    }
+
+    #[test]
+    fn test_canonicalize() {
+        let values = [ipld!(1), ipld!("two"), ipld!([3]), ipld!(1)];
+        let root = ipld!({ "root": true });
+        let mut a = batch(&values);
+        a.insert(&root).unwrap();
+        let mut b = batch(&[ipld!([3]), ipld!(1), ipld!("two"), root.clone()]);
+        b.insert(&root).unwrap();
+        assert_ne!(encoded(&a), encoded(&b));
+        a.canonicalize();
+        b.canonicalize();
+        assert_eq!(a.blocks().len(), 4);
+        assert_eq!(encoded(&a), encoded(&b));
+        assert_eq!(a.root_cid(), b.root_cid());
+        assert_eq!(a.blocks()[3].cid, *a.root_cid().unwrap());
+    }
 }
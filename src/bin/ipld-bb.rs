@@ -0,0 +1,198 @@
+//! Command line tool for inspecting an `FsStore`.
+use core::convert::TryFrom;
+use futures::io::AllowStdIo;
+use ipld_block_builder::{
+    to_json, BlockBuilder, CarProgress, Codec, Encoder, FsStore, IpldDecoder, Key, StrobeCodec,
+    WalkControl,
+};
+use libipld::cbor::DagCborCodec;
+use libipld::cid::Cid;
+use libipld::ipld::Ipld;
+use std::error::Error;
+use std::io::Write;
+
+type Result<T> = core::result::Result<T, Box<dyn Error>>;
+
+const USAGE: &str = "usage: ipld-bb [--store <dir>] [--key <hex>] <command>
+
+commands:
+  cat <cid>                write the raw block to stdout
+  get <cid> [<depth>]      print the dag as dag-json, following links up to depth
+  put <file>               insert a dag-json block, - reads stdin, and print its cid
+  path <cid>/<path>        resolve a path and print the value as dag-json
+  refs [-r] <cid>          list the links of a block, recursively with -r
+  import <file>            import a CAR file and print its roots
+  export <file> <cid>...   export the dags of the roots to a CAR file
+  rekey <cid> <hex>        re-encrypt the dag under a new key and print the new root
+  stats <cid>...           print block and deduplication statistics
+
+The store defaults to $IPLD_BB_STORE and the key to $IPLD_BB_KEY. Blocks are
+decrypted with the key if one is given.";
+
+fn usage() -> Box<dyn Error> {
+    USAGE.into()
+}
+
+fn print_progress(progress: &CarProgress) {
+    eprint!("\r{} blocks, {} bytes", progress.blocks, progress.bytes);
+}
+
+fn parse_key(hex: &str) -> Result<Key> {
+    if !hex.len().is_multiple_of(2) {
+        return Err("key must be hex encoded".into());
+    }
+    let key = (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..(i + 2)], 16))
+        .collect::<core::result::Result<Vec<u8>, _>>()
+        .map_err(|_| "key must be hex encoded")?;
+    Ok(Key::from(key))
+}
+
+fn parse_cid(cid: &str) -> Result<Cid> {
+    Cid::try_from(cid).map_err(|err| format!("invalid cid {}: {}", cid, err).into())
+}
+
+fn parse_cids(cids: &[String]) -> Result<Vec<Cid>> {
+    if cids.is_empty() {
+        return Err(usage());
+    }
+    cids.iter().map(|cid| parse_cid(cid)).collect()
+}
+
+async fn run<C>(builder: BlockBuilder<FsStore, C>, args: &[String]) -> Result<()>
+where
+    C: Encoder<Codec = DagCborCodec> + IpldDecoder + Clone,
+{
+    let mut stdout = std::io::stdout();
+    match (args[0].as_str(), &args[1..]) {
+        ("cat", [cid]) => {
+            let data =
+                libipld::store::ReadonlyStore::get(builder.store(), &parse_cid(cid)?).await?;
+            stdout.write_all(&data)?;
+        }
+        ("get", [cid]) => println!("{}", builder.dump(&parse_cid(cid)?, 0).await?),
+        ("get", [cid, depth]) => {
+            let depth = depth.parse().map_err(|_| usage())?;
+            println!("{}", builder.dump(&parse_cid(cid)?, depth).await?);
+        }
+        ("put", [file]) => {
+            let json = if file == "-" {
+                let mut json = String::new();
+                std::io::Read::read_to_string(&mut std::io::stdin(), &mut json)?;
+                json
+            } else {
+                std::fs::read_to_string(file)?
+            };
+            println!("{}", builder.insert_json(&json).await?);
+        }
+        ("path", [path]) => {
+            let (cid, path) = match path.find('/') {
+                Some(i) => (&path[..i], &path[(i + 1)..]),
+                None => (path.as_str(), ""),
+            };
+            let cid = parse_cid(cid)?;
+            let ipld = builder
+                .get_path(&ipld_block_builder::DagPath::new(&cid, path))
+                .await?;
+            println!("{}", to_json(&ipld));
+        }
+        ("refs", [cid]) => {
+            for link in builder.get_ipld(&parse_cid(cid)?).await?.iter() {
+                if let Ipld::Link(cid) = link {
+                    println!("{}", cid);
+                }
+            }
+        }
+        ("refs", [flag, cid]) if flag == "-r" => {
+            let root = parse_cid(cid)?;
+            builder
+                .walk(&root, |_: usize, cid: &Cid, _: &Ipld| {
+                    if cid != &root {
+                        println!("{}", cid);
+                    }
+                    WalkControl::Descend
+                })
+                .await?;
+        }
+        ("import", [file]) => {
+            let car = AllowStdIo::new(std::io::BufReader::new(std::fs::File::open(file)?));
+            let roots = builder.import_car_from(car, print_progress).await?;
+            eprintln!();
+            for root in roots {
+                println!("{}", root);
+            }
+        }
+        ("export", [file, roots @ ..]) => {
+            let car = AllowStdIo::new(std::io::BufWriter::new(std::fs::File::create(file)?));
+            builder
+                .export_car_to(&parse_cids(roots)?, car, print_progress)
+                .await?;
+            eprintln!();
+        }
+        ("rekey", [cid, key]) => {
+            let codec = StrobeCodec::new(parse_key(key)?);
+            let target = BlockBuilder::new_private(builder.store().clone(), codec);
+            println!("{}", builder.rekey(&parse_cid(cid)?, &target).await?);
+        }
+        ("stats", roots) => {
+            let report = builder.dedup_report(&parse_cids(roots)?, 10).await?;
+            println!("blocks:        {}", report.blocks);
+            println!("references:    {}", report.references);
+            println!("shared blocks: {}", report.shared_blocks);
+            println!("unique bytes:  {}", report.unique_bytes);
+            println!("total bytes:   {}", report.total_bytes);
+            println!("bytes saved:   {}", report.bytes_saved());
+            for subtree in &report.top {
+                println!(
+                    "  {} occurrences: {} bytes saved: {}",
+                    subtree.cid,
+                    subtree.occurrences,
+                    subtree.bytes_saved()
+                );
+            }
+        }
+        _ => return Err(usage()),
+    }
+    Ok(())
+}
+
+async fn main_async(mut args: Vec<String>) -> Result<()> {
+    let mut store = std::env::var("IPLD_BB_STORE").ok();
+    let mut key = std::env::var("IPLD_BB_KEY").ok();
+    while args.len() > 1 && args[0].starts_with("--") {
+        let value = args.remove(1);
+        match args.remove(0).as_str() {
+            "--store" => store = Some(value),
+            "--key" => key = Some(value),
+            _ => return Err(usage()),
+        }
+    }
+    if args.is_empty() {
+        return Err(usage());
+    }
+    let store = FsStore::open(store.ok_or_else(usage)?).await?;
+    match key {
+        Some(key) => {
+            let codec = StrobeCodec::new(parse_key(&key)?);
+            run(BlockBuilder::new_private(store, codec), &args).await
+        }
+        None => run(BlockBuilder::new(store, Codec::new()), &args).await,
+    }
+}
+
+fn main() {
+    let args = std::env::args().skip(1).collect();
+    #[cfg(feature = "tokio")]
+    let result = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .expect("failed to start runtime")
+        .block_on(main_async(args));
+    #[cfg(not(feature = "tokio"))]
+    let result = futures::executor::block_on(main_async(args));
+    if let Err(err) = result {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    }
+}
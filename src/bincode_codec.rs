@@ -0,0 +1,67 @@
+use libipld::cid::Codec as Code;
+use libipld::codec::{Codec, Decode, Encode};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::io::{Read, Write};
+
+/// Bincode codec for serde types.
+///
+/// Bincode is faster than dag-cbor but isn't self describing, so blocks
+/// can only be decoded into the type they were encoded from and links
+/// aren't visible to the store. Meant for internal data only. The
+/// multicodec table of `cid` has no private codes, so blocks are tagged
+/// `Raw`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Bincode;
+
+impl Codec for Bincode {
+    const CODE: Code = Code::Raw;
+
+    type Error = bincode::Error;
+}
+
+/// Wraps a serde type to encode it with `Bincode`.
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+pub struct Bin<T>(pub T);
+
+impl<T: Serialize> Encode<Bincode> for Bin<T> {
+    fn encode<W: Write>(&self, w: &mut W) -> Result<(), bincode::Error> {
+        bincode::serialize_into(w, &self.0)
+    }
+}
+
+impl<T: DeserializeOwned> Decode<Bincode> for Bin<T> {
+    fn decode<R: Read>(r: &mut R) -> Result<Self, bincode::Error> {
+        bincode::deserialize_from(r).map(Bin)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BincodeCodec, BlockBuilder};
+    use libipld::mem::MemStore;
+    use serde::Deserialize;
+
+    #[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+    struct Sample {
+        id: u64,
+        values: Vec<f32>,
+        tag: Option<String>,
+    }
+
+    #[async_std::test]
+    async fn test_bincode() {
+        let builder = BlockBuilder::new(MemStore::default(), BincodeCodec::new());
+        let sample = Sample {
+            id: 7,
+            values: vec![0.5, 1.5],
+            tag: Some("a".into()),
+        };
+        let cid = builder.insert(&Bin(sample.clone())).await.unwrap();
+        assert_eq!(cid.codec(), Code::Raw);
+        let Bin(sample2): Bin<Sample> = builder.get(&cid).await.unwrap();
+        assert_eq!(sample2, sample);
+        assert!(builder.get::<Bin<Vec<u64>>>(&cid).await.is_err());
+    }
+}
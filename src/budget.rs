@@ -0,0 +1,272 @@
+use crate::eviction::EvictionCache;
+use core::future::Future;
+use core::hash::Hash;
+use core::pin::Pin;
+use core::task::{Context, Poll, Waker};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, Weak};
+
+struct State {
+    available: usize,
+    next_id: u64,
+    waiters: VecDeque<(u64, Waker)>,
+}
+
+impl State {
+    fn wake_head(&self) {
+        if let Some((_, waker)) = self.waiters.front() {
+            waker.wake_by_ref();
+        }
+    }
+}
+
+/// Releases reservations of cached entries when a batch waits for memory.
+pub(crate) trait Reclaim: Send + Sync {
+    /// Evicts entries until `bytes` were released or nothing is left.
+    fn reclaim(&self, bytes: usize);
+}
+
+impl<K, V> Reclaim for Mutex<EvictionCache<K, (V, Option<Reservation>)>>
+where
+    K: Clone + Eq + Hash + Send,
+    V: Send,
+{
+    fn reclaim(&self, bytes: usize) {
+        let mut cache = self.lock().unwrap();
+        let mut released = 0;
+        while released < bytes {
+            match cache.pop() {
+                Some((_, (_, reservation))) => {
+                    released += reservation.map(|r| r.bytes()).unwrap_or_default();
+                }
+                None => break,
+            }
+        }
+    }
+}
+
+struct Inner {
+    capacity: usize,
+    state: Mutex<State>,
+    reclaimers: Mutex<Vec<Weak<dyn Reclaim>>>,
+}
+
+/// Byte budget shared by batches, caches and prefetch buffers.
+///
+/// Memory is reserved with `acquire`, which waits until enough bytes are
+/// released, or `try_acquire`, which fails instead. Waiters are served in
+/// order. Reservations larger than the capacity are clamped to the capacity,
+/// so a single large block can't wait forever. Caches and prefetch buffers
+/// holding reservations are evicted while `acquire` waits, so they can't
+/// starve batches.
+#[derive(Clone)]
+pub struct MemoryBudget {
+    inner: Arc<Inner>,
+}
+
+impl MemoryBudget {
+    /// Creates a budget of `capacity` bytes.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                capacity,
+                state: Mutex::new(State {
+                    available: capacity,
+                    next_id: 0,
+                    waiters: Default::default(),
+                }),
+                reclaimers: Default::default(),
+            }),
+        }
+    }
+
+    /// Returns the capacity in bytes.
+    pub fn capacity(&self) -> usize {
+        self.inner.capacity
+    }
+
+    /// Returns the bytes that are not reserved.
+    pub fn available(&self) -> usize {
+        self.inner.state.lock().unwrap().available
+    }
+
+    fn reservation(&self, bytes: usize) -> Reservation {
+        Reservation {
+            budget: self.clone(),
+            bytes,
+        }
+    }
+
+    /// Reserves `bytes`, waiting until they are available.
+    pub fn acquire(&self, bytes: usize) -> Acquire {
+        Acquire {
+            budget: self.clone(),
+            bytes: bytes.min(self.inner.capacity),
+            id: None,
+        }
+    }
+
+    /// Reserves `bytes` if they are available and nobody is waiting.
+    pub fn try_acquire(&self, bytes: usize) -> Option<Reservation> {
+        let bytes = bytes.min(self.inner.capacity);
+        let mut state = self.inner.state.lock().unwrap();
+        if !state.waiters.is_empty() || state.available < bytes {
+            return None;
+        }
+        state.available -= bytes;
+        Some(self.reservation(bytes))
+    }
+
+    /// Registers a cache whose entries are evicted while `acquire` waits.
+    pub(crate) fn add_reclaimer(&self, reclaimer: Weak<dyn Reclaim>) {
+        let mut reclaimers = self.inner.reclaimers.lock().unwrap();
+        reclaimers.retain(|reclaimer| reclaimer.strong_count() > 0);
+        reclaimers.push(reclaimer);
+    }
+
+    /// Evicts cached entries until `bytes` are available, returning if any
+    /// cache was asked to.
+    fn reclaim(&self, bytes: usize) -> bool {
+        let reclaimers: Vec<_> = self
+            .inner
+            .reclaimers
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(Weak::upgrade)
+            .collect();
+        for reclaimer in &reclaimers {
+            let available = self.available();
+            if available >= bytes {
+                break;
+            }
+            reclaimer.reclaim(bytes - available);
+        }
+        !reclaimers.is_empty()
+    }
+
+    fn release(&self, bytes: usize) {
+        let mut state = self.inner.state.lock().unwrap();
+        state.available += bytes;
+        state.wake_head();
+    }
+}
+
+/// Bytes reserved from a `MemoryBudget`, released on drop.
+pub struct Reservation {
+    budget: MemoryBudget,
+    bytes: usize,
+}
+
+impl Reservation {
+    /// Returns the reserved bytes.
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        self.budget.release(self.bytes);
+    }
+}
+
+/// Future returned by `MemoryBudget::acquire`.
+pub struct Acquire {
+    budget: MemoryBudget,
+    bytes: usize,
+    id: Option<u64>,
+}
+
+impl Acquire {
+    /// Reserves the bytes if this is the first waiter and they are
+    /// available, otherwise queues the waiter and returns the bytes missing
+    /// for the first waiter.
+    fn try_reserve(&mut self, cx: &mut Context) -> core::result::Result<Reservation, usize> {
+        let budget = self.budget.clone();
+        let mut state = budget.inner.state.lock().unwrap();
+        let first = match self.id {
+            Some(id) => state.waiters.front().map(|(head, _)| *head) == Some(id),
+            None => state.waiters.is_empty(),
+        };
+        if first && state.available >= self.bytes {
+            state.available -= self.bytes;
+            if self.id.take().is_some() {
+                state.waiters.pop_front();
+                state.wake_head();
+            }
+            return Ok(budget.reservation(self.bytes));
+        }
+        match self.id {
+            Some(id) => {
+                let waiter = state.waiters.iter_mut().find(|(other, _)| *other == id);
+                waiter.unwrap().1 = cx.waker().clone();
+            }
+            None => {
+                let id = state.next_id;
+                state.next_id += 1;
+                state.waiters.push_back((id, cx.waker().clone()));
+                self.id = Some(id);
+            }
+        }
+        Err(if first { self.bytes } else { 0 })
+    }
+}
+
+impl Future for Acquire {
+    type Output = Reservation;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let bytes = match self.try_reserve(cx) {
+            Ok(reservation) => return Poll::Ready(reservation),
+            Err(bytes) => bytes,
+        };
+        // evicting caches with the state unlocked, dropping their
+        // reservations locks it
+        if bytes > 0 && self.budget.reclaim(bytes) {
+            if let Ok(reservation) = self.try_reserve(cx) {
+                return Poll::Ready(reservation);
+            }
+        }
+        Poll::Pending
+    }
+}
+
+impl Drop for Acquire {
+    fn drop(&mut self) {
+        if let Some(id) = self.id {
+            let mut state = self.budget.inner.state.lock().unwrap();
+            state.waiters.retain(|(other, _)| *other != id);
+            state.wake_head();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::FutureExt;
+
+    #[async_std::test]
+    async fn test_budget() {
+        let budget = MemoryBudget::new(100);
+        let a = budget.acquire(60).await;
+        assert_eq!(budget.available(), 40);
+        assert!(budget.try_acquire(50).is_none());
+
+        let mut b = budget.acquire(50);
+        assert!((&mut b).now_or_never().is_none());
+        // queued waiters go first
+        assert!(budget.try_acquire(10).is_none());
+        drop(a);
+        let b = b.await;
+        assert_eq!(budget.available(), 50);
+        drop(b);
+
+        let c = budget.acquire(1000).await;
+        assert_eq!(c.bytes(), 100);
+        assert_eq!(budget.available(), 0);
+        drop(c);
+        assert_eq!(budget.available(), 100);
+    }
+}
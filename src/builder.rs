@@ -1,18 +1,180 @@
 use crate::batch::Batch;
-use crate::codec::{Decoder, Encoder, Encrypted, IpldDecoder};
-use crate::path::DagPath;
-use libipld::cid::Cid;
+use crate::budget::MemoryBudget;
+use crate::codec::{Decoder, Encoder, Encrypted, HeaderEncoder, IpldDecoder};
+use crate::error::{Error, Result};
+use crate::observer::Observer;
+use crate::path::{DagPath, IpldPath, IpldView, PathStats};
+use crate::prefetch::Prefetcher;
+use crate::rt::Instant;
+use crate::store::RemoteStore;
+use crate::timeout::{timeout, Timeouts};
+#[cfg(feature = "fs")]
+use crate::wal::Wal;
+use crate::walk::links;
+use futures::future::join_all;
+use futures::stream::{FuturesUnordered, StreamExt};
+use libipld::block::Block;
+use libipld::cid::{Cid, Codec as CidCodec};
 use libipld::codec::{Decode, Encode};
-use libipld::error::Result;
+use libipld::error::StoreError;
 use libipld::ipld::Ipld;
-use libipld::store::{AliasStore, MultiUserStore, ReadonlyStore, Store, Visibility};
+use libipld::multihash::Identity;
+use libipld::store::{AliasStore, MultiUserStore, ReadonlyStore, Store, StoreResult, Visibility};
+use std::collections::{HashMap, VecDeque};
+use std::convert::TryFrom;
 use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Object safe local reads of a `RemoteStore`.
+trait LocalStore: Send + Sync {
+    fn get_local<'a>(&'a self, cid: &'a Cid) -> StoreResult<'a, Box<[u8]>>;
+}
+
+impl<S: RemoteStore + Send + Sync> LocalStore for S {
+    fn get_local<'a>(&'a self, cid: &'a Cid) -> StoreResult<'a, Box<[u8]>> {
+        RemoteStore::get_local(self, cid)
+    }
+}
+
+/// Flushes a clone of the store when the builder is dropped.
+type FlushOnDrop = Box<dyn FnOnce() -> StoreResult<'static, ()> + Send + Sync>;
+
+/// Minimum number of values encoded by a thread in `insert_many`.
+#[cfg(not(target_arch = "wasm32"))]
+const MIN_VALUES_PER_THREAD: usize = 64;
+
+/// Maximum number of unpins in flight in `unpin_many`.
+const UNPIN_CONCURRENCY: usize = 16;
+
+/// Returns the number of threads encoding `len` values.
+#[cfg(not(target_arch = "wasm32"))]
+fn encode_threads(len: usize) -> usize {
+    std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+        .min(len / MIN_VALUES_PER_THREAD)
+        .max(1)
+}
+
+/// Returns the number of threads encoding `len` values, wasm32 has no
+/// threads.
+#[cfg(target_arch = "wasm32")]
+fn encode_threads(_len: usize) -> usize {
+    1
+}
+
+/// Encodes values on multiple threads, preserving their order.
+fn encode_parallel<C, E>(codec: &C, values: &[E]) -> Result<Vec<Block>>
+where
+    C: Encoder + Sync,
+    E: Encode<C::Codec> + Sync,
+{
+    let threads = encode_threads(values.len());
+    if threads == 1 {
+        return values.iter().map(|value| codec.encode(value)).collect();
+    }
+    let chunk = values.len().div_ceil(threads);
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = values
+            .chunks(chunk)
+            .map(|chunk| {
+                scope.spawn(move || {
+                    chunk
+                        .iter()
+                        .map(|value| codec.encode(value))
+                        .collect::<Result<Vec<_>>>()
+                })
+            })
+            .collect();
+        let mut blocks = Vec::with_capacity(values.len());
+        for handle in handles {
+            match handle.join() {
+                Ok(chunk) => blocks.extend(chunk?),
+                Err(err) => std::panic::resume_unwind(err),
+            }
+        }
+        Ok(blocks)
+    })
+}
+
+/// Resolved alias and the time it was resolved.
+type AliasEntry = (Option<Cid>, Instant);
+
+/// Resolved aliases cached for `ttl`.
+struct AliasCache {
+    ttl: Duration,
+    entries: std::sync::Mutex<HashMap<Vec<u8>, AliasEntry>>,
+}
+
+impl AliasCache {
+    fn get(&self, alias: &[u8]) -> Option<Option<Cid>> {
+        let mut entries = self.entries.lock().unwrap();
+        match entries.get(alias) {
+            Some((cid, resolved)) if resolved.elapsed() < self.ttl => Some(cid.clone()),
+            Some(_) => {
+                entries.remove(alias);
+                None
+            }
+            None => None,
+        }
+    }
+
+    fn insert(&self, alias: &[u8], cid: Option<Cid>) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.insert(alias.to_vec(), (cid, Instant::now()));
+    }
+}
+
+/// Unpinned blocks waiting for their grace period to end.
+struct Tombstones {
+    grace: Duration,
+    pending: std::sync::Mutex<VecDeque<(Cid, Instant)>>,
+}
+
+impl Tombstones {
+    /// Takes the tombstones whose grace period ended, or all of them.
+    fn take(&self, all: bool) -> Vec<Cid> {
+        let mut pending = self.pending.lock().unwrap();
+        let mut expired = vec![];
+        while let Some((_, unpinned)) = pending.front() {
+            if !all && unpinned.elapsed() < self.grace {
+                break;
+            }
+            expired.push(pending.pop_front().unwrap().0);
+        }
+        expired
+    }
+
+    /// Removes the oldest tombstone of `cid`, returning if there was one.
+    fn cancel(&self, cid: &Cid) -> bool {
+        let mut pending = self.pending.lock().unwrap();
+        match pending.iter().position(|(pending, _)| pending == cid) {
+            Some(i) => pending.remove(i).is_some(),
+            None => false,
+        }
+    }
+}
 
 /// Generic block builder for creating blocks.
 pub struct BlockBuilder<S, C> {
     store: S,
     codec: C,
     visibility: Visibility,
+    verify: bool,
+    observers: Vec<Arc<dyn Observer>>,
+    prefetcher: Option<Prefetcher>,
+    budget: Option<MemoryBudget>,
+    timeouts: Timeouts,
+    #[cfg(feature = "fs")]
+    wal: Option<Wal>,
+    dirty: AtomicBool,
+    flush_on_drop: Option<FlushOnDrop>,
+    offline: Option<Box<dyn LocalStore>>,
+    aliases: futures::lock::Mutex<()>,
+    alias_cache: Option<AliasCache>,
+    tombstones: Option<Tombstones>,
 }
 
 impl<S, C> BlockBuilder<S, C> {
@@ -22,6 +184,19 @@ impl<S, C> BlockBuilder<S, C> {
             store,
             codec,
             visibility: Visibility::Public,
+            verify: false,
+            observers: Default::default(),
+            prefetcher: None,
+            budget: None,
+            timeouts: Timeouts::default(),
+            #[cfg(feature = "fs")]
+            wal: None,
+            dirty: AtomicBool::new(false),
+            flush_on_drop: None,
+            offline: None,
+            aliases: Default::default(),
+            alias_cache: None,
+            tombstones: None,
         }
     }
 
@@ -30,6 +205,100 @@ impl<S, C> BlockBuilder<S, C> {
         self.visibility
     }
 
+    /// Returns if blocks are verified on read.
+    pub fn verify(&self) -> bool {
+        self.verify
+    }
+
+    /// Enables verifying blocks on read.
+    ///
+    /// The data returned by the store is hashed and compared with the cid
+    /// before it is decoded, returning an `IntegrityError` on mismatch.
+    pub fn set_verify(&mut self, verify: bool) {
+        self.verify = verify;
+    }
+
+    /// Registers an observer that is notified of operations on the builder.
+    pub fn add_observer(&mut self, observer: Arc<dyn Observer>) {
+        self.observers.push(observer);
+    }
+
+    /// Sets a prefetcher that fetches the links of blocks returned from
+    /// `get_ipld` and `get_path` in the background.
+    pub fn set_prefetcher(&mut self, prefetcher: Prefetcher) {
+        self.prefetcher = Some(prefetcher);
+    }
+
+    /// Sets a memory budget for batches.
+    ///
+    /// Inserting a batch waits until its bytes can be reserved and holds the
+    /// reservation until the store finished writing it.
+    pub fn set_budget(&mut self, budget: MemoryBudget) {
+        self.budget = Some(budget);
+    }
+
+    /// Returns the memory budget.
+    pub fn budget(&self) -> Option<&MemoryBudget> {
+        self.budget.as_ref()
+    }
+
+    /// Returns if reads are restricted to locally available blocks.
+    pub fn is_offline(&self) -> bool {
+        self.offline.is_some()
+    }
+
+    /// Sets the timeouts of store operations.
+    pub fn set_timeouts(&mut self, timeouts: Timeouts) {
+        self.timeouts = timeouts;
+    }
+
+    /// Returns the timeouts of store operations.
+    pub fn timeouts(&self) -> Timeouts {
+        self.timeouts
+    }
+
+    /// Caches resolved aliases for `ttl`.
+    ///
+    /// Aliases set or removed through this builder update the cache
+    /// immediately, changes made by others are seen after at most `ttl`.
+    /// `alias_cas` always resolves the alias from the store.
+    pub fn set_alias_cache_ttl(&mut self, ttl: Option<Duration>) {
+        self.alias_cache = ttl.map(|ttl| AliasCache {
+            ttl,
+            entries: Default::default(),
+        });
+    }
+
+    /// Delays unpins by `grace`, so recently unpinned blocks aren't
+    /// collectable while concurrent readers still hold their cids.
+    ///
+    /// `unpin` records a tombstone and the block is unpinned in the store by
+    /// a later `unpin` or `unpin_expired` once `grace` elapsed. Inserting or
+    /// pinning the block again before that cancels the tombstone, the block
+    /// stays pinned once. Tombstones are only kept in memory and are not
+    /// recovered after a restart, their blocks stay pinned. Call
+    /// `unpin_pending` before shutdown.
+    pub fn set_unpin_grace(&mut self, grace: Option<Duration>) {
+        self.tombstones = grace.map(|grace| Tombstones {
+            grace,
+            pending: Default::default(),
+        });
+    }
+
+    /// Returns the number of unpins waiting for their grace period to end.
+    pub fn tombstones(&self) -> usize {
+        match &self.tombstones {
+            Some(tombstones) => tombstones.pending.lock().unwrap().len(),
+            None => 0,
+        }
+    }
+
+    /// Sets a write-ahead log that journals batches until they are flushed.
+    #[cfg(feature = "fs")]
+    pub fn set_wal(&mut self, wal: Wal) {
+        self.wal = Some(wal);
+    }
+
     /// Gets the store of the builder.
     pub fn store(&self) -> &S {
         &self.store
@@ -44,41 +313,261 @@ impl<S, C> BlockBuilder<S, C> {
 impl<S, C: Encrypted> BlockBuilder<S, C> {
     /// Creates a builder for private blocks.
     pub fn new_private(store: S, codec: C) -> Self {
-        Self {
-            store,
-            codec,
-            visibility: Visibility::Private,
+        let mut builder = Self::new(store, codec);
+        builder.visibility = Visibility::Private;
+        builder
+    }
+}
+
+impl<S: RemoteStore + Send + Sync + 'static, C> BlockBuilder<S, C> {
+    /// Restricts reads to blocks that are available locally.
+    ///
+    /// Reading a block that would have to be fetched from the network fails
+    /// with `Error::NotFoundLocally` instead and prefetching is disabled.
+    /// `fetch` still goes to the network.
+    pub fn set_offline(&mut self, offline: bool) {
+        self.offline = if offline {
+            Some(Box::new(self.store.clone()))
+        } else {
+            None
+        };
+    }
+}
+
+impl<S: ReadonlyStore, C> BlockBuilder<S, C> {
+    pub(crate) async fn get_verified(&self, cid: &Cid) -> Result<Box<[u8]>> {
+        self.get_verified_from(cid, self.offline.as_deref()).await
+    }
+
+    async fn get_verified_from(
+        &self,
+        cid: &Cid,
+        local: Option<&dyn LocalStore>,
+    ) -> Result<Box<[u8]>> {
+        Ok(self.load(cid, local).await?.0)
+    }
+
+    /// Returns a verified block and if it was served by the prefetcher.
+    async fn load(&self, cid: &Cid, local: Option<&dyn LocalStore>) -> Result<(Box<[u8]>, bool)> {
+        let prefetched = self.prefetcher.as_ref().and_then(|p| p.get(cid));
+        let hit = prefetched.is_some();
+        let data = if let Some(data) = prefetched {
+            data
+        } else {
+            #[cfg(feature = "metrics")]
+            let start = crate::rt::Instant::now();
+            let data = timeout("get", self.timeouts.get, async {
+                match local {
+                    Some(local) => match local.get_local(cid).await {
+                        Err(StoreError::BlockNotFound(_)) => {
+                            Err(Error::NotFoundLocally(cid.clone()))
+                        }
+                        res => Ok(res?),
+                    },
+                    None => Ok(self.store.get(cid).await?),
+                }
+            })
+            .await?;
+            #[cfg(feature = "metrics")]
+            crate::metrics::store_get(data.len(), start.elapsed());
+            data
+        };
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("bytes", data.len());
+        if self.verify {
+            crate::error::verify(cid, &data)?;
+        }
+        for observer in &self.observers {
+            observer.on_get(cid);
         }
+        Ok((data, hit))
+    }
+
+    /// Returns the plaintext header of an encrypted block without the key.
+    ///
+    /// The header is not authenticated until the block is decoded.
+    #[cfg(feature = "crypto")]
+    pub async fn get_header(&self, cid: &Cid) -> Result<Option<Vec<u8>>> {
+        let data = self.get_verified(cid).await?;
+        Ok(crate::crypto::decode_header(&data).map(<[u8]>::to_vec))
+    }
+
+    /// Returns the codec of an encrypted block without the key, if it was
+    /// exposed with `GenericStrobeCodec::set_expose_codec`.
+    #[cfg(feature = "crypto")]
+    pub async fn get_codec(&self, cid: &Cid) -> Result<Option<libipld::cid::Codec>> {
+        let data = self.get_verified(cid).await?;
+        Ok(crate::crypto::decode_codec(&data))
     }
 }
 
 impl<S: ReadonlyStore, C: Decoder> BlockBuilder<S, C> {
     /// Returns the decoded block with cid.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip(self, cid), fields(cid = %cid, bytes))
+    )]
     pub async fn get<D: Decode<C::Codec>>(&self, cid: &Cid) -> Result<D> {
-        let data = self.store.get(cid).await?;
-        self.codec.decode(cid, &data)
+        let data = self.get_verified(cid).await?;
+        self.codec.decode_owned(cid, data)
+    }
+
+    /// Returns the decoded block with cid, fetching it from the network even
+    /// if the builder is offline.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip(self, cid), fields(cid = %cid, bytes))
+    )]
+    pub async fn fetch<D: Decode<C::Codec>>(&self, cid: &Cid) -> Result<D> {
+        let data = self.get_verified_from(cid, None).await?;
+        self.codec.decode_owned(cid, data)
     }
 }
 
 impl<S: ReadonlyStore, C: IpldDecoder> BlockBuilder<S, C> {
     /// Returns the ipld representation of a block with cid.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip(self, cid), fields(cid = %cid, bytes))
+    )]
     pub async fn get_ipld(&self, cid: &Cid) -> Result<Ipld> {
-        let data = self.store.get(cid).await?;
-        self.codec.decode_ipld(cid, &data)
+        let data = self.get_verified(cid).await?;
+        self.decode_ipld(cid, data)
+    }
+
+    fn decode_ipld(&self, cid: &Cid, data: Box<[u8]>) -> Result<Ipld> {
+        let ipld = self.codec.decode_ipld_owned(cid, data)?;
+        if let (Some(prefetcher), None) = (&self.prefetcher, &self.offline) {
+            for link in links(&ipld) {
+                prefetcher.prefetch(&link);
+            }
+        }
+        Ok(ipld)
     }
 
     /// Resolves a path recursively and returns the ipld.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            skip(self, path),
+            fields(root = %path.root(), path = %path.path().to_string())
+        )
+    )]
     pub async fn get_path(&self, path: &DagPath<'_>) -> Result<Ipld> {
-        let mut root = self.get_ipld(path.root()).await?;
+        Ok(self.get_path_view(path).await?.into_ipld())
+    }
+
+    /// Resolves a path recursively and returns a view into the last block
+    /// it crosses.
+    ///
+    /// Unlike `get_path` the resolved ipld isn't cloned, which matters when
+    /// resolving into large nodes.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            skip(self, path),
+            fields(root = %path.root(), path = %path.path().to_string())
+        )
+    )]
+    pub async fn get_path_view(&self, path: &DagPath<'_>) -> Result<IpldView> {
+        let mut stats = PathStats::default();
+        timeout(
+            "get_path",
+            self.timeouts.get_path,
+            self.walk_path(path, &mut stats),
+        )
+        .await
+        .map(|(_, view)| view)
+    }
+
+    /// Resolves a path recursively and returns the ipld together with the
+    /// number of blocks fetched, bytes decoded and prefetcher hits.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            skip(self, path),
+            fields(root = %path.root(), path = %path.path().to_string())
+        )
+    )]
+    pub async fn get_path_with_stats(&self, path: &DagPath<'_>) -> Result<(Ipld, PathStats)> {
+        let start = Instant::now();
+        let mut stats = PathStats::default();
+        let (_, view) = timeout(
+            "get_path",
+            self.timeouts.get_path,
+            self.walk_path(path, &mut stats),
+        )
+        .await?;
+        stats.elapsed = start.elapsed();
+        Ok((view.into_ipld(), stats))
+    }
+
+    /// Walks a path once, returning the cid of the last block it crosses
+    /// and a view of the remainder inside that block.
+    async fn walk_path(
+        &self,
+        path: &DagPath<'_>,
+        stats: &mut PathStats,
+    ) -> Result<(Cid, IpldView)> {
+        let mut cid = path.root().clone();
+        let mut root = self.get_ipld_counted(&cid, stats).await?;
         let mut ipld = &root;
+        let mut remainder = vec![];
         for segment in path.path().iter() {
-            ipld = ipld.get(segment)?;
-            if let Ipld::Link(cid) = ipld {
-                root = self.get_ipld(cid).await?;
+            ipld = ipld.get(segment).map_err(|source| Error::Path {
+                path: path.path().to_string(),
+                source,
+            })?;
+            remainder.push(segment);
+            if let Ipld::Link(link) = ipld {
+                cid = link.clone();
+                root = self.get_ipld_counted(&cid, stats).await?;
                 ipld = &root;
+                remainder.clear();
             }
         }
-        Ok(ipld.clone())
+        Ok((cid, IpldView::new(root, remainder.into())))
+    }
+
+    async fn get_ipld_counted(&self, cid: &Cid, stats: &mut PathStats) -> Result<Ipld> {
+        let (data, hit) = self.load(cid, self.offline.as_deref()).await?;
+        stats.blocks += 1;
+        stats.bytes += data.len();
+        stats.cache_hits += usize::from(hit);
+        self.decode_ipld(cid, data)
+    }
+
+    /// Resolves a path to the cid of the last block it crosses and the
+    /// remainder of the path inside that block.
+    ///
+    /// A path ending in a link resolves to the linked block with an empty
+    /// remainder.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            skip(self, path),
+            fields(root = %path.root(), path = %path.path().to_string())
+        )
+    )]
+    pub async fn resolve_path(&self, path: &DagPath<'_>) -> Result<(Cid, IpldPath)> {
+        timeout(
+            "get_path",
+            self.timeouts.get_path,
+            self.walk_path(path, &mut PathStats::default()),
+        )
+        .await
+        .map(|(cid, view)| (cid, view.path().clone()))
+    }
+}
+
+impl<S, C: Encoder> BlockBuilder<S, C> {
+    /// Returns the cid `value` gets when it is inserted, without writing it
+    /// to the store.
+    ///
+    /// Encrypted codecs with random nonces return a different cid on every
+    /// encoding.
+    pub fn compute_cid<E: Encode<C::Codec>>(&self, value: &E) -> Result<Cid> {
+        Ok(self.codec.encode(value)?.cid)
     }
 }
 
@@ -94,67 +583,599 @@ impl<S: Store, C: Encoder + Clone> BlockBuilder<S, C> {
     }
 
     /// Encodes and inserts a block into the store.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self, e)))]
     pub async fn insert<E: Encode<C::Codec>>(&self, e: &E) -> Result<Cid> {
         let mut batch = self.create_batch();
         batch.insert(e)?;
         self.insert_batch(batch).await
     }
 
+    /// Encodes values on all cores into a batch, preserving their order.
+    ///
+    /// The calling thread blocks until every value is encoded, so large
+    /// batches should be encoded from a blocking context and inserted with
+    /// `insert_batch`.
+    pub fn encode_many<E>(&self, values: &[E]) -> Result<Batch<C>>
+    where
+        C: Sync,
+        E: Encode<C::Codec> + Sync,
+    {
+        let mut batch = Batch::with_capacity(self.codec.clone(), values.len());
+        for block in encode_parallel(&self.codec, values)? {
+            batch.push(block);
+        }
+        Ok(batch)
+    }
+
+    /// Encodes values in parallel and inserts them as a single batch,
+    /// returning the cids in the order of `values`.
+    ///
+    /// Like `insert_batch` only the last block is pinned, so the values need
+    /// to be linked from a root or pinned to survive garbage collection.
+    /// The values are encoded with `encode_many`, which blocks the executor
+    /// thread, async callers with large batches should use
+    /// `insert_many_owned`.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self, values)))]
+    pub async fn insert_many<E>(&self, values: &[E]) -> Result<Vec<Cid>>
+    where
+        C: Sync,
+        E: Encode<C::Codec> + Sync,
+    {
+        if values.is_empty() {
+            return Ok(vec![]);
+        }
+        let batch = self.encode_many(values)?;
+        let cids = batch.cids().cloned().collect();
+        self.insert_batch(batch).await?;
+        Ok(cids)
+    }
+
+    /// Encodes owned values on the blocking thread pool and inserts them as
+    /// a single batch, returning the cids in the order of `values`.
+    ///
+    /// Unlike `insert_many` the async task only waits for the encoders, so
+    /// the executor stays responsive while large batches are hashed and
+    /// encrypted on all cores.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self, values)))]
+    pub async fn insert_many_owned<E>(&self, mut values: Vec<E>) -> Result<Vec<Cid>>
+    where
+        C: Send + 'static,
+        E: Encode<C::Codec> + Send + 'static,
+    {
+        if values.is_empty() {
+            return Ok(vec![]);
+        }
+        let chunk = values.len().div_ceil(encode_threads(values.len()));
+        let mut chunks = vec![];
+        while values.len() > chunk {
+            let rest = values.split_off(chunk);
+            chunks.push(std::mem::replace(&mut values, rest));
+        }
+        chunks.push(values);
+        let encoded = join_all(chunks.into_iter().map(|chunk| {
+            let codec = self.codec.clone();
+            crate::rt::unblock(move || {
+                chunk
+                    .iter()
+                    .map(|value| codec.encode(value))
+                    .collect::<Result<Vec<_>>>()
+            })
+        }))
+        .await;
+        let mut blocks = vec![];
+        for chunk in encoded {
+            blocks.extend(chunk?);
+        }
+        self.insert_blocks(blocks).await
+    }
+
+    async fn insert_blocks(&self, blocks: Vec<Block>) -> Result<Vec<Cid>> {
+        let cids = blocks.iter().map(|block| block.cid.clone()).collect();
+        let mut batch = Batch::with_capacity(self.codec.clone(), blocks.len());
+        for block in blocks {
+            batch.push(block);
+        }
+        self.insert_batch(batch).await?;
+        Ok(cids)
+    }
+
+    /// Encodes and inserts a block with a plaintext header.
+    ///
+    /// The header of an encrypted block is covered by its mac, but can be
+    /// read without the key using `get_header`.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self, e, header)))]
+    pub async fn insert_with_header<E: Encode<C::Codec>>(&self, e: &E, header: &[u8]) -> Result<Cid>
+    where
+        C: HeaderEncoder,
+    {
+        let mut batch = self.create_batch();
+        batch.push(self.codec.encode_with_header(e, header)?);
+        self.insert_batch(batch).await
+    }
+
     /// Inserts a batch of blocks atomically pinning the last one.
+    ///
+    /// Dropping the future or hitting the `insert_batch` timeout never
+    /// leaves part of the batch in the store, as long as the store's own
+    /// `insert_batch` is atomic. The batch is either inserted completely or
+    /// not at all, but after a timeout it is unknown which. A batch that was
+    /// already journaled in the write-ahead log is replayed by `recover`.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip(self, batch), fields(blocks, bytes, cid))
+    )]
     pub async fn insert_batch<T>(&self, batch: Batch<T>) -> Result<Cid> {
-        Ok(self
-            .store
-            .insert_batch(batch.into_vec(), self.visibility)
-            .await?)
+        timeout(
+            "insert_batch",
+            self.timeouts.insert_batch,
+            self.insert_batch_inner(batch),
+        )
+        .await
+    }
+
+    async fn insert_batch_inner<T>(&self, batch: Batch<T>) -> Result<Cid> {
+        let _reservation = match &self.budget {
+            Some(budget) => Some(budget.acquire(batch.bytes()).await),
+            None => None,
+        };
+        let blocks = batch.into_vec();
+        #[cfg(feature = "metrics")]
+        let (len, bytes, start) = (
+            blocks.len(),
+            blocks.iter().map(|block| block.data.len()).sum(),
+            crate::rt::Instant::now(),
+        );
+        #[cfg(feature = "tracing")]
+        {
+            let span = tracing::Span::current();
+            span.record("blocks", blocks.len());
+            span.record(
+                "bytes",
+                blocks.iter().map(|block| block.data.len()).sum::<usize>(),
+            );
+        }
+        let inserted: Vec<Block> = if self.observers.is_empty() {
+            Default::default()
+        } else {
+            blocks.iter().map(crate::store::clone_block).collect()
+        };
+        #[cfg(feature = "fs")]
+        let _guard = match &self.wal {
+            Some(wal) => {
+                let guard = wal.lock().await;
+                wal.append(&blocks).await?;
+                Some(guard)
+            }
+            None => None,
+        };
+        let cid = self.store.insert_batch(blocks, self.visibility).await?;
+        self.dirty.store(true, Ordering::Release);
+        self.cancel_tombstone(&cid).await?;
+        for block in &inserted {
+            for observer in &self.observers {
+                observer.on_insert(block);
+            }
+        }
+        #[cfg(feature = "metrics")]
+        crate::metrics::store_insert(len, bytes, start.elapsed());
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("cid", tracing::field::display(&cid));
+        Ok(cid)
+    }
+}
+
+impl<S: Store, C: Encoder + IpldDecoder + Clone> BlockBuilder<S, C> {
+    /// Encodes and inserts a block into the store, returning its cid and
+    /// its links.
+    ///
+    /// The value is encoded once, the links are read back from the encoded
+    /// block, decrypting it for encrypted codecs. Observers are informed
+    /// with `Observer::on_refs`.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self, e)))]
+    pub async fn insert_with_refs<E: Encode<C::Codec>>(&self, e: &E) -> Result<(Cid, Vec<Cid>)> {
+        let block = self.codec.encode(e)?;
+        let refs = links(&self.codec.decode_ipld(&block.cid, &block.data)?);
+        let mut batch = self.create_batch();
+        batch.push(block);
+        let cid = self.insert_batch(batch).await?;
+        for observer in &self.observers {
+            observer.on_refs(&cid, &refs);
+        }
+        Ok((cid, refs))
     }
 }
 
 impl<S: Store, C> BlockBuilder<S, C> {
     /// Flushes the store to disk.
     pub async fn flush(&self) -> Result<()> {
+        self.dirty.store(false, Ordering::Release);
+        let res = self.flush_store().await;
+        if res.is_err() {
+            self.dirty.store(true, Ordering::Release);
+        }
+        res
+    }
+
+    async fn flush_store(&self) -> Result<()> {
+        #[cfg(feature = "fs")]
+        if let Some(wal) = &self.wal {
+            let _guard = wal.lock().await;
+            self.store.flush().await?;
+            return wal.truncate().await;
+        }
         Ok(self.store.flush().await?)
     }
 
+    /// Flushes the store every `interval` if blocks were inserted since the
+    /// last flush.
+    ///
+    /// Only returns if a flush fails. The future is meant to be spawned next
+    /// to the service using the builder.
+    pub async fn flush_periodically(&self, interval: Duration) -> Result<()> {
+        loop {
+            crate::rt::sleep(interval).await;
+            if self.dirty.load(Ordering::Acquire) {
+                self.flush().await?;
+            }
+        }
+    }
+
+    /// Flushes the store when the builder is dropped if blocks were inserted
+    /// since the last flush.
+    ///
+    /// This is a best-effort safety net, errors are ignored. Dropping blocks
+    /// the current thread until the store is flushed, so calling `flush`
+    /// before shutdown is still preferred in async code.
+    pub fn set_flush_on_drop(&mut self, enabled: bool)
+    where
+        S: Send + Sync + 'static,
+    {
+        self.flush_on_drop = if enabled {
+            let store = self.store.clone();
+            Some(Box::new(move || {
+                Box::pin(async move { store.flush().await })
+            }))
+        } else {
+            None
+        };
+    }
+
+    /// Replays the batches left in the write-ahead log after a crash.
+    ///
+    /// Batches whose last block is already in the store are skipped. Returns
+    /// the number of replayed batches.
+    #[cfg(feature = "fs")]
+    pub async fn recover(&self) -> Result<usize> {
+        let wal = match &self.wal {
+            Some(wal) => wal,
+            None => return Ok(0),
+        };
+        let _guard = wal.lock().await;
+        let mut replayed = 0;
+        for batch in wal.batches().await? {
+            let last = match batch.last() {
+                Some(block) => block.cid.clone(),
+                None => continue,
+            };
+            if self.store.get(&last).await.is_ok() {
+                continue;
+            }
+            self.store.insert_batch(batch, self.visibility).await?;
+            replayed += 1;
+        }
+        self.store.flush().await?;
+        wal.truncate().await?;
+        Ok(replayed)
+    }
+
     /// Unpins a block from the store marking it ready for garbage collection.
+    ///
+    /// With an unpin grace period the block is unpinned once the grace
+    /// period ended, see `set_unpin_grace`.
     pub async fn unpin(&self, cid: &Cid) -> Result<()> {
-        Ok(self.store.unpin(cid).await?)
+        match &self.tombstones {
+            Some(tombstones) => {
+                let tombstone = (cid.clone(), Instant::now());
+                tombstones.pending.lock().unwrap().push_back(tombstone);
+                self.unpin_expired().await?;
+                Ok(())
+            }
+            None => self.unpin_now(cid).await,
+        }
+    }
+
+    /// Cancels a tombstone of a block that was pinned again.
+    ///
+    /// The pin waiting for the grace period is released now, the new pin
+    /// takes its place. Blocks inserted as children of a batch aren't pinned,
+    /// so their tombstones are kept.
+    async fn cancel_tombstone(&self, cid: &Cid) -> Result<()> {
+        let cancelled = match &self.tombstones {
+            Some(tombstones) => tombstones.cancel(cid),
+            None => false,
+        };
+        if cancelled {
+            self.unpin_now(cid).await?;
+        }
+        Ok(())
+    }
+
+    async fn unpin_now(&self, cid: &Cid) -> Result<()> {
+        self.store.unpin(cid).await?;
+        for observer in &self.observers {
+            observer.on_unpin(cid);
+        }
+        Ok(())
+    }
+
+    /// Unpins the blocks whose grace period ended, returning how many were
+    /// unpinned.
+    pub async fn unpin_expired(&self) -> Result<usize> {
+        self.unpin_tombstones(false).await
+    }
+
+    /// Unpins all blocks waiting for their grace period to end, returning
+    /// how many were unpinned.
+    pub async fn unpin_pending(&self) -> Result<usize> {
+        self.unpin_tombstones(true).await
+    }
+
+    async fn unpin_tombstones(&self, all: bool) -> Result<usize> {
+        let cids = match &self.tombstones {
+            Some(tombstones) => tombstones.take(all),
+            None => return Ok(0),
+        };
+        let mut result = Ok(cids.len());
+        for cid in &cids {
+            if let Err(err) = self.unpin_now(cid).await {
+                result = result.and(Err(err));
+            }
+        }
+        result
+    }
+
+    /// Unpins blocks concurrently.
+    ///
+    /// Up to 16 unpins are in flight at a time. All blocks are attempted,
+    /// the first error is returned.
+    pub async fn unpin_many(&self, cids: &[Cid]) -> Result<()> {
+        let mut pending = FuturesUnordered::new();
+        let mut result = Ok(());
+        for cid in cids {
+            if pending.len() >= UNPIN_CONCURRENCY {
+                if let Some(Err(err)) = pending.next().await {
+                    result = result.and(Err(err));
+                }
+            }
+            pending.push(self.unpin(cid));
+        }
+        while let Some(res) = pending.next().await {
+            result = result.and(res);
+        }
+        result
     }
 }
 
-impl<S: MultiUserStore, C> BlockBuilder<S, C> {
+impl<S: Store + MultiUserStore, C> BlockBuilder<S, C> {
     /// Pins a block in the store.
     pub async fn pin(&self, cid: &Cid, path: &Path) -> Result<()> {
-        Ok(self.store.pin(cid, path).await?)
+        self.store.pin(cid, path).await?;
+        self.cancel_tombstone(cid).await
     }
 }
 
 impl<S: AliasStore, C> BlockBuilder<S, C> {
     /// Creates an alias for a cid.
     pub async fn alias(&self, alias: &[u8], cid: &Cid) -> Result<()> {
-        Ok(self.store.alias(alias, cid, self.visibility).await?)
+        let _guard = self.aliases.lock().await;
+        self.set_alias(alias, cid).await
+    }
+
+    async fn set_alias(&self, alias: &[u8], cid: &Cid) -> Result<()> {
+        self.store.alias(alias, cid, self.visibility).await?;
+        if let Some(cache) = &self.alias_cache {
+            cache.insert(alias, Some(cid.clone()));
+        }
+        for observer in &self.observers {
+            observer.on_alias(alias, Some(cid));
+        }
+        Ok(())
+    }
+
+    /// Points an alias to `cid` if it currently resolves to `expected`,
+    /// returning if the alias was updated.
+    ///
+    /// The swap is atomic with respect to other alias updates made through
+    /// this builder.
+    pub async fn alias_cas(&self, alias: &[u8], expected: Option<&Cid>, cid: &Cid) -> Result<bool> {
+        let _guard = self.aliases.lock().await;
+        if self.store.resolve(alias).await?.as_ref() != expected {
+            return Ok(false);
+        }
+        self.set_alias(alias, cid).await?;
+        Ok(true)
+    }
+
+    /// Points an alias to `cid`, returning the cid it resolved to before.
+    pub(crate) async fn swap_alias(&self, alias: &[u8], cid: &Cid) -> Result<Option<Cid>> {
+        let _guard = self.aliases.lock().await;
+        let prev = self.store.resolve(alias).await?;
+        self.set_alias(alias, cid).await?;
+        Ok(prev)
+    }
+
+    /// Removes an alias, returning the cid it resolved to.
+    pub(crate) async fn take_alias(&self, alias: &[u8]) -> Result<Option<Cid>> {
+        let _guard = self.aliases.lock().await;
+        let prev = self.store.resolve(alias).await?;
+        self.remove_alias(alias).await?;
+        Ok(prev)
     }
 
     /// Removes an alias.
     pub async fn unalias(&self, alias: &[u8]) -> Result<()> {
-        Ok(self.store.unalias(alias).await?)
+        let _guard = self.aliases.lock().await;
+        self.remove_alias(alias).await
+    }
+
+    async fn remove_alias(&self, alias: &[u8]) -> Result<()> {
+        self.store.unalias(alias).await?;
+        if let Some(cache) = &self.alias_cache {
+            cache.insert(alias, None);
+        }
+        for observer in &self.observers {
+            observer.on_alias(alias, None);
+        }
+        Ok(())
     }
 
     /// Resolves an alias.
     pub async fn resolve(&self, alias: &[u8]) -> Result<Option<Cid>> {
-        Ok(self.store.resolve(alias).await?)
+        let cache = match &self.alias_cache {
+            Some(cache) => cache,
+            None => return Ok(self.store.resolve(alias).await?),
+        };
+        if let Some(cid) = cache.get(alias) {
+            return Ok(cid);
+        }
+        let cid = self.store.resolve(alias).await?;
+        cache.insert(alias, cid.clone());
+        Ok(cid)
+    }
+}
+
+/// Returns the alias of the pending commit of `alias`.
+fn intent_alias(alias: &[u8]) -> Vec<u8> {
+    let mut intent = b"commit/".to_vec();
+    intent.extend_from_slice(alias);
+    intent
+}
+
+/// Encodes the new and previous root of a commit as an identity-hashed
+/// block, so the intent can be recorded before the batch is inserted.
+fn encode_intent(root: &Cid, prev: Option<&Cid>) -> Block {
+    let mut data = root.to_bytes();
+    if let Some(prev) = prev {
+        data.extend_from_slice(&prev.to_bytes());
+    }
+    let cid = Cid::new_v1(CidCodec::Raw, Identity::digest(&data));
+    Block {
+        cid,
+        data: data.into_boxed_slice(),
+    }
+}
+
+fn decode_intent(intent: &Cid) -> Result<(Cid, Option<Cid>)> {
+    let invalid = || Error::InvalidCommit(intent.clone());
+    let (root, prev) = crate::car::split_cid(intent.hash().digest()).map_err(|_| invalid())?;
+    let prev = if prev.is_empty() {
+        None
+    } else {
+        Some(Cid::try_from(prev).map_err(|_| invalid())?)
+    };
+    Ok((root, prev))
+}
+
+impl<S: Store + AliasStore, C: Encoder + Clone> BlockBuilder<S, C> {
+    /// Inserts a batch and points `alias` to its root, returning the root.
+    ///
+    /// Before the batch is inserted an intent recording the new and the
+    /// previous root is aliased under `commit/<alias>`. After the alias was
+    /// updated the intent is removed and the previous root is unpinned. If
+    /// the process crashes in between, `recover_commit` either rolls the
+    /// commit forward, when the batch was inserted, or discards the intent.
+    /// A crash between removing the intent and unpinning the previous root
+    /// leaves the previous root pinned, a commit never unpins a root twice.
+    pub async fn commit<T>(&self, batch: Batch<T>, alias: &[u8]) -> Result<Cid> {
+        let root = match batch.blocks().last() {
+            Some(block) => block.cid.clone(),
+            None => return self.insert_batch(batch).await,
+        };
+        let _guard = self.aliases.lock().await;
+        let prev = self.store.resolve(alias).await?;
+        let intent = intent_alias(alias);
+        let block = encode_intent(&root, prev.as_ref());
+        self.store
+            .insert(&block.cid, block.data, Visibility::Public)
+            .await?;
+        self.store
+            .alias(&intent, &block.cid, Visibility::Public)
+            .await?;
+        self.insert_batch(batch).await?;
+        self.set_alias(alias, &root).await?;
+        self.store.unalias(&intent).await?;
+        self.store.unpin(&block.cid).await?;
+        if let Some(prev) = prev {
+            if prev != root {
+                self.unpin(&prev).await?;
+            }
+        }
+        Ok(root)
+    }
+
+    /// Completes or discards a commit of `alias` interrupted by a crash,
+    /// returning the root if the commit was completed.
+    pub async fn recover_commit(&self, alias: &[u8]) -> Result<Option<Cid>> {
+        let _guard = self.aliases.lock().await;
+        let intent = intent_alias(alias);
+        let cid = match self.store.resolve(&intent).await? {
+            Some(cid) => cid,
+            None => return Ok(None),
+        };
+        let (root, prev) = decode_intent(&cid)?;
+        let inserted = match self.store.get(&root).await {
+            Ok(_) => true,
+            Err(StoreError::BlockNotFound(_)) => false,
+            Err(err) => return Err(err.into()),
+        };
+        if inserted && self.store.resolve(alias).await?.as_ref() != Some(&root) {
+            self.set_alias(alias, &root).await?;
+        }
+        self.store.unalias(&intent).await?;
+        self.store.unpin(&cid).await?;
+        if !inserted {
+            return Ok(None);
+        }
+        if let Some(prev) = prev {
+            if prev != root {
+                self.unpin(&prev).await?;
+            }
+        }
+        Ok(Some(root))
+    }
+}
+
+impl<S, C> Drop for BlockBuilder<S, C> {
+    fn drop(&mut self) {
+        if let Some(flush) = self.flush_on_drop.take() {
+            if self.dirty.load(Ordering::Acquire) {
+                let _res = futures::executor::block_on(flush());
+                #[cfg(feature = "tracing")]
+                if let Err(err) = _res {
+                    tracing::warn!("failed to flush on drop: {}", err);
+                }
+            }
+        }
     }
 }
 
 #[cfg(test)]
+#[allow(non_local_definitions)]
 mod tests {
     use super::*;
     #[cfg(feature = "crypto")]
     use crate::crypto::Key;
+    use crate::error::IntegrityError;
+    use crate::store::CappedMemStore;
     use crate::Codec;
     #[cfg(feature = "crypto")]
     use crate::StrobeCodec;
+    use libipld::ipld;
     use libipld::mem::MemStore;
-    use libipld::{ipld, DagCbor};
+    use libipld::store::StoreResult;
+    #[cfg(feature = "crypto")]
+    use libipld::DagCbor;
+    use std::sync::atomic::{AtomicUsize, Ordering};
 
     #[async_std::test]
     async fn test_block_builder() {
@@ -188,8 +1209,111 @@ mod tests {
         let root = builder.insert(&ipld2).await.unwrap();
         let path = DagPath::new(&root, "root/0/child/a");
         assert_eq!(builder.get_path(&path).await.unwrap(), Ipld::Integer(3));
+        let view = builder.get_path_view(&path).await.unwrap();
+        assert_eq!(*view, Ipld::Integer(3));
+        assert_eq!(view.block(), &ipld1);
+        assert_eq!(view.path().to_string(), "a");
+        assert_eq!(view.into_ipld(), Ipld::Integer(3));
+        let (ipld, stats) = builder.get_path_with_stats(&path).await.unwrap();
+        assert_eq!(ipld, Ipld::Integer(3));
+        let bytes = builder.store().get(&root).await.unwrap().len()
+            + builder.store().get(&cid).await.unwrap().len();
+        assert_eq!((stats.blocks, stats.bytes, stats.cache_hits), (2, bytes, 0));
+        let path = DagPath::new(&root, "root/1/child");
+        match builder.get_path(&path).await {
+            Err(Error::Path { path, .. }) => assert_eq!(path, "root/1/child"),
+            _ => panic!("expected path error"),
+        }
+
+        let path = DagPath::new(&root, "root/0/child/a");
+        let (block, remainder) = builder.resolve_path(&path).await.unwrap();
+        assert_eq!(block, cid);
+        assert_eq!(remainder.to_string(), "a");
+        let path = DagPath::new(&root, "root/0");
+        let (block, remainder) = builder.resolve_path(&path).await.unwrap();
+        assert_eq!(block, root);
+        assert_eq!(remainder.to_string(), "root/0");
+        let path = DagPath::new(&root, "root/0/child");
+        let (block, remainder) = builder.resolve_path(&path).await.unwrap();
+        assert_eq!(block, cid);
+        assert_eq!(remainder.to_string(), "");
     }
 
+    #[derive(Clone)]
+    struct TamperedStore;
+
+    impl ReadonlyStore for TamperedStore {
+        fn get<'a>(&'a self, _cid: &'a Cid) -> StoreResult<'a, Box<[u8]>> {
+            Box::pin(async move { Ok(vec![0xf6].into_boxed_slice()) })
+        }
+    }
+
+    #[async_std::test]
+    async fn test_verify() {
+        let codec = Codec::new();
+        let block = codec.encode(&ipld!({"a": 3})).unwrap();
+        let mut builder = BlockBuilder::new(TamperedStore, codec);
+        builder.set_verify(true);
+        match builder.get_ipld(&block.cid).await {
+            Err(Error::Integrity(IntegrityError::Hash(cid))) => assert_eq!(cid, block.cid),
+            _ => panic!("expected integrity error"),
+        }
+    }
+
+    #[derive(Default)]
+    struct Counter {
+        inserts: AtomicUsize,
+        gets: AtomicUsize,
+        unpins: AtomicUsize,
+        aliases: AtomicUsize,
+        refs: AtomicUsize,
+    }
+
+    impl Observer for Counter {
+        fn on_insert(&self, _block: &Block) {
+            self.inserts.fetch_add(1, Ordering::SeqCst);
+        }
+
+        fn on_refs(&self, _cid: &Cid, refs: &[Cid]) {
+            self.refs.fetch_add(refs.len(), Ordering::SeqCst);
+        }
+
+        fn on_get(&self, _cid: &Cid) {
+            self.gets.fetch_add(1, Ordering::SeqCst);
+        }
+
+        fn on_unpin(&self, _cid: &Cid) {
+            self.unpins.fetch_add(1, Ordering::SeqCst);
+        }
+
+        fn on_alias(&self, _alias: &[u8], _cid: Option<&Cid>) {
+            self.aliases.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[async_std::test]
+    async fn test_observer() {
+        let counter = Arc::new(Counter::default());
+        let mut builder = BlockBuilder::new(MemStore::default(), Codec::new());
+        builder.add_observer(counter.clone());
+        let cid = builder.insert(&ipld!({"a": 3})).await.unwrap();
+        builder.get_ipld(&cid).await.unwrap();
+        builder.alias(b"root", &cid).await.unwrap();
+        builder.unalias(b"root").await.unwrap();
+        builder.unpin(&cid).await.unwrap();
+        assert_eq!(counter.inserts.load(Ordering::SeqCst), 1);
+        assert_eq!(counter.gets.load(Ordering::SeqCst), 1);
+        assert_eq!(counter.aliases.load(Ordering::SeqCst), 2);
+        assert_eq!(counter.unpins.load(Ordering::SeqCst), 1);
+
+        let ipld = ipld!({"a": &cid, "b": [&cid]});
+        let (root, refs) = builder.insert_with_refs(&ipld).await.unwrap();
+        assert_eq!(refs, vec![cid]);
+        assert_eq!(builder.get_ipld(&root).await.unwrap(), ipld);
+        assert_eq!(counter.refs.load(Ordering::SeqCst), 1);
+    }
+
+    #[cfg(feature = "crypto")]
     #[derive(Clone, DagCbor, Debug, Eq, PartialEq)]
     struct Identity {
         id: u64,
@@ -225,8 +1349,439 @@ mod tests {
         let ipld1 = ipld!({"a": 3});
         let cid = builder.insert(&ipld1).await.unwrap();
         let ipld2 = ipld!({"root": [{"child": &cid}]});
-        let root = builder.insert(&ipld2).await.unwrap();
+        let (root, refs) = builder.insert_with_refs(&ipld2).await.unwrap();
+        assert_eq!(refs, vec![cid]);
         let path = DagPath::new(&root, "root/0/child/a");
         assert_eq!(builder.get_path(&path).await.unwrap(), Ipld::Integer(3));
     }
+
+    #[async_std::test]
+    #[cfg(feature = "crypto")]
+    async fn test_block_builder_siv() {
+        let store = MemStore::default();
+        let siv = StrobeCodec::new_siv(Key::from(b"private encryption key".to_vec()));
+        let builder = BlockBuilder::new_private(store.clone(), siv);
+        let cid = builder.insert(&ipld!({"a": 3})).await.unwrap();
+        assert_eq!(builder.insert(&ipld!({"a": 3})).await.unwrap(), cid);
+
+        let codec = StrobeCodec::new(Key::from(b"private encryption key".to_vec()));
+        let builder = BlockBuilder::new_private(store, codec);
+        assert_eq!(builder.get_ipld(&cid).await.unwrap(), ipld!({"a": 3}));
+        assert_ne!(builder.insert(&ipld!({"a": 3})).await.unwrap(), cid);
+    }
+
+    #[async_std::test]
+    #[cfg(feature = "crypto")]
+    async fn test_block_builder_header() {
+        let store = MemStore::default();
+        let codec = StrobeCodec::new(Key::from(b"private encryption key".to_vec()));
+        let builder = BlockBuilder::new_private(store.clone(), codec);
+        let cid = builder
+            .insert_with_header(&ipld!({"a": 3}), b"schema/v1")
+            .await
+            .unwrap();
+        assert_eq!(builder.get_ipld(&cid).await.unwrap(), ipld!({"a": 3}));
+
+        let reader = BlockBuilder::new(store, Codec::new());
+        let header = reader.get_header(&cid).await.unwrap();
+        assert_eq!(header.as_deref(), Some(&b"schema/v1"[..]));
+        let cid = builder.insert(&ipld!({"a": 3})).await.unwrap();
+        assert_eq!(reader.get_header(&cid).await.unwrap(), None);
+        assert_eq!(reader.get_codec(&cid).await.unwrap(), None);
+    }
+
+    #[async_std::test]
+    #[cfg(feature = "crypto")]
+    async fn test_block_builder_expose_codec() {
+        let store = MemStore::default();
+        let mut codec = StrobeCodec::new(Key::from(b"private encryption key".to_vec()));
+        codec.set_expose_codec(true);
+        let builder = BlockBuilder::new_private(store.clone(), codec);
+        let cid = builder.insert(&ipld!({"a": 3})).await.unwrap();
+        let cid2 = builder.insert_with_header(&ipld!(3), b"v1").await.unwrap();
+        assert_eq!(builder.get_ipld(&cid).await.unwrap(), ipld!({"a": 3}));
+
+        let reader = BlockBuilder::new(store, Codec::new());
+        let codec = reader.get_codec(&cid).await.unwrap();
+        assert_eq!(codec, Some(libipld::cid::Codec::DagCBOR));
+        let codec = reader.get_codec(&cid2).await.unwrap();
+        assert_eq!(codec, Some(libipld::cid::Codec::DagCBOR));
+        assert_eq!(reader.get_header(&cid2).await.unwrap().unwrap(), b"v1");
+    }
+
+    #[derive(Clone, Default)]
+    struct FlushStore {
+        store: MemStore,
+        flushes: Arc<AtomicUsize>,
+    }
+
+    impl ReadonlyStore for FlushStore {
+        fn get<'a>(&'a self, cid: &'a Cid) -> StoreResult<'a, Box<[u8]>> {
+            self.store.get(cid)
+        }
+    }
+
+    impl Store for FlushStore {
+        fn insert<'a>(
+            &'a self,
+            cid: &'a Cid,
+            data: Box<[u8]>,
+            visibility: Visibility,
+        ) -> StoreResult<'a, ()> {
+            self.store.insert(cid, data, visibility)
+        }
+
+        fn insert_batch<'a>(
+            &'a self,
+            batch: Vec<Block>,
+            visibility: Visibility,
+        ) -> StoreResult<'a, Cid> {
+            self.store.insert_batch(batch, visibility)
+        }
+
+        fn flush(&self) -> StoreResult<'_, ()> {
+            self.flushes.fetch_add(1, Ordering::SeqCst);
+            self.store.flush()
+        }
+
+        fn unpin<'a>(&'a self, cid: &'a Cid) -> StoreResult<'a, ()> {
+            self.store.unpin(cid)
+        }
+    }
+
+    #[async_std::test]
+    async fn test_flush_on_drop() {
+        let store = FlushStore::default();
+        let mut builder = BlockBuilder::new(store.clone(), Codec::new());
+        builder.set_flush_on_drop(true);
+        drop(builder);
+        assert_eq!(store.flushes.load(Ordering::SeqCst), 0);
+
+        let mut builder = BlockBuilder::new(store.clone(), Codec::new());
+        builder.set_flush_on_drop(true);
+        builder.insert(&ipld!(1)).await.unwrap();
+        drop(builder);
+        assert_eq!(store.flushes.load(Ordering::SeqCst), 1);
+
+        let mut builder = BlockBuilder::new(store.clone(), Codec::new());
+        builder.set_flush_on_drop(true);
+        builder.insert(&ipld!(2)).await.unwrap();
+        builder.flush().await.unwrap();
+        drop(builder);
+        assert_eq!(store.flushes.load(Ordering::SeqCst), 2);
+    }
+
+    #[cfg_attr(not(feature = "tokio"), async_std::test)]
+    #[cfg_attr(feature = "tokio", tokio::test)]
+    async fn test_flush_periodically() {
+        let store = FlushStore::default();
+        let builder = BlockBuilder::new(store.clone(), Codec::new());
+        builder.insert(&ipld!(1)).await.unwrap();
+        let interval = Duration::from_millis(10);
+        let flush = builder.flush_periodically(interval);
+        let wait = crate::rt::sleep(Duration::from_millis(55));
+        futures::pin_mut!(flush, wait);
+        futures::future::select(flush, wait).await;
+        // only flushed once, as nothing was inserted afterwards
+        assert_eq!(store.flushes.load(Ordering::SeqCst), 1);
+    }
+
+    #[cfg_attr(not(feature = "tokio"), async_std::test)]
+    #[cfg_attr(feature = "tokio", tokio::test)]
+    async fn test_insert_many() {
+        let builder = BlockBuilder::new(MemStore::default(), Codec::new());
+        assert!(builder.insert_many::<u32>(&[]).await.unwrap().is_empty());
+
+        let values: Vec<Ipld> = (0..1000).map(|i| ipld!({ "row": i })).collect();
+        let cids = builder.insert_many(&values).await.unwrap();
+        assert_eq!(cids.len(), values.len());
+        for (cid, value) in cids.iter().zip(&values) {
+            assert_eq!(cid, &builder.codec().encode(value).unwrap().cid);
+        }
+        let last = cids.last().unwrap();
+        assert_eq!(
+            &builder.get_ipld(last).await.unwrap(),
+            values.last().unwrap()
+        );
+        let owned = builder.insert_many_owned(values.clone()).await.unwrap();
+        assert_eq!(owned, cids);
+        let batch = builder.encode_many(&values).unwrap();
+        assert!(batch.cids().eq(cids.iter()));
+        assert!(builder
+            .insert_many_owned::<u32>(vec![])
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[cfg_attr(not(feature = "tokio"), async_std::test)]
+    #[cfg_attr(feature = "tokio", tokio::test)]
+    async fn test_alias_cache() {
+        let store = MemStore::default();
+        let mut builder = BlockBuilder::new(store.clone(), Codec::new());
+        builder.set_alias_cache_ttl(Some(Duration::from_millis(50)));
+        let a = builder.insert(&ipld!(1)).await.unwrap();
+        let b = builder.insert(&ipld!(2)).await.unwrap();
+        assert_eq!(builder.resolve(b"root").await.unwrap(), None);
+        builder.alias(b"root", &a).await.unwrap();
+        assert_eq!(builder.resolve(b"root").await.unwrap(), Some(a.clone()));
+
+        // changes made by others are seen after the ttl
+        store.alias(b"root", &b, Visibility::Public).await.unwrap();
+        assert_eq!(builder.resolve(b"root").await.unwrap(), Some(a));
+        crate::rt::sleep(Duration::from_millis(60)).await;
+        assert_eq!(builder.resolve(b"root").await.unwrap(), Some(b));
+
+        builder.unalias(b"root").await.unwrap();
+        assert_eq!(builder.resolve(b"root").await.unwrap(), None);
+    }
+
+    #[cfg_attr(not(feature = "tokio"), async_std::test)]
+    #[cfg_attr(feature = "tokio", tokio::test)]
+    async fn test_unpin_grace() {
+        let mut builder = BlockBuilder::new(MemStore::default(), Codec::new());
+        builder.set_unpin_grace(Some(Duration::from_millis(50)));
+        let insert = |value| {
+            let block = builder.codec().encode(&value).unwrap();
+            let store = builder.store().clone();
+            async move {
+                store
+                    .insert(&block.cid, block.data, Visibility::Public)
+                    .await
+                    .unwrap();
+                block.cid
+            }
+        };
+        let a = insert(ipld!(1)).await;
+        builder.unpin(&a).await.unwrap();
+        assert_eq!(builder.tombstones(), 1);
+        assert!(builder.get_ipld(&a).await.is_ok());
+        assert_eq!(builder.unpin_expired().await.unwrap(), 0);
+
+        crate::rt::sleep(Duration::from_millis(60)).await;
+        let b = insert(ipld!(2)).await;
+        // unpinning b collects a
+        builder.unpin(&b).await.unwrap();
+        assert!(builder.get_ipld(&a).await.is_err());
+        assert!(builder.get_ipld(&b).await.is_ok());
+
+        assert_eq!(builder.unpin_pending().await.unwrap(), 1);
+        assert!(builder.get_ipld(&b).await.is_err());
+        assert_eq!(builder.tombstones(), 0);
+    }
+
+    #[async_std::test]
+    async fn test_unpin_grace_reinsert() {
+        let mut builder = BlockBuilder::new(CappedMemStore::new(0), Codec::new());
+        builder.set_unpin_grace(Some(Duration::from_secs(60)));
+        let a = builder.insert(&ipld!(1)).await.unwrap();
+        builder.unpin(&a).await.unwrap();
+        assert_eq!(builder.tombstones(), 1);
+
+        // inserting again cancels the tombstone, the block is pinned once
+        assert_eq!(builder.insert(&ipld!(1)).await.unwrap(), a);
+        assert_eq!(builder.tombstones(), 0);
+        assert_eq!(builder.unpin_pending().await.unwrap(), 0);
+        assert!(builder.get_ipld(&a).await.is_ok());
+        builder.unpin(&a).await.unwrap();
+        assert_eq!(builder.unpin_pending().await.unwrap(), 1);
+        assert!(builder.get_ipld(&a).await.is_err());
+    }
+
+    #[async_std::test]
+    async fn test_unpin_grace_reinsert_child() {
+        let mut builder = BlockBuilder::new(CappedMemStore::new(usize::MAX), Codec::new());
+        builder.set_unpin_grace(Some(Duration::from_secs(60)));
+        let a = builder.insert(&ipld!(1)).await.unwrap();
+        builder.unpin(&a).await.unwrap();
+
+        // inserting the block as a child doesn't pin it
+        let mut batch = builder.create_batch();
+        batch.insert(&ipld!(1)).unwrap();
+        let b = batch.insert(&ipld!({ "a": &a })).unwrap().clone();
+        builder.insert_batch(batch).await.unwrap();
+        assert_eq!(builder.tombstones(), 1);
+        assert_eq!(builder.unpin_pending().await.unwrap(), 1);
+        assert_eq!(builder.store().pins(&a).await, 0);
+        assert_eq!(builder.store().pins(&b).await, 1);
+    }
+
+    #[async_std::test]
+    async fn test_unpin_many() {
+        let builder = BlockBuilder::new(MemStore::default(), Codec::new());
+        let mut cids = vec![];
+        for i in 0..40 {
+            let block = builder.codec().encode(&ipld!({ "row": i })).unwrap();
+            let store = builder.store();
+            store
+                .insert(&block.cid, block.data, Visibility::Public)
+                .await
+                .unwrap();
+            cids.push(block.cid);
+        }
+        builder.unpin_many(&cids[..30]).await.unwrap();
+        for (i, cid) in cids.iter().enumerate() {
+            assert_eq!(builder.store().get(cid).await.is_ok(), i >= 30);
+        }
+    }
+
+    #[async_std::test]
+    async fn test_commit() {
+        let builder = BlockBuilder::new(MemStore::default(), Codec::new());
+        let mut batch = builder.create_batch();
+        let a = batch.insert(&ipld!(1)).unwrap().clone();
+        assert_eq!(builder.commit(batch, b"root").await.unwrap(), a);
+        assert_eq!(builder.resolve(b"root").await.unwrap(), Some(a.clone()));
+
+        let mut batch = builder.create_batch();
+        let b = batch.insert(&ipld!(2)).unwrap().clone();
+        builder.commit(batch, b"root").await.unwrap();
+        assert_eq!(builder.resolve(b"root").await.unwrap(), Some(b.clone()));
+        assert_eq!(builder.recover_commit(b"root").await.unwrap(), None);
+
+        // crashed after inserting the batch
+        let block = builder.codec().encode(&ipld!(3)).unwrap();
+        let intent = encode_intent(&block.cid, Some(&b));
+        let store = builder.store();
+        store
+            .insert(&intent.cid, intent.data, Visibility::Public)
+            .await
+            .unwrap();
+        store
+            .alias(b"commit/root", &intent.cid, Visibility::Public)
+            .await
+            .unwrap();
+        store
+            .insert(&block.cid, block.data, Visibility::Public)
+            .await
+            .unwrap();
+        let c = builder.recover_commit(b"root").await.unwrap();
+        assert_eq!(c, Some(block.cid.clone()));
+        assert_eq!(builder.resolve(b"root").await.unwrap(), c);
+        assert_eq!(store.resolve(b"commit/root").await.unwrap(), None);
+
+        // crashed before inserting the batch
+        let block = builder.codec().encode(&ipld!(4)).unwrap();
+        let intent = encode_intent(&block.cid, c.as_ref());
+        store
+            .insert(&intent.cid, intent.data, Visibility::Public)
+            .await
+            .unwrap();
+        store
+            .alias(b"commit/root", &intent.cid, Visibility::Public)
+            .await
+            .unwrap();
+        assert_eq!(builder.recover_commit(b"root").await.unwrap(), None);
+        assert_eq!(builder.resolve(b"root").await.unwrap(), c);
+        assert!(builder.get_ipld(c.as_ref().unwrap()).await.is_ok());
+    }
+
+    #[async_std::test]
+    async fn test_compute_cid() {
+        let builder = BlockBuilder::new(MemStore::default(), Codec::new());
+        let value = ipld!({ "value": 42 });
+        let cid = builder.compute_cid(&value).unwrap();
+        assert!(builder.store().get(&cid).await.is_err());
+        let mut batch = builder.create_batch();
+        batch.insert(&ipld!([1, 2])).unwrap();
+        batch.insert(&value).unwrap();
+        assert_eq!(batch.root_cid(), Some(&cid));
+        assert!(builder.store().get(&cid).await.is_err());
+        assert_eq!(builder.insert_batch(batch).await.unwrap(), cid);
+    }
+
+    #[derive(Clone)]
+    struct StalledStore;
+
+    impl ReadonlyStore for StalledStore {
+        fn get<'a>(&'a self, _: &'a Cid) -> StoreResult<'a, Box<[u8]>> {
+            Box::pin(futures::future::pending())
+        }
+    }
+
+    impl Store for StalledStore {
+        fn insert<'a>(&'a self, _: &'a Cid, _: Box<[u8]>, _: Visibility) -> StoreResult<'a, ()> {
+            Box::pin(futures::future::pending())
+        }
+
+        fn insert_batch<'a>(&'a self, _: Vec<Block>, _: Visibility) -> StoreResult<'a, Cid> {
+            Box::pin(futures::future::pending())
+        }
+
+        fn flush(&self) -> StoreResult<'_, ()> {
+            Box::pin(async { Ok(()) })
+        }
+
+        fn unpin<'a>(&'a self, _: &'a Cid) -> StoreResult<'a, ()> {
+            Box::pin(async { Ok(()) })
+        }
+    }
+
+    #[cfg_attr(not(feature = "tokio"), async_std::test)]
+    #[cfg_attr(feature = "tokio", tokio::test)]
+    async fn test_timeouts() {
+        let mut builder = BlockBuilder::new(StalledStore, Codec::new());
+        let timeout = Some(Duration::from_millis(10));
+        builder.set_timeouts(Timeouts {
+            get: timeout,
+            get_path: None,
+            insert_batch: timeout,
+        });
+        let cid = builder.codec().encode(&ipld!(1)).unwrap().cid;
+        let res = builder.get::<Ipld>(&cid).await;
+        assert!(matches!(res, Err(Error::Timeout("get", _))));
+        let res = builder.insert(&ipld!(1)).await;
+        assert!(matches!(res, Err(Error::Timeout("insert_batch", _))));
+
+        builder.set_timeouts(Timeouts {
+            get_path: timeout,
+            ..Default::default()
+        });
+        let path = DagPath::new(&cid, "a");
+        let res = builder.get_path(&path).await;
+        assert!(matches!(res, Err(Error::Timeout("get_path", _))));
+    }
+
+    #[derive(Clone, Default)]
+    struct RemoteMemStore {
+        local: MemStore,
+        remote: MemStore,
+    }
+
+    impl ReadonlyStore for RemoteMemStore {
+        fn get<'a>(&'a self, cid: &'a Cid) -> StoreResult<'a, Box<[u8]>> {
+            Box::pin(async move {
+                match self.local.get(cid).await {
+                    Err(StoreError::BlockNotFound(_)) => self.remote.get(cid).await,
+                    res => res,
+                }
+            })
+        }
+    }
+
+    impl RemoteStore for RemoteMemStore {
+        fn get_local<'a>(&'a self, cid: &'a Cid) -> StoreResult<'a, Box<[u8]>> {
+            self.local.get(cid)
+        }
+    }
+
+    #[async_std::test]
+    async fn test_offline() {
+        let store = RemoteMemStore::default();
+        let local = BlockBuilder::new(store.local.clone(), Codec::new());
+        let remote = BlockBuilder::new(store.remote.clone(), Codec::new());
+        let local = local.insert(&ipld!(1)).await.unwrap();
+        let remote = remote.insert(&ipld!(2)).await.unwrap();
+
+        let mut builder = BlockBuilder::new(store, Codec::new());
+        builder.set_offline(true);
+        assert!(builder.is_offline());
+        assert_eq!(builder.get::<Ipld>(&local).await.unwrap(), ipld!(1));
+        let res = builder.get::<Ipld>(&remote).await;
+        assert!(matches!(res, Err(Error::NotFoundLocally(cid)) if cid == remote));
+        assert_eq!(builder.fetch::<Ipld>(&remote).await.unwrap(), ipld!(2));
+
+        builder.set_offline(false);
+        assert_eq!(builder.get::<Ipld>(&remote).await.unwrap(), ipld!(2));
+    }
 }
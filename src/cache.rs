@@ -1,29 +1,440 @@
 use crate::batch::Batch;
+use crate::budget::{MemoryBudget, Reclaim, Reservation};
 use crate::builder::BlockBuilder;
-use crate::codec::{Decoder, Encoder};
-use async_std::sync::Mutex;
+use crate::car::{read_section, split_cid, write_varint};
+use crate::codec::{Decoder, Encoder, IpldDecoder};
+use crate::error::{Error, Result};
+use crate::eviction::{EvictionCache, EvictionPolicy};
+use crate::index::{BoundIndexes, IndexManager, Indexer};
 use async_trait::async_trait;
-use cached::stores::SizedCache;
-use cached::Cached;
 use libipld::cid::Cid;
 use libipld::codec::{Decode, Encode};
-use libipld::error::Result;
-use libipld::store::{ReadonlyStore, Store};
+use libipld::error::StoreError;
+use libipld::ipld::Ipld;
+use libipld::store::{AliasStore, ReadonlyStore, Store};
 use std::marker::PhantomData;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
+
+/// Application key of a cached value.
+///
+/// Keys are compared by their encoding, tuples encode their fields length
+/// prefixed so `("ab", "c")` and `("a", "bc")` are different keys.
+pub trait CacheKey {
+    /// Appends the encoded key to `out`.
+    fn encode_key(&self, out: &mut Vec<u8>);
+}
+
+impl CacheKey for [u8] {
+    fn encode_key(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(self);
+    }
+}
+
+impl CacheKey for Vec<u8> {
+    fn encode_key(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(self);
+    }
+}
+
+impl CacheKey for str {
+    fn encode_key(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(self.as_bytes());
+    }
+}
+
+impl CacheKey for String {
+    fn encode_key(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(self.as_bytes());
+    }
+}
+
+impl CacheKey for u64 {
+    fn encode_key(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.to_be_bytes());
+    }
+}
+
+impl<K: CacheKey + ?Sized> CacheKey for &K {
+    fn encode_key(&self, out: &mut Vec<u8>) {
+        (**self).encode_key(out);
+    }
+}
+
+fn encode_field<K: CacheKey + ?Sized>(key: &K, out: &mut Vec<u8>) {
+    let mut field = vec![];
+    key.encode_key(&mut field);
+    write_varint(out, field.len() as u64);
+    out.extend_from_slice(&field);
+}
+
+impl<A: CacheKey, B: CacheKey> CacheKey for (A, B) {
+    fn encode_key(&self, out: &mut Vec<u8>) {
+        encode_field(&self.0, out);
+        encode_field(&self.1, out);
+    }
+}
+
+impl<A: CacheKey, B: CacheKey, C: CacheKey> CacheKey for (A, B, C) {
+    fn encode_key(&self, out: &mut Vec<u8>) {
+        encode_field(&self.0, out);
+        encode_field(&self.1, out);
+        encode_field(&self.2, out);
+    }
+}
+
+/// Alias of the value inserted with `key`.
+fn key_alias<K: CacheKey + ?Sized>(key: &K) -> Vec<u8> {
+    let mut alias = b"cache-key/".to_vec();
+    key.encode_key(&mut alias);
+    alias
+}
+
+type ValueCache<T> = Arc<Mutex<EvictionCache<Cid, (T, Option<Reservation>)>>>;
 
 /// Cache for ipld blocks.
 pub struct IpldCache<S, C, T> {
-    builder: BlockBuilder<S, C>,
-    cache: Mutex<SizedCache<Cid, T>>,
+    builder: Arc<BlockBuilder<S, C>>,
+    budget: Option<MemoryBudget>,
+    cache: ValueCache<T>,
+    reclaimable: AtomicBool,
+    indexes: Option<Arc<dyn Indexer<C>>>,
 }
 
 impl<S, C, T> IpldCache<S, C, T> {
-    /// Creates a new cache of size `size`.
+    /// Creates a new lru cache of size `size`.
     pub fn new(store: S, codec: C, size: usize) -> Self {
+        Self::with_policy(store, codec, size, EvictionPolicy::Lru)
+    }
+
+    /// Creates a new cache of size `size` with an eviction policy.
+    pub fn with_policy(store: S, codec: C, size: usize, policy: EvictionPolicy) -> Self {
+        Self::with_builder_and_policy(BlockBuilder::new(store, codec), size, policy)
+    }
+
+    /// Creates a new lru cache of size `size` using a configured builder.
+    ///
+    /// Passing an `Arc` lets multiple typed caches share one builder.
+    pub fn with_builder(builder: impl Into<Arc<BlockBuilder<S, C>>>, size: usize) -> Self {
+        Self::with_builder_and_policy(builder, size, EvictionPolicy::Lru)
+    }
+
+    /// Creates a new cache of size `size` with an eviction policy using a
+    /// configured builder.
+    pub fn with_builder_and_policy(
+        builder: impl Into<Arc<BlockBuilder<S, C>>>,
+        size: usize,
+        policy: EvictionPolicy,
+    ) -> Self {
+        let builder = builder.into();
         Self {
-            builder: BlockBuilder::new(store, codec),
-            cache: Mutex::new(SizedCache::with_size(size)),
+            budget: builder.budget().cloned(),
+            builder,
+            cache: Arc::new(Mutex::new(EvictionCache::new(policy, size))),
+            reclaimable: AtomicBool::new(false),
+            indexes: None,
+        }
+    }
+
+    /// Returns the builder.
+    pub fn builder(&self) -> &Arc<BlockBuilder<S, C>> {
+        &self.builder
+    }
+
+    /// Sets a memory budget shared with other caches and batches.
+    ///
+    /// Cached values hold a reservation of their encoded size until they are
+    /// evicted. When the budget is exhausted values are returned without
+    /// being cached, batches wait as described in `BlockBuilder::set_budget`
+    /// while cached values are evicted to make room for them.
+    ///
+    /// Fails with `Error::SharedBuilder` if the builder is shared, its budget
+    /// needs to be set before sharing it.
+    pub fn set_budget(&mut self, budget: MemoryBudget) -> Result<()> {
+        let builder = Arc::get_mut(&mut self.builder).ok_or(Error::SharedBuilder)?;
+        builder.set_budget(budget.clone());
+        self.budget = Some(budget);
+        self.reclaimable = AtomicBool::new(false);
+        Ok(())
+    }
+
+    /// Flushes the store of the builder when it is dropped, see
+    /// `BlockBuilder::set_flush_on_drop`.
+    ///
+    /// Fails with `Error::SharedBuilder` if the builder is shared, the
+    /// option needs to be set before sharing it.
+    pub fn set_flush_on_drop(&mut self, enabled: bool) -> Result<()>
+    where
+        S: Store + Send + Sync + 'static,
+    {
+        let builder = Arc::get_mut(&mut self.builder).ok_or(Error::SharedBuilder)?;
+        builder.set_flush_on_drop(enabled);
+        Ok(())
+    }
+
+    /// Flushes the store every `interval`, see
+    /// `BlockBuilder::flush_periodically`.
+    pub async fn flush_periodically(&self, interval: Duration) -> Result<()>
+    where
+        S: Store,
+    {
+        self.builder.flush_periodically(interval).await
+    }
+
+    /// Maintains the secondary indexes of `indexes` for inserted values.
+    ///
+    /// Values inserted before the indexes were set are not indexed.
+    pub fn set_indexes(&mut self, indexes: IndexManager)
+    where
+        S: Store + AliasStore + Send + Sync + 'static,
+        C: Encoder + IpldDecoder + Clone + Send + Sync + 'static,
+        Ipld: Encode<<C as Encoder>::Codec>,
+    {
+        let indexes = BoundIndexes::new(self.builder.clone(), indexes);
+        self.indexes = Some(Arc::new(indexes));
+    }
+
+    /// Returns the values containing `value` in the index `name`.
+    pub async fn find(&self, name: &str, value: &Ipld) -> Result<Vec<Cid>> {
+        match &self.indexes {
+            Some(indexes) => indexes.find(name, value).await,
+            None => Err(Error::UnknownIndex(name.to_string())),
+        }
+    }
+}
+
+impl<S, C, T: Send + 'static> IpldCache<S, C, T> {
+    async fn cache(&self, cid: Cid, value: T, bytes: usize) {
+        let reservation = match &self.budget {
+            Some(budget) => match budget.try_acquire(bytes) {
+                Some(reservation) => {
+                    if !self.reclaimable.swap(true, Ordering::AcqRel) {
+                        let cache: Weak<dyn Reclaim> = Arc::downgrade(&self.cache) as _;
+                        budget.add_reclaimer(cache);
+                    }
+                    Some(reservation)
+                }
+                None => return,
+            },
+            None => None,
+        };
+        self.cache.lock().unwrap().insert(cid, (value, reservation));
+    }
+}
+
+impl<S, C, T> IpldCache<S, C, T>
+where
+    S: ReadonlyStore,
+    C: Decoder,
+    T: Decode<<C as Decoder>::Codec> + Send + 'static,
+{
+    /// Serializes the cached entries so a warm cache can be persisted.
+    ///
+    /// Entries are written as the blocks they were decoded from, read from
+    /// the store, so snapshots of an encrypted cache stay encrypted. They are
+    /// written in eviction order, restoring a snapshot preserves which
+    /// entries are evicted first. Entries whose block was removed from the
+    /// store are skipped.
+    pub async fn snapshot(&self) -> Result<Vec<u8>> {
+        let cids: Vec<Cid> = self
+            .cache
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(cid, _)| cid.clone())
+            .collect();
+        let mut out = vec![];
+        for cid in cids {
+            let data = match self.builder.get_verified(&cid).await {
+                Ok(data) => data,
+                Err(Error::Store(StoreError::BlockNotFound(_))) => continue,
+                Err(err) => return Err(err),
+            };
+            let cid = cid.to_bytes();
+            write_varint(&mut out, (cid.len() + data.len()) as u64);
+            out.extend_from_slice(&cid);
+            out.extend_from_slice(&data);
+        }
+        Ok(out)
+    }
+
+    /// Restores the entries of a snapshot and returns how many were cached.
+    ///
+    /// Every entry is verified against its cid and decoded before anything
+    /// is cached. The cache's size and memory budget apply as if the entries
+    /// were fetched.
+    pub async fn restore(&self, mut snapshot: &[u8]) -> Result<usize> {
+        let mut entries = vec![];
+        while !snapshot.is_empty() {
+            let section = read_section(&mut snapshot)?;
+            let (cid, data) = split_cid(section)?;
+            crate::error::verify(&cid, data)?;
+            let bytes = data.len();
+            let value: T = self.builder.codec().decode_owned(&cid, data.into())?;
+            entries.push((cid, value, bytes));
+        }
+        let mut restored = 0;
+        for (cid, value, bytes) in entries {
+            self.cache(cid.clone(), value, bytes).await;
+            if self.cache.lock().unwrap().contains(&cid) {
+                restored += 1;
+            }
+        }
+        Ok(restored)
+    }
+}
+
+impl<S: ReadonlyStore, C: IpldDecoder> IpldCache<S, C, Ipld> {
+    /// Returns a block decoded as ipld.
+    ///
+    /// Unlike `ReadonlyCache::get` blocks of any codec supported by the
+    /// decoder are returned, not only the ones of the cache's codec.
+    pub async fn get_ipld(&self, cid: &Cid) -> Result<Ipld> {
+        if let Some((value, _)) = self.cache.lock().unwrap().get(cid) {
+            #[cfg(feature = "metrics")]
+            crate::metrics::cache_hit(None);
+            return Ok(value.clone());
         }
+        #[cfg(feature = "metrics")]
+        crate::metrics::cache_miss(None);
+        let data = self.builder.get_verified(cid).await?;
+        let bytes = data.len();
+        let value = self.builder.codec().decode_ipld_owned(cid, data)?;
+        self.cache(cid.clone(), value.clone(), bytes).await;
+        Ok(value)
+    }
+}
+
+impl<S, C, T> IpldCache<S, C, T>
+where
+    S: ReadonlyStore,
+    C: Decoder,
+    T: Decode<C::Codec> + Clone + Send + 'static,
+{
+    /// Returns a decoded block, labeling the metrics with the cache name.
+    #[doc(hidden)]
+    pub async fn get_labeled(&self, cid: &Cid, label: Option<&'static str>) -> Result<T> {
+        if let Some((value, _)) = self.cache.lock().unwrap().get(cid) {
+            let value = value.clone();
+            #[cfg(feature = "metrics")]
+            crate::metrics::cache_hit(label);
+            return Ok(value);
+        }
+        #[cfg(feature = "metrics")]
+        crate::metrics::cache_miss(label);
+        let data = self.builder.get_verified(cid).await?;
+        let bytes = data.len();
+        #[cfg(feature = "metrics")]
+        let start = crate::rt::Instant::now();
+        let value: T = self.builder.codec().decode_owned(cid, data)?;
+        #[cfg(feature = "metrics")]
+        crate::metrics::cache_decode(label, start.elapsed());
+        #[cfg(not(feature = "metrics"))]
+        let _ = label;
+        self.cache(cid.clone(), value.clone(), bytes).await;
+        Ok(value)
+    }
+}
+
+impl<S: AliasStore, C, T> IpldCache<S, C, T> {
+    /// Returns the cid of the value inserted with `key`.
+    ///
+    /// Keys are stored in the store as aliases `cache-key/<key>`, so caches
+    /// sharing a store share their keys.
+    pub async fn cid_by_key<K: CacheKey + ?Sized>(&self, key: &K) -> Result<Option<Cid>> {
+        self.builder.resolve(&key_alias(key)).await
+    }
+}
+
+impl<S, C, T> IpldCache<S, C, T>
+where
+    S: ReadonlyStore + AliasStore,
+    C: Decoder,
+    T: Decode<C::Codec> + Clone + Send + 'static,
+{
+    /// Returns the value inserted with `key`.
+    pub async fn get_by_key<K: CacheKey + ?Sized>(&self, key: &K) -> Result<Option<T>> {
+        match self.cid_by_key(key).await? {
+            Some(cid) => Ok(Some(self.get_labeled(&cid, None).await?)),
+            None => Ok(None),
+        }
+    }
+}
+
+impl<S, C, T> IpldCache<S, C, T>
+where
+    S: Store,
+    C: Encoder + Clone,
+    T: Encode<C::Codec> + Send + 'static,
+{
+    async fn write(&self, batch: Batch<C>) -> Result<Cid> {
+        match &self.indexes {
+            Some(indexes) => indexes.insert_batch(batch).await,
+            None => self.builder.insert_batch(batch).await,
+        }
+    }
+
+    /// Inserts a batch, labeling the metrics with the cache name.
+    #[doc(hidden)]
+    pub async fn insert_batch_labeled(
+        &self,
+        batch: CacheBatch<C, T>,
+        label: Option<&'static str>,
+    ) -> Result<Cid> {
+        let (batch, values) = batch.into_parts();
+        let cid = self.write(batch).await?;
+        #[cfg(feature = "metrics")]
+        crate::metrics::cache_insert(label, values.len());
+        #[cfg(not(feature = "metrics"))]
+        let _ = label;
+        for (cid, value, bytes) in values {
+            self.cache(cid, value, bytes).await;
+        }
+        Ok(cid)
+    }
+
+    /// Inserts a value, labeling the metrics with the cache name.
+    #[doc(hidden)]
+    pub async fn insert_labeled(&self, value: T, label: Option<&'static str>) -> Result<Cid> {
+        let mut batch = self.builder.create_batch();
+        batch.insert(&value)?;
+        let bytes = batch.bytes();
+        let cid = self.write(batch).await?;
+        #[cfg(feature = "metrics")]
+        crate::metrics::cache_insert(label, 1);
+        #[cfg(not(feature = "metrics"))]
+        let _ = label;
+        self.cache(cid.clone(), value, bytes).await;
+        Ok(cid)
+    }
+}
+
+impl<S, C, T> IpldCache<S, C, T>
+where
+    S: Store + AliasStore,
+    C: Encoder + Clone,
+    T: Encode<C::Codec> + Send + 'static,
+{
+    /// Inserts a value and maps `key` to it.
+    ///
+    /// The value is pinned while it is mapped to `key`, a value previously
+    /// inserted with `key` is unpinned.
+    pub async fn insert_with_key<K: CacheKey + ?Sized>(&self, key: &K, value: T) -> Result<Cid> {
+        let cid = self.insert_labeled(value, None).await?;
+        if let Some(prev) = self.builder.swap_alias(&key_alias(key), &cid).await? {
+            self.builder.unpin(&prev).await?;
+        }
+        Ok(cid)
+    }
+
+    /// Removes `key` and unpins its value, returning the cid it mapped to.
+    pub async fn remove_key<K: CacheKey + ?Sized>(&self, key: &K) -> Result<Option<Cid>> {
+        let prev = self.builder.take_alias(&key_alias(key)).await?;
+        if let Some(prev) = &prev {
+            self.builder.unpin(prev).await?;
+        }
+        Ok(prev)
     }
 }
 
@@ -42,18 +453,14 @@ where
 impl<S: ReadonlyStore + Send + Sync, C, T> ReadonlyCache<C, T> for IpldCache<S, C, T>
 where
     C: Decoder + Clone + Send + Sync,
-    T: Decode<<C as Decoder>::Codec> + Clone + Send + Sync,
+    T: Decode<<C as Decoder>::Codec> + Clone + Send + Sync + 'static,
 {
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(skip(self, cid), fields(cid = %cid))
+    )]
     async fn get(&self, cid: &Cid) -> Result<T> {
-        if let Some(value) = self.cache.lock().await.cache_get(cid).cloned() {
-            return Ok(value);
-        }
-        let value: T = self.builder.get(cid).await?;
-        self.cache
-            .lock()
-            .await
-            .cache_set(cid.clone(), value.clone());
-        Ok(value)
+        self.get_labeled(cid, None).await
     }
 }
 
@@ -81,13 +488,26 @@ where
 
     /// Unpins a block.
     async fn unpin(&self, cid: &Cid) -> Result<()>;
+
+    /// Unpins blocks, returning the first error.
+    async fn unpin_many(&self, cids: &[Cid]) -> Result<()> {
+        for cid in cids {
+            self.unpin(cid).await?;
+        }
+        Ok(())
+    }
 }
 
 #[async_trait]
 impl<S: Store + Send + Sync, C, T> Cache<C, T> for IpldCache<S, C, T>
 where
     C: Decoder + Encoder + Clone + Send + Sync,
-    T: Decode<<C as Decoder>::Codec> + Encode<<C as Encoder>::Codec> + Clone + Send + Sync,
+    T: Decode<<C as Decoder>::Codec>
+        + Encode<<C as Encoder>::Codec>
+        + Clone
+        + Send
+        + Sync
+        + 'static,
 {
     fn create_batch(&self) -> CacheBatch<C, T> {
         CacheBatch::new(self.builder.codec().clone())
@@ -97,19 +517,14 @@ where
         CacheBatch::with_capacity(self.builder.codec().clone(), capacity)
     }
 
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self, batch)))]
     async fn insert_batch(&self, batch: CacheBatch<C, T>) -> Result<Cid> {
-        let cid = self.builder.insert_batch(batch.batch).await?;
-        let mut cache = self.cache.lock().await;
-        for (cid, value) in batch.cache {
-            cache.cache_set(cid, value);
-        }
-        Ok(cid)
+        self.insert_batch_labeled(batch, None).await
     }
 
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self, value)))]
     async fn insert(&self, value: T) -> Result<Cid> {
-        let cid = self.builder.insert(&value).await?;
-        self.cache.lock().await.cache_set(cid.clone(), value);
-        Ok(cid)
+        self.insert_labeled(value, None).await
     }
 
     async fn flush(&self) -> Result<()> {
@@ -119,12 +534,16 @@ where
     async fn unpin(&self, cid: &Cid) -> Result<()> {
         self.builder.unpin(cid).await
     }
+
+    async fn unpin_many(&self, cids: &[Cid]) -> Result<()> {
+        self.builder.unpin_many(cids).await
+    }
 }
 
 /// Typed batch.
 pub struct CacheBatch<C, T> {
     _marker: PhantomData<T>,
-    cache: Vec<(Cid, T)>,
+    cache: Vec<(Cid, T, usize)>,
     batch: Batch<C>,
 }
 
@@ -149,23 +568,57 @@ impl<C: Encoder, T: Encode<C::Codec>> CacheBatch<C, T> {
 
     /// Inserts a value into the batch.
     pub fn insert(&mut self, value: T) -> Result<&Cid> {
-        let cid = self.batch.insert(&value)?;
-        self.cache.push((cid.clone(), value));
-        Ok(cid)
+        self.batch.insert(&value)?;
+        let block = self.batch.blocks().last().unwrap();
+        self.cache
+            .push((block.cid.clone(), value, block.data.len()));
+        Ok(&block.cid)
+    }
+
+    /// Returns the untyped batch and the values to cache.
+    pub(crate) fn into_parts(self) -> (Batch<C>, Vec<(Cid, T, usize)>) {
+        (self.batch, self.cache)
+    }
+
+    /// Inserts an untyped block into the batch without caching it.
+    pub fn insert_ipld(&mut self, ipld: &Ipld) -> Result<&Cid>
+    where
+        Ipld: Encode<C::Codec>,
+    {
+        self.batch.insert_ipld(ipld)
     }
 }
 
 /// Macro to derive cache trait for a struct.
+///
+/// Passing `metrics` as the last argument labels the cache metrics of the
+/// field with its name, which needs the field to be an `IpldCache`.
 #[macro_export]
 macro_rules! derive_cache {
     ($struct:tt, $field:ident, $codec:ty, $type:ty) => {
+        $crate::derive_cache!(@impl $struct, $field, $codec, $type, get, insert, insert_batch, ());
+    };
+    ($struct:tt, $field:ident, $codec:ty, $type:ty, metrics) => {
+        $crate::derive_cache!(
+            @impl $struct,
+            $field,
+            $codec,
+            $type,
+            get_labeled,
+            insert_labeled,
+            insert_batch_labeled,
+            (Some(stringify!($field)))
+        );
+    };
+    (@impl $struct:tt, $field:ident, $codec:ty, $type:ty,
+     $get:ident, $insert:ident, $insert_batch:ident, ($($label:expr)?)) => {
         #[async_trait::async_trait]
         impl<S> $crate::ReadonlyCache<$codec, $type> for $struct<S>
         where
             S: libipld::store::ReadonlyStore + Send + Sync,
         {
-            async fn get(&self, cid: &libipld::cid::Cid) -> libipld::error::Result<$type> {
-                self.$field.get(cid).await
+            async fn get(&self, cid: &libipld::cid::Cid) -> $crate::Result<$type> {
+                self.$field.$get(cid $(, $label)?).await
             }
         }
 
@@ -188,21 +641,25 @@ macro_rules! derive_cache {
             async fn insert_batch(
                 &self,
                 batch: $crate::CacheBatch<$codec, $type>,
-            ) -> libipld::error::Result<libipld::cid::Cid> {
-                self.$field.insert_batch(batch).await
+            ) -> $crate::Result<libipld::cid::Cid> {
+                self.$field.$insert_batch(batch $(, $label)?).await
             }
 
-            async fn insert(&self, value: $type) -> libipld::error::Result<libipld::cid::Cid> {
-                self.$field.insert(value).await
+            async fn insert(&self, value: $type) -> $crate::Result<libipld::cid::Cid> {
+                self.$field.$insert(value $(, $label)?).await
             }
 
-            async fn flush(&self) -> libipld::error::Result<()> {
+            async fn flush(&self) -> $crate::Result<()> {
                 self.$field.flush().await
             }
 
-            async fn unpin(&self, cid: &libipld::cid::Cid) -> libipld::error::Result<()> {
+            async fn unpin(&self, cid: &libipld::cid::Cid) -> $crate::Result<()> {
                 self.$field.unpin(cid).await
             }
+
+            async fn unpin_many(&self, cids: &[libipld::cid::Cid]) -> $crate::Result<()> {
+                self.$field.unpin_many(cids).await
+            }
         }
     };
 }
@@ -210,8 +667,12 @@ macro_rules! derive_cache {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::store::CappedMemStore;
     use crate::Codec;
+    use libipld::ipld;
     use libipld::mem::MemStore;
+    use libipld::multihash::Blake2b256;
+    use libipld::raw::RawCodec;
 
     struct OffchainClient<S> {
         number: IpldCache<S, Codec, u32>,
@@ -219,6 +680,14 @@ mod tests {
 
     derive_cache!(OffchainClient, number, Codec, u32);
 
+    struct LabeledClient<S> {
+        number: IpldCache<S, Codec, u32>,
+        text: IpldCache<S, Codec, String>,
+    }
+
+    derive_cache!(LabeledClient, number, Codec, u32, metrics);
+    derive_cache!(LabeledClient, text, Codec, String, metrics);
+
     #[async_std::test]
     async fn test_cache() {
         let store = MemStore::default();
@@ -230,4 +699,202 @@ mod tests {
         let res = client.get(&cid).await.unwrap();
         assert_eq!(res, 42);
     }
+
+    #[async_std::test]
+    async fn test_cache_labeled() {
+        let store = MemStore::default();
+        let client = LabeledClient {
+            number: IpldCache::new(store.clone(), Codec::new(), 1),
+            text: IpldCache::new(store, Codec::new(), 1),
+        };
+        let cid = client.insert(42).await.unwrap();
+        assert_eq!(
+            ReadonlyCache::<_, u32>::get(&client, &cid).await.unwrap(),
+            42
+        );
+        let cid = client.insert("a".to_string()).await.unwrap();
+        assert_eq!(
+            ReadonlyCache::<_, String>::get(&client, &cid)
+                .await
+                .unwrap(),
+            "a"
+        );
+    }
+
+    #[async_std::test]
+    async fn test_cache_budget() {
+        let budget = MemoryBudget::new(1024);
+        let mut cache = IpldCache::<_, _, String>::new(MemStore::default(), Codec::new(), 1);
+        cache.set_budget(budget.clone()).unwrap();
+        let a = cache.insert("a".repeat(100)).await.unwrap();
+        let used = 1024 - budget.available();
+        assert!(used > 100);
+        cache.insert("b".repeat(100)).await.unwrap();
+        // the evicted value released its reservation
+        assert_eq!(1024 - budget.available(), used);
+
+        let _hog = budget.acquire(budget.available()).await;
+        assert_eq!(cache.get(&a).await.unwrap(), "a".repeat(100));
+        assert_eq!(1024 - budget.available(), 1024);
+    }
+
+    #[async_std::test]
+    async fn test_cache_budget_reclaim() {
+        let budget = MemoryBudget::new(1024);
+        let mut builder = BlockBuilder::new(MemStore::default(), Codec::new());
+        builder.set_budget(budget.clone());
+        let cache = IpldCache::<_, _, String>::with_builder(builder, 16);
+        for i in 0..8 {
+            let value = format!("{:0100}", i);
+            let cid = cache.builder().insert(&value).await.unwrap();
+            cache.get(&cid).await.unwrap();
+        }
+        let cached = cache.cache.lock().unwrap().len();
+        assert!(budget.available() < 300);
+
+        // the batch evicts cached values instead of waiting for them
+        let mut batch = cache.builder().create_batch();
+        batch.insert(&"x".repeat(500)).unwrap();
+        let insert = cache.builder().insert_batch(batch);
+        async_std::future::timeout(Duration::from_secs(5), insert)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(cache.cache.lock().unwrap().len() < cached);
+    }
+
+    #[async_std::test]
+    async fn test_cache_shared_builder() {
+        let budget = MemoryBudget::new(1024);
+        let mut builder = BlockBuilder::new(MemStore::default(), Codec::new());
+        builder.set_budget(budget.clone());
+        let builder = Arc::new(builder);
+        let mut numbers = IpldCache::<_, _, u32>::with_builder(builder.clone(), 1);
+        let strings = IpldCache::<_, _, String>::with_builder(builder.clone(), 1);
+        assert_eq!(Arc::strong_count(numbers.builder()), 3);
+        // the budget of a shared builder can't be replaced
+        let res = numbers.set_budget(MemoryBudget::new(1));
+        assert!(matches!(res, Err(Error::SharedBuilder)));
+        let res = numbers.set_flush_on_drop(true);
+        assert!(matches!(res, Err(Error::SharedBuilder)));
+
+        let n = numbers.insert(42).await.unwrap();
+        let s = strings.insert("a".into()).await.unwrap();
+        assert_eq!(builder.get::<String>(&s).await.unwrap(), "a");
+        assert_eq!(numbers.get(&n).await.unwrap(), 42);
+        // both caches reserve from the builder's budget
+        assert!(budget.available() < 1024);
+    }
+
+    #[async_std::test]
+    async fn test_cache_ipld() {
+        let store = MemStore::default();
+        let cache = IpldCache::<_, _, u32>::new(store.clone(), Codec::new(), 2);
+        let mut batch = cache.create_batch();
+        let n = batch.insert(42).unwrap().clone();
+        let raw = batch.insert_ipld(&ipld!({"n": n.clone()})).unwrap().clone();
+        let root = batch.insert(43).unwrap().clone();
+        cache.insert_batch(batch).await.unwrap();
+        assert_eq!(cache.get(&n).await.unwrap(), 42);
+        assert_eq!(cache.get(&root).await.unwrap(), 43);
+
+        let ipld_cache = IpldCache::<_, _, Ipld>::new(store.clone(), Codec::new(), 2);
+        assert_eq!(ipld_cache.get_ipld(&raw).await.unwrap(), ipld!({"n": n}));
+        let block = libipld::block::encode::<RawCodec, Blake2b256, _>(&Ipld::Bytes(vec![1, 2]));
+        let block = block.unwrap();
+        let public = libipld::store::Visibility::Public;
+        store.insert(&block.cid, block.data, public).await.unwrap();
+        let bytes = block.cid;
+        assert!(ipld_cache.get(&bytes).await.is_err());
+        assert_eq!(
+            ipld_cache.get_ipld(&bytes).await.unwrap(),
+            Ipld::Bytes(vec![1, 2])
+        );
+    }
+
+    #[async_std::test]
+    async fn test_cache_keys() {
+        let cache = IpldCache::<_, _, String>::new(MemStore::default(), Codec::new(), 1);
+        let a = cache
+            .insert_with_key(&("users", 1u64), "alice".into())
+            .await
+            .unwrap();
+        cache
+            .insert_with_key(&("users", 2u64), "bob".into())
+            .await
+            .unwrap();
+        assert_eq!(
+            cache.cid_by_key(&("users", 1u64)).await.unwrap(),
+            Some(a.clone())
+        );
+        // evicted values are fetched from the store
+        assert_eq!(
+            cache.get_by_key(&("users", 1u64)).await.unwrap().as_deref(),
+            Some("alice")
+        );
+        assert_eq!(cache.get_by_key(&("groups", 1u64)).await.unwrap(), None);
+        assert_eq!(cache.get_by_key("users").await.unwrap(), None);
+
+        assert_ne!(key_alias(&("ab", "c")), key_alias(&("a", "bc")));
+        assert_eq!(cache.remove_key(&("users", 1u64)).await.unwrap(), Some(a));
+        assert_eq!(cache.get_by_key(&("users", 1u64)).await.unwrap(), None);
+    }
+
+    #[async_std::test]
+    async fn test_cache_keys_persisted() {
+        let store = CappedMemStore::new(0);
+        let cache = IpldCache::<_, _, String>::new(store.clone(), Codec::new(), 0);
+        let a = cache.insert_with_key("user", "alice".into()).await.unwrap();
+        let b = cache.insert_with_key("user", "bob".into()).await.unwrap();
+        // the replaced value is unpinned
+        assert!(cache.get(&a).await.is_err());
+
+        let reopened = IpldCache::<_, _, String>::new(store, Codec::new(), 0);
+        assert_eq!(reopened.cid_by_key("user").await.unwrap(), Some(b.clone()));
+        assert_eq!(
+            reopened.get_by_key("user").await.unwrap().as_deref(),
+            Some("bob")
+        );
+        assert_eq!(reopened.remove_key("user").await.unwrap(), Some(b.clone()));
+        assert!(reopened.get(&b).await.is_err());
+    }
+
+    #[async_std::test]
+    async fn test_cache_snapshot() {
+        let store = MemStore::default();
+        let cache = IpldCache::<_, _, String>::new(store.clone(), Codec::new(), 2);
+        let a = cache.insert("a".into()).await.unwrap();
+        let b = cache.insert("b".into()).await.unwrap();
+        let c = cache.insert("c".into()).await.unwrap();
+        let snapshot = cache.snapshot().await.unwrap();
+
+        let warm = IpldCache::<_, _, String>::new(MemStore::default(), Codec::new(), 2);
+        assert_eq!(warm.restore(&snapshot).await.unwrap(), 2);
+        assert_eq!(warm.get(&b).await.unwrap(), "b");
+        assert_eq!(warm.get(&c).await.unwrap(), "c");
+        assert!(warm.get(&a).await.is_err());
+
+        let mut corrupt = snapshot.clone();
+        *corrupt.last_mut().unwrap() ^= 1;
+        assert!(matches!(
+            warm.restore(&corrupt).await,
+            Err(Error::Integrity(_))
+        ));
+        assert!(warm.restore(&snapshot[..3]).await.is_err());
+    }
+
+    #[cfg(feature = "crypto")]
+    #[async_std::test]
+    async fn test_encrypted_cache_snapshot() {
+        use crate::{Key, StrobeCodec};
+        let store = MemStore::default();
+        let codec = StrobeCodec::new(Key::from(b"private encryption key".to_vec()));
+        let cache = IpldCache::<_, _, String>::new(store.clone(), codec.clone(), 2);
+        let a = cache.insert("a".into()).await.unwrap();
+        let snapshot = cache.snapshot().await.unwrap();
+
+        let warm = IpldCache::<_, _, String>::new(MemStore::default(), codec, 2);
+        assert_eq!(warm.restore(&snapshot).await.unwrap(), 1);
+        assert_eq!(warm.get(&a).await.unwrap(), "a");
+    }
 }
@@ -0,0 +1,586 @@
+use crate::error::{Error, Result};
+use crate::rt::{SystemTime, UNIX_EPOCH};
+use core::convert::TryFrom;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use libipld::block::Block;
+use libipld::cbor::DagCborCodec;
+use libipld::cid::Cid;
+use libipld::codec::Codec;
+use libipld::error::StoreError;
+use libipld::ipld::Ipld;
+use libipld::store::{AliasStore, ReadonlyStore, Store, StoreResult, Visibility};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+fn invalid(msg: impl Into<String>) -> Error {
+    Error::InvalidToken(msg.into())
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Mutating operation granted by a capability token.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Ability {
+    /// Insert blocks.
+    Insert,
+    /// Set and remove aliases.
+    Alias,
+    /// Unpin blocks.
+    Unpin,
+}
+
+impl Ability {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Insert => "insert",
+            Self::Alias => "alias",
+            Self::Unpin => "unpin",
+        }
+    }
+
+    fn from_str(ability: &str) -> Result<Self> {
+        match ability {
+            "insert" => Ok(Self::Insert),
+            "alias" => Ok(Self::Alias),
+            "unpin" => Ok(Self::Unpin),
+            _ => Err(invalid(format!("unknown ability {}", ability))),
+        }
+    }
+}
+
+/// Token granting abilities to the holder of the audience key.
+///
+/// Like an ucan a token is either issued by the root key or delegated by
+/// the audience of its proof, granting at most the abilities, aliases and
+/// validity of the proof.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CapabilityToken {
+    /// Key that signed the token.
+    pub issuer: VerifyingKey,
+    /// Key the abilities are granted to.
+    pub audience: VerifyingKey,
+    /// Granted abilities.
+    pub abilities: Vec<Ability>,
+    /// Prefix of the aliases that can be set and removed.
+    pub alias_prefix: Vec<u8>,
+    /// Expiry of the token in seconds since the unix epoch.
+    pub expiry: u64,
+    /// Token delegating the abilities to the issuer.
+    pub proof: Option<Box<CapabilityToken>>,
+    /// Signature of the other fields.
+    pub signature: Signature,
+}
+
+struct Claims<'a> {
+    issuer: &'a VerifyingKey,
+    audience: &'a VerifyingKey,
+    abilities: &'a [Ability],
+    alias_prefix: &'a [u8],
+    expiry: u64,
+    proof: Option<&'a CapabilityToken>,
+}
+
+impl Claims<'_> {
+    fn to_ipld(&self) -> BTreeMap<String, Ipld> {
+        let mut map = BTreeMap::new();
+        map.insert(
+            "iss".to_string(),
+            Ipld::Bytes(self.issuer.as_bytes().to_vec()),
+        );
+        map.insert(
+            "aud".to_string(),
+            Ipld::Bytes(self.audience.as_bytes().to_vec()),
+        );
+        let abilities = self
+            .abilities
+            .iter()
+            .map(|ability| Ipld::String(ability.as_str().to_string()))
+            .collect();
+        map.insert("can".to_string(), Ipld::List(abilities));
+        map.insert("with".to_string(), Ipld::Bytes(self.alias_prefix.to_vec()));
+        map.insert("exp".to_string(), Ipld::Integer(self.expiry.into()));
+        let proof = match self.proof {
+            Some(proof) => proof.to_ipld(),
+            None => Ipld::Null,
+        };
+        map.insert("prf".to_string(), proof);
+        map
+    }
+
+    fn sign(self, key: &SigningKey) -> Result<CapabilityToken> {
+        if key.verifying_key() != *self.issuer {
+            return Err(invalid("signing key is not the issuer"));
+        }
+        let msg = encode(self.to_ipld())?;
+        Ok(CapabilityToken {
+            issuer: *self.issuer,
+            audience: *self.audience,
+            abilities: self.abilities.to_vec(),
+            alias_prefix: self.alias_prefix.to_vec(),
+            expiry: self.expiry,
+            proof: self.proof.map(|proof| Box::new(proof.clone())),
+            signature: key.sign(&msg),
+        })
+    }
+}
+
+fn encode(map: BTreeMap<String, Ipld>) -> Result<Box<[u8]>> {
+    DagCborCodec::encode(&Ipld::Map(map)).map_err(|err| invalid(err.to_string()))
+}
+
+fn public_key(ipld: &Ipld, key: &str) -> Result<VerifyingKey> {
+    match ipld.get(key) {
+        Ok(Ipld::Bytes(bytes)) => <[u8; 32]>::try_from(&bytes[..])
+            .ok()
+            .and_then(|key| VerifyingKey::from_bytes(&key).ok())
+            .ok_or_else(|| invalid(format!("invalid {}", key))),
+        _ => Err(invalid(format!("missing {}", key))),
+    }
+}
+
+impl CapabilityToken {
+    /// Issues a token signed by the root `key`.
+    pub fn issue(
+        key: &SigningKey,
+        audience: &VerifyingKey,
+        abilities: &[Ability],
+        alias_prefix: &[u8],
+        validity: Duration,
+    ) -> Result<Self> {
+        Claims {
+            issuer: &key.verifying_key(),
+            audience,
+            abilities,
+            alias_prefix,
+            expiry: now().saturating_add(validity.as_secs()),
+            proof: None,
+        }
+        .sign(key)
+    }
+
+    /// Delegates a subset of the abilities of the token, signed by its
+    /// audience `key`.
+    ///
+    /// The validity is capped at the expiry of the token.
+    pub fn delegate(
+        &self,
+        key: &SigningKey,
+        audience: &VerifyingKey,
+        abilities: &[Ability],
+        alias_prefix: &[u8],
+        validity: Duration,
+    ) -> Result<Self> {
+        if key.verifying_key() != self.audience {
+            return Err(invalid("signing key is not the audience"));
+        }
+        if !abilities
+            .iter()
+            .all(|ability| self.abilities.contains(ability))
+        {
+            return Err(invalid("abilities exceed the proof"));
+        }
+        if !alias_prefix.starts_with(&self.alias_prefix) {
+            return Err(invalid("aliases exceed the proof"));
+        }
+        Claims {
+            issuer: &self.audience,
+            audience,
+            abilities,
+            alias_prefix,
+            expiry: now().saturating_add(validity.as_secs()).min(self.expiry),
+            proof: Some(self),
+        }
+        .sign(key)
+    }
+
+    fn claims(&self) -> Claims<'_> {
+        Claims {
+            issuer: &self.issuer,
+            audience: &self.audience,
+            abilities: &self.abilities,
+            alias_prefix: &self.alias_prefix,
+            expiry: self.expiry,
+            proof: self.proof.as_deref(),
+        }
+    }
+
+    /// Verifies the signatures, attenuation and validity of the chain of
+    /// tokens up to the `root` key.
+    pub fn verify(&self, root: &VerifyingKey) -> Result<()> {
+        let msg = encode(self.claims().to_ipld())?;
+        self.issuer
+            .verify(&msg, &self.signature)
+            .map_err(|_| invalid("invalid signature"))?;
+        if self.expiry < now() {
+            return Err(invalid("expired"));
+        }
+        match &self.proof {
+            None if self.issuer == *root => Ok(()),
+            None => Err(invalid("not issued by the root key")),
+            Some(proof) => {
+                if proof.audience != self.issuer {
+                    return Err(invalid("issuer is not the audience of the proof"));
+                }
+                if !self
+                    .abilities
+                    .iter()
+                    .all(|ability| proof.abilities.contains(ability))
+                {
+                    return Err(invalid("abilities exceed the proof"));
+                }
+                if !self.alias_prefix.starts_with(&proof.alias_prefix) {
+                    return Err(invalid("aliases exceed the proof"));
+                }
+                if self.expiry > proof.expiry {
+                    return Err(invalid("validity exceeds the proof"));
+                }
+                proof.verify(root)
+            }
+        }
+    }
+
+    /// Returns if the token grants `ability`, on `alias` for aliases.
+    pub fn allows(&self, ability: Ability, alias: Option<&[u8]>) -> bool {
+        self.abilities.contains(&ability)
+            && alias.is_none_or(|alias| alias.starts_with(&self.alias_prefix))
+    }
+
+    fn to_ipld(&self) -> Ipld {
+        let mut map = self.claims().to_ipld();
+        let signature = self.signature.to_bytes().to_vec();
+        map.insert("sig".to_string(), Ipld::Bytes(signature));
+        Ipld::Map(map)
+    }
+
+    fn from_ipld(ipld: &Ipld) -> Result<Self> {
+        let abilities = match ipld.get("can") {
+            Ok(Ipld::List(abilities)) => abilities
+                .iter()
+                .map(|ability| match ability {
+                    Ipld::String(ability) => Ability::from_str(ability),
+                    _ => Err(invalid("invalid ability")),
+                })
+                .collect::<Result<_>>()?,
+            _ => return Err(invalid("missing can")),
+        };
+        let alias_prefix = match ipld.get("with") {
+            Ok(Ipld::Bytes(prefix)) => prefix.clone(),
+            _ => return Err(invalid("missing with")),
+        };
+        let expiry = match ipld.get("exp") {
+            Ok(Ipld::Integer(n)) => u64::try_from(*n).map_err(|_| invalid("invalid exp"))?,
+            _ => return Err(invalid("missing exp")),
+        };
+        let proof = match ipld.get("prf") {
+            Ok(Ipld::Null) => None,
+            Ok(proof) => Some(Box::new(Self::from_ipld(proof)?)),
+            Err(_) => return Err(invalid("missing prf")),
+        };
+        let signature = match ipld.get("sig") {
+            Ok(Ipld::Bytes(bytes)) => {
+                Signature::from_slice(bytes).map_err(|_| invalid("invalid signature"))?
+            }
+            _ => return Err(invalid("missing sig")),
+        };
+        Ok(Self {
+            issuer: public_key(ipld, "iss")?,
+            audience: public_key(ipld, "aud")?,
+            abilities,
+            alias_prefix,
+            expiry,
+            proof,
+            signature,
+        })
+    }
+
+    fn invocation(&self, challenge: &[u8]) -> Vec<u8> {
+        let mut msg = b"ipld-block-builder/invoke".to_vec();
+        msg.extend_from_slice(&self.signature.to_bytes());
+        msg.extend_from_slice(challenge);
+        msg
+    }
+
+    /// Signs a `challenge` chosen by the verifier with the audience `key`,
+    /// proving possession of the key the token was granted to.
+    pub fn invoke(&self, key: &SigningKey, challenge: &[u8]) -> Result<Signature> {
+        if key.verifying_key() != self.audience {
+            return Err(invalid("signing key is not the audience"));
+        }
+        Ok(key.sign(&self.invocation(challenge)))
+    }
+
+    /// Verifies that `signature` was created by `invoke` for `challenge`.
+    pub fn verify_invocation(&self, challenge: &[u8], signature: &Signature) -> Result<()> {
+        self.audience
+            .verify(&self.invocation(challenge), signature)
+            .map_err(|_| Error::Unauthorized("invalid invocation".into()))
+    }
+
+    /// Encodes the token as dag-cbor.
+    pub fn encode(&self) -> Result<Box<[u8]>> {
+        DagCborCodec::encode(&self.to_ipld()).map_err(|err| invalid(err.to_string()))
+    }
+
+    /// Decodes a token without verifying it.
+    pub fn decode(data: &[u8]) -> Result<Self> {
+        let ipld: Ipld = DagCborCodec::decode(data).map_err(|err| invalid(err.to_string()))?;
+        Self::from_ipld(&ipld)
+    }
+}
+
+/// A store only performing the mutations granted by a capability token.
+///
+/// Reads are not restricted. Holding the token is not enough to mutate the
+/// store: the store is only created for an invocation of the token, a
+/// challenge signed with the audience key. The challenge must be fresh
+/// random bytes chosen by the verifier, so an invocation can't be replayed.
+/// The token is verified when the store is created and its expiry is checked
+/// before every mutation.
+#[derive(Clone)]
+pub struct AuthorizedStore<S> {
+    store: S,
+    token: Arc<CapabilityToken>,
+}
+
+impl<S> AuthorizedStore<S> {
+    /// Wraps `store` after verifying that `token` is chained to `root` and
+    /// that `signature` is an invocation of `token` for `challenge`.
+    pub fn new(
+        store: S,
+        root: &VerifyingKey,
+        token: CapabilityToken,
+        challenge: &[u8],
+        signature: &Signature,
+    ) -> Result<Self> {
+        token.verify(root)?;
+        token.verify_invocation(challenge, signature)?;
+        Ok(Self {
+            store,
+            token: Arc::new(token),
+        })
+    }
+
+    /// Returns the wrapped store.
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    /// Returns the token.
+    pub fn token(&self) -> &CapabilityToken {
+        &self.token
+    }
+
+    fn authorize(
+        &self,
+        ability: Ability,
+        alias: Option<&[u8]>,
+    ) -> core::result::Result<(), StoreError> {
+        let err = if self.token.expiry < now() {
+            Error::Unauthorized("token expired".into())
+        } else if !self.token.allows(ability, alias) {
+            Error::Unauthorized(format!("{} not granted", ability.as_str()))
+        } else {
+            return Ok(());
+        };
+        Err(StoreError::Other(Box::new(err)))
+    }
+}
+
+impl<S: ReadonlyStore> ReadonlyStore for AuthorizedStore<S> {
+    fn get<'a>(&'a self, cid: &'a Cid) -> StoreResult<'a, Box<[u8]>> {
+        self.store.get(cid)
+    }
+}
+
+impl<S: Store + Send + Sync> Store for AuthorizedStore<S> {
+    fn insert<'a>(
+        &'a self,
+        cid: &'a Cid,
+        data: Box<[u8]>,
+        visibility: Visibility,
+    ) -> StoreResult<'a, ()> {
+        Box::pin(async move {
+            self.authorize(Ability::Insert, None)?;
+            self.store.insert(cid, data, visibility).await
+        })
+    }
+
+    fn insert_batch<'a>(
+        &'a self,
+        batch: Vec<Block>,
+        visibility: Visibility,
+    ) -> StoreResult<'a, Cid> {
+        Box::pin(async move {
+            self.authorize(Ability::Insert, None)?;
+            self.store.insert_batch(batch, visibility).await
+        })
+    }
+
+    fn flush(&self) -> StoreResult<'_, ()> {
+        self.store.flush()
+    }
+
+    fn unpin<'a>(&'a self, cid: &'a Cid) -> StoreResult<'a, ()> {
+        Box::pin(async move {
+            self.authorize(Ability::Unpin, None)?;
+            self.store.unpin(cid).await
+        })
+    }
+}
+
+impl<S: AliasStore + Send + Sync> AliasStore for AuthorizedStore<S> {
+    fn alias<'a>(
+        &'a self,
+        alias: &'a [u8],
+        cid: &'a Cid,
+        visibility: Visibility,
+    ) -> StoreResult<'a, ()> {
+        Box::pin(async move {
+            self.authorize(Ability::Alias, Some(alias))?;
+            self.store.alias(alias, cid, visibility).await
+        })
+    }
+
+    fn unalias<'a>(&'a self, alias: &'a [u8]) -> StoreResult<'a, ()> {
+        Box::pin(async move {
+            self.authorize(Ability::Alias, Some(alias))?;
+            self.store.unalias(alias).await
+        })
+    }
+
+    fn resolve<'a>(&'a self, alias: &'a [u8]) -> StoreResult<'a, Option<Cid>> {
+        self.store.resolve(alias)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BlockBuilder, Codec};
+    use libipld::ipld;
+    use libipld::mem::MemStore;
+
+    const HOUR: Duration = Duration::from_secs(60 * 60);
+
+    #[test]
+    fn test_delegation() {
+        let root = SigningKey::from_bytes(&[1; 32]);
+        let alice = SigningKey::from_bytes(&[2; 32]);
+        let bob = SigningKey::from_bytes(&[3; 32]);
+        let all = [Ability::Insert, Ability::Alias, Ability::Unpin];
+        let token =
+            CapabilityToken::issue(&root, &alice.verifying_key(), &all, b"apps/", HOUR).unwrap();
+        let delegated = token
+            .delegate(
+                &alice,
+                &bob.verifying_key(),
+                &[Ability::Insert],
+                b"apps/bob/",
+                HOUR,
+            )
+            .unwrap();
+        delegated.verify(&root.verifying_key()).unwrap();
+        assert!(delegated.verify(&alice.verifying_key()).is_err());
+        let decoded = CapabilityToken::decode(&delegated.encode().unwrap()).unwrap();
+        assert_eq!(decoded, delegated);
+
+        // attenuation is enforced
+        assert!(token
+            .delegate(
+                &bob,
+                &bob.verifying_key(),
+                &[Ability::Insert],
+                b"apps/",
+                HOUR
+            )
+            .is_err());
+        assert!(delegated
+            .delegate(
+                &bob,
+                &alice.verifying_key(),
+                &[Ability::Unpin],
+                b"apps/bob/",
+                HOUR
+            )
+            .is_err());
+        assert!(delegated
+            .delegate(
+                &bob,
+                &alice.verifying_key(),
+                &[Ability::Insert],
+                b"other/",
+                HOUR
+            )
+            .is_err());
+        let mut forged = delegated.clone();
+        forged.abilities.push(Ability::Unpin);
+        assert!(forged.verify(&root.verifying_key()).is_err());
+    }
+
+    #[async_std::test]
+    async fn test_authorized_store() {
+        let root = SigningKey::from_bytes(&[1; 32]);
+        let client = SigningKey::from_bytes(&[2; 32]);
+        let abilities = [Ability::Insert, Ability::Alias];
+        let token =
+            CapabilityToken::issue(&root, &client.verifying_key(), &abilities, b"apps/", HOUR)
+                .unwrap();
+        let store = MemStore::default();
+        let other = CapabilityToken::issue(&client, &client.verifying_key(), &abilities, b"", HOUR)
+            .unwrap();
+        let challenge = [7; 32];
+        let signature = other.invoke(&client, &challenge).unwrap();
+        let res = AuthorizedStore::new(
+            store.clone(),
+            &root.verifying_key(),
+            other,
+            &challenge,
+            &signature,
+        );
+        assert!(res.is_err());
+
+        // the token alone can't be used without the audience key
+        let thief = SigningKey::from_bytes(&[3; 32]);
+        assert!(token.invoke(&thief, &challenge).is_err());
+        let forged = thief.sign(&token.invocation(&challenge));
+        let res = AuthorizedStore::new(
+            store.clone(),
+            &root.verifying_key(),
+            token.clone(),
+            &challenge,
+            &forged,
+        );
+        assert!(res.is_err());
+        let replayed = token.invoke(&client, &[8; 32]).unwrap();
+        let res = AuthorizedStore::new(
+            store.clone(),
+            &root.verifying_key(),
+            token.clone(),
+            &challenge,
+            &replayed,
+        );
+        assert!(res.is_err());
+
+        let signature = token.invoke(&client, &challenge).unwrap();
+        let authorized = AuthorizedStore::new(
+            store.clone(),
+            &root.verifying_key(),
+            token,
+            &challenge,
+            &signature,
+        )
+        .unwrap();
+        let builder = BlockBuilder::new(authorized, Codec::new());
+        let cid = builder.insert(&ipld!({ "a": 1 })).await.unwrap();
+        builder.alias(b"apps/a", &cid).await.unwrap();
+        assert!(builder.alias(b"system", &cid).await.is_err());
+        assert!(builder.unpin(&cid).await.is_err());
+        assert_eq!(store.resolve(b"apps/a").await.unwrap(), Some(cid));
+    }
+}
@@ -0,0 +1,624 @@
+use crate::batch::Batch;
+use crate::builder::BlockBuilder;
+use crate::codec::{Encoder, IpldDecoder};
+use crate::error::{verify, Error, Result};
+use crate::graphsync::Selector;
+use crate::path::DagPath;
+use crate::store::clone_block;
+use core::convert::TryFrom;
+use futures::future::LocalBoxFuture;
+use futures::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use libipld::block::Block;
+use libipld::cbor::DagCborCodec;
+use libipld::cid::{Cid, Codec as CidCodec};
+use libipld::codec::Codec;
+use libipld::error::StoreError;
+use libipld::ipld::Ipld;
+use libipld::multihash::Identity;
+use libipld::store::{AliasStore, ReadonlyStore, Store, Visibility};
+use std::collections::{BTreeMap, HashSet};
+
+/// Bytes of blocks inserted per batch by `import_car_from`.
+const CAR_BATCH_SIZE: usize = 1 << 24;
+
+/// Maximum length of a section read by `import_car_from`.
+const MAX_SECTION_SIZE: u64 = libipld::MAX_BLOCK_SIZE as u64 + 1024;
+
+fn invalid(msg: impl Into<String>) -> Error {
+    Error::InvalidCar(msg.into())
+}
+
+/// Progress of a streaming CAR import or export.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CarProgress {
+    /// Number of blocks processed so far.
+    pub blocks: usize,
+    /// Number of bytes processed so far.
+    pub bytes: u64,
+    /// Number of blocks skipped because they were already in the store.
+    pub skipped: usize,
+    /// The last processed block.
+    pub cid: Cid,
+}
+
+pub(crate) fn write_varint(out: &mut Vec<u8>, mut n: u64) {
+    while n >= 0x80 {
+        out.push(n as u8 | 0x80);
+        n >>= 7;
+    }
+    out.push(n as u8);
+}
+
+fn read_varint(buf: &mut &[u8]) -> Result<u64> {
+    let mut n = 0u64;
+    for (i, byte) in buf.iter().enumerate().take(10) {
+        n |= u64::from(byte & 0x7f) << (7 * i);
+        if byte & 0x80 == 0 {
+            *buf = &buf[(i + 1)..];
+            return Ok(n);
+        }
+    }
+    Err(invalid("invalid varint"))
+}
+
+pub(crate) fn read_section<'a>(buf: &mut &'a [u8]) -> Result<&'a [u8]> {
+    let len = read_varint(buf)? as usize;
+    if len > buf.len() {
+        return Err(invalid("truncated section"));
+    }
+    let (section, rest) = buf.split_at(len);
+    *buf = rest;
+    Ok(section)
+}
+
+/// Reads a section, returning `None` at the end of the archive.
+async fn read_section_from<R: AsyncRead + Unpin>(reader: &mut R) -> Result<Option<Vec<u8>>> {
+    let mut len = 0u64;
+    let mut byte = [0u8];
+    for i in 0..10 {
+        if reader.read(&mut byte).await? == 0 {
+            if i == 0 {
+                return Ok(None);
+            }
+            return Err(invalid("truncated section"));
+        }
+        len |= u64::from(byte[0] & 0x7f) << (7 * i);
+        if byte[0] & 0x80 == 0 {
+            break;
+        }
+        if i == 9 {
+            return Err(invalid("invalid varint"));
+        }
+    }
+    if len > MAX_SECTION_SIZE {
+        return Err(invalid("section too large"));
+    }
+    let mut section = vec![0; len as usize];
+    reader
+        .read_exact(&mut section)
+        .await
+        .map_err(|_| invalid("truncated section"))?;
+    Ok(Some(section))
+}
+
+/// Splits a section into the cid and the block data.
+pub(crate) fn split_cid(section: &[u8]) -> Result<(Cid, &[u8])> {
+    let mut rest = section;
+    let len = if section.starts_with(&[0x12, 0x20]) {
+        34
+    } else {
+        for _ in 0..3 {
+            read_varint(&mut rest)?;
+        }
+        let digest = read_varint(&mut rest)? as usize;
+        section.len() - rest.len() + digest
+    };
+    if len > section.len() {
+        return Err(invalid("truncated cid"));
+    }
+    let (cid, data) = section.split_at(len);
+    let cid = Cid::try_from(cid).map_err(|err| invalid(err.to_string()))?;
+    Ok((cid, data))
+}
+
+/// Encodes a CARv1 header.
+fn header(roots: &[Cid]) -> Result<Box<[u8]>> {
+    let mut map = BTreeMap::new();
+    let roots = roots.iter().cloned().map(Ipld::Link).collect();
+    map.insert("roots".to_string(), Ipld::List(roots));
+    map.insert("version".to_string(), Ipld::Integer(1));
+    DagCborCodec::encode(&Ipld::Map(map)).map_err(|err| invalid(err.to_string()))
+}
+
+/// Decodes a CARv1 header and returns the roots.
+fn roots(header: &[u8]) -> Result<Vec<Cid>> {
+    let header: Ipld = DagCborCodec::decode(header).map_err(|err| invalid(err.to_string()))?;
+    match header.get("version") {
+        Ok(Ipld::Integer(1)) => {}
+        _ => return Err(invalid("unsupported version")),
+    }
+    match header.get("roots") {
+        Ok(Ipld::List(roots)) => roots
+            .iter()
+            .map(|root| match root {
+                Ipld::Link(cid) => Ok(cid.clone()),
+                _ => Err(invalid("invalid root")),
+            })
+            .collect(),
+        _ => Err(invalid("missing roots")),
+    }
+}
+
+/// Parses a CARv1 archive into its roots and blocks.
+fn parse(mut car: &[u8]) -> Result<(Vec<Cid>, Vec<Block>)> {
+    let roots = roots(read_section(&mut car)?)?;
+    let mut blocks = vec![];
+    while !car.is_empty() {
+        let (cid, data) = split_cid(read_section(&mut car)?)?;
+        verify(&cid, data)?;
+        blocks.push(Block {
+            cid,
+            data: data.into(),
+        });
+    }
+    Ok((roots, blocks))
+}
+
+impl<S: ReadonlyStore, C: IpldDecoder> BlockBuilder<S, C> {
+    /// Exports the dags of `roots` as a CARv1 archive.
+    ///
+    /// Links are found by decoding blocks with the codec, so encrypted dags
+    /// can only be exported by their owner.
+    pub async fn export_car(&self, roots: &[Cid]) -> Result<Vec<u8>> {
+        let mut car = vec![];
+        self.export_car_to(roots, &mut car, |_| {}).await?;
+        Ok(car)
+    }
+
+    /// Streams the dags of `roots` as a CARv1 archive to `writer`, calling
+    /// `progress` after every block.
+    pub async fn export_car_to<W, F>(&self, roots: &[Cid], writer: W, progress: F) -> Result<()>
+    where
+        W: AsyncWrite + Unpin,
+        F: FnMut(&CarProgress),
+    {
+        let selected: Vec<_> = roots
+            .iter()
+            .map(|root| (root.clone(), Selector::All))
+            .collect();
+        self.write_car(roots, selected, writer, progress).await
+    }
+
+    /// Exports the blocks of the dag of `root` matched by `selector` as a
+    /// CARv1 archive.
+    pub async fn export_car_selected(&self, root: &Cid, selector: &Selector) -> Result<Vec<u8>> {
+        let mut car = vec![];
+        self.export_car_selected_to(root, selector, &mut car, |_| {})
+            .await?;
+        Ok(car)
+    }
+
+    /// Streams the blocks of the dag of `root` matched by `selector` as a
+    /// CARv1 archive to `writer`, calling `progress` after every block.
+    pub async fn export_car_selected_to<W, F>(
+        &self,
+        root: &Cid,
+        selector: &Selector,
+        writer: W,
+        progress: F,
+    ) -> Result<()>
+    where
+        W: AsyncWrite + Unpin,
+        F: FnMut(&CarProgress),
+    {
+        let roots = std::slice::from_ref(root);
+        let selected = vec![(root.clone(), selector.clone())];
+        self.write_car(roots, selected, writer, progress).await
+    }
+
+    /// Exports the blocks crossed by `path` and the dag below its end as a
+    /// CARv1 archive rooted at the root of the path.
+    pub async fn export_car_path(&self, path: &DagPath<'_>) -> Result<Vec<u8>> {
+        let selector = Selector::Path(path.path().clone(), Box::new(Selector::All));
+        self.export_car_selected(path.root(), &selector).await
+    }
+
+    async fn write_car<W, F>(
+        &self,
+        roots: &[Cid],
+        selected: Vec<(Cid, Selector)>,
+        mut writer: W,
+        mut progress: F,
+    ) -> Result<()>
+    where
+        W: AsyncWrite + Unpin,
+        F: FnMut(&CarProgress),
+    {
+        let mut section = vec![];
+        let header = header(roots)?;
+        write_varint(&mut section, header.len() as u64);
+        section.extend_from_slice(&header);
+        writer.write_all(&section).await?;
+        let mut bytes = section.len() as u64;
+        let mut visited = HashSet::new();
+        let mut written = HashSet::new();
+        let mut stack: Vec<_> = selected.into_iter().rev().collect();
+        while let Some((cid, selector)) = stack.pop() {
+            if !visited.insert((cid.clone(), selector.clone())) {
+                continue;
+            }
+            let data = self.get_verified(&cid).await?;
+            let ipld = self.codec().decode_ipld(&cid, &data)?;
+            stack.extend(selector.select(&ipld).into_iter().rev());
+            if !written.insert(cid.clone()) {
+                continue;
+            }
+            let cid_bytes = cid.to_bytes();
+            section.clear();
+            write_varint(&mut section, (cid_bytes.len() + data.len()) as u64);
+            section.extend_from_slice(&cid_bytes);
+            section.extend_from_slice(&data);
+            writer.write_all(&section).await?;
+            bytes += section.len() as u64;
+            progress(&CarProgress {
+                blocks: written.len(),
+                bytes,
+                skipped: 0,
+                cid,
+            });
+        }
+        writer.flush().await?;
+        Ok(())
+    }
+}
+
+impl<S: Store, C: Encoder + Clone> BlockBuilder<S, C> {
+    /// Imports a CARv1 archive and returns its roots.
+    ///
+    /// Every block is verified against its cid before anything is inserted.
+    /// The blocks are inserted as a batch and the roots contained in the
+    /// archive are pinned.
+    pub async fn import_car(&self, car: &[u8]) -> Result<Vec<Cid>> {
+        let (roots, blocks) = parse(car)?;
+        if blocks.is_empty() {
+            return Ok(roots);
+        }
+        let (mut tail, head): (Vec<_>, Vec<_>) = blocks
+            .into_iter()
+            .partition(|block| roots.contains(&block.cid));
+        let extra: Vec<Block> = tail.iter().skip(1).map(clone_block).collect();
+        let mut batch = Batch::with_capacity((), head.len() + tail.len());
+        let first = if tail.is_empty() {
+            None
+        } else {
+            Some(tail.remove(0))
+        };
+        for block in head.into_iter().chain(tail).chain(first) {
+            batch.push(block);
+        }
+        let last = self.insert_batch(batch).await?;
+        if !roots.contains(&last) {
+            self.store().unpin(&last).await?;
+        }
+        for block in extra {
+            self.store()
+                .insert(&block.cid, block.data, self.visibility())
+                .await?;
+        }
+        Ok(roots)
+    }
+
+    /// Streams a CARv1 archive from `reader` into the store, calling
+    /// `progress` after every block, and returns its roots.
+    ///
+    /// Every block is verified against its cid. Unlike `import_car` the
+    /// blocks are inserted in batches while the archive is read, so a failed
+    /// import leaves the blocks read so far in the store. Blocks that are
+    /// already in the store are skipped, so importing the archive again
+    /// resumes where the failed import stopped. The roots are pinned after
+    /// the whole archive was read. Wrap unbuffered readers in a
+    /// `futures::io::BufReader`.
+    pub async fn import_car_from<R, F>(&self, reader: R, progress: F) -> Result<Vec<Cid>>
+    where
+        R: AsyncRead + Unpin,
+        F: FnMut(&CarProgress),
+    {
+        self.read_car(reader, progress, 0, |_| Box::pin(async { Ok(()) }))
+            .await
+    }
+
+    /// Streams the archive, skipping the first `resume` blocks, and calls
+    /// `checkpoint` with the number of blocks after every inserted batch.
+    async fn read_car<'a, R, F, K>(
+        &self,
+        mut reader: R,
+        mut progress: F,
+        resume: usize,
+        mut checkpoint: K,
+    ) -> Result<Vec<Cid>>
+    where
+        R: AsyncRead + Unpin,
+        F: FnMut(&CarProgress),
+        K: FnMut(usize) -> LocalBoxFuture<'a, Result<()>>,
+    {
+        let header = read_section_from(&mut reader)
+            .await?
+            .ok_or_else(|| invalid("missing header"))?;
+        let roots = roots(&header)?;
+        let (mut blocks, mut bytes, mut skipped) = (0, header.len() as u64, 0);
+        let mut batch = vec![];
+        let mut batch_bytes = 0;
+        let mut root_blocks: Vec<Block> = vec![];
+        while let Some(section) = read_section_from(&mut reader).await? {
+            let (cid, data) = split_cid(&section)?;
+            let is_root = roots.contains(&cid);
+            if blocks >= resume || is_root {
+                verify(&cid, data)?;
+            }
+            blocks += 1;
+            bytes += section.len() as u64;
+            let block = Block {
+                cid: cid.clone(),
+                data: data.into(),
+            };
+            if is_root {
+                if !root_blocks.iter().any(|root| root.cid == cid) {
+                    root_blocks.push(block);
+                }
+            } else if blocks <= resume || self.contains(&cid).await? {
+                skipped += 1;
+            } else {
+                batch_bytes += block.data.len();
+                batch.push(block);
+                if batch_bytes >= CAR_BATCH_SIZE {
+                    self.insert_unpinned(std::mem::take(&mut batch)).await?;
+                    batch_bytes = 0;
+                    checkpoint(blocks).await?;
+                }
+            }
+            progress(&CarProgress {
+                blocks,
+                bytes,
+                skipped,
+                cid,
+            });
+        }
+        if !batch.is_empty() {
+            self.insert_unpinned(batch).await?;
+        }
+        for block in root_blocks {
+            self.store()
+                .insert(&block.cid, block.data, self.visibility())
+                .await?;
+        }
+        Ok(roots)
+    }
+
+    /// Returns if a block is in the store.
+    async fn contains(&self, cid: &Cid) -> Result<bool> {
+        match self.store().get(cid).await {
+            Ok(_) => Ok(true),
+            Err(StoreError::BlockNotFound(_)) => Ok(false),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Inserts blocks as a batch without pinning any of them.
+    async fn insert_unpinned(&self, blocks: Vec<Block>) -> Result<()> {
+        let mut batch = Batch::with_capacity((), blocks.len());
+        for block in blocks {
+            batch.push(block);
+        }
+        let last = self.insert_batch(batch).await?;
+        self.store().unpin(&last).await?;
+        Ok(())
+    }
+}
+
+impl<S: Store + AliasStore, C: Encoder + Clone> BlockBuilder<S, C> {
+    /// Streams a CARv1 archive like `import_car_from`, persisting a cursor
+    /// of the processed blocks under `source`, and returns its roots.
+    ///
+    /// The cursor is updated after every inserted batch. When an interrupted
+    /// import of the same `source` is restarted, the blocks before the
+    /// cursor are read but neither verified nor looked up in the store, so
+    /// multi-hour imports continue where they left off. The cursor is
+    /// removed once the archive was imported.
+    pub async fn import_car_resumable<R, F>(
+        &self,
+        source: &str,
+        reader: R,
+        progress: F,
+    ) -> Result<Vec<Cid>>
+    where
+        R: AsyncRead + Unpin,
+        F: FnMut(&CarProgress),
+    {
+        let alias = cursor_alias(source);
+        let resume = self.import_car_cursor(source).await?.unwrap_or_default();
+        let roots = self
+            .read_car(reader, progress, resume, |blocks| {
+                Box::pin(self.save_cursor(&alias, blocks))
+            })
+            .await?;
+        if let Some(cursor) = self.store().resolve(&alias).await? {
+            self.store().unalias(&alias).await?;
+            self.store().unpin(&cursor).await?;
+        }
+        Ok(roots)
+    }
+
+    /// Returns the number of blocks processed by an interrupted import of
+    /// `source`.
+    pub async fn import_car_cursor(&self, source: &str) -> Result<Option<usize>> {
+        let cursor = match self.store().resolve(&cursor_alias(source)).await? {
+            Some(cursor) => cursor,
+            None => return Ok(None),
+        };
+        let mut digest = cursor.hash().digest();
+        Ok(Some(read_varint(&mut digest)? as usize))
+    }
+
+    /// Points the cursor of `alias` at `blocks`.
+    ///
+    /// The cursor is an identity-hashed block holding the block count, the
+    /// previous cursor is unpinned after the alias was updated.
+    async fn save_cursor(&self, alias: &[u8], blocks: usize) -> Result<()> {
+        let mut data = vec![];
+        write_varint(&mut data, blocks as u64);
+        let cid = Cid::new_v1(CidCodec::Raw, Identity::digest(&data));
+        let prev = self.store().resolve(alias).await?;
+        self.store()
+            .insert(&cid, data.into_boxed_slice(), Visibility::Public)
+            .await?;
+        self.store().alias(alias, &cid, Visibility::Public).await?;
+        if let Some(prev) = prev {
+            if prev != cid {
+                self.store().unpin(&prev).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn cursor_alias(source: &str) -> Vec<u8> {
+    format!("car-import/{}", source).into_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Codec;
+    use libipld::ipld;
+    use libipld::mem::MemStore;
+
+    #[async_std::test]
+    async fn test_car_roundtrip() {
+        let builder = BlockBuilder::new(MemStore::default(), Codec::new());
+        let leaf = builder.insert(&ipld!({"leaf": true})).await.unwrap();
+        let a = builder.insert(&ipld!([leaf.clone(), 1])).await.unwrap();
+        let b = builder.insert(&ipld!([leaf.clone(), 2])).await.unwrap();
+        let car = builder.export_car(&[a.clone(), b.clone()]).await.unwrap();
+
+        let (roots, blocks) = parse(&car).unwrap();
+        assert_eq!(roots, vec![a.clone(), b.clone()]);
+        let cids: Vec<_> = blocks.into_iter().map(|block| block.cid).collect();
+        assert_eq!(cids, vec![a.clone(), leaf.clone(), b.clone()]);
+
+        let other = BlockBuilder::new(MemStore::default(), Codec::new());
+        let roots = other.import_car(&car).await.unwrap();
+        assert_eq!(roots, vec![a.clone(), b.clone()]);
+        assert_eq!(other.get_ipld(&leaf).await.unwrap(), ipld!({"leaf": true}));
+        // both roots are pinned
+        other.unpin(&a).await.unwrap();
+        assert!(other.get_ipld(&b).await.is_ok());
+        assert!(other.get_ipld(&leaf).await.is_ok());
+    }
+
+    #[async_std::test]
+    async fn test_car_streaming() {
+        let builder = BlockBuilder::new(MemStore::default(), Codec::new());
+        let leaf = builder.insert(&ipld!({"leaf": true})).await.unwrap();
+        let root = builder.insert(&ipld!([leaf.clone(), 1])).await.unwrap();
+        let roots = [root.clone()];
+        let mut car = vec![];
+        let mut exported = vec![];
+        builder
+            .export_car_to(&roots, &mut car, |p| exported.push(p.clone()))
+            .await
+            .unwrap();
+        assert_eq!(car, builder.export_car(&roots).await.unwrap());
+        assert_eq!(exported.len(), 2);
+        assert_eq!(exported[1].cid, leaf);
+        assert_eq!(exported[1].bytes, car.len() as u64);
+
+        // the leaf is skipped when resuming an import
+        let other = BlockBuilder::new(MemStore::default(), Codec::new());
+        other.insert(&ipld!({"leaf": true})).await.unwrap();
+        let mut imported = vec![];
+        let reader = futures::io::Cursor::new(&car);
+        let imported_roots = other
+            .import_car_from(reader, |p| imported.push(p.clone()))
+            .await
+            .unwrap();
+        assert_eq!(imported_roots, roots);
+        assert_eq!(imported.last().unwrap().blocks, 2);
+        assert_eq!(imported.last().unwrap().skipped, 1);
+        assert!(other.get_ipld(&root).await.is_ok());
+
+        let last = car.len() - 1;
+        let reader = futures::io::Cursor::new(&car[..last]);
+        let res = other.import_car_from(reader, |_| {}).await;
+        assert!(matches!(res, Err(Error::InvalidCar(_))));
+    }
+
+    #[async_std::test]
+    async fn test_car_resumable() {
+        let builder = BlockBuilder::new(MemStore::default(), Codec::new());
+        let a = builder.insert(&ipld!({"a": 1})).await.unwrap();
+        let b = builder.insert(&ipld!({"b": 2})).await.unwrap();
+        let root = builder
+            .insert(&ipld!([a.clone(), b.clone()]))
+            .await
+            .unwrap();
+        let roots = [root.clone()];
+        let car = builder.export_car(&roots).await.unwrap();
+
+        // an interrupted import inserted a before saving its cursor
+        let other = BlockBuilder::new(MemStore::default(), Codec::new());
+        other.insert(&ipld!({"a": 1})).await.unwrap();
+        other.save_cursor(b"car-import/test", 2).await.unwrap();
+        assert_eq!(other.import_car_cursor("test").await.unwrap(), Some(2));
+
+        let mut imported = vec![];
+        let reader = futures::io::Cursor::new(&car);
+        let imported_roots = other
+            .import_car_resumable("test", reader, |p| imported.push(p.clone()))
+            .await
+            .unwrap();
+        assert_eq!(imported_roots, roots);
+        assert_eq!(imported.len(), 3);
+        assert_eq!(imported.last().unwrap().skipped, 1);
+        assert_eq!(other.get_ipld(&b).await.unwrap(), ipld!({"b": 2}));
+        assert!(other.get_ipld(&root).await.is_ok());
+        assert_eq!(other.import_car_cursor("test").await.unwrap(), None);
+    }
+
+    #[async_std::test]
+    async fn test_car_selected() {
+        let builder = BlockBuilder::new(MemStore::default(), Codec::new());
+        let public = builder.insert(&ipld!({"leaf": 1})).await.unwrap();
+        let secret = builder.insert(&ipld!({"leaf": 2})).await.unwrap();
+        let root = builder
+            .insert(&ipld!({"public": [public.clone()], "secret": secret.clone()}))
+            .await
+            .unwrap();
+
+        let car = builder
+            .export_car_path(&DagPath::new(&root, "public"))
+            .await
+            .unwrap();
+        let (roots, blocks) = parse(&car).unwrap();
+        assert_eq!(roots, vec![root.clone()]);
+        let cids: Vec<_> = blocks.into_iter().map(|block| block.cid).collect();
+        assert_eq!(cids, vec![root.clone(), public.clone()]);
+
+        let car = builder
+            .export_car_selected(&root, &Selector::Depth(0))
+            .await
+            .unwrap();
+        let (_, blocks) = parse(&car).unwrap();
+        assert_eq!(blocks.len(), 1);
+    }
+
+    #[async_std::test]
+    async fn test_car_corrupt() {
+        let builder = BlockBuilder::new(MemStore::default(), Codec::new());
+        let root = builder.insert(&ipld!({"a": 1})).await.unwrap();
+        let mut car = builder.export_car(&[root]).await.unwrap();
+        let last = car.len() - 1;
+        car[last] ^= 1;
+        assert!(builder.import_car(&car).await.is_err());
+        assert!(builder.import_car(&car[..last]).await.is_err());
+    }
+}
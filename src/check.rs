@@ -0,0 +1,392 @@
+use crate::builder::BlockBuilder;
+use crate::codec::IpldDecoder;
+use crate::error::{verify, Result};
+use crate::walk::links;
+use libipld::cid::Cid;
+use libipld::error::StoreError;
+use libipld::ipld::Ipld;
+use libipld::store::{ReadonlyStore, Store};
+use std::collections::HashSet;
+
+/// Kind of damage of a block.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Damage {
+    /// The block is missing from the store.
+    Missing,
+    /// The block doesn't hash to its cid.
+    Corrupt,
+    /// The block can't be decoded with its codec.
+    Undecodable(String),
+}
+
+/// Damaged block found by `BlockBuilder::check`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DamagedBlock {
+    /// Cid of the block.
+    pub cid: Cid,
+    /// Block linking to the damaged block, `None` for roots.
+    pub parent: Option<Cid>,
+    /// Kind of damage.
+    pub damage: Damage,
+    /// If the block was repaired from a secondary store.
+    pub repaired: bool,
+}
+
+/// Result of checking the dags of a set of roots.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct CheckReport {
+    /// Number of intact blocks.
+    pub blocks: usize,
+    /// Damaged blocks.
+    pub damaged: Vec<DamagedBlock>,
+}
+
+impl CheckReport {
+    /// Returns if no unrepaired damage was found.
+    pub fn is_ok(&self) -> bool {
+        self.damaged.iter().all(|block| block.repaired)
+    }
+}
+
+/// Block missing from the store.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MissingBlock {
+    /// Cid of the block.
+    pub cid: Cid,
+    /// Block linking to the missing block, `None` for the root.
+    pub parent: Option<Cid>,
+}
+
+/// Result of `BlockBuilder::verify_complete`.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct MissingReport {
+    /// Number of blocks present in the store.
+    pub present: usize,
+    /// Blocks referenced by the dag but absent from the store.
+    pub missing: Vec<MissingBlock>,
+    /// Blocks that are present but corrupt or undecodable, the blocks below
+    /// them can't be enumerated.
+    pub unreadable: Vec<Cid>,
+}
+
+impl MissingReport {
+    /// Returns if every block of the dag is present and readable.
+    pub fn is_complete(&self) -> bool {
+        self.missing.is_empty() && self.unreadable.is_empty()
+    }
+}
+
+impl<S: ReadonlyStore, C: IpldDecoder> BlockBuilder<S, C> {
+    /// Loads and checks a block from `store`.
+    async fn load_checked<T: ReadonlyStore>(
+        &self,
+        store: &T,
+        cid: &Cid,
+    ) -> Result<core::result::Result<(Box<[u8]>, Ipld), Damage>> {
+        let data = match store.get(cid).await {
+            Ok(data) => data,
+            Err(StoreError::BlockNotFound(_)) => return Ok(Err(Damage::Missing)),
+            Err(err) => return Err(err.into()),
+        };
+        if verify(cid, &data).is_err() {
+            return Ok(Err(Damage::Corrupt));
+        }
+        match self.codec().decode_ipld(cid, &data) {
+            Ok(ipld) => Ok(Ok((data, ipld))),
+            Err(err) => Ok(Err(Damage::Undecodable(err.to_string()))),
+        }
+    }
+
+    /// Checks that every block reachable from `roots` is in the store,
+    /// hashes to its cid and decodes with its codec.
+    ///
+    /// Links of damaged blocks can't be followed, so blocks below them are
+    /// not checked.
+    pub async fn check(&self, roots: &[Cid]) -> Result<CheckReport> {
+        let mut report = CheckReport::default();
+        let mut visited = HashSet::new();
+        let mut stack: Vec<(Option<Cid>, Cid)> = roots
+            .iter()
+            .rev()
+            .map(|root| (None, root.clone()))
+            .collect();
+        while let Some((parent, cid)) = stack.pop() {
+            if !visited.insert(cid.clone()) {
+                continue;
+            }
+            match self.load_checked(self.store(), &cid).await? {
+                Ok((_, ipld)) => {
+                    report.blocks += 1;
+                    for link in links(&ipld).into_iter().rev() {
+                        stack.push((Some(cid.clone()), link));
+                    }
+                }
+                Err(damage) => report.damaged.push(DamagedBlock {
+                    cid,
+                    parent,
+                    damage,
+                    repaired: false,
+                }),
+            }
+        }
+        Ok(report)
+    }
+
+    /// Walks the dag of `root` and lists every referenced block absent from
+    /// the store, continuing past holes.
+    pub async fn verify_complete(&self, root: &Cid) -> Result<MissingReport> {
+        let report = self.check(std::slice::from_ref(root)).await?;
+        let mut missing = MissingReport {
+            present: report.blocks,
+            ..Default::default()
+        };
+        for block in report.damaged {
+            match block.damage {
+                Damage::Missing => missing.missing.push(MissingBlock {
+                    cid: block.cid,
+                    parent: block.parent,
+                }),
+                _ => {
+                    missing.present += 1;
+                    missing.unreadable.push(block.cid);
+                }
+            }
+        }
+        Ok(missing)
+    }
+
+    /// Returns up to `limit` blocks of the dag of `root` missing from the
+    /// store, in traversal order.
+    ///
+    /// The walk stops once `limit` blocks were found, so fetching them and
+    /// calling `wants` again yields the next batch. An empty result means
+    /// every readable part of the dag is present.
+    pub async fn wants(&self, root: &Cid, limit: usize) -> Result<Vec<Cid>> {
+        let mut wants = vec![];
+        let mut visited = HashSet::new();
+        let mut stack = vec![root.clone()];
+        while let Some(cid) = stack.pop() {
+            if wants.len() >= limit {
+                break;
+            }
+            if !visited.insert(cid.clone()) {
+                continue;
+            }
+            match self.load_checked(self.store(), &cid).await? {
+                Ok((_, ipld)) => stack.extend(links(&ipld).into_iter().rev()),
+                Err(Damage::Missing) => wants.push(cid),
+                Err(_) => {}
+            }
+        }
+        Ok(wants)
+    }
+}
+
+impl<S: Store, C: IpldDecoder> BlockBuilder<S, C> {
+    /// Checks the dags of `roots` and replaces damaged blocks with intact
+    /// copies from `secondary`.
+    ///
+    /// Repaired blocks are checked again, including the blocks below them.
+    /// Replacing corrupt blocks requires a store that overwrites existing
+    /// blocks on insert.
+    pub async fn repair<T: ReadonlyStore>(
+        &self,
+        roots: &[Cid],
+        secondary: &T,
+    ) -> Result<CheckReport> {
+        let mut attempted = HashSet::new();
+        let mut repaired = vec![];
+        loop {
+            let mut report = self.check(roots).await?;
+            let mut progress = false;
+            for block in &mut report.damaged {
+                if !attempted.insert(block.cid.clone()) {
+                    continue;
+                }
+                if let Ok((data, _)) = self.load_checked(secondary, &block.cid).await? {
+                    self.store()
+                        .insert(&block.cid, data, self.visibility())
+                        .await?;
+                    self.store().unpin(&block.cid).await?;
+                    block.repaired = true;
+                    progress = true;
+                }
+            }
+            repaired.extend(report.damaged.iter().filter(|b| b.repaired).cloned());
+            if !progress {
+                report.damaged.retain(|block| !block.repaired);
+                repaired.append(&mut report.damaged);
+                report.damaged = repaired;
+                return Ok(report);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Codec, Encoder};
+    use libipld::block::Block;
+    use libipld::cid::Codec as CidCodec;
+    use libipld::ipld;
+    use libipld::mem::MemStore;
+    use libipld::multihash::Blake2b256;
+    use libipld::store::{StoreResult, Visibility};
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    /// Store that overwrites blocks and doesn't garbage collect.
+    #[derive(Clone, Default)]
+    struct MapStore(Arc<Mutex<HashMap<Cid, Box<[u8]>>>>);
+
+    impl ReadonlyStore for MapStore {
+        fn get<'a>(&'a self, cid: &'a Cid) -> StoreResult<'a, Box<[u8]>> {
+            let res = self.0.lock().unwrap().get(cid).cloned();
+            Box::pin(async move { res.ok_or_else(|| StoreError::BlockNotFound(cid.clone())) })
+        }
+    }
+
+    impl Store for MapStore {
+        fn insert<'a>(
+            &'a self,
+            cid: &'a Cid,
+            data: Box<[u8]>,
+            _: Visibility,
+        ) -> StoreResult<'a, ()> {
+            self.0.lock().unwrap().insert(cid.clone(), data);
+            Box::pin(async move { Ok(()) })
+        }
+
+        fn insert_batch<'a>(&'a self, batch: Vec<Block>, _: Visibility) -> StoreResult<'a, Cid> {
+            let mut cid = None;
+            for block in batch {
+                self.0.lock().unwrap().insert(block.cid.clone(), block.data);
+                cid = Some(block.cid);
+            }
+            Box::pin(async move { cid.ok_or(StoreError::EmptyBatch) })
+        }
+
+        fn flush(&self) -> StoreResult<'_, ()> {
+            Box::pin(async move { Ok(()) })
+        }
+
+        fn unpin<'a>(&'a self, _: &'a Cid) -> StoreResult<'a, ()> {
+            Box::pin(async move { Ok(()) })
+        }
+    }
+
+    #[async_std::test]
+    async fn test_check_and_repair() {
+        let codec = Codec::new();
+        let store = MapStore::default();
+        let secondary = MemStore::default();
+        let builder = BlockBuilder::new(store.clone(), codec.clone());
+        let backup = BlockBuilder::new(secondary.clone(), codec.clone());
+
+        let missing = backup.insert(&ipld!({"missing": true})).await.unwrap();
+        let corrupt = backup.insert(&ipld!({"corrupt": true})).await.unwrap();
+        let bytes = vec![0xff, 0x00];
+        let undecodable = Cid::new_v1(CidCodec::DagCBOR, Blake2b256::digest(&bytes));
+        let block = codec
+            .encode(&ipld!([
+                missing.clone(),
+                corrupt.clone(),
+                undecodable.clone()
+            ]))
+            .unwrap();
+        let root = block.cid.clone();
+        let public = Visibility::Public;
+        store.insert(&root, block.data, public).await.unwrap();
+        store
+            .insert(&corrupt, Box::new([0x80]), public)
+            .await
+            .unwrap();
+        store
+            .insert(&undecodable, bytes.into(), public)
+            .await
+            .unwrap();
+
+        let report = builder.check(std::slice::from_ref(&root)).await.unwrap();
+        assert!(!report.is_ok());
+        assert_eq!(report.blocks, 1);
+        let damage: Vec<_> = report
+            .damaged
+            .iter()
+            .map(|block| (block.cid.clone(), block.damage.clone()))
+            .collect();
+        assert_eq!(damage[0], (missing.clone(), Damage::Missing));
+        assert_eq!(damage[1], (corrupt.clone(), Damage::Corrupt));
+        assert_eq!(damage[2].0, undecodable);
+        assert!(report
+            .damaged
+            .iter()
+            .all(|b| b.parent == Some(root.clone())));
+
+        let complete = builder.verify_complete(&root).await.unwrap();
+        assert!(!complete.is_complete());
+        assert_eq!(complete.present, 3);
+        assert_eq!(
+            complete.missing,
+            vec![MissingBlock {
+                cid: missing.clone(),
+                parent: Some(root.clone()),
+            }]
+        );
+        assert_eq!(
+            complete.unreadable,
+            vec![corrupt.clone(), undecodable.clone()]
+        );
+
+        let report = builder
+            .repair(std::slice::from_ref(&root), &secondary)
+            .await
+            .unwrap();
+        assert_eq!(report.blocks, 3);
+        let repaired: Vec<_> = report
+            .damaged
+            .iter()
+            .map(|block| (block.cid.clone(), block.repaired))
+            .collect();
+        assert_eq!(
+            repaired,
+            vec![(missing, true), (corrupt, true), (undecodable, false)]
+        );
+        assert!(!report.is_ok());
+    }
+
+    #[async_std::test]
+    async fn test_wants() {
+        let remote = BlockBuilder::new(MemStore::default(), Codec::new());
+        let mut leaves = vec![];
+        for i in 0..3u64 {
+            leaves.push(remote.insert(&ipld!({ "leaf": i })).await.unwrap());
+        }
+        let root = remote
+            .insert(&Ipld::List(leaves.into_iter().map(Ipld::Link).collect()))
+            .await
+            .unwrap();
+
+        let builder = BlockBuilder::new(MemStore::default(), Codec::new());
+        assert_eq!(builder.wants(&root, 2).await.unwrap(), vec![root.clone()]);
+        let mut rounds = 0;
+        loop {
+            let wants = builder.wants(&root, 2).await.unwrap();
+            if wants.is_empty() {
+                break;
+            }
+            assert!(wants.len() <= 2);
+            for cid in wants {
+                let data = remote.store().get(&cid).await.unwrap();
+                builder
+                    .store()
+                    .insert(&cid, data, Visibility::Public)
+                    .await
+                    .unwrap();
+            }
+            rounds += 1;
+        }
+        assert_eq!(rounds, 3);
+        assert!(builder.verify_complete(&root).await.unwrap().is_complete());
+    }
+}
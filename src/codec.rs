@@ -1,18 +1,51 @@
 #[cfg(feature = "crypto")]
 use crate::crypto::Key;
+#[cfg(feature = "crypto")]
+use crate::error::IntegrityError;
+use crate::error::{Error, Result};
+use crate::pool::{BufferPool, PooledBuffer};
+#[cfg(feature = "crypto")]
+use alloc::sync::Arc;
+use core::marker::PhantomData;
 use libipld::block::Block;
 use libipld::cid::Cid;
+use libipld::codec::Code as CCode;
 use libipld::codec::{Codec, Decode, Encode};
-#[cfg(feature = "crypto")]
-use libipld::error::Error;
-use libipld::error::Result;
 use libipld::ipld::Ipld;
 use libipld::multihash::{Code, Multihasher};
 #[cfg(feature = "crypto")]
 use libipld::raw::RawCodec;
-use std::marker::PhantomData;
-#[cfg(feature = "crypto")]
-use std::sync::Arc;
+
+/// Encodes `value` into `buf`.
+fn encode_pooled<'a, C: Codec, T: Encode<C>>(
+    mut buf: PooledBuffer<'a>,
+    value: &T,
+) -> Result<PooledBuffer<'a>> {
+    value
+        .encode(&mut *buf)
+        .map_err(|e| Error::encode(libipld::error::Error::CodecError(Box::new(e))))?;
+    Ok(buf)
+}
+
+/// Hashes encoded data into a block.
+///
+/// Borrowed data is copied into the block after it was checked and hashed.
+fn hash_block<H, D>(codec: CCode, data: D) -> Result<Block>
+where
+    H: Multihasher<Code>,
+    D: AsRef<[u8]> + Into<Box<[u8]>>,
+{
+    let len = data.as_ref().len();
+    if len > libipld::MAX_BLOCK_SIZE {
+        let err = libipld::error::Error::BlockTooLarge(len);
+        return Err(Error::encode(err));
+    }
+    let cid = Cid::new_v1(codec, H::digest(data.as_ref()));
+    Ok(Block {
+        cid,
+        data: data.into(),
+    })
+}
 
 /// Encoder trait.
 pub trait Encoder {
@@ -32,12 +65,34 @@ pub trait Decoder {
 
     /// Decodes the block into a value.
     fn decode<T: Decode<Self::Codec>>(&self, cid: &Cid, data: &[u8]) -> Result<T>;
+
+    /// Decodes an owned block into a value.
+    ///
+    /// Codecs that transform the data, like decryption, reuse the buffer
+    /// instead of copying it.
+    fn decode_owned<T: Decode<Self::Codec>>(&self, cid: &Cid, data: Box<[u8]>) -> Result<T> {
+        self.decode(cid, &data)
+    }
 }
 
 /// Ipld decoder trait.
 pub trait IpldDecoder {
     /// Decodes the block into `Ipld`.
     fn decode_ipld(&self, cid: &Cid, data: &[u8]) -> Result<Ipld>;
+
+    /// Decodes an owned block into `Ipld`, reusing the buffer like
+    /// `Decoder::decode_owned`.
+    fn decode_ipld_owned(&self, cid: &Cid, data: Box<[u8]>) -> Result<Ipld> {
+        self.decode_ipld(cid, &data)
+    }
+}
+
+/// Encoder attaching a plaintext header to blocks.
+pub trait HeaderEncoder: Encoder {
+    /// Encodes a value with a header that is readable without decoding the
+    /// block.
+    fn encode_with_header<T: Encode<Self::Codec>>(&self, value: &T, header: &[u8])
+        -> Result<Block>;
 }
 
 /// Marker trait for encrypted encoders.
@@ -63,7 +118,8 @@ impl<C: Codec, H: Multihasher<Code>> Encoder for GenericCodec<C, H> {
     type Hash = H;
 
     fn encode<T: Encode<C>>(&self, value: &T) -> Result<Block> {
-        libipld::block::encode::<C, H, T>(value)
+        let data = encode_pooled::<C, T>(BufferPool::global().get(), value)?;
+        hash_block::<H, _>(C::CODE, data.as_slice())
     }
 }
 
@@ -71,22 +127,29 @@ impl<C: Codec, H> Decoder for GenericCodec<C, H> {
     type Codec = C;
 
     fn decode<T: Decode<C>>(&self, cid: &Cid, data: &[u8]) -> Result<T> {
-        libipld::block::decode::<C, T>(cid, data)
+        libipld::block::decode::<C, T>(cid, data).map_err(|e| Error::decode(cid, e))
     }
 }
 
 impl<C, H> IpldDecoder for GenericCodec<C, H> {
     fn decode_ipld(&self, cid: &Cid, data: &[u8]) -> Result<Ipld> {
-        libipld::block::decode_ipld(cid, data)
+        libipld::block::decode_ipld(cid, data).map_err(|e| Error::decode(cid, e))
     }
 }
 
 /// Generic encrypted codec.
+///
+/// Encrypted blocks are tagged with the `Raw` codec, the cid codec table of
+/// cid 0.5 has no private-use codes. Blocks written with `set_expose_codec`
+/// or a header start with a magic prefix and are recognized by
+/// `decode_codec` and `decode_header`.
 #[cfg(feature = "crypto")]
 #[derive(Clone)]
 pub struct GenericStrobeCodec<C, H> {
     _marker: PhantomData<(C, H)>,
     key: Arc<Key>,
+    siv: bool,
+    expose_codec: bool,
 }
 
 #[cfg(feature = "crypto")]
@@ -96,8 +159,57 @@ impl<C, H> GenericStrobeCodec<C, H> {
         Self {
             _marker: PhantomData,
             key: Arc::new(key),
+            siv: false,
+            expose_codec: false,
         }
     }
+
+    /// Creates a new generic strobe codec deriving nonces from the plaintext.
+    ///
+    /// The nonce is derived from the key and the plaintext, so a failing rng
+    /// can't cause nonce reuse, but equal values produce equal blocks. Blocks
+    /// from either mode can be decoded by both codecs.
+    pub fn new_siv(key: Key) -> Self {
+        Self {
+            siv: true,
+            ..Self::new(key)
+        }
+    }
+
+    /// Writes the codec of the plaintext unencrypted in new blocks.
+    ///
+    /// The codec is covered by the mac and can be read without the key with
+    /// `decode_codec`, so owners can tell dag-cbor from raw blocks without
+    /// decrypting them.
+    pub fn set_expose_codec(&mut self, expose: bool) {
+        self.expose_codec = expose;
+    }
+
+    /// Returns a codec with the same settings using `key`.
+    pub(crate) fn with_key(&self, key: Key) -> Self {
+        Self {
+            _marker: PhantomData,
+            key: Arc::new(key),
+            siv: self.siv,
+            expose_codec: self.expose_codec,
+        }
+    }
+
+    /// Verifies and decrypts the block in place.
+    fn decrypt<'a>(&self, cid: &Cid, data: &'a mut [u8]) -> Result<(CCode, &'a [u8])> {
+        if data.len() > libipld::MAX_BLOCK_SIZE {
+            return Err(Error::BlockTooLarge(data.len()));
+        }
+        if cid.codec() != RawCodec::CODE {
+            let err = libipld::error::Error::UnsupportedCodec(cid.codec());
+            return Err(Error::decode(cid, err));
+        }
+        crate::error::verify(cid, data)?;
+        crate::crypto::decrypt(&self.key, data).map_err(|e| match e {
+            crate::crypto::Error::Integrity => IntegrityError::Mac(cid.clone()).into(),
+            e => e.into(),
+        })
+    }
 }
 
 #[cfg(feature = "crypto")]
@@ -106,10 +218,31 @@ impl<C: Codec, H: Multihasher<Code>> Encoder for GenericStrobeCodec<C, H> {
     type Hash = H;
 
     fn encode<T: Encode<C>>(&self, value: &T) -> Result<Block> {
-        let data = C::encode(value).map_err(|e| Error::CodecError(Box::new(e)))?;
-        let ct = crate::crypto::encrypt(&self.key, C::CODE, &data)
-            .map_err(|e| Error::CodecError(Box::new(e)))?;
-        libipld::block::encode::<RawCodec, H, _>(&ct)
+        let data = encode_pooled::<C, T>(BufferPool::global().get_secret(), value)?;
+        let ct = match (self.siv, self.expose_codec) {
+            (false, false) => crate::crypto::encrypt(&self.key, C::CODE, &data)?,
+            (true, false) => crate::crypto::encrypt_siv(&self.key, C::CODE, &data)?,
+            (siv, true) => crate::crypto::seal(&self.key, C::CODE, None, &data, siv, true)?,
+        };
+        hash_block::<H, _>(RawCodec::CODE, ct)
+    }
+}
+
+#[cfg(feature = "crypto")]
+impl<C: Codec, H: Multihasher<Code>> HeaderEncoder for GenericStrobeCodec<C, H> {
+    /// The header is covered by the mac and can be read without the key with
+    /// `decode_header`.
+    fn encode_with_header<T: Encode<C>>(&self, value: &T, header: &[u8]) -> Result<Block> {
+        let data = encode_pooled::<C, T>(BufferPool::global().get_secret(), value)?;
+        let ct = crate::crypto::seal(
+            &self.key,
+            C::CODE,
+            Some(header),
+            &data,
+            self.siv,
+            self.expose_codec,
+        )?;
+        hash_block::<H, _>(RawCodec::CODE, ct)
     }
 }
 
@@ -118,20 +251,24 @@ impl<C: Codec, H> Decoder for GenericStrobeCodec<C, H> {
     type Codec = C;
 
     fn decode<T: Decode<C>>(&self, cid: &Cid, data: &[u8]) -> Result<T> {
-        let ct = libipld::block::decode::<RawCodec, Box<[u8]>>(cid, data)?;
-        let (codec, data) =
-            crate::crypto::decrypt(&self.key, ct).map_err(|e| Error::CodecError(Box::new(e)))?;
-        libipld::block::raw_decode::<C, T>(codec, &data)
+        self.decode_owned(cid, data.into())
+    }
+
+    fn decode_owned<T: Decode<C>>(&self, cid: &Cid, mut data: Box<[u8]>) -> Result<T> {
+        let (codec, data) = self.decrypt(cid, &mut data)?;
+        libipld::block::raw_decode::<C, T>(codec, data).map_err(|e| Error::decode(cid, e))
     }
 }
 
 #[cfg(feature = "crypto")]
 impl<C, H> IpldDecoder for GenericStrobeCodec<C, H> {
     fn decode_ipld(&self, cid: &Cid, data: &[u8]) -> Result<Ipld> {
-        let ct = libipld::block::decode::<RawCodec, Box<[u8]>>(cid, data)?;
-        let (codec, data) =
-            crate::crypto::decrypt(&self.key, ct).map_err(|e| Error::CodecError(Box::new(e)))?;
-        libipld::block::raw_decode_ipld(codec, &data)
+        self.decode_ipld_owned(cid, data.into())
+    }
+
+    fn decode_ipld_owned(&self, cid: &Cid, mut data: Box<[u8]>) -> Result<Ipld> {
+        let (codec, data) = self.decrypt(cid, &mut data)?;
+        libipld::block::raw_decode_ipld(codec, data).map_err(|e| Error::decode(cid, e))
     }
 }
 
@@ -0,0 +1,542 @@
+use crate::batch::Batch;
+use crate::builder::BlockBuilder;
+use crate::codec::{Encoder, IpldDecoder};
+use crate::error::{Error, Result};
+use core::marker::PhantomData;
+use core::ops::{Bound, RangeBounds};
+use futures::lock::Mutex;
+use futures::stream::{self, Stream, TryStreamExt};
+use libipld::cbor::{DagCborCodec, Error as CborError};
+use libipld::cid::Cid;
+use libipld::codec::{Codec, Decode, Encode};
+use libipld::ipld::Ipld;
+use libipld::store::{AliasStore, Store};
+use libipld::MAX_BLOCK_SIZE;
+use std::collections::BTreeMap;
+
+/// Default maximum size of a node.
+pub const DEFAULT_MAX_NODE_SIZE: usize = MAX_BLOCK_SIZE / 4;
+
+fn to_ipld<T: Encode<DagCborCodec>>(value: &T) -> core::result::Result<Ipld, CborError> {
+    DagCborCodec::decode(&DagCborCodec::encode(value)?)
+}
+
+fn from_ipld<T: Decode<DagCborCodec>>(ipld: &Ipld) -> core::result::Result<T, CborError> {
+    DagCborCodec::decode(&DagCborCodec::encode(ipld)?)
+}
+
+fn encode_error(err: CborError) -> Error {
+    Error::encode(libipld::error::Error::CodecError(Box::new(err)))
+}
+
+fn decode_error(cid: &Cid, err: CborError) -> Error {
+    Error::decode(cid, libipld::error::Error::CodecError(Box::new(err)))
+}
+
+fn encoded_len(ipld: &Ipld) -> Result<usize> {
+    Ok(DagCborCodec::encode(ipld).map_err(encode_error)?.len())
+}
+
+enum Node<K> {
+    /// Sorted entries.
+    Leaf(Vec<(K, Ipld)>),
+    /// Children with the smallest key of their subtree.
+    Branch(Vec<(K, Cid)>),
+}
+
+impl<K: Encode<DagCborCodec> + Decode<DagCborCodec>> Node<K> {
+    fn len(&self) -> usize {
+        match self {
+            Self::Leaf(entries) => entries.len(),
+            Self::Branch(children) => children.len(),
+        }
+    }
+
+    fn split(self) -> (Self, Self) {
+        match self {
+            Self::Leaf(mut entries) => {
+                let right = entries.split_off(entries.len() / 2);
+                (Self::Leaf(entries), Self::Leaf(right))
+            }
+            Self::Branch(mut children) => {
+                let right = children.split_off(children.len() / 2);
+                (Self::Branch(children), Self::Branch(right))
+            }
+        }
+    }
+
+    fn to_ipld(&self) -> Result<Ipld> {
+        let mut map = BTreeMap::new();
+        let (keys, other): (Vec<_>, (_, Vec<_>)) = match self {
+            Self::Leaf(entries) => {
+                let keys = entries.iter().map(|(key, _)| to_ipld(key)).collect();
+                let values = entries.iter().map(|(_, value)| value.clone()).collect();
+                (keys, ("values", values))
+            }
+            Self::Branch(children) => {
+                let keys = children.iter().map(|(key, _)| to_ipld(key)).collect();
+                let links = children
+                    .iter()
+                    .map(|(_, cid)| Ipld::Link(cid.clone()))
+                    .collect();
+                (keys, ("children", links))
+            }
+        };
+        let keys = keys
+            .into_iter()
+            .collect::<core::result::Result<_, _>>()
+            .map_err(encode_error)?;
+        map.insert("keys".to_string(), Ipld::List(keys));
+        map.insert(other.0.to_string(), Ipld::List(other.1));
+        Ok(Ipld::Map(map))
+    }
+
+    fn from_ipld(cid: &Cid, ipld: Ipld) -> Result<Self> {
+        let invalid = || Error::InvalidCollection(cid.clone());
+        let mut map = match ipld {
+            Ipld::Map(map) => map,
+            _ => return Err(invalid()),
+        };
+        let keys = match map.remove("keys") {
+            Some(Ipld::List(keys)) => keys
+                .iter()
+                .map(|key| from_ipld(key).map_err(|_| invalid()))
+                .collect::<Result<Vec<K>>>()?,
+            _ => return Err(invalid()),
+        };
+        match (map.remove("values"), map.remove("children")) {
+            (Some(Ipld::List(values)), None) if values.len() == keys.len() => {
+                Ok(Self::Leaf(keys.into_iter().zip(values).collect()))
+            }
+            (None, Some(Ipld::List(children))) if children.len() == keys.len() => {
+                let children = keys
+                    .into_iter()
+                    .zip(children)
+                    .map(|(key, child)| match child {
+                        Ipld::Link(cid) => Ok((key, cid)),
+                        _ => Err(invalid()),
+                    })
+                    .collect::<Result<_>>()?;
+                Ok(Self::Branch(children))
+            }
+            _ => Err(invalid()),
+        }
+    }
+}
+
+/// Splits `items` into consecutive groups of at most `max` bytes.
+fn pack<T>(items: Vec<(T, usize)>, max: usize) -> Vec<Vec<T>> {
+    let mut groups = vec![];
+    let mut group = vec![];
+    let mut size = 0;
+    for (item, len) in items {
+        if !group.is_empty() && size + len > max {
+            groups.push(core::mem::take(&mut group));
+            size = 0;
+        }
+        group.push(item);
+        size += len;
+    }
+    if !group.is_empty() {
+        groups.push(group);
+    }
+    groups
+}
+
+/// Returns if the keys in `[lo, hi)` can be in `range`.
+fn overlaps<K: Ord>(lo: &K, hi: Option<&K>, range: &(Bound<K>, Bound<K>)) -> bool {
+    let above_start = match (&range.0, hi) {
+        (Bound::Included(start), Some(hi)) | (Bound::Excluded(start), Some(hi)) => hi > start,
+        _ => true,
+    };
+    let below_end = match &range.1 {
+        Bound::Included(end) => lo <= end,
+        Bound::Excluded(end) => lo < end,
+        Bound::Unbounded => true,
+    };
+    above_start && below_end
+}
+
+/// Ordered map stored as a copy-on-write b+tree.
+///
+/// Keys and values are stored as dag-cbor ipld inside the nodes, which are
+/// encoded with the codec of the tree and split when their encoding
+/// exceeds the maximum node size. Every change writes the path to the
+/// changed leaf in a single batch. The root is referenced by the alias
+/// `btree/<name>` and is the only pinned node.
+pub struct BTree<S, C, K, V> {
+    builder: BlockBuilder<S, C>,
+    alias: Vec<u8>,
+    lock: Mutex<()>,
+    max_node_size: usize,
+    _marker: PhantomData<(K, V)>,
+}
+
+impl<S, C, K, V> BTree<S, C, K, V> {
+    /// Creates a tree named `name`.
+    pub fn new(store: S, codec: C, name: &str) -> Self {
+        Self {
+            builder: BlockBuilder::new(store, codec),
+            alias: format!("btree/{}", name).into_bytes(),
+            lock: Mutex::new(()),
+            max_node_size: DEFAULT_MAX_NODE_SIZE,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Returns the alias of the root.
+    pub fn alias(&self) -> &[u8] {
+        &self.alias
+    }
+
+    /// Returns the builder writing the nodes.
+    pub(crate) fn builder(&self) -> &BlockBuilder<S, C> {
+        &self.builder
+    }
+
+    /// Sets the size above which nodes are split, defaults to
+    /// `DEFAULT_MAX_NODE_SIZE`.
+    pub fn set_max_node_size(&mut self, size: usize) {
+        self.max_node_size = size;
+    }
+}
+
+impl<S, C, K, V> BTree<S, C, K, V>
+where
+    S: Store + AliasStore,
+    C: Encoder + IpldDecoder + Clone,
+    Ipld: Encode<C::Codec>,
+    K: Ord + Clone + Encode<DagCborCodec> + Decode<DagCborCodec>,
+    V: Encode<DagCborCodec> + Decode<DagCborCodec>,
+{
+    async fn node(&self, cid: &Cid) -> Result<Node<K>> {
+        Node::from_ipld(cid, self.builder.get_ipld(cid).await?)
+    }
+
+    /// Adds a node to the batch, splitting it if it is too large, and
+    /// returns the parts with their smallest key.
+    fn write(&self, batch: &mut Batch<C>, node: Node<K>) -> Result<Vec<(K, Cid)>> {
+        if node.len() == 0 {
+            return Ok(vec![]);
+        }
+        let ipld = node.to_ipld()?;
+        if node.len() > 2 && encoded_len(&ipld)? > self.max_node_size {
+            let (left, right) = node.split();
+            let mut parts = self.write(batch, left)?;
+            parts.extend(self.write(batch, right)?);
+            return Ok(parts);
+        }
+        let key = match node {
+            Node::Leaf(mut entries) => entries.swap_remove(0).0,
+            Node::Branch(mut children) => children.swap_remove(0).0,
+        };
+        Ok(vec![(key, batch.insert(&ipld)?.clone())])
+    }
+
+    /// Writes the parts of the root and makes it the new root.
+    async fn set_root(
+        &self,
+        old: Option<Cid>,
+        mut batch: Batch<C>,
+        mut parts: Vec<(K, Cid)>,
+    ) -> Result<()> {
+        while parts.len() > 1 {
+            parts = self.write(&mut batch, Node::Branch(parts))?;
+        }
+        if parts.is_empty() {
+            self.builder.unalias(&self.alias).await?;
+        } else {
+            let root = self.builder.insert_batch(batch).await?;
+            self.builder.alias(&self.alias, &root).await?;
+        }
+        if let Some(old) = old {
+            self.builder.unpin(&old).await?;
+        }
+        Ok(())
+    }
+
+    /// Returns the root node.
+    pub async fn root(&self) -> Result<Option<Cid>> {
+        self.builder.resolve(&self.alias).await
+    }
+
+    /// Returns the value of `key`.
+    pub async fn get(&self, key: &K) -> Result<Option<V>> {
+        let mut next = self.root().await?;
+        while let Some(cid) = next {
+            match self.node(&cid).await? {
+                Node::Branch(children) => {
+                    next = children
+                        .into_iter()
+                        .take_while(|(first, _)| first <= key)
+                        .last()
+                        .map(|(_, child)| child);
+                }
+                Node::Leaf(entries) => {
+                    return match entries.binary_search_by(|(k, _)| k.cmp(key)) {
+                        Ok(i) => Ok(Some(
+                            from_ipld(&entries[i].1).map_err(|err| decode_error(&cid, err))?,
+                        )),
+                        Err(_) => Ok(None),
+                    };
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Inserts or replaces the value of `key`.
+    pub async fn insert(&self, key: &K, value: &V) -> Result<()> {
+        let batch = self.builder.create_batch();
+        self.update(key, Some(value), batch).await?;
+        Ok(())
+    }
+
+    /// Removes `key`, returning if it was in the tree.
+    ///
+    /// Nodes are not merged, empty nodes are removed from their parent.
+    pub async fn remove(&self, key: &K) -> Result<bool> {
+        let batch = self.builder.create_batch();
+        self.update(key, None, batch).await
+    }
+
+    /// Inserts, replaces or removes the value of `key`, returning if the
+    /// tree changed.
+    ///
+    /// The changed path is appended to `batch`, so blocks referenced by the
+    /// value can be inserted in the same batch.
+    pub(crate) async fn update(
+        &self,
+        key: &K,
+        value: Option<&V>,
+        mut batch: Batch<C>,
+    ) -> Result<bool> {
+        let _guard = self.lock.lock().await;
+        let old = self.root().await?;
+        let value = match value {
+            Some(value) => Some(to_ipld(value).map_err(encode_error)?),
+            None => None,
+        };
+        let mut path = vec![];
+        let mut entries = vec![];
+        let mut next = old.clone();
+        while let Some(cid) = next.take() {
+            match self.node(&cid).await? {
+                Node::Branch(children) => {
+                    let i = children
+                        .iter()
+                        .rposition(|(first, _)| first <= key)
+                        .unwrap_or(0);
+                    next = Some(children[i].1.clone());
+                    path.push((children, i));
+                }
+                Node::Leaf(leaf) => entries = leaf,
+            }
+        }
+        match (entries.binary_search_by(|(k, _)| k.cmp(key)), value) {
+            (Ok(i), Some(value)) => entries[i].1 = value,
+            (Err(i), Some(value)) => entries.insert(i, (key.clone(), value)),
+            (Ok(i), None) => {
+                entries.remove(i);
+            }
+            (Err(_), None) => return Ok(false),
+        }
+        let mut parts = self.write(&mut batch, Node::Leaf(entries))?;
+        while let Some((mut children, i)) = path.pop() {
+            children.splice(i..=i, parts);
+            parts = self.write(&mut batch, Node::Branch(children))?;
+        }
+        self.set_root(old, batch, parts).await?;
+        Ok(true)
+    }
+
+    /// Replaces the content of the tree with `entries`.
+    ///
+    /// The tree is built bottom up with full nodes. Of entries with equal
+    /// keys the last one wins.
+    pub async fn bulk_load<I: IntoIterator<Item = (K, V)>>(&self, entries: I) -> Result<()> {
+        let _guard = self.lock.lock().await;
+        let old = self.root().await?;
+        let mut sorted: Vec<(K, Ipld)> = vec![];
+        let mut entries = entries
+            .into_iter()
+            .map(|(key, value)| Ok((key, to_ipld(&value).map_err(encode_error)?)))
+            .collect::<Result<Vec<_>>>()?;
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        for entry in entries {
+            match sorted.last_mut() {
+                Some(last) if last.0 == entry.0 => *last = entry,
+                _ => sorted.push(entry),
+            }
+        }
+        let mut batch = self.builder.create_batch();
+        let mut items = vec![];
+        for entry in sorted {
+            let key = to_ipld(&entry.0).map_err(encode_error)?;
+            let len = encoded_len(&key)? + encoded_len(&entry.1)?;
+            items.push((entry, len));
+        }
+        let mut parts = vec![];
+        for leaf in pack(items, self.max_node_size) {
+            parts.extend(self.write(&mut batch, Node::Leaf(leaf))?);
+        }
+        while parts.len() > 1 {
+            let mut items = vec![];
+            for part in parts {
+                let key = to_ipld(&part.0).map_err(encode_error)?;
+                let len = encoded_len(&key)? + part.1.to_bytes().len() + 4;
+                items.push((part, len));
+            }
+            parts = vec![];
+            for branch in pack(items, self.max_node_size) {
+                parts.extend(self.write(&mut batch, Node::Branch(branch))?);
+            }
+        }
+        self.set_root(old, batch, parts).await
+    }
+
+    /// Returns the entries with keys in `range` in ascending order.
+    ///
+    /// Only the nodes that can contain keys in the range are fetched.
+    pub fn range<R: RangeBounds<K>>(&self, range: R) -> impl Stream<Item = Result<(K, V)>> + '_ {
+        let range = (range.start_bound().cloned(), range.end_bound().cloned());
+        stream::once(self.root())
+            .map_ok(move |root| {
+                let range = range.clone();
+                stream::try_unfold(root.into_iter().collect::<Vec<_>>(), move |mut stack| {
+                    let range = range.clone();
+                    async move {
+                        let cid = match stack.pop() {
+                            Some(cid) => cid,
+                            None => return Ok(None),
+                        };
+                        let mut items: Vec<Result<(K, V)>> = vec![];
+                        match self.node(&cid).await? {
+                            Node::Branch(children) => {
+                                for i in (0..children.len()).rev() {
+                                    let hi = children.get(i + 1).map(|(key, _)| key);
+                                    if overlaps(&children[i].0, hi, &range) {
+                                        stack.push(children[i].1.clone());
+                                    }
+                                }
+                            }
+                            Node::Leaf(entries) => {
+                                for (key, value) in entries {
+                                    if range.contains(&key) {
+                                        let value = from_ipld(&value)
+                                            .map_err(|err| decode_error(&cid, err))?;
+                                        items.push(Ok((key, value)));
+                                    }
+                                }
+                            }
+                        }
+                        Result::Ok(Some((stream::iter(items), stack)))
+                    }
+                })
+                .try_flatten()
+            })
+            .try_flatten()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Codec;
+    use libipld::mem::MemStore;
+
+    fn tree() -> BTree<MemStore, Codec, u64, String> {
+        let mut tree = BTree::new(MemStore::default(), Codec::new(), "index");
+        tree.set_max_node_size(128);
+        tree
+    }
+
+    async fn depth(tree: &BTree<MemStore, Codec, u64, String>) -> usize {
+        let mut depth = 0;
+        let mut next = tree.root().await.unwrap();
+        while let Some(cid) = next {
+            depth += 1;
+            next = match tree.node(&cid).await.unwrap() {
+                Node::Branch(children) => Some(children[0].1.clone()),
+                Node::Leaf(_) => None,
+            };
+        }
+        depth
+    }
+
+    #[async_std::test]
+    async fn test_btree() {
+        let tree = tree();
+        assert_eq!(tree.get(&1).await.unwrap(), None);
+        for i in (0..100u64).rev().map(|i| i * 2) {
+            tree.insert(&i, &i.to_string()).await.unwrap();
+        }
+        for i in (1..200u64).step_by(2) {
+            tree.insert(&i, &i.to_string()).await.unwrap();
+        }
+        tree.insert(&7, &"seven".to_string()).await.unwrap();
+        assert!(depth(&tree).await > 2);
+        assert_eq!(tree.get(&7).await.unwrap(), Some("seven".to_string()));
+        assert_eq!(tree.get(&150).await.unwrap(), Some("150".to_string()));
+        assert_eq!(tree.get(&200).await.unwrap(), None);
+
+        let all: Vec<_> = tree.range(..).try_collect().await.unwrap();
+        let keys: Vec<_> = all.iter().map(|(key, _)| *key).collect();
+        assert_eq!(keys, (0..200).collect::<Vec<_>>());
+        let some: Vec<_> = tree.range(10..=12).try_collect().await.unwrap();
+        let expected: Vec<_> = (10..=12u64).map(|i| (i, i.to_string())).collect();
+        assert_eq!(some, expected);
+        let none: Vec<_> = tree.range(300..).try_collect().await.unwrap();
+        assert!(none.is_empty());
+    }
+
+    #[async_std::test]
+    async fn test_btree_bulk_load() {
+        let tree = tree();
+        tree.insert(&1000, &"gone".to_string()).await.unwrap();
+        let entries = (0..500u64).rev().map(|i| (i, i.to_string()));
+        tree.bulk_load(entries.chain(Some((3, "three".to_string()))))
+            .await
+            .unwrap();
+        assert!(depth(&tree).await > 2);
+        assert_eq!(tree.get(&1000).await.unwrap(), None);
+        assert_eq!(tree.get(&3).await.unwrap(), Some("three".to_string()));
+        let range: Vec<_> = tree.range(250..260).try_collect().await.unwrap();
+        let keys: Vec<_> = range.iter().map(|(key, _)| *key).collect();
+        assert_eq!(keys, (250..260).collect::<Vec<_>>());
+        tree.insert(&600, &"600".to_string()).await.unwrap();
+        assert_eq!(
+            tree.range(..).try_collect::<Vec<_>>().await.unwrap().len(),
+            501
+        );
+
+        tree.bulk_load(vec![]).await.unwrap();
+        assert_eq!(tree.root().await.unwrap(), None);
+    }
+
+    #[async_std::test]
+    async fn test_btree_remove() {
+        let tree = tree();
+        tree.bulk_load((0..100u64).map(|i| (i, i.to_string())))
+            .await
+            .unwrap();
+        assert!(!tree.remove(&100).await.unwrap());
+        for i in (0..100u64).filter(|i| i % 3 != 0) {
+            assert!(tree.remove(&i).await.unwrap());
+        }
+        assert_eq!(tree.get(&1).await.unwrap(), None);
+        assert_eq!(tree.get(&3).await.unwrap(), Some("3".to_string()));
+        let keys: Vec<_> = tree
+            .range(..)
+            .map_ok(|(key, _)| key)
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(keys, (0..100).step_by(3).collect::<Vec<_>>());
+
+        for i in (0..100u64).step_by(3) {
+            assert!(tree.remove(&i).await.unwrap());
+        }
+        assert_eq!(tree.root().await.unwrap(), None);
+    }
+}
@@ -0,0 +1,261 @@
+use crate::builder::BlockBuilder;
+use crate::codec::{Decoder, Encoder, IpldDecoder};
+use crate::error::{Error, Result};
+use core::convert::TryFrom;
+use core::marker::PhantomData;
+use futures::lock::Mutex;
+use futures::stream::{self, Stream, TryStreamExt};
+use libipld::cid::Cid;
+use libipld::codec::{Decode, Encode};
+use libipld::ipld::Ipld;
+use libipld::store::{AliasStore, Store};
+use std::collections::BTreeMap;
+
+/// Node of the log chain.
+struct Node {
+    seq: u64,
+    entry: Cid,
+    /// Links to the nodes `seq - 2^k` for `k` up to the trailing zeros of
+    /// `seq`, the first link is the previous node.
+    links: Vec<Cid>,
+}
+
+impl Node {
+    fn to_ipld(&self) -> Ipld {
+        let mut map = BTreeMap::new();
+        map.insert("seq".to_string(), Ipld::Integer(self.seq.into()));
+        map.insert("entry".to_string(), Ipld::Link(self.entry.clone()));
+        map.insert(
+            "links".to_string(),
+            Ipld::List(self.links.iter().cloned().map(Ipld::Link).collect()),
+        );
+        Ipld::Map(map)
+    }
+
+    fn from_ipld(cid: &Cid, ipld: Ipld) -> Result<Self> {
+        let invalid = || Error::InvalidLogEntry(cid.clone());
+        let mut map = match ipld {
+            Ipld::Map(map) => map,
+            _ => return Err(invalid()),
+        };
+        let seq = match map.remove("seq") {
+            Some(Ipld::Integer(seq)) => u64::try_from(seq).map_err(|_| invalid())?,
+            _ => return Err(invalid()),
+        };
+        let entry = match map.remove("entry") {
+            Some(Ipld::Link(entry)) => entry,
+            _ => return Err(invalid()),
+        };
+        let links = match map.remove("links") {
+            Some(Ipld::List(links)) => links
+                .into_iter()
+                .map(|link| match link {
+                    Ipld::Link(link) => Ok(link),
+                    _ => Err(invalid()),
+                })
+                .collect::<Result<_>>()?,
+            _ => return Err(invalid()),
+        };
+        Ok(Self { seq, entry, links })
+    }
+}
+
+/// Returns the number of skip links of the node `seq`.
+fn link_count(seq: u64) -> u32 {
+    if seq == 0 {
+        0
+    } else {
+        seq.trailing_zeros() + 1
+    }
+}
+
+/// Append-only log of values.
+///
+/// Every value is stored in its own block next to a node with its sequence
+/// number. Besides the previous node, the node `seq` links to the nodes
+/// `seq - 2^k` for `k` up to the trailing zeros of `seq`, so any entry is
+/// found in `O(log n)` blocks. The head node is referenced by the alias
+/// `log/<name>` and is the only pinned node.
+pub struct Log<S, C, T> {
+    builder: BlockBuilder<S, C>,
+    alias: Vec<u8>,
+    lock: Mutex<()>,
+    _marker: PhantomData<T>,
+}
+
+impl<S, C, T> Log<S, C, T> {
+    /// Creates a log named `name`.
+    pub fn new(store: S, codec: C, name: &str) -> Self {
+        Self {
+            builder: BlockBuilder::new(store, codec),
+            alias: format!("log/{}", name).into_bytes(),
+            lock: Mutex::new(()),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Returns the alias of the head node.
+    pub fn alias(&self) -> &[u8] {
+        &self.alias
+    }
+
+    /// Returns the block builder.
+    pub fn builder(&self) -> &BlockBuilder<S, C> {
+        &self.builder
+    }
+}
+
+impl<S, C, T> Log<S, C, T>
+where
+    S: Store + AliasStore,
+    C: Encoder + Decoder + IpldDecoder + Clone,
+    Ipld: Encode<<C as Encoder>::Codec>,
+    T: Encode<<C as Encoder>::Codec> + Decode<<C as Decoder>::Codec>,
+{
+    async fn node(&self, cid: &Cid) -> Result<Node> {
+        Node::from_ipld(cid, self.builder.get_ipld(cid).await?)
+    }
+
+    /// Follows the skip links from the node `cid` with sequence number `seq`
+    /// to the node `target`.
+    async fn seek(&self, mut cid: Cid, mut seq: u64, target: u64) -> Result<Cid> {
+        while seq > target {
+            let node = self.node(&cid).await?;
+            let k = node
+                .links
+                .iter()
+                .enumerate()
+                .rev()
+                .find(|(k, _)| seq - (1 << k) >= target)
+                .map(|(k, _)| k)
+                .ok_or_else(|| Error::InvalidLogEntry(cid.clone()))?;
+            seq -= 1 << k;
+            cid = node.links[k].clone();
+        }
+        Ok(cid)
+    }
+
+    /// Returns the head node.
+    pub async fn head(&self) -> Result<Option<Cid>> {
+        self.builder.resolve(&self.alias).await
+    }
+
+    /// Returns the number of entries.
+    pub async fn len(&self) -> Result<u64> {
+        match self.head().await? {
+            Some(head) => Ok(self.node(&head).await?.seq + 1),
+            None => Ok(0),
+        }
+    }
+
+    /// Returns if the log is empty.
+    pub async fn is_empty(&self) -> Result<bool> {
+        Ok(self.head().await?.is_none())
+    }
+
+    /// Appends an entry and returns its sequence number.
+    pub async fn append(&self, entry: &T) -> Result<u64> {
+        self.extend(core::slice::from_ref(entry))
+            .await
+            .map(|len| len - 1)
+    }
+
+    /// Appends the entries in a single batch and returns the new length.
+    pub async fn extend(&self, entries: &[T]) -> Result<u64> {
+        let _guard = self.lock.lock().await;
+        let head = self.head().await?;
+        let len = match &head {
+            Some(head) => self.node(head).await?.seq + 1,
+            None => 0,
+        };
+        if entries.is_empty() {
+            return Ok(len);
+        }
+        let mut batch = self.builder.create_batch();
+        let mut nodes = BTreeMap::new();
+        for (seq, entry) in (len..).zip(entries) {
+            let entry = batch.insert(entry)?.clone();
+            let mut links = vec![];
+            for k in 0..link_count(seq) {
+                let target = seq - (1 << k);
+                let link = match (nodes.get(&target), &head) {
+                    (Some(cid), _) => Cid::clone(cid),
+                    (None, Some(head)) => self.seek(head.clone(), len - 1, target).await?,
+                    (None, None) => unreachable!(),
+                };
+                links.push(link);
+            }
+            let node = Node { seq, entry, links };
+            let cid = batch.insert(&node.to_ipld())?.clone();
+            nodes.insert(seq, cid);
+        }
+        let new = self.builder.insert_batch(batch).await?;
+        self.builder.alias(&self.alias, &new).await?;
+        if let Some(head) = head {
+            self.builder.unpin(&head).await?;
+        }
+        Ok(len + entries.len() as u64)
+    }
+
+    /// Returns the entry with sequence number `seq`.
+    pub async fn get(&self, seq: u64) -> Result<Option<T>> {
+        let head = match self.head().await? {
+            Some(head) => head,
+            None => return Ok(None),
+        };
+        let len = self.node(&head).await?.seq + 1;
+        if seq >= len {
+            return Ok(None);
+        }
+        let cid = self.seek(head, len - 1, seq).await?;
+        let node = self.node(&cid).await?;
+        Ok(Some(self.builder.get(&node.entry).await?))
+    }
+
+    /// Returns the entries and their sequence numbers starting at the head.
+    pub fn iter_from_head(&self) -> impl Stream<Item = Result<(u64, T)>> + '_ {
+        stream::once(self.head())
+            .map_ok(move |head| {
+                stream::try_unfold(head, move |next| async move {
+                    let cid = match next {
+                        Some(cid) => cid,
+                        None => return Ok(None),
+                    };
+                    let node = self.node(&cid).await?;
+                    let entry = self.builder.get(&node.entry).await?;
+                    Ok(Some(((node.seq, entry), node.links.first().cloned())))
+                })
+            })
+            .try_flatten()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Codec;
+    use libipld::mem::MemStore;
+
+    #[async_std::test]
+    async fn test_log() {
+        let log = Log::<_, _, u64>::new(MemStore::default(), Codec::new(), "events");
+        assert!(log.is_empty().await.unwrap());
+        assert_eq!(log.get(0).await.unwrap(), None);
+
+        assert_eq!(log.append(&0).await.unwrap(), 0);
+        let entries: Vec<u64> = (1..37).collect();
+        assert_eq!(log.extend(&entries).await.unwrap(), 37);
+        for i in 37..100 {
+            assert_eq!(log.append(&i).await.unwrap(), i);
+        }
+        assert_eq!(log.len().await.unwrap(), 100);
+        for seq in 0..100 {
+            assert_eq!(log.get(seq).await.unwrap(), Some(seq));
+        }
+        assert_eq!(log.get(100).await.unwrap(), None);
+
+        let entries: Vec<_> = log.iter_from_head().try_collect().await.unwrap();
+        let expected: Vec<_> = (0..100).rev().map(|seq| (seq, seq)).collect();
+        assert_eq!(entries, expected);
+    }
+}
@@ -0,0 +1,10 @@
+//! Persistent collections.
+mod btree;
+mod log;
+mod queue;
+mod stack;
+
+pub use btree::{BTree, DEFAULT_MAX_NODE_SIZE};
+pub use log::Log;
+pub use queue::Queue;
+pub use stack::Stack;
@@ -0,0 +1,221 @@
+use crate::builder::BlockBuilder;
+use crate::codec::{Decoder, Encoder, IpldDecoder};
+use crate::collections::stack::Cons;
+use crate::error::{Error, Result};
+use core::convert::TryFrom;
+use core::marker::PhantomData;
+use futures::lock::Mutex;
+use libipld::cid::Cid;
+use libipld::codec::{Decode, Encode};
+use libipld::ipld::Ipld;
+use libipld::store::{AliasStore, Store};
+use std::collections::BTreeMap;
+
+/// Root of a queue.
+#[derive(Default)]
+struct Root {
+    /// List of values to pop, oldest first.
+    front: Option<Cid>,
+    /// List of pushed values, newest first.
+    back: Option<Cid>,
+    len: u64,
+}
+
+fn link(cid: &Option<Cid>) -> Ipld {
+    cid.clone().map(Ipld::Link).unwrap_or(Ipld::Null)
+}
+
+impl Root {
+    fn to_ipld(&self) -> Ipld {
+        let mut map = BTreeMap::new();
+        map.insert("front".to_string(), link(&self.front));
+        map.insert("back".to_string(), link(&self.back));
+        map.insert("len".to_string(), Ipld::Integer(self.len.into()));
+        Ipld::Map(map)
+    }
+
+    fn from_ipld(cid: &Cid, ipld: Ipld) -> Result<Self> {
+        let invalid = || Error::InvalidCollection(cid.clone());
+        let mut map = match ipld {
+            Ipld::Map(map) => map,
+            _ => return Err(invalid()),
+        };
+        let mut list = |key| match map.remove(key) {
+            Some(Ipld::Link(cid)) => Ok(Some(cid)),
+            Some(Ipld::Null) => Ok(None),
+            _ => Err(invalid()),
+        };
+        let front = list("front")?;
+        let back = list("back")?;
+        let len = match map.remove("len") {
+            Some(Ipld::Integer(len)) => u64::try_from(len).map_err(|_| invalid())?,
+            _ => return Err(invalid()),
+        };
+        Ok(Self { front, back, len })
+    }
+}
+
+/// Persistent first in first out queue of values.
+///
+/// The queue is made of two linked lists referenced by a root block, values
+/// are pushed to the back list and popped from the front list. When the
+/// front list is empty the back list is reversed into it, so `push` and
+/// `pop` read and write an amortized constant number of blocks. The root is
+/// referenced by the alias `queue/<name>` and is the only pinned block.
+pub struct Queue<S, C, T> {
+    builder: BlockBuilder<S, C>,
+    alias: Vec<u8>,
+    lock: Mutex<()>,
+    _marker: PhantomData<T>,
+}
+
+impl<S, C, T> Queue<S, C, T> {
+    /// Creates a queue named `name`.
+    pub fn new(store: S, codec: C, name: &str) -> Self {
+        Self {
+            builder: BlockBuilder::new(store, codec),
+            alias: format!("queue/{}", name).into_bytes(),
+            lock: Mutex::new(()),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Returns the alias of the root.
+    pub fn alias(&self) -> &[u8] {
+        &self.alias
+    }
+}
+
+impl<S, C, T> Queue<S, C, T>
+where
+    S: Store + AliasStore,
+    C: Encoder + Decoder + IpldDecoder + Clone,
+    Ipld: Encode<<C as Encoder>::Codec>,
+    T: Encode<<C as Encoder>::Codec> + Decode<<C as Decoder>::Codec>,
+{
+    async fn root(&self) -> Result<(Option<Cid>, Root)> {
+        match self.builder.resolve(&self.alias).await? {
+            Some(cid) => {
+                let root = Root::from_ipld(&cid, self.builder.get_ipld(&cid).await?)?;
+                Ok((Some(cid), root))
+            }
+            None => Ok((None, Root::default())),
+        }
+    }
+
+    async fn node(&self, cid: &Cid) -> Result<Cons> {
+        Cons::from_ipld(cid, self.builder.get_ipld(cid).await?)
+    }
+
+    /// Returns the number of values.
+    pub async fn len(&self) -> Result<u64> {
+        Ok(self.root().await?.1.len)
+    }
+
+    /// Returns if the queue is empty.
+    pub async fn is_empty(&self) -> Result<bool> {
+        Ok(self.len().await? == 0)
+    }
+
+    /// Pushes a value to the back and returns the new length.
+    pub async fn push(&self, value: &T) -> Result<u64> {
+        let _guard = self.lock.lock().await;
+        let (old, mut root) = self.root().await?;
+        let len = match &root.back {
+            Some(back) => self.node(back).await?.len,
+            None => 0,
+        };
+        let mut batch = self.builder.create_batch();
+        let node = Cons {
+            value: batch.insert(value)?.clone(),
+            next: root.back.take(),
+            len: len + 1,
+        };
+        root.back = Some(batch.insert(&node.to_ipld())?.clone());
+        root.len += 1;
+        batch.insert(&root.to_ipld())?;
+        let cid = self.builder.insert_batch(batch).await?;
+        self.builder.alias(&self.alias, &cid).await?;
+        if let Some(old) = old {
+            self.builder.unpin(&old).await?;
+        }
+        Ok(root.len)
+    }
+
+    /// Removes and returns the front value.
+    pub async fn pop(&self) -> Result<Option<T>> {
+        let _guard = self.lock.lock().await;
+        let (old, mut root) = self.root().await?;
+        let old = match old {
+            Some(old) => old,
+            None => return Ok(None),
+        };
+        let mut batch = self.builder.create_batch();
+        let front = match root.front.take() {
+            Some(front) => self.node(&front).await?,
+            None => {
+                // reverse the back list into the front list
+                let mut values = vec![];
+                let mut next = root.back.take();
+                while let Some(cid) = next {
+                    let node = self.node(&cid).await?;
+                    values.push(node.value);
+                    next = node.next;
+                }
+                let oldest = values
+                    .pop()
+                    .ok_or_else(|| Error::InvalidCollection(old.clone()))?;
+                let mut front = None;
+                for (len, value) in (1..).zip(values) {
+                    let node = Cons {
+                        value,
+                        next: front,
+                        len,
+                    };
+                    front = Some(batch.insert(&node.to_ipld())?.clone());
+                }
+                Cons {
+                    value: oldest,
+                    next: front,
+                    len: root.len,
+                }
+            }
+        };
+        let value = self.builder.get(&front.value).await?;
+        root.front = front.next;
+        root.len -= 1;
+        if root.len == 0 {
+            self.builder.unalias(&self.alias).await?;
+        } else {
+            batch.insert(&root.to_ipld())?;
+            let cid = self.builder.insert_batch(batch).await?;
+            self.builder.alias(&self.alias, &cid).await?;
+        }
+        self.builder.unpin(&old).await?;
+        Ok(Some(value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Codec;
+    use libipld::mem::MemStore;
+
+    #[async_std::test]
+    async fn test_queue() {
+        let queue = Queue::<_, _, u64>::new(MemStore::default(), Codec::new(), "jobs");
+        assert_eq!(queue.pop().await.unwrap(), None);
+        for i in 0..3 {
+            queue.push(&i).await.unwrap();
+        }
+        assert_eq!(queue.pop().await.unwrap(), Some(0));
+        assert_eq!(queue.push(&3).await.unwrap(), 3);
+        assert_eq!(queue.len().await.unwrap(), 3);
+        for i in 1..4 {
+            assert_eq!(queue.pop().await.unwrap(), Some(i));
+        }
+        assert_eq!(queue.pop().await.unwrap(), None);
+        assert!(queue.is_empty().await.unwrap());
+    }
+}
@@ -0,0 +1,185 @@
+use crate::builder::BlockBuilder;
+use crate::codec::{Decoder, Encoder, IpldDecoder};
+use crate::error::{Error, Result};
+use core::convert::TryFrom;
+use core::marker::PhantomData;
+use futures::lock::Mutex;
+use libipld::cid::Cid;
+use libipld::codec::{Decode, Encode};
+use libipld::ipld::Ipld;
+use libipld::store::{AliasStore, Store};
+use std::collections::BTreeMap;
+
+/// Node of a linked list of values.
+pub(super) struct Cons {
+    pub value: Cid,
+    pub next: Option<Cid>,
+    /// Number of nodes in the list starting at this node.
+    pub len: u64,
+}
+
+impl Cons {
+    pub fn to_ipld(&self) -> Ipld {
+        let mut map = BTreeMap::new();
+        map.insert("value".to_string(), Ipld::Link(self.value.clone()));
+        let next = self.next.clone().map(Ipld::Link).unwrap_or(Ipld::Null);
+        map.insert("next".to_string(), next);
+        map.insert("len".to_string(), Ipld::Integer(self.len.into()));
+        Ipld::Map(map)
+    }
+
+    pub fn from_ipld(cid: &Cid, ipld: Ipld) -> Result<Self> {
+        let invalid = || Error::InvalidCollection(cid.clone());
+        let mut map = match ipld {
+            Ipld::Map(map) => map,
+            _ => return Err(invalid()),
+        };
+        let value = match map.remove("value") {
+            Some(Ipld::Link(value)) => value,
+            _ => return Err(invalid()),
+        };
+        let next = match map.remove("next") {
+            Some(Ipld::Link(next)) => Some(next),
+            Some(Ipld::Null) => None,
+            _ => return Err(invalid()),
+        };
+        let len = match map.remove("len") {
+            Some(Ipld::Integer(len)) => u64::try_from(len).map_err(|_| invalid())?,
+            _ => return Err(invalid()),
+        };
+        Ok(Self { value, next, len })
+    }
+}
+
+/// Persistent stack of values.
+///
+/// The stack is a linked list with every value stored in its own block. The
+/// top node is referenced by the alias `stack/<name>` and is the only pinned
+/// node. `push` and `pop` read and write a constant number of blocks.
+pub struct Stack<S, C, T> {
+    builder: BlockBuilder<S, C>,
+    alias: Vec<u8>,
+    lock: Mutex<()>,
+    _marker: PhantomData<T>,
+}
+
+impl<S, C, T> Stack<S, C, T> {
+    /// Creates a stack named `name`.
+    pub fn new(store: S, codec: C, name: &str) -> Self {
+        Self {
+            builder: BlockBuilder::new(store, codec),
+            alias: format!("stack/{}", name).into_bytes(),
+            lock: Mutex::new(()),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Returns the alias of the top node.
+    pub fn alias(&self) -> &[u8] {
+        &self.alias
+    }
+}
+
+impl<S, C, T> Stack<S, C, T>
+where
+    S: Store + AliasStore,
+    C: Encoder + Decoder + IpldDecoder + Clone,
+    Ipld: Encode<<C as Encoder>::Codec>,
+    T: Encode<<C as Encoder>::Codec> + Decode<<C as Decoder>::Codec>,
+{
+    async fn top(&self) -> Result<Option<(Cid, Cons)>> {
+        match self.builder.resolve(&self.alias).await? {
+            Some(cid) => {
+                let node = Cons::from_ipld(&cid, self.builder.get_ipld(&cid).await?)?;
+                Ok(Some((cid, node)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the number of values.
+    pub async fn len(&self) -> Result<u64> {
+        Ok(self
+            .top()
+            .await?
+            .map(|(_, node)| node.len)
+            .unwrap_or_default())
+    }
+
+    /// Returns if the stack is empty.
+    pub async fn is_empty(&self) -> Result<bool> {
+        Ok(self.builder.resolve(&self.alias).await?.is_none())
+    }
+
+    /// Returns the top value without removing it.
+    pub async fn peek(&self) -> Result<Option<T>> {
+        match self.top().await? {
+            Some((_, node)) => Ok(Some(self.builder.get(&node.value).await?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Pushes a value and returns the new length.
+    pub async fn push(&self, value: &T) -> Result<u64> {
+        let _guard = self.lock.lock().await;
+        let top = self.top().await?;
+        let len = top.as_ref().map(|(_, node)| node.len).unwrap_or_default() + 1;
+        let mut batch = self.builder.create_batch();
+        let node = Cons {
+            value: batch.insert(value)?.clone(),
+            next: top.as_ref().map(|(cid, _)| cid.clone()),
+            len,
+        };
+        batch.insert(&node.to_ipld())?;
+        let cid = self.builder.insert_batch(batch).await?;
+        self.builder.alias(&self.alias, &cid).await?;
+        if let Some((top, _)) = top {
+            self.builder.unpin(&top).await?;
+        }
+        Ok(len)
+    }
+
+    /// Removes and returns the top value.
+    pub async fn pop(&self) -> Result<Option<T>> {
+        let _guard = self.lock.lock().await;
+        let (top, node) = match self.top().await? {
+            Some(top) => top,
+            None => return Ok(None),
+        };
+        let value = self.builder.get(&node.value).await?;
+        match &node.next {
+            Some(next) => {
+                // pins the next node
+                let ipld = self.builder.get_ipld(next).await?;
+                self.builder.insert(&ipld).await?;
+                self.builder.alias(&self.alias, next).await?;
+            }
+            None => self.builder.unalias(&self.alias).await?,
+        }
+        self.builder.unpin(&top).await?;
+        Ok(Some(value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Codec;
+    use libipld::mem::MemStore;
+
+    #[async_std::test]
+    async fn test_stack() {
+        let stack = Stack::<_, _, String>::new(MemStore::default(), Codec::new(), "work");
+        assert_eq!(stack.pop().await.unwrap(), None);
+        assert_eq!(stack.push(&"a".to_string()).await.unwrap(), 1);
+        assert_eq!(stack.push(&"b".to_string()).await.unwrap(), 2);
+        assert_eq!(stack.peek().await.unwrap(), Some("b".to_string()));
+        assert_eq!(stack.len().await.unwrap(), 2);
+        assert_eq!(stack.pop().await.unwrap(), Some("b".to_string()));
+        assert_eq!(stack.push(&"c".to_string()).await.unwrap(), 2);
+        assert_eq!(stack.pop().await.unwrap(), Some("c".to_string()));
+        assert_eq!(stack.pop().await.unwrap(), Some("a".to_string()));
+        assert_eq!(stack.pop().await.unwrap(), None);
+        assert!(stack.is_empty().await.unwrap());
+    }
+}
@@ -0,0 +1,247 @@
+use crate::budget::{MemoryBudget, Reclaim, Reservation};
+use crate::builder::BlockBuilder;
+use crate::cache::{Cache, CacheBatch, ReadonlyCache};
+use crate::codec::{Decoder, Encoder};
+use crate::error::Result;
+use async_trait::async_trait;
+use dashmap::DashMap;
+use libipld::cid::Cid;
+use libipld::codec::{Decode, Encode};
+use libipld::store::{ReadonlyStore, Store};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Weak};
+
+struct Entry<T> {
+    value: T,
+    referenced: AtomicBool,
+    reservation: Option<Reservation>,
+}
+
+impl<T: Send + Sync> Reclaim for DashMap<Cid, Entry<T>> {
+    fn reclaim(&self, bytes: usize) {
+        let mut released = 0;
+        while released < bytes {
+            let cid = match self.iter().next() {
+                Some(entry) => entry.key().clone(),
+                None => break,
+            };
+            if let Some((_, entry)) = self.remove(&cid) {
+                released += entry.reservation.map(|r| r.bytes()).unwrap_or_default();
+            }
+        }
+    }
+}
+
+/// Cache for ipld blocks optimized for concurrent reads.
+///
+/// Unlike `IpldCache`, which locks the whole cache on every access, values
+/// are kept in a sharded map, so concurrent reads of different blocks don't
+/// contend. Eviction is approximate: a second chance scan evicts a value
+/// that wasn't read since the last scan, and concurrent inserts may exceed
+/// the size by the number of inserting tasks. Values holding a reservation
+/// of the budget are evicted while a batch waits for it.
+pub struct ConcurrentIpldCache<S, C, T> {
+    builder: Arc<BlockBuilder<S, C>>,
+    budget: Option<MemoryBudget>,
+    size: usize,
+    cache: Arc<DashMap<Cid, Entry<T>>>,
+    reclaimable: AtomicBool,
+}
+
+impl<S, C, T> ConcurrentIpldCache<S, C, T> {
+    /// Creates a new cache of size `size`.
+    pub fn new(store: S, codec: C, size: usize) -> Self {
+        Self::with_builder(BlockBuilder::new(store, codec), size)
+    }
+
+    /// Creates a new cache of size `size` using a configured builder.
+    pub fn with_builder(builder: impl Into<Arc<BlockBuilder<S, C>>>, size: usize) -> Self {
+        let builder = builder.into();
+        Self {
+            budget: builder.budget().cloned(),
+            builder,
+            size,
+            cache: Arc::new(DashMap::with_capacity(size)),
+            reclaimable: AtomicBool::new(false),
+        }
+    }
+
+    /// Returns the builder.
+    pub fn builder(&self) -> &Arc<BlockBuilder<S, C>> {
+        &self.builder
+    }
+
+    /// Returns the number of cached values.
+    pub fn len(&self) -> usize {
+        self.cache.len()
+    }
+
+    /// Returns if the cache is empty.
+    pub fn is_empty(&self) -> bool {
+        self.cache.is_empty()
+    }
+
+    /// Evicts a value that wasn't read since the last scan, clearing the
+    /// referenced flag of the values it passes.
+    fn evict(&self) {
+        let mut victim = None;
+        for entry in self.cache.iter() {
+            if victim.is_none() {
+                victim = Some(entry.key().clone());
+            }
+            if !entry.referenced.swap(false, Ordering::Relaxed) {
+                victim = Some(entry.key().clone());
+                break;
+            }
+        }
+        if let Some(cid) = victim {
+            self.cache.remove(&cid);
+        }
+    }
+}
+
+impl<S, C, T: Send + Sync + 'static> ConcurrentIpldCache<S, C, T> {
+    fn cache(&self, cid: Cid, value: T, bytes: usize) {
+        if self.size == 0 {
+            return;
+        }
+        let reservation = match &self.budget {
+            Some(budget) => match budget.try_acquire(bytes) {
+                Some(reservation) => {
+                    if !self.reclaimable.swap(true, Ordering::AcqRel) {
+                        let cache: Weak<dyn Reclaim> = Arc::downgrade(&self.cache) as _;
+                        budget.add_reclaimer(cache);
+                    }
+                    Some(reservation)
+                }
+                None => return,
+            },
+            None => None,
+        };
+        if !self.cache.contains_key(&cid) && self.cache.len() >= self.size {
+            self.evict();
+        }
+        let entry = Entry {
+            value,
+            referenced: AtomicBool::new(false),
+            reservation,
+        };
+        self.cache.insert(cid, entry);
+    }
+}
+
+#[async_trait]
+impl<S: ReadonlyStore + Send + Sync, C, T> ReadonlyCache<C, T> for ConcurrentIpldCache<S, C, T>
+where
+    C: Decoder + Clone + Send + Sync,
+    T: Decode<<C as Decoder>::Codec> + Clone + Send + Sync + 'static,
+{
+    async fn get(&self, cid: &Cid) -> Result<T> {
+        let hit = self.cache.get(cid).map(|entry| {
+            entry.referenced.store(true, Ordering::Relaxed);
+            entry.value.clone()
+        });
+        if let Some(value) = hit {
+            #[cfg(feature = "metrics")]
+            crate::metrics::cache_hit(None);
+            return Ok(value);
+        }
+        #[cfg(feature = "metrics")]
+        crate::metrics::cache_miss(None);
+        let data = self.builder.get_verified(cid).await?;
+        let bytes = data.len();
+        let value: T = self.builder.codec().decode_owned(cid, data)?;
+        self.cache(cid.clone(), value.clone(), bytes);
+        Ok(value)
+    }
+}
+
+#[async_trait]
+impl<S: Store + Send + Sync, C, T> Cache<C, T> for ConcurrentIpldCache<S, C, T>
+where
+    C: Decoder + Encoder + Clone + Send + Sync,
+    T: Decode<<C as Decoder>::Codec>
+        + Encode<<C as Encoder>::Codec>
+        + Clone
+        + Send
+        + Sync
+        + 'static,
+{
+    fn create_batch(&self) -> CacheBatch<C, T> {
+        CacheBatch::new(self.builder.codec().clone())
+    }
+
+    fn create_batch_with_capacity(&self, capacity: usize) -> CacheBatch<C, T> {
+        CacheBatch::with_capacity(self.builder.codec().clone(), capacity)
+    }
+
+    async fn insert_batch(&self, batch: CacheBatch<C, T>) -> Result<Cid> {
+        let (batch, values) = batch.into_parts();
+        let cid = self.builder.insert_batch(batch).await?;
+        #[cfg(feature = "metrics")]
+        crate::metrics::cache_insert(None, values.len());
+        for (cid, value, bytes) in values {
+            self.cache(cid, value, bytes);
+        }
+        Ok(cid)
+    }
+
+    async fn insert(&self, value: T) -> Result<Cid> {
+        let mut batch = self.create_batch_with_capacity(1);
+        batch.insert(value)?;
+        self.insert_batch(batch).await
+    }
+
+    async fn flush(&self) -> Result<()> {
+        self.builder.flush().await
+    }
+
+    async fn unpin(&self, cid: &Cid) -> Result<()> {
+        self.builder.unpin(cid).await
+    }
+
+    async fn unpin_many(&self, cids: &[Cid]) -> Result<()> {
+        self.builder.unpin_many(cids).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Codec;
+    use libipld::ipld;
+    use libipld::ipld::Ipld;
+    use libipld::mem::MemStore;
+
+    #[async_std::test]
+    async fn test_concurrent_cache() {
+        let cache = Arc::new(ConcurrentIpldCache::new(
+            MemStore::default(),
+            Codec::new(),
+            2,
+        ));
+        let a = cache.insert(ipld!(0)).await.unwrap();
+        let b = cache.insert(ipld!(1)).await.unwrap();
+        assert_eq!(cache.len(), 2);
+        // a was read, so b is evicted
+        assert_eq!(cache.get(&a).await.unwrap(), ipld!(0));
+        let c = cache.insert(ipld!(2)).await.unwrap();
+        assert_eq!(cache.len(), 2);
+        assert!(cache.cache.contains_key(&a));
+        assert!(cache.cache.contains_key(&c));
+
+        let tasks: Vec<_> = (0..8)
+            .map(|_| {
+                let cache = cache.clone();
+                let b = b.clone();
+                async_std::task::spawn(async move {
+                    let value: Ipld = cache.get(&b).await.unwrap();
+                    value
+                })
+            })
+            .collect();
+        for task in tasks {
+            assert_eq!(task.await, ipld!(1));
+        }
+    }
+}
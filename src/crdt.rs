@@ -0,0 +1,368 @@
+use crate::cache::{Cache, IpldCache, ReadonlyCache};
+use crate::codec::{Decoder, Encoder};
+use crate::error::Result;
+use libipld::cbor::{DagCborCodec, Error as CborError};
+use libipld::cid::Cid;
+use libipld::codec::{Decode, Encode};
+use libipld::ipld::Ipld;
+use libipld::store::Store;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{Read, Write};
+
+/// Conflict free replicated data type.
+pub trait Crdt {
+    /// Merges the state of another replica into this one.
+    fn merge(&mut self, other: &Self);
+}
+
+macro_rules! impl_dag_cbor {
+    ($ty:ty) => {
+        impl Encode<DagCborCodec> for $ty {
+            fn encode<W: Write>(&self, w: &mut W) -> core::result::Result<(), CborError> {
+                Encode::<DagCborCodec>::encode(&self.to_ipld(), w)
+            }
+        }
+
+        impl Decode<DagCborCodec> for $ty {
+            fn decode<R: Read>(r: &mut R) -> core::result::Result<Self, CborError> {
+                let ipld: Ipld = Decode::<DagCborCodec>::decode(r)?;
+                Self::from_ipld(ipld).ok_or(CborError::UnexpectedKey)
+            }
+        }
+    };
+}
+
+fn to_u64(ipld: &Ipld) -> Option<u64> {
+    match ipld {
+        Ipld::Integer(n) if *n >= 0 && *n <= u64::MAX as i128 => Some(*n as u64),
+        _ => None,
+    }
+}
+
+fn to_string(ipld: &Ipld) -> Option<String> {
+    match ipld {
+        Ipld::String(s) => Some(s.clone()),
+        _ => None,
+    }
+}
+
+fn counts_to_ipld(counts: &BTreeMap<String, u64>) -> Ipld {
+    Ipld::Map(
+        counts
+            .iter()
+            .map(|(k, v)| (k.clone(), Ipld::Integer(*v as i128)))
+            .collect(),
+    )
+}
+
+fn counts_from_ipld(ipld: &Ipld) -> Option<BTreeMap<String, u64>> {
+    match ipld {
+        Ipld::Map(map) => map
+            .iter()
+            .map(|(k, v)| Some((k.clone(), to_u64(v)?)))
+            .collect(),
+        _ => None,
+    }
+}
+
+fn strings_to_ipld(strings: &BTreeSet<String>) -> Ipld {
+    Ipld::List(strings.iter().cloned().map(Ipld::String).collect())
+}
+
+fn strings_from_ipld(ipld: &Ipld) -> Option<BTreeSet<String>> {
+    match ipld {
+        Ipld::List(list) => list.iter().map(to_string).collect(),
+        _ => None,
+    }
+}
+
+/// Grow only counter.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct GCounter {
+    counts: BTreeMap<String, u64>,
+}
+
+impl GCounter {
+    /// Creates a new counter.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Increments the count of `replica` by `n`.
+    pub fn increment(&mut self, replica: &str, n: u64) {
+        *self.counts.entry(replica.to_string()).or_default() += n;
+    }
+
+    /// Returns the value of the counter.
+    pub fn value(&self) -> u64 {
+        self.counts.values().sum()
+    }
+
+    fn to_ipld(&self) -> Ipld {
+        counts_to_ipld(&self.counts)
+    }
+
+    fn from_ipld(ipld: Ipld) -> Option<Self> {
+        Some(Self {
+            counts: counts_from_ipld(&ipld)?,
+        })
+    }
+}
+
+impl Crdt for GCounter {
+    fn merge(&mut self, other: &Self) {
+        for (replica, count) in &other.counts {
+            let entry = self.counts.entry(replica.clone()).or_default();
+            *entry = (*entry).max(*count);
+        }
+    }
+}
+
+impl_dag_cbor!(GCounter);
+
+/// Last writer wins register.
+///
+/// Concurrent writes are ordered by timestamp and then by replica.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LwwRegister {
+    value: Ipld,
+    timestamp: u64,
+    replica: String,
+}
+
+impl LwwRegister {
+    /// Creates a new register.
+    pub fn new(value: Ipld, timestamp: u64, replica: &str) -> Self {
+        Self {
+            value,
+            timestamp,
+            replica: replica.to_string(),
+        }
+    }
+
+    /// Sets the value if `timestamp` is newer than the current write.
+    pub fn set(&mut self, value: Ipld, timestamp: u64, replica: &str) {
+        let other = Self::new(value, timestamp, replica);
+        self.merge(&other);
+    }
+
+    /// Returns the value of the register.
+    pub fn value(&self) -> &Ipld {
+        &self.value
+    }
+
+    /// Returns the timestamp of the last write.
+    pub fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    fn to_ipld(&self) -> Ipld {
+        let mut map = BTreeMap::new();
+        map.insert("value".to_string(), self.value.clone());
+        map.insert(
+            "timestamp".to_string(),
+            Ipld::Integer(self.timestamp as i128),
+        );
+        map.insert("replica".to_string(), Ipld::String(self.replica.clone()));
+        Ipld::Map(map)
+    }
+
+    fn from_ipld(ipld: Ipld) -> Option<Self> {
+        match ipld {
+            Ipld::Map(map) => Some(Self {
+                value: map.get("value")?.clone(),
+                timestamp: to_u64(map.get("timestamp")?)?,
+                replica: to_string(map.get("replica")?)?,
+            }),
+            _ => None,
+        }
+    }
+}
+
+impl Crdt for LwwRegister {
+    fn merge(&mut self, other: &Self) {
+        if (other.timestamp, &other.replica) > (self.timestamp, &self.replica) {
+            *self = other.clone();
+        }
+    }
+}
+
+impl_dag_cbor!(LwwRegister);
+
+/// Observed remove set.
+///
+/// Every add is tagged uniquely, a remove only removes the adds observed by
+/// the replica, so a concurrent add wins over a remove.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct OrSet {
+    elements: BTreeMap<String, BTreeSet<String>>,
+    tombstones: BTreeSet<String>,
+    clock: BTreeMap<String, u64>,
+}
+
+impl OrSet {
+    /// Creates a new set.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an element on `replica`.
+    pub fn add(&mut self, element: &str, replica: &str) {
+        let clock = self.clock.entry(replica.to_string()).or_default();
+        *clock += 1;
+        let tag = format!("{}:{}", replica, clock);
+        self.elements
+            .entry(element.to_string())
+            .or_default()
+            .insert(tag);
+    }
+
+    /// Removes an element.
+    pub fn remove(&mut self, element: &str) {
+        if let Some(tags) = self.elements.remove(element) {
+            self.tombstones.extend(tags);
+        }
+    }
+
+    /// Returns if the set contains an element.
+    pub fn contains(&self, element: &str) -> bool {
+        self.elements.contains_key(element)
+    }
+
+    /// Returns an iterator over the elements of the set.
+    pub fn iter(&self) -> impl Iterator<Item = &str> {
+        self.elements.keys().map(|element| element.as_str())
+    }
+
+    fn to_ipld(&self) -> Ipld {
+        let mut map = BTreeMap::new();
+        map.insert(
+            "elements".to_string(),
+            Ipld::Map(
+                self.elements
+                    .iter()
+                    .map(|(k, v)| (k.clone(), strings_to_ipld(v)))
+                    .collect(),
+            ),
+        );
+        map.insert("tombstones".to_string(), strings_to_ipld(&self.tombstones));
+        map.insert("clock".to_string(), counts_to_ipld(&self.clock));
+        Ipld::Map(map)
+    }
+
+    fn from_ipld(ipld: Ipld) -> Option<Self> {
+        let map = match ipld {
+            Ipld::Map(map) => map,
+            _ => return None,
+        };
+        let elements = match map.get("elements")? {
+            Ipld::Map(elements) => elements
+                .iter()
+                .map(|(k, v)| Some((k.clone(), strings_from_ipld(v)?)))
+                .collect::<Option<_>>()?,
+            _ => return None,
+        };
+        Some(Self {
+            elements,
+            tombstones: strings_from_ipld(map.get("tombstones")?)?,
+            clock: counts_from_ipld(map.get("clock")?)?,
+        })
+    }
+}
+
+impl Crdt for OrSet {
+    fn merge(&mut self, other: &Self) {
+        self.tombstones.extend(other.tombstones.iter().cloned());
+        for (element, tags) in &other.elements {
+            self.elements
+                .entry(element.clone())
+                .or_default()
+                .extend(tags.iter().cloned());
+        }
+        let tombstones = &self.tombstones;
+        for tags in self.elements.values_mut() {
+            tags.retain(|tag| !tombstones.contains(tag));
+        }
+        self.elements.retain(|_, tags| !tags.is_empty());
+        for (replica, clock) in &other.clock {
+            let entry = self.clock.entry(replica.clone()).or_default();
+            *entry = (*entry).max(*clock);
+        }
+    }
+}
+
+impl_dag_cbor!(OrSet);
+
+impl<S, C, T> IpldCache<S, C, T>
+where
+    S: Store + Send + Sync,
+    C: Decoder + Encoder + Clone + Send + Sync,
+    T: Crdt
+        + Decode<<C as Decoder>::Codec>
+        + Encode<<C as Encoder>::Codec>
+        + Clone
+        + Send
+        + Sync
+        + 'static,
+{
+    /// Merges the replicas `a` and `b`, returning the cid of the merged
+    /// state.
+    pub async fn merge(&self, a: &Cid, b: &Cid) -> Result<Cid> {
+        let mut value = self.get(a).await?;
+        value.merge(&self.get(b).await?);
+        self.insert(value).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Codec;
+    use libipld::ipld;
+    use libipld::mem::MemStore;
+
+    #[async_std::test]
+    async fn test_crdts() {
+        let store = MemStore::default();
+        let counters = IpldCache::new(store.clone(), Codec::new(), 16);
+        let mut a = GCounter::new();
+        a.increment("a", 2);
+        let mut b = a.clone();
+        a.increment("a", 1);
+        b.increment("b", 5);
+        let (a, b) = (
+            counters.insert(a).await.unwrap(),
+            counters.insert(b).await.unwrap(),
+        );
+        let merged = counters.merge(&a, &b).await.unwrap();
+        let counter: GCounter = counters.get(&merged).await.unwrap();
+        assert_eq!(counter.value(), 8);
+
+        let registers = IpldCache::new(store.clone(), Codec::new(), 16);
+        let a = LwwRegister::new(ipld!("a"), 2, "a");
+        let mut b = a.clone();
+        b.set(ipld!("b"), 1, "b");
+        assert_eq!(b.value(), &ipld!("a"));
+        b.set(ipld!("b"), 2, "b");
+        let (a, b) = (
+            registers.insert(a).await.unwrap(),
+            registers.insert(b).await.unwrap(),
+        );
+        let merged = registers.merge(&a, &b).await.unwrap();
+        let register: LwwRegister = registers.get(&merged).await.unwrap();
+        assert_eq!(register.value(), &ipld!("b"));
+
+        let sets = IpldCache::new(store, Codec::new(), 16);
+        let mut a = OrSet::new();
+        a.add("x", "a");
+        a.add("y", "a");
+        let mut b = a.clone();
+        a.remove("x");
+        b.add("x", "b");
+        b.remove("y");
+        let (a, b) = (sets.insert(a).await.unwrap(), sets.insert(b).await.unwrap());
+        let merged = sets.merge(&a, &b).await.unwrap();
+        let set: OrSet = sets.get(&merged).await.unwrap();
+        assert_eq!(set.iter().collect::<Vec<_>>(), vec!["x"]);
+    }
+}
@@ -1,5 +1,6 @@
+use crate::pool::BufferPool;
 use core::convert::TryFrom;
-use core::ops::Deref;
+use core::ops::{Deref, Range};
 use libipld::cid::Codec;
 use rand::RngCore;
 use secrecy::{ExposeSecret, Secret};
@@ -9,6 +10,12 @@ use zeroize::Zeroize;
 
 const NONCE_LEN: usize = 24;
 const TAG_LEN: usize = 16;
+/// Marks messages with a plaintext header.
+const HEADER_MAGIC: &[u8; 8] = b"\0ipldhdr";
+/// Marks messages with a plaintext codec.
+const CODEC_MAGIC: &[u8; 8] = b"\0ipldcdc";
+/// Maximum length of a plaintext header.
+pub const MAX_HEADER_LEN: usize = 1024;
 
 /// A secret key.
 ///
@@ -19,7 +26,7 @@ impl Deref for Key {
     type Target = [u8];
 
     fn deref(&self) -> &Self::Target {
-        &self.0.expose_secret()
+        self.0.expose_secret()
     }
 }
 
@@ -37,6 +44,28 @@ impl From<&mut [u8]> for Key {
     }
 }
 
+impl Key {
+    /// Generates a random 256 bit key.
+    pub fn generate() -> Self {
+        let mut key = vec![0; 32];
+        rand::thread_rng().fill_bytes(&mut key);
+        Self::from(key)
+    }
+
+    /// Encrypts the key with the key of a recipient.
+    pub fn wrap(&self, recipient: &Key) -> Result<Box<[u8]>, Error> {
+        encrypt(recipient, Codec::Raw, self)
+    }
+
+    /// Decrypts a key wrapped for a recipient.
+    pub fn unwrap(wrapped: &[u8], recipient: &Key) -> Result<Self, Error> {
+        let mut buf = wrapped.to_vec();
+        let key = Self::from(decrypt(recipient, &mut buf)?.1.to_vec());
+        buf.zeroize();
+        Ok(key)
+    }
+}
+
 /// Crypto error.
 #[derive(Debug, Error)]
 pub enum Error {
@@ -49,13 +78,49 @@ pub enum Error {
     /// Mac integrity check failed.
     #[error("mac integrity check failed.")]
     Integrity,
+    /// Header exceeds `MAX_HEADER_LEN`.
+    #[error("header needs to be at most 1024 bytes.")]
+    HeaderTooLarge,
     /// Failed to decode data.
     #[error("failed to decode data: {0}.")]
     Codec(Box<dyn std::error::Error + Send>),
 }
 
 /// Encrypts and MACs a plaintext message with a key of any size greater than 128 bits (16 bytes).
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(skip(key, data), fields(bytes = data.len()))
+)]
 pub fn encrypt(key: &Key, codec: Codec, data: &[u8]) -> Result<Box<[u8]>, Error> {
+    seal(key, codec, None, data, false, false)
+}
+
+/// Encrypts and MACs a plaintext message like `encrypt`, deriving the nonce from the key
+/// and the plaintext instead of the rng.
+///
+/// A failing rng can't cause nonce reuse, but encrypting the same plaintext with the same key
+/// always produces the same cipher text. The result is decrypted with `decrypt`.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(skip(key, data), fields(bytes = data.len()))
+)]
+pub fn encrypt_siv(key: &Key, codec: Codec, data: &[u8]) -> Result<Box<[u8]>, Error> {
+    seal(key, codec, None, data, true, false)
+}
+
+/// Encrypts and MACs a plaintext message, prefixed with an optional plaintext `header`
+/// that is covered by the MAC but can be read without the key using `decode_header`.
+///
+/// When `expose_codec` is set, the codec is also written in plaintext before the header
+/// and can be read with `decode_codec`.
+pub(crate) fn seal(
+    key: &Key,
+    codec: Codec,
+    header: Option<&[u8]>,
+    data: &[u8],
+    siv: bool,
+    expose_codec: bool,
+) -> Result<Box<[u8]>, Error> {
     if key.len() < 16 {
         return Err(Error::KeyTooShort);
     }
@@ -68,15 +133,42 @@ pub fn encrypt(key: &Key, codec: Codec, data: &[u8]) -> Result<Box<[u8]>, Error>
     // Absorb the key
     s.ad(key.deref(), false);
 
+    // Write and absorb the codec and header.
+    let mut prefix = BufferPool::global().get_secret();
+    if expose_codec {
+        prefix.extend_from_slice(CODEC_MAGIC);
+        prefix.extend_from_slice(codec);
+        s.ad(codec, false);
+    }
+    if let Some(header) = header {
+        if header.len() > MAX_HEADER_LEN {
+            return Err(Error::HeaderTooLarge);
+        }
+        let mut len = unsigned_varint::encode::usize_buffer();
+        prefix.extend_from_slice(HEADER_MAGIC);
+        prefix.extend_from_slice(unsigned_varint::encode::usize(header.len(), &mut len));
+        prefix.extend_from_slice(header);
+        s.ad(header, false);
+    }
+
     // Create buffer.
-    let mut buf = Vec::with_capacity(NONCE_LEN + codec.len() + data.len() + TAG_LEN);
-    buf.resize(buf.capacity(), 0);
-    //unsafe { buf.set_len(buf.capacity()) };
+    let mut out = vec![0; prefix.len() + NONCE_LEN + codec.len() + data.len() + TAG_LEN];
+    out[..prefix.len()].copy_from_slice(&prefix);
+    let buf = &mut out[prefix.len()..];
 
     // Generate 192-bit nonce and absorb it
     let nonce = &mut buf[..NONCE_LEN];
-    let mut rng = rand::thread_rng();
-    rng.fill_bytes(nonce);
+    if siv {
+        let mut s = Strobe::new(b"ipld-block-builder-siv", SecParam::B128);
+        s.key(key.deref(), false);
+        s.ad(header.unwrap_or_default(), false);
+        s.ad(codec, false);
+        s.ad(data, false);
+        s.prf(nonce, false);
+    } else {
+        let mut rng = rand::thread_rng();
+        rng.fill_bytes(nonce);
+    }
     s.ad(nonce, false);
 
     // Copy data to buffer and encrypt in place.
@@ -90,16 +182,25 @@ pub fn encrypt(key: &Key, codec: Codec, data: &[u8]) -> Result<Box<[u8]>, Error>
     let mac = &mut buf[(buf_len - TAG_LEN)..];
     s.send_mac(mac, false);
 
-    Ok(buf.into_boxed_slice())
+    Ok(out.into_boxed_slice())
 }
 
-/// Decrypts and checks the MAC of an encrypted message, given a key of any size greater
-/// than 128 bits (16 bytes).
-pub fn decrypt(key: &Key, mut buf: Box<[u8]>) -> Result<(Codec, Box<[u8]>), Error> {
+/// Decrypts and checks the MAC of an encrypted message in place, given a key of any size
+/// greater than 128 bits (16 bytes).
+///
+/// Returns the codec and the plaintext, which borrows from `buf`.
+#[cfg_attr(
+    feature = "tracing",
+    tracing::instrument(skip(key, buf), fields(bytes = buf.len()))
+)]
+pub fn decrypt<'a>(key: &Key, buf: &'a mut [u8]) -> Result<(Codec, &'a [u8]), Error> {
     if key.len() < 16 {
         return Err(Error::KeyTooShort);
     }
 
+    let (codec, header, offset) = prefix(buf);
+    let (prefix, buf) = buf.split_at_mut(offset);
+
     if buf.len() < TAG_LEN + NONCE_LEN {
         return Err(Error::CipherTooShort);
     }
@@ -109,21 +210,67 @@ pub fn decrypt(key: &Key, mut buf: Box<[u8]>) -> Result<(Codec, Box<[u8]>), Erro
 
     // Absorb the key
     s.ad(key.deref(), false);
+    if let Some(codec) = codec {
+        s.ad(&prefix[codec], false);
+    }
+    if let Some(header) = header {
+        s.ad(&prefix[header], false);
+    }
     s.ad(nonce, false);
 
     let buf_len = buf.len();
-    let data = &mut buf[NONCE_LEN..(buf_len - TAG_LEN)];
+    let (data, mac) = buf[NONCE_LEN..].split_at_mut(buf_len - NONCE_LEN - TAG_LEN);
     s.recv_enc(data, false);
+    s.recv_mac(mac, false).map_err(|_| Error::Integrity)?;
 
-    let (raw_codec, data) =
+    let (raw_codec, rest) =
         unsigned_varint::decode::u64(data).map_err(|e| Error::Codec(Box::new(e)))?;
     let codec = Codec::try_from(raw_codec).map_err(|e| Error::Codec(Box::new(e)))?;
-    let data = data.to_vec().into_boxed_slice();
 
-    let mac = &mut buf[(buf_len - TAG_LEN)..];
-    s.recv_mac(mac, false).map_err(|_| Error::Integrity)?;
+    Ok((codec, rest))
+}
+
+/// Returns the positions of the plaintext codec and header of a message and where the
+/// encrypted part starts.
+fn prefix(buf: &[u8]) -> (Option<Range<usize>>, Option<Range<usize>>, usize) {
+    let mut offset = 0;
+    let codec = buf.strip_prefix(&CODEC_MAGIC[..]).and_then(|rest| {
+        let (_, tail) = unsigned_varint::decode::u64(rest).ok()?;
+        let start = CODEC_MAGIC.len();
+        offset = buf.len() - tail.len();
+        Some(start..offset)
+    });
+    let header = buf[offset..]
+        .strip_prefix(&HEADER_MAGIC[..])
+        .and_then(|rest| {
+            let (len, tail) = unsigned_varint::decode::usize(rest).ok()?;
+            if len > MAX_HEADER_LEN || len > tail.len() {
+                return None;
+            }
+            let start = buf.len() - tail.len();
+            offset = start + len;
+            Some(start..offset)
+        });
+    (codec, header, offset)
+}
+
+/// Returns the plaintext header of an encrypted message without decrypting it.
+///
+/// The header is only authenticated when the message is decrypted, without the key it
+/// can't be told apart from a forged one. Messages without a header return `None`. A
+/// message without a header has a 2^-64 chance of being mistaken for one with a header.
+pub fn decode_header(buf: &[u8]) -> Option<&[u8]> {
+    prefix(buf).1.map(|header| &buf[header])
+}
 
-    Ok((codec, data))
+/// Returns the codec of an encrypted message without decrypting it, if it was exposed
+/// when encrypting.
+///
+/// Like the header, the codec is only authenticated when the message is decrypted.
+pub fn decode_codec(buf: &[u8]) -> Option<Codec> {
+    let codec = &buf[prefix(buf).0?];
+    let (codec, _) = unsigned_varint::decode::u64(codec).ok()?;
+    Codec::try_from(codec).ok()
 }
 
 #[cfg(test)]
@@ -151,10 +298,70 @@ mod tests {
         ];
 
         for pt in plaintexts.iter() {
-            let ct = encrypt(&key, Codec::Raw, pt).unwrap();
-            let (codec, pt2) = decrypt(&key, ct).unwrap();
-            assert_eq!(pt, &pt2.deref());
+            let mut ct = encrypt(&key, Codec::Raw, pt).unwrap();
+            let (codec, pt2) = decrypt(&key, &mut ct).unwrap();
+            assert_eq!(pt, &pt2);
             assert_eq!(codec, Codec::Raw);
         }
     }
+
+    #[test]
+    fn test_encryption_siv() {
+        let key = Key::from(vec![7; 32]);
+        let other = Key::from(vec![8; 32]);
+        let ct = encrypt_siv(&key, Codec::Raw, b"hello").unwrap();
+        assert_eq!(ct, encrypt_siv(&key, Codec::Raw, b"hello").unwrap());
+        assert_ne!(ct, encrypt_siv(&key, Codec::Raw, b"hellp").unwrap());
+        assert_ne!(ct, encrypt_siv(&key, Codec::DagCBOR, b"hello").unwrap());
+        assert_ne!(ct, encrypt_siv(&other, Codec::Raw, b"hello").unwrap());
+
+        let mut ct = ct;
+        let (codec, pt) = decrypt(&key, &mut ct).unwrap();
+        assert_eq!(pt, b"hello");
+        assert_eq!(codec, Codec::Raw);
+    }
+
+    #[test]
+    fn test_encryption_header() {
+        let key = Key::from(vec![7; 32]);
+        let mut ct = seal(&key, Codec::Raw, Some(b"schema"), b"hello", false, false).unwrap();
+        assert_eq!(decode_header(&ct), Some(&b"schema"[..]));
+        let mut buf = ct.clone();
+        let (codec, pt) = decrypt(&key, &mut buf).unwrap();
+        assert_eq!((codec, pt), (Codec::Raw, &b"hello"[..]));
+
+        // the header is covered by the mac
+        ct[HEADER_MAGIC.len() + 1] ^= 1;
+        assert!(matches!(decrypt(&key, &mut ct), Err(Error::Integrity)));
+
+        let ct = encrypt(&key, Codec::Raw, b"hello").unwrap();
+        assert_eq!(decode_header(&ct), None);
+        let header = vec![0; MAX_HEADER_LEN + 1];
+        let res = seal(&key, Codec::Raw, Some(&header), b"hello", false, false);
+        assert!(matches!(res, Err(Error::HeaderTooLarge)));
+    }
+
+    #[test]
+    fn test_encryption_codec() {
+        let key = Key::from(vec![7; 32]);
+        let mut ct = seal(&key, Codec::DagCBOR, Some(b"schema"), b"\xa0", false, true).unwrap();
+        assert_eq!(decode_codec(&ct), Some(Codec::DagCBOR));
+        assert_eq!(decode_header(&ct), Some(&b"schema"[..]));
+        let mut buf = ct.clone();
+        let (codec, pt) = decrypt(&key, &mut buf).unwrap();
+        assert_eq!((codec, pt), (Codec::DagCBOR, &b"\xa0"[..]));
+
+        let ct2 = seal(&key, Codec::Raw, None, b"hello", false, true).unwrap();
+        assert_eq!(decode_codec(&ct2), Some(Codec::Raw));
+        assert_eq!(decode_header(&ct2), None);
+        assert_eq!(
+            decode_codec(&encrypt(&key, Codec::Raw, b"hello").unwrap()),
+            None
+        );
+
+        // the codec is covered by the mac
+        ct[CODEC_MAGIC.len()] = 0x55;
+        assert_eq!(decode_codec(&ct), Some(Codec::Raw));
+        assert!(matches!(decrypt(&key, &mut ct), Err(Error::Integrity)));
+    }
 }
@@ -0,0 +1,199 @@
+use crate::builder::BlockBuilder;
+use crate::codec::IpldDecoder;
+use crate::error::Result;
+use libipld::cid::Cid;
+use libipld::ipld::Ipld;
+use libipld::store::ReadonlyStore;
+use std::collections::{HashMap, HashSet};
+
+/// Subtree referenced more than once.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DuplicatedSubtree {
+    /// Root of the subtree.
+    pub cid: Cid,
+    /// Number of times the subtree is referenced.
+    pub occurrences: u64,
+    /// Size of the subtree if it was stored without sharing.
+    pub bytes: u64,
+}
+
+impl DuplicatedSubtree {
+    /// Bytes saved by storing the subtree once.
+    pub fn bytes_saved(&self) -> u64 {
+        self.bytes.saturating_mul(self.occurrences - 1)
+    }
+}
+
+/// Structural sharing statistics of a set of dags.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct DedupReport {
+    /// Number of unique blocks.
+    pub blocks: usize,
+    /// Number of blocks if every reference was stored separately.
+    pub references: u64,
+    /// Number of blocks referenced more than once.
+    pub shared_blocks: usize,
+    /// Size of the unique blocks.
+    pub unique_bytes: u64,
+    /// Size of the blocks if every reference was stored separately.
+    pub total_bytes: u64,
+    /// Duplicated subtrees ordered by bytes saved.
+    pub top: Vec<DuplicatedSubtree>,
+}
+
+impl DedupReport {
+    /// Bytes saved by structural sharing.
+    pub fn bytes_saved(&self) -> u64 {
+        self.total_bytes - self.unique_bytes
+    }
+}
+
+struct Node {
+    cid: Cid,
+    size: u64,
+    links: Vec<Cid>,
+}
+
+fn links(ipld: &Ipld) -> Vec<Cid> {
+    ipld.iter()
+        .filter_map(|ipld| match ipld {
+            Ipld::Link(cid) => Some(cid.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+impl<S: ReadonlyStore, C: IpldDecoder> BlockBuilder<S, C> {
+    async fn node(&self, cid: &Cid) -> Result<Node> {
+        let data = self.get_verified(cid).await?;
+        let ipld = self.codec().decode_ipld(cid, &data)?;
+        Ok(Node {
+            cid: cid.clone(),
+            size: data.len() as u64,
+            links: links(&ipld),
+        })
+    }
+
+    /// Walks the dags of `roots` and reports how much structural sharing
+    /// saves, including the `top` duplicated subtrees with the most bytes
+    /// saved.
+    pub async fn dedup_report(&self, roots: &[Cid], top: usize) -> Result<DedupReport> {
+        // blocks in post-order, so links come before the blocks linking them
+        let mut nodes: Vec<Node> = vec![];
+        let mut visited = HashSet::new();
+        for root in roots {
+            if !visited.insert(root.clone()) {
+                continue;
+            }
+            let mut stack = vec![(self.node(root).await?, 0)];
+            while let Some((node, next)) = stack.last_mut() {
+                if let Some(link) = node.links.get(*next).cloned() {
+                    *next += 1;
+                    if visited.insert(link.clone()) {
+                        let node = self.node(&link).await?;
+                        stack.push((node, 0));
+                    }
+                } else {
+                    nodes.push(stack.pop().unwrap().0);
+                }
+            }
+        }
+
+        let mut occurrences: HashMap<&Cid, u64> = HashMap::new();
+        for root in roots {
+            *occurrences.entry(root).or_default() += 1;
+        }
+        for node in nodes.iter().rev() {
+            let n = occurrences.get(&node.cid).copied().unwrap_or_default();
+            for link in &node.links {
+                let entry = occurrences.entry(link).or_default();
+                *entry = entry.saturating_add(n);
+            }
+        }
+
+        let mut bytes: HashMap<&Cid, u64> = HashMap::new();
+        for node in &nodes {
+            let size = node
+                .links
+                .iter()
+                .map(|link| bytes[link])
+                .fold(node.size, u64::saturating_add);
+            bytes.insert(&node.cid, size);
+        }
+
+        let mut report = DedupReport {
+            blocks: nodes.len(),
+            ..Default::default()
+        };
+        let mut duplicated = vec![];
+        for node in &nodes {
+            let n = occurrences[&node.cid];
+            report.references = report.references.saturating_add(n);
+            report.unique_bytes += node.size;
+            report.total_bytes = report
+                .total_bytes
+                .saturating_add(node.size.saturating_mul(n));
+            if n > 1 {
+                report.shared_blocks += 1;
+                duplicated.push(DuplicatedSubtree {
+                    cid: node.cid.clone(),
+                    occurrences: n,
+                    bytes: bytes[&node.cid],
+                });
+            }
+        }
+        duplicated.sort_by_key(|subtree| std::cmp::Reverse(subtree.bytes_saved()));
+        duplicated.truncate(top);
+        report.top = duplicated;
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Codec;
+    use libipld::ipld;
+    use libipld::mem::MemStore;
+
+    #[async_std::test]
+    async fn test_dedup_report() {
+        let builder = BlockBuilder::new(MemStore::default(), Codec::new());
+        let leaf = builder.insert(&ipld!({"leaf": true})).await.unwrap();
+        let node = builder
+            .insert(&ipld!([leaf.clone(), leaf.clone()]))
+            .await
+            .unwrap();
+        let a = builder.insert(&ipld!({"a": node.clone()})).await.unwrap();
+        let b = builder.insert(&ipld!({"b": node.clone()})).await.unwrap();
+
+        let size = |cid: Cid| {
+            let builder = &builder;
+            async move { builder.get_verified(&cid).await.unwrap().len() as u64 }
+        };
+        let (leaf_size, node_size) = (size(leaf.clone()).await, size(node.clone()).await);
+        let (a_size, b_size) = (size(a.clone()).await, size(b.clone()).await);
+
+        let report = builder
+            .dedup_report(&[a.clone(), b.clone()], 1)
+            .await
+            .unwrap();
+        assert_eq!(report.blocks, 4);
+        assert_eq!(report.references, 8);
+        assert_eq!(report.shared_blocks, 2);
+        assert_eq!(report.unique_bytes, leaf_size + node_size + a_size + b_size);
+        assert_eq!(
+            report.total_bytes,
+            4 * leaf_size + 2 * node_size + a_size + b_size
+        );
+        assert_eq!(report.bytes_saved(), 3 * leaf_size + node_size);
+        assert_eq!(
+            report.top,
+            vec![DuplicatedSubtree {
+                cid: node,
+                occurrences: 2,
+                bytes: node_size + 2 * leaf_size,
+            }]
+        );
+    }
+}
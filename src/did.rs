@@ -0,0 +1,149 @@
+use crate::announce::{Signer, Verifier};
+use crate::builder::BlockBuilder;
+use crate::error::{Error, Result};
+use crate::signed_head::SignedHead;
+use core::convert::TryFrom;
+use core::fmt;
+use core::str::FromStr;
+use ed25519_dalek::{Signature, SigningKey, VerifyingKey};
+use libipld::cid::Cid;
+use libipld::store::{AliasStore, Store};
+use multibase::Base;
+
+/// Multicodec prefix of an ed25519 public key.
+const ED25519_PUB: [u8; 2] = [0xed, 0x01];
+
+fn invalid(msg: impl Into<String>) -> Error {
+    Error::InvalidDid(msg.into())
+}
+
+/// Ed25519 public key identified by a `did:key` identifier.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct DidKey(VerifyingKey);
+
+impl DidKey {
+    /// Creates a did for a public key.
+    pub fn new(public_key: VerifyingKey) -> Self {
+        Self(public_key)
+    }
+
+    /// Returns the public key.
+    pub fn public_key(&self) -> &VerifyingKey {
+        &self.0
+    }
+}
+
+impl From<VerifyingKey> for DidKey {
+    fn from(public_key: VerifyingKey) -> Self {
+        Self(public_key)
+    }
+}
+
+impl From<&SigningKey> for DidKey {
+    fn from(key: &SigningKey) -> Self {
+        Self(key.verifying_key())
+    }
+}
+
+impl FromStr for DidKey {
+    type Err = Error;
+
+    fn from_str(did: &str) -> Result<Self> {
+        let key = did
+            .strip_prefix("did:key:")
+            .ok_or_else(|| invalid("not a did:key"))?;
+        let (base, bytes) = multibase::decode(key).map_err(|err| invalid(err.to_string()))?;
+        if base != Base::Base58Btc {
+            return Err(invalid("expected base58btc"));
+        }
+        let key = bytes
+            .strip_prefix(&ED25519_PUB[..])
+            .ok_or_else(|| invalid("unsupported key type"))?;
+        let key = <[u8; 32]>::try_from(key).map_err(|_| invalid("invalid key length"))?;
+        let key = VerifyingKey::from_bytes(&key).map_err(|_| invalid("invalid ed25519 key"))?;
+        Ok(Self(key))
+    }
+}
+
+impl fmt::Display for DidKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let bytes = [&ED25519_PUB[..], self.0.as_bytes()].concat();
+        write!(f, "did:key:{}", multibase::encode(Base::Base58Btc, bytes))
+    }
+}
+
+impl Signer for SigningKey {
+    fn sign(&self, msg: &[u8]) -> Vec<u8> {
+        ed25519_dalek::Signer::sign(self, msg).to_bytes().to_vec()
+    }
+}
+
+impl Verifier for VerifyingKey {
+    fn verify(&self, msg: &[u8], signature: &[u8]) -> bool {
+        match Signature::from_slice(signature) {
+            Ok(signature) => self.verify_strict(msg, &signature).is_ok(),
+            Err(_) => false,
+        }
+    }
+}
+
+impl Verifier for DidKey {
+    fn verify(&self, msg: &[u8], signature: &[u8]) -> bool {
+        Verifier::verify(&self.0, msg, signature)
+    }
+}
+
+impl SignedHead {
+    /// Returns the did of the key that signed the head.
+    pub fn did(&self) -> DidKey {
+        DidKey(self.public_key)
+    }
+}
+
+impl<S: Store + AliasStore, C> BlockBuilder<S, C> {
+    /// Returns the cid the head of a `did:key` points to after verifying its
+    /// signature and validity.
+    pub async fn resolve_did(&self, did: &str) -> Result<Option<Cid>> {
+        let did: DidKey = did.parse()?;
+        self.resolve_verified(did.public_key()).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Codec;
+    use libipld::ipld;
+    use libipld::mem::MemStore;
+
+    #[test]
+    fn test_did_key() {
+        // test vector from the did:key spec
+        let did = "did:key:z6MkiTBz1ymuepAQ4HEHYSF1H8quG5GLVVQR3djdX3mDooWp";
+        let key: DidKey = did.parse().unwrap();
+        assert_eq!(key.to_string(), did);
+        assert!("did:key:zQ3shokFTS3brHcDQrn82RUDfCZESWL1ZdCEJwekUDPQiYBme"
+            .parse::<DidKey>()
+            .is_err());
+        assert!("did:web:example.com".parse::<DidKey>().is_err());
+    }
+
+    #[async_std::test]
+    async fn test_did_heads() {
+        let key = SigningKey::from_bytes(&[2; 32]);
+        let did = DidKey::from(&key);
+        let builder = BlockBuilder::new(MemStore::default(), Codec::new());
+        let cid = builder.insert(&ipld!(1)).await.unwrap();
+        let head = builder.publish(&key, &cid).await.unwrap();
+        assert_eq!(head.did(), did);
+        let resolved = builder.resolve_did(&did.to_string()).await.unwrap();
+        assert_eq!(resolved, Some(cid.clone()));
+
+        let msg = builder.announce(b"head", &cid, &key).await.unwrap();
+        let other = BlockBuilder::new(MemStore::default(), Codec::new());
+        assert!(other.apply_head(&msg, &did).await.unwrap());
+        let stranger = DidKey::from(&SigningKey::from_bytes(&[3; 32]));
+        let res = other.apply_head(&msg, &stranger).await;
+        assert!(matches!(res, Err(Error::InvalidAnnouncement(_))));
+    }
+}
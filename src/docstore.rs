@@ -0,0 +1,116 @@
+use crate::cache::IpldCache;
+use crate::codec::{Decoder, Encoder, IpldDecoder};
+use crate::collections::BTree;
+use crate::error::Result;
+use crate::root::Root;
+use futures::stream::{Stream, TryStreamExt};
+use libipld::cid::Cid;
+use libipld::codec::{Decode, Encode};
+use libipld::ipld::Ipld;
+use libipld::store::{AliasStore, Store};
+
+/// Collection of documents addressed by id.
+///
+/// Every document is stored in its own block, read through a cache. The ids
+/// are kept in a `BTree` mapping them to links to the documents, whose root
+/// is referenced by the alias `btree/<name>`. Every mutation writes the
+/// document and the changed path of the tree in a single batch, so the
+/// documents stay pinned as long as the tree links to them.
+pub struct DocStore<S, C, T> {
+    cache: IpldCache<S, C, T>,
+    ids: BTree<S, C, String, Root<T>>,
+}
+
+impl<S: Clone, C: Clone, T> DocStore<S, C, T> {
+    /// Creates a document store named `name` caching `size` documents.
+    pub fn new(store: S, codec: C, name: &str, size: usize) -> Self {
+        Self {
+            cache: IpldCache::new(store.clone(), codec.clone(), size),
+            ids: BTree::new(store, codec, name),
+        }
+    }
+}
+
+impl<S, C, T> DocStore<S, C, T> {
+    /// Returns the alias of the root of the collection.
+    pub fn alias(&self) -> &[u8] {
+        self.ids.alias()
+    }
+}
+
+impl<S, C, T> DocStore<S, C, T>
+where
+    S: Store + AliasStore + Send + Sync,
+    C: Encoder + Decoder + IpldDecoder + Clone + Send + Sync,
+    Ipld: Encode<<C as Encoder>::Codec>,
+    T: Decode<<C as Decoder>::Codec>
+        + Encode<<C as Encoder>::Codec>
+        + Clone
+        + Send
+        + Sync
+        + 'static,
+{
+    /// Returns the root of the collection.
+    pub async fn root(&self) -> Result<Option<Cid>> {
+        self.ids.root().await
+    }
+
+    /// Inserts or replaces the document `id`, returning its root.
+    pub async fn put(&self, id: &str, doc: &T) -> Result<Root<T>> {
+        let mut batch = self.ids.builder().create_batch();
+        let root = Root::new(batch.insert(doc)?.clone());
+        self.ids.update(&id.to_string(), Some(&root), batch).await?;
+        Ok(root)
+    }
+
+    /// Returns the document `id`.
+    pub async fn get(&self, id: &str) -> Result<Option<T>> {
+        match self.ids.get(&id.to_string()).await? {
+            Some(root) => Ok(Some(root.load(&self.cache).await?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Removes the document `id`, returning if it was in the collection.
+    pub async fn delete(&self, id: &str) -> Result<bool> {
+        self.ids.remove(&id.to_string()).await
+    }
+
+    /// Returns the ids of the documents in ascending order.
+    pub fn list(&self) -> impl Stream<Item = Result<String>> + '_ {
+        self.ids.range(..).map_ok(|(id, _)| id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Codec;
+    use libipld::ipld;
+    use libipld::mem::MemStore;
+
+    #[async_std::test]
+    async fn test_doc_store() {
+        let store = MemStore::default();
+        let docs = DocStore::new(store.clone(), Codec::new(), "users", 16);
+        assert_eq!(docs.get("alice").await.unwrap(), None);
+        docs.put("bob", &ipld!({"age": 30})).await.unwrap();
+        let alice = docs.put("alice", &ipld!({"age": 25})).await.unwrap();
+        assert_eq!(alice.load(&docs.cache).await.unwrap(), ipld!({"age": 25}));
+        assert_eq!(docs.get("alice").await.unwrap(), Some(ipld!({"age": 25})));
+
+        let root = docs.root().await.unwrap().unwrap();
+        assert_eq!(store.resolve(docs.alias()).await.unwrap(), Some(root));
+        docs.put("alice", &ipld!({"age": 26})).await.unwrap();
+        assert_eq!(docs.get("alice").await.unwrap(), Some(ipld!({"age": 26})));
+
+        let ids: Vec<_> = docs.list().try_collect().await.unwrap();
+        assert_eq!(ids, vec!["alice".to_string(), "bob".to_string()]);
+
+        assert!(docs.delete("bob").await.unwrap());
+        assert!(!docs.delete("bob").await.unwrap());
+        assert_eq!(docs.get("bob").await.unwrap(), None);
+        let ids: Vec<_> = docs.list().try_collect().await.unwrap();
+        assert_eq!(ids, vec!["alice".to_string()]);
+    }
+}
@@ -0,0 +1,188 @@
+use crate::builder::BlockBuilder;
+use crate::codec::IpldDecoder;
+use crate::error::Result;
+use crate::walk::links;
+use core::fmt::Write;
+use libipld::cid::Cid;
+use libipld::ipld::Ipld;
+use libipld::multibase::Base;
+use libipld::store::ReadonlyStore;
+use std::collections::HashMap;
+
+const INDENT: &str = "  ";
+
+fn newline(out: &mut String, indent: usize) {
+    out.push('\n');
+    for _ in 0..indent {
+        out.push_str(INDENT);
+    }
+}
+
+fn write_str(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// Loaded blocks, or the error loading them.
+type Blocks = HashMap<Cid, core::result::Result<Ipld, String>>;
+
+fn render(out: &mut String, ipld: &Ipld, blocks: &Blocks, depth: usize, indent: usize) {
+    match ipld {
+        Ipld::Null => out.push_str("null"),
+        Ipld::Bool(b) => write!(out, "{}", b).unwrap(),
+        Ipld::Integer(i) => write!(out, "{}", i).unwrap(),
+        Ipld::Float(f) => write!(out, "{:?}", f).unwrap(),
+        Ipld::String(s) => write_str(out, s),
+        Ipld::Bytes(bytes) => {
+            let bytes = Base::Base64.encode(bytes);
+            write!(out, "{{\"/\": {{\"bytes\": \"{}\"}}}}", bytes).unwrap();
+        }
+        Ipld::List(list) if list.is_empty() => out.push_str("[]"),
+        Ipld::List(list) => {
+            out.push('[');
+            for (i, item) in list.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                newline(out, indent + 1);
+                render(out, item, blocks, depth, indent + 1);
+            }
+            newline(out, indent);
+            out.push(']');
+        }
+        Ipld::Map(map) if map.is_empty() => out.push_str("{}"),
+        Ipld::Map(map) => {
+            out.push('{');
+            for (i, (key, value)) in map.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                newline(out, indent + 1);
+                write_str(out, key);
+                out.push_str(": ");
+                render(out, value, blocks, depth, indent + 1);
+            }
+            newline(out, indent);
+            out.push('}');
+        }
+        Ipld::Link(cid) => match blocks.get(cid) {
+            Some(block) if depth > 0 => {
+                out.push('{');
+                newline(out, indent + 1);
+                write!(out, "\"/\": \"{}\",", cid).unwrap();
+                newline(out, indent + 1);
+                match block {
+                    Ok(block) => {
+                        out.push_str("\"block\": ");
+                        render(out, block, blocks, depth - 1, indent + 1);
+                    }
+                    Err(err) => {
+                        out.push_str("\"error\": ");
+                        write_str(out, err);
+                    }
+                }
+                newline(out, indent);
+                out.push('}');
+            }
+            _ => write!(out, "{{\"/\": \"{}\"}}", cid).unwrap(),
+        },
+    }
+}
+
+/// Renders ipld as pretty printed dag-json.
+pub fn to_json(ipld: &Ipld) -> String {
+    let mut out = String::new();
+    render(&mut out, ipld, &Blocks::new(), 0, 0);
+    out
+}
+
+impl<S: ReadonlyStore, C: IpldDecoder> BlockBuilder<S, C> {
+    /// Renders the dag of `root` as pretty printed dag-json for debugging.
+    ///
+    /// Links up to `depth` levels below the root are followed and the linked
+    /// block is inlined as `{"/": "<cid>", "block": ...}`, deeper links are
+    /// rendered as plain `{"/": "<cid>"}`. Blocks that can't be loaded are
+    /// annotated with an `"error"` instead of failing the dump.
+    pub async fn dump(&self, root: &Cid, depth: usize) -> Result<String> {
+        let ipld = self.get_ipld(root).await?;
+        let mut blocks = Blocks::new();
+        let mut level = links(&ipld);
+        for _ in 0..depth {
+            let mut next = vec![];
+            for cid in level {
+                if blocks.contains_key(&cid) {
+                    continue;
+                }
+                let block = self.get_ipld(&cid).await.map_err(|err| err.to_string());
+                if let Ok(block) = &block {
+                    next.extend(links(block));
+                }
+                blocks.insert(cid, block);
+            }
+            level = next;
+        }
+        let mut out = String::new();
+        render(&mut out, &ipld, &blocks, depth, 0);
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Codec;
+    use libipld::ipld;
+    use libipld::mem::MemStore;
+
+    #[async_std::test]
+    async fn test_dump() {
+        let builder = BlockBuilder::new(MemStore::default(), Codec::new());
+        let leaf = builder
+            .insert(&ipld!({"bytes": vec![1u8, 2, 3]}))
+            .await
+            .unwrap();
+        let child = builder
+            .insert(&ipld!([leaf.clone(), "a\"b"]))
+            .await
+            .unwrap();
+        let root = builder
+            .insert(&ipld!({"child": child.clone(), "n": 1}))
+            .await
+            .unwrap();
+
+        let dump = builder.dump(&root, 0).await.unwrap();
+        let expected = format!("{{\n  \"child\": {{\"/\": \"{}\"}},\n  \"n\": 1\n}}", child);
+        assert_eq!(dump, expected);
+
+        let dump = builder.dump(&root, 1).await.unwrap();
+        let expected = format!(
+            r#"{{
+  "child": {{
+    "/": "{}",
+    "block": [
+      {{"/": "{}"}},
+      "a\"b"
+    ]
+  }},
+  "n": 1
+}}"#,
+            child, leaf
+        );
+        assert_eq!(dump, expected);
+
+        let dump = builder.dump(&root, 2).await.unwrap();
+        assert!(dump.contains(r#""bytes": {"/": {"bytes": "AQID"}}"#));
+        assert_eq!(to_json(&ipld!([1, "a"])), "[\n  1,\n  \"a\"\n]");
+    }
+}
@@ -0,0 +1,164 @@
+use libipld::cid::Cid;
+use libipld::error::{StoreError, TypeError};
+use thiserror::Error;
+
+/// Result alias.
+pub type Result<T> = core::result::Result<T, Error>;
+
+/// Block builder error.
+#[derive(Debug, Error)]
+pub enum Error {
+    /// The store returned an error.
+    #[error("{0}")]
+    Store(#[from] StoreError),
+    /// Failed to encode a block.
+    #[error("failed to encode block: {0}")]
+    Encode(#[source] libipld::error::Error),
+    /// Failed to decode a block.
+    #[error("failed to decode block {0}: {1}")]
+    Decode(Cid, #[source] libipld::error::Error),
+    /// Block failed an integrity check.
+    #[error("{0}")]
+    Integrity(#[from] IntegrityError),
+    /// Failed to encrypt or decrypt a block.
+    #[cfg(feature = "crypto")]
+    #[error("{0}")]
+    Crypto(#[from] crate::crypto::Error),
+    /// Failed to resolve a path.
+    #[error("failed to resolve path {path}: {source}")]
+    Path {
+        /// The path that failed to resolve.
+        path: String,
+        /// The type error at the failing segment.
+        source: TypeError,
+    },
+    /// Block is not a valid commit.
+    #[error("block {0} is not a commit.")]
+    InvalidCommit(Cid),
+    /// Block is not a valid log entry.
+    #[error("block {0} is not a log entry.")]
+    InvalidLogEntry(Cid),
+    /// Block is not a valid collection node.
+    #[error("block {0} is not a collection node.")]
+    InvalidCollection(Cid),
+    /// Index is not declared.
+    #[error("unknown index {0}.")]
+    UnknownIndex(String),
+    /// Tenant is not registered.
+    #[error("unknown tenant {0}.")]
+    UnknownTenant(String),
+    /// Tenant prefix overlaps the prefix of another tenant.
+    #[error("prefix of tenant {0} overlaps the prefix of tenant {1}.")]
+    OverlappingTenant(String, String),
+    /// Builder option can't be changed while the builder is shared.
+    #[error("builder is shared.")]
+    SharedBuilder,
+    /// Block exceeds `MAX_BLOCK_SIZE`.
+    #[error("block size {0} exceeds MAX_BLOCK_SIZE.")]
+    BlockTooLarge(usize),
+    /// Invalid dag-json.
+    #[cfg(feature = "json")]
+    #[error("invalid dag-json: {0}")]
+    InvalidJson(String),
+    /// Invalid CAR archive.
+    #[error("invalid car: {0}")]
+    InvalidCar(String),
+    /// Block is not available without fetching it from the network.
+    #[error("block {0} is not available locally.")]
+    NotFoundLocally(Cid),
+    /// Operation did not complete in time.
+    #[error("{0} timed out after {1:?}.")]
+    Timeout(&'static str, std::time::Duration),
+    /// Invalid did.
+    #[error("invalid did: {0}")]
+    InvalidDid(String),
+    /// Invalid signed head.
+    #[error("invalid signed head: {0}")]
+    InvalidRecord(String),
+    /// Invalid capability token.
+    #[error("invalid capability token: {0}")]
+    InvalidToken(String),
+    /// Operation not granted by the capability token.
+    #[error("unauthorized: {0}")]
+    Unauthorized(String),
+    /// Invalid head announcement.
+    #[error("invalid head announcement: {0}")]
+    InvalidAnnouncement(String),
+    /// Requests per second of a rate limit are not positive and finite.
+    #[error("invalid rate limit of {0} requests per second.")]
+    InvalidRateLimit(f64),
+    /// Invalid audit log entry.
+    #[cfg(feature = "audit")]
+    #[error("invalid audit entry: {0}")]
+    InvalidAuditEntry(String),
+    /// Invalid graphsync request or response.
+    #[error("invalid graphsync message: {0}")]
+    InvalidGraphsync(String),
+    /// Io error.
+    #[error("{0}")]
+    Io(#[from] std::io::Error),
+    /// Other ipld error.
+    #[error("{0}")]
+    Ipld(libipld::error::Error),
+}
+
+impl Error {
+    /// Classifies an error returned while encoding a block.
+    pub(crate) fn encode(err: libipld::error::Error) -> Self {
+        match err {
+            libipld::error::Error::BlockTooLarge(size) => Self::BlockTooLarge(size),
+            err => Self::Encode(err),
+        }
+    }
+
+    /// Classifies an error returned while decoding the block `cid`.
+    pub(crate) fn decode(cid: &Cid, err: libipld::error::Error) -> Self {
+        match err {
+            libipld::error::Error::BlockTooLarge(size) => Self::BlockTooLarge(size),
+            libipld::error::Error::InvalidHash(_) => IntegrityError::Hash(cid.clone()).into(),
+            libipld::error::Error::StoreError(err) => Self::Store(err),
+            err => Self::Decode(cid.clone(), err),
+        }
+    }
+}
+
+impl From<libipld::error::Error> for Error {
+    fn from(err: libipld::error::Error) -> Self {
+        match err {
+            libipld::error::Error::BlockTooLarge(size) => Self::BlockTooLarge(size),
+            libipld::error::Error::StoreError(err) => Self::Store(err),
+            err => Self::Ipld(err),
+        }
+    }
+}
+
+impl From<Error> for libipld::error::Error {
+    fn from(err: Error) -> Self {
+        match err {
+            Error::Store(err) => Self::StoreError(err),
+            Error::BlockTooLarge(size) => Self::BlockTooLarge(size),
+            Error::Ipld(err) => err,
+            err => Self::CodecError(Box::new(err)),
+        }
+    }
+}
+
+/// Integrity error.
+#[derive(Debug, Error)]
+pub enum IntegrityError {
+    /// Hash of the block data does not match the cid.
+    #[error("hash of block {0} does not match the cid.")]
+    Hash(Cid),
+    /// Mac integrity check failed.
+    #[error("mac integrity check of block {0} failed.")]
+    Mac(Cid),
+}
+
+/// Verifies that the data hashes to the cid.
+pub fn verify(cid: &Cid, data: &[u8]) -> core::result::Result<(), IntegrityError> {
+    let hash = cid.hash().algorithm().digest(data);
+    if hash.as_ref() != cid.hash() {
+        return Err(IntegrityError::Hash(cid.clone()));
+    }
+    Ok(())
+}
@@ -0,0 +1,258 @@
+use std::collections::hash_map::RandomState;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{BuildHasher, Hash};
+
+/// Cache eviction policy.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum EvictionPolicy {
+    /// Evicts the least recently used entry.
+    #[default]
+    Lru,
+    /// Evicts the least frequently used entry.
+    Lfu,
+    /// Evicts the oldest entry.
+    Fifo,
+    /// Evicts the least recently used entry, but only admits a new entry if
+    /// it is accessed at least as frequently as the entry it replaces.
+    TinyLfu,
+}
+
+/// Approximate access frequencies with periodic aging.
+struct Sketch {
+    hasher: RandomState,
+    counters: Vec<u8>,
+    mask: usize,
+    additions: usize,
+    sample: usize,
+}
+
+impl Sketch {
+    const ROWS: usize = 4;
+    const MAX: u8 = 15;
+
+    fn new(capacity: usize) -> Self {
+        let width = (capacity * 2).max(64).next_power_of_two();
+        Self {
+            hasher: RandomState::new(),
+            counters: vec![0; width * Self::ROWS],
+            mask: width - 1,
+            additions: 0,
+            sample: capacity.max(1) * 10,
+        }
+    }
+
+    fn indices<K: Hash>(&self, key: &K) -> [usize; Self::ROWS] {
+        let mut indices = [0; Self::ROWS];
+        for (row, index) in indices.iter_mut().enumerate() {
+            let hash = self.hasher.hash_one((row, key)) as usize;
+            *index = row * (self.mask + 1) + (hash & self.mask);
+        }
+        indices
+    }
+
+    fn increment<K: Hash>(&mut self, key: &K) {
+        for i in self.indices(key) {
+            if self.counters[i] < Self::MAX {
+                self.counters[i] += 1;
+            }
+        }
+        self.additions += 1;
+        if self.additions >= self.sample {
+            for counter in &mut self.counters {
+                *counter /= 2;
+            }
+            self.additions /= 2;
+        }
+    }
+
+    fn estimate<K: Hash>(&self, key: &K) -> u8 {
+        self.indices(key)
+            .iter()
+            .map(|i| self.counters[*i])
+            .min()
+            .unwrap_or_default()
+    }
+}
+
+struct Entry<V> {
+    value: V,
+    hits: u64,
+    rank: (u64, u64),
+}
+
+fn rank(policy: EvictionPolicy, hits: u64, tick: u64) -> (u64, u64) {
+    match policy {
+        EvictionPolicy::Lfu => (hits, tick),
+        _ => (0, tick),
+    }
+}
+
+/// Bounded map evicting entries according to an `EvictionPolicy`.
+pub(crate) struct EvictionCache<K, V> {
+    policy: EvictionPolicy,
+    capacity: usize,
+    tick: u64,
+    entries: HashMap<K, Entry<V>>,
+    order: BTreeMap<(u64, u64), K>,
+    sketch: Option<Sketch>,
+}
+
+impl<K: Clone + Eq + Hash, V> EvictionCache<K, V> {
+    /// Creates a new cache holding at most `capacity` entries.
+    pub fn new(policy: EvictionPolicy, capacity: usize) -> Self {
+        let sketch = if policy == EvictionPolicy::TinyLfu {
+            Some(Sketch::new(capacity))
+        } else {
+            None
+        };
+        Self {
+            policy,
+            capacity,
+            tick: 0,
+            entries: HashMap::with_capacity(capacity),
+            order: BTreeMap::new(),
+            sketch,
+        }
+    }
+
+    fn next_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+
+    /// Returns the number of cached entries.
+    #[cfg(test)]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns if an entry is cached without recording an access.
+    pub fn contains(&self, key: &K) -> bool {
+        self.entries.contains_key(key)
+    }
+
+    /// Returns a cached entry, recording the access.
+    pub fn get(&mut self, key: &K) -> Option<&V> {
+        if let Some(sketch) = &mut self.sketch {
+            sketch.increment(key);
+        }
+        let tick = self.next_tick();
+        let entry = self.entries.get_mut(key)?;
+        entry.hits += 1;
+        if self.policy != EvictionPolicy::Fifo {
+            self.order.remove(&entry.rank);
+            entry.rank = rank(self.policy, entry.hits, tick);
+            self.order.insert(entry.rank, key.clone());
+        }
+        Some(&entry.value)
+    }
+
+    /// Returns the entries in eviction order without recording an access.
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.order
+            .values()
+            .map(move |key| (key, &self.entries[key].value))
+    }
+
+    /// Removes an entry and returns it.
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let entry = self.entries.remove(key)?;
+        self.order.remove(&entry.rank);
+        Some(entry.value)
+    }
+
+    /// Removes the entry that is evicted next and returns it.
+    pub fn pop(&mut self) -> Option<(K, V)> {
+        let (_, key) = self.order.pop_first()?;
+        let entry = self.entries.remove(&key)?;
+        Some((key, entry.value))
+    }
+
+    /// Inserts an entry, evicting another one if the cache is full.
+    pub fn insert(&mut self, key: K, value: V) {
+        if self.capacity == 0 {
+            return;
+        }
+        if let Some(sketch) = &mut self.sketch {
+            sketch.increment(&key);
+        }
+        if let Some(entry) = self.entries.get_mut(&key) {
+            entry.value = value;
+            return;
+        }
+        if self.entries.len() >= self.capacity {
+            let (rank, victim) = match self.order.iter().next() {
+                Some((rank, victim)) => (*rank, victim),
+                None => return,
+            };
+            if let Some(sketch) = &self.sketch {
+                if sketch.estimate(&key) < sketch.estimate(victim) {
+                    return;
+                }
+            }
+            if let Some(victim) = self.order.remove(&rank) {
+                self.entries.remove(&victim);
+            }
+        }
+        let tick = self.next_tick();
+        let rank = rank(self.policy, 1, tick);
+        self.order.insert(rank, key.clone());
+        self.entries.insert(
+            key,
+            Entry {
+                value,
+                hits: 1,
+                rank,
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cache(policy: EvictionPolicy) -> EvictionCache<u32, u32> {
+        let mut cache = EvictionCache::new(policy, 2);
+        cache.insert(1, 1);
+        cache.insert(2, 2);
+        cache.get(&1);
+        cache.get(&1);
+        cache.get(&2);
+        cache
+    }
+
+    #[test]
+    fn test_eviction_policies() {
+        let mut lru = cache(EvictionPolicy::Lru);
+        lru.insert(3, 3);
+        assert!(lru.get(&1).is_none());
+        assert!(lru.get(&2).is_some());
+
+        let mut lfu = cache(EvictionPolicy::Lfu);
+        lfu.insert(3, 3);
+        assert!(lfu.get(&1).is_some());
+        assert!(lfu.get(&2).is_none());
+
+        let mut fifo = cache(EvictionPolicy::Fifo);
+        fifo.insert(3, 3);
+        assert!(fifo.get(&1).is_none());
+        assert!(fifo.get(&2).is_some());
+        assert_eq!(fifo.len(), 2);
+    }
+
+    #[test]
+    fn test_tiny_lfu_admission() {
+        let mut cache = cache(EvictionPolicy::TinyLfu);
+        cache.insert(3, 3);
+        assert!(cache.get(&3).is_none());
+        assert!(cache.get(&1).is_some());
+        for _ in 0..4 {
+            cache.get(&3);
+        }
+        cache.insert(3, 3);
+        assert!(cache.get(&3).is_some());
+        assert!(cache.get(&1).is_some());
+        assert!(cache.get(&2).is_none());
+    }
+}
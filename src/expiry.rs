@@ -0,0 +1,190 @@
+use crate::builder::BlockBuilder;
+use crate::codec::{Encoder, IpldDecoder};
+use crate::error::Result;
+use crate::rt::{SystemTime, UNIX_EPOCH};
+use core::convert::TryFrom;
+use futures::lock::Mutex;
+use libipld::cid::Cid;
+use libipld::codec::Encode;
+use libipld::ipld::Ipld;
+use libipld::store::{AliasStore, Store};
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// Alias of the expiring pins.
+const ALIAS: &[u8] = b"pins/expiring";
+
+fn millis(time: SystemTime) -> u64 {
+    let millis = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+    u64::try_from(millis).unwrap_or(u64::MAX)
+}
+
+/// Pins that expire at a deadline.
+///
+/// The pins are stored as a list of `[link, deadline]` pairs in a single
+/// block referenced by the alias `pins/expiring`, so they survive restarts.
+/// Deadlines are milliseconds since the unix epoch. Expired pins are removed
+/// by `sweep`, either called periodically or driven by `run`.
+pub struct ExpiringPins<S, C> {
+    builder: BlockBuilder<S, C>,
+    lock: Mutex<()>,
+}
+
+impl<S, C> ExpiringPins<S, C> {
+    /// Creates the expiring pins.
+    pub fn new(store: S, codec: C) -> Self {
+        Self {
+            builder: BlockBuilder::new(store, codec),
+            lock: Mutex::new(()),
+        }
+    }
+}
+
+impl<S, C> ExpiringPins<S, C>
+where
+    S: Store + AliasStore,
+    C: Encoder + IpldDecoder + Clone,
+    Ipld: Encode<C::Codec>,
+{
+    async fn read(&self) -> Result<(Option<Cid>, BTreeMap<Cid, u64>)> {
+        let root = match self.builder.resolve(ALIAS).await? {
+            Some(root) => root,
+            None => return Ok((None, Default::default())),
+        };
+        let pins = match self.builder.get_ipld(&root).await? {
+            Ipld::List(pins) => pins
+                .into_iter()
+                .filter_map(|ipld| match ipld {
+                    Ipld::List(pin) => match pin.as_slice() {
+                        [Ipld::Link(cid), Ipld::Integer(deadline)] => {
+                            Some((cid.clone(), u64::try_from(*deadline).ok()?))
+                        }
+                        _ => None,
+                    },
+                    _ => None,
+                })
+                .collect(),
+            _ => Default::default(),
+        };
+        Ok((Some(root), pins))
+    }
+
+    async fn write(&self, old: Option<Cid>, pins: BTreeMap<Cid, u64>) -> Result<()> {
+        if pins.is_empty() {
+            self.builder.unalias(ALIAS).await?;
+        } else {
+            let ipld = Ipld::List(
+                pins.into_iter()
+                    .map(|(cid, deadline)| {
+                        Ipld::List(vec![Ipld::Link(cid), Ipld::Integer(deadline.into())])
+                    })
+                    .collect(),
+            );
+            let root = self.builder.insert(&ipld).await?;
+            if Some(&root) == old.as_ref() {
+                return self.builder.unpin(&root).await;
+            }
+            self.builder.alias(ALIAS, &root).await?;
+        }
+        if let Some(old) = old {
+            self.builder.unpin(&old).await?;
+        }
+        Ok(())
+    }
+
+    /// Pins `cid` until `deadline`, replacing an earlier deadline.
+    pub async fn pin_until(&self, cid: &Cid, deadline: SystemTime) -> Result<()> {
+        let _guard = self.lock.lock().await;
+        let (root, mut pins) = self.read().await?;
+        let deadline = millis(deadline);
+        if pins.insert(cid.clone(), deadline) == Some(deadline) {
+            return Ok(());
+        }
+        self.write(root, pins).await
+    }
+
+    /// Removes the pin of `cid` before its deadline.
+    pub async fn unpin(&self, cid: &Cid) -> Result<()> {
+        let _guard = self.lock.lock().await;
+        let (root, mut pins) = self.read().await?;
+        if pins.remove(cid).is_none() {
+            return Ok(());
+        }
+        self.write(root, pins).await
+    }
+
+    /// Returns the deadline of the pin of `cid`.
+    pub async fn deadline(&self, cid: &Cid) -> Result<Option<SystemTime>> {
+        let deadline = self.read().await?.1.get(cid).copied();
+        Ok(deadline.map(|millis| UNIX_EPOCH + Duration::from_millis(millis)))
+    }
+
+    /// Removes the pins that expired at `now` and returns them.
+    pub async fn sweep_at(&self, now: SystemTime) -> Result<Vec<Cid>> {
+        let _guard = self.lock.lock().await;
+        let (root, pins) = self.read().await?;
+        let now = millis(now);
+        let (expired, pins): (BTreeMap<_, _>, _) =
+            pins.into_iter().partition(|(_, deadline)| *deadline <= now);
+        if !expired.is_empty() {
+            self.write(root, pins).await?;
+        }
+        Ok(expired.into_keys().collect())
+    }
+
+    /// Removes the expired pins and returns them.
+    pub async fn sweep(&self) -> Result<Vec<Cid>> {
+        self.sweep_at(SystemTime::now()).await
+    }
+
+    /// Sweeps the expired pins every `interval`.
+    ///
+    /// Only returns if a sweep fails. The future is meant to be spawned
+    /// next to the service holding the pins.
+    pub async fn run(&self, interval: Duration) -> Result<()> {
+        loop {
+            self.sweep().await?;
+            crate::rt::sleep(interval).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Codec;
+    use libipld::ipld;
+    use libipld::mem::MemStore;
+
+    #[async_std::test]
+    async fn test_expiring_pins() {
+        let store = MemStore::default();
+        let builder = BlockBuilder::new(store.clone(), Codec::new());
+        let a = builder.insert(&ipld!({"a": 1})).await.unwrap();
+        let b = builder.insert(&ipld!({"b": 2})).await.unwrap();
+        let now = SystemTime::now();
+        let hour = Duration::from_secs(3600);
+
+        let pins = ExpiringPins::new(store.clone(), Codec::new());
+        pins.pin_until(&a, now + hour).await.unwrap();
+        pins.pin_until(&b, now + hour * 2).await.unwrap();
+        assert!(pins.sweep_at(now).await.unwrap().is_empty());
+
+        // expirations survive restarts
+        let pins = ExpiringPins::new(store.clone(), Codec::new());
+        assert_eq!(
+            pins.deadline(&a).await.unwrap(),
+            Some(UNIX_EPOCH + Duration::from_millis(millis(now + hour)))
+        );
+        assert_eq!(pins.sweep_at(now + hour).await.unwrap(), vec![a.clone()]);
+        assert_eq!(pins.deadline(&a).await.unwrap(), None);
+        assert!(pins.deadline(&b).await.unwrap().is_some());
+
+        pins.unpin(&b).await.unwrap();
+        assert!(pins.sweep_at(now + hour * 3).await.unwrap().is_empty());
+        assert_eq!(builder.resolve(ALIAS).await.unwrap(), None);
+    }
+}
@@ -0,0 +1,396 @@
+use crate::batch::Batch;
+use crate::builder::BlockBuilder;
+use crate::car::{split_cid, write_varint};
+use crate::codec::{Encoder, IpldDecoder};
+use crate::error::{verify, Error, Result};
+use crate::path::IpldPath;
+use crate::walk::links;
+use core::convert::TryFrom;
+use futures::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use libipld::block::Block;
+use libipld::cbor::DagCborCodec;
+use libipld::cid::Cid;
+use libipld::codec::Codec;
+use libipld::error::StoreError;
+use libipld::ipld::Ipld;
+use libipld::store::{ReadonlyStore, Store};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+fn invalid(msg: impl Into<String>) -> Error {
+    Error::InvalidGraphsync(msg.into())
+}
+
+/// Selects a subset of the blocks of a dag.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum Selector {
+    /// Selects every block.
+    All,
+    /// Selects the blocks at most `depth` links below the root.
+    Depth(u64),
+    /// Selects the blocks crossed while resolving a path and the blocks
+    /// selected by the inner selector where the path ends.
+    Path(IpldPath, Box<Selector>),
+}
+
+impl Selector {
+    /// Returns the links of `ipld` selected by `self` together with the
+    /// selector to apply to the linked blocks.
+    pub(crate) fn select(&self, ipld: &Ipld) -> Vec<(Cid, Selector)> {
+        match self {
+            Self::All => links(ipld)
+                .into_iter()
+                .map(|cid| (cid, Self::All))
+                .collect(),
+            Self::Depth(0) => vec![],
+            Self::Depth(depth) => links(ipld)
+                .into_iter()
+                .map(|cid| (cid, Self::Depth(depth - 1)))
+                .collect(),
+            Self::Path(path, inner) => {
+                let segments: Vec<&str> = path.iter().collect();
+                let mut ipld = ipld;
+                for (i, segment) in segments.iter().enumerate() {
+                    ipld = match ipld.get(*segment) {
+                        Ok(ipld) => ipld,
+                        Err(_) => return vec![],
+                    };
+                    if let Ipld::Link(cid) = ipld {
+                        let rest = IpldPath::from(segments[(i + 1)..].to_vec());
+                        return vec![(cid.clone(), Self::Path(rest, inner.clone()))];
+                    }
+                }
+                inner.select(ipld)
+            }
+        }
+    }
+
+    fn to_ipld(&self) -> Ipld {
+        let mut map = BTreeMap::new();
+        match self {
+            Self::All => map.insert("all".to_string(), Ipld::Null),
+            Self::Depth(depth) => map.insert("depth".to_string(), Ipld::Integer(*depth as _)),
+            Self::Path(path, inner) => {
+                let segments = path.iter().map(|s| Ipld::String(s.to_string())).collect();
+                map.insert(
+                    "path".to_string(),
+                    Ipld::List(vec![Ipld::List(segments), inner.to_ipld()]),
+                )
+            }
+        };
+        Ipld::Map(map)
+    }
+
+    fn from_ipld(ipld: &Ipld) -> Result<Self> {
+        if ipld.get("all").is_ok() {
+            return Ok(Self::All);
+        }
+        if let Ok(Ipld::Integer(depth)) = ipld.get("depth") {
+            let depth = u64::try_from(*depth).map_err(|_| invalid("invalid depth"))?;
+            return Ok(Self::Depth(depth));
+        }
+        if let Ok(Ipld::List(path)) = ipld.get("path") {
+            if let [Ipld::List(segments), inner] = &path[..] {
+                let segments = segments
+                    .iter()
+                    .map(|segment| match segment {
+                        Ipld::String(segment) => Ok(segment.clone()),
+                        _ => Err(invalid("invalid path segment")),
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                let inner = Self::from_ipld(inner)?;
+                return Ok(Self::Path(segments.into(), Box::new(inner)));
+            }
+        }
+        Err(invalid("invalid selector"))
+    }
+}
+
+/// Request for the blocks selected by a selector starting at a root.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct GraphsyncRequest {
+    /// Root of the dag.
+    pub root: Cid,
+    /// Blocks to sync.
+    pub selector: Selector,
+}
+
+impl GraphsyncRequest {
+    /// Creates a new request.
+    pub fn new(root: Cid, selector: Selector) -> Self {
+        Self { root, selector }
+    }
+
+    /// Encodes the request as dag-cbor.
+    pub fn encode(&self) -> Result<Box<[u8]>> {
+        let mut map = BTreeMap::new();
+        map.insert("root".to_string(), Ipld::Link(self.root.clone()));
+        map.insert("selector".to_string(), self.selector.to_ipld());
+        DagCborCodec::encode(&Ipld::Map(map)).map_err(|err| invalid(err.to_string()))
+    }
+
+    /// Decodes a request.
+    pub fn decode(data: &[u8]) -> Result<Self> {
+        let ipld: Ipld = DagCborCodec::decode(data).map_err(|err| invalid(err.to_string()))?;
+        let root = match ipld.get("root") {
+            Ok(Ipld::Link(root)) => root.clone(),
+            _ => return Err(invalid("missing root")),
+        };
+        let selector = match ipld.get("selector") {
+            Ok(selector) => Selector::from_ipld(selector)?,
+            _ => return Err(invalid("missing selector")),
+        };
+        Ok(Self { root, selector })
+    }
+}
+
+/// Summary of a received graphsync response.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct GraphsyncReport {
+    /// Number of blocks received.
+    pub blocks: usize,
+    /// Selected blocks the responder doesn't have, in traversal order.
+    pub missing: Vec<Cid>,
+}
+
+/// Status of a block in a response frame.
+const PRESENT: u8 = 0;
+const MISSING: u8 = 1;
+const DUPLICATE: u8 = 2;
+
+async fn write_frame<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    status: u8,
+    cid: &Cid,
+    data: &[u8],
+) -> Result<()> {
+    let cid = cid.to_bytes();
+    let mut frame = Vec::with_capacity(cid.len() + data.len() + 11);
+    write_varint(&mut frame, (1 + cid.len() + data.len()) as u64);
+    frame.push(status);
+    frame.extend_from_slice(&cid);
+    frame.extend_from_slice(data);
+    writer.write_all(&frame).await?;
+    Ok(())
+}
+
+async fn read_frame<R: AsyncRead + Unpin>(reader: &mut R) -> Result<(u8, Cid, Vec<u8>)> {
+    let mut len = 0u64;
+    let mut byte = [0u8];
+    for i in 0..10 {
+        if reader.read(&mut byte).await? == 0 {
+            return Err(invalid("truncated response"));
+        }
+        len |= u64::from(byte[0] & 0x7f) << (7 * i);
+        if byte[0] & 0x80 == 0 {
+            break;
+        }
+        if i == 9 {
+            return Err(invalid("invalid varint"));
+        }
+    }
+    if len == 0 || len > libipld::MAX_BLOCK_SIZE as u64 + 128 {
+        return Err(invalid("invalid frame length"));
+    }
+    let mut frame = vec![0; len as usize];
+    reader
+        .read_exact(&mut frame)
+        .await
+        .map_err(|_| invalid("truncated response"))?;
+    let (cid, data) = split_cid(&frame[1..]).map_err(|_| invalid("invalid cid"))?;
+    Ok((frame[0], cid, data.to_vec()))
+}
+
+impl<S: ReadonlyStore, C: IpldDecoder> BlockBuilder<S, C> {
+    /// Answers a graphsync request by writing the selected blocks to
+    /// `writer` in depth first pre-order.
+    ///
+    /// Blocks missing from the store are reported instead of failing the
+    /// response. Links are found by decoding blocks with the codec, so
+    /// encrypted dags can only be served by their owner.
+    pub async fn serve_graphsync<W: AsyncWrite + Unpin>(
+        &self,
+        request: &GraphsyncRequest,
+        mut writer: W,
+    ) -> Result<()> {
+        let mut visited = HashSet::new();
+        let mut sent = HashSet::new();
+        let mut stack = vec![(request.root.clone(), request.selector.clone())];
+        while let Some((cid, selector)) = stack.pop() {
+            if !visited.insert((cid.clone(), selector.clone())) {
+                continue;
+            }
+            let data = match self.get_verified(&cid).await {
+                Ok(data) => data,
+                Err(Error::Store(StoreError::BlockNotFound(_))) => {
+                    write_frame(&mut writer, MISSING, &cid, &[]).await?;
+                    continue;
+                }
+                Err(err) => return Err(err),
+            };
+            let ipld = self.codec().decode_ipld(&cid, &data)?;
+            if sent.insert(cid.clone()) {
+                write_frame(&mut writer, PRESENT, &cid, &data).await?;
+            } else {
+                write_frame(&mut writer, DUPLICATE, &cid, &[]).await?;
+            }
+            stack.extend(selector.select(&ipld).into_iter().rev());
+        }
+        writer.flush().await?;
+        Ok(())
+    }
+}
+
+impl<S: Store, C: IpldDecoder + Encoder + Clone> BlockBuilder<S, C> {
+    /// Reads the response to a graphsync request from `reader` and inserts
+    /// the received blocks.
+    ///
+    /// The selector is replayed on the received blocks, so blocks that were
+    /// not requested or arrive out of order fail the response. Every block
+    /// is verified against its cid before anything is inserted. The blocks
+    /// are inserted as a batch pinning the root.
+    pub async fn receive_graphsync<R: AsyncRead + Unpin>(
+        &self,
+        request: &GraphsyncRequest,
+        mut reader: R,
+    ) -> Result<GraphsyncReport> {
+        let mut report = GraphsyncReport::default();
+        let mut visited = HashSet::new();
+        let mut received: HashMap<Cid, Ipld> = HashMap::new();
+        let mut blocks = vec![];
+        let mut stack = vec![(request.root.clone(), request.selector.clone())];
+        while let Some((cid, selector)) = stack.pop() {
+            if !visited.insert((cid.clone(), selector.clone())) {
+                continue;
+            }
+            let (status, frame_cid, data) = read_frame(&mut reader).await?;
+            if frame_cid != cid {
+                return Err(invalid(format!("expected block {} got {}", cid, frame_cid)));
+            }
+            let ipld = match status {
+                PRESENT if !received.contains_key(&cid) => {
+                    verify(&cid, &data)?;
+                    let ipld = self.codec().decode_ipld(&cid, &data)?;
+                    received.insert(cid.clone(), ipld.clone());
+                    blocks.push(Block {
+                        cid: cid.clone(),
+                        data: data.into_boxed_slice(),
+                    });
+                    ipld
+                }
+                DUPLICATE => match received.get(&cid) {
+                    Some(ipld) => ipld.clone(),
+                    None => return Err(invalid(format!("unknown duplicate {}", cid))),
+                },
+                MISSING => {
+                    report.missing.push(cid);
+                    continue;
+                }
+                _ => return Err(invalid("invalid frame")),
+            };
+            stack.extend(selector.select(&ipld).into_iter().rev());
+        }
+        report.blocks = blocks.len();
+        if blocks.is_empty() {
+            return Ok(report);
+        }
+        // the root is received first, move it to the end to pin it
+        let root = blocks.remove(0);
+        let mut batch = Batch::with_capacity((), blocks.len() + 1);
+        for block in blocks.into_iter().chain(std::iter::once(root)) {
+            batch.push(block);
+        }
+        self.insert_batch(batch).await?;
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Codec;
+    use libipld::ipld;
+    use libipld::mem::MemStore;
+
+    async fn sync(
+        from: &BlockBuilder<MemStore, Codec>,
+        request: &GraphsyncRequest,
+    ) -> (BlockBuilder<MemStore, Codec>, GraphsyncReport) {
+        let request = GraphsyncRequest::decode(&request.encode().unwrap()).unwrap();
+        let mut response = vec![];
+        from.serve_graphsync(&request, &mut response).await.unwrap();
+        let to = BlockBuilder::new(MemStore::default(), Codec::new());
+        let report = to
+            .receive_graphsync(&request, futures::io::Cursor::new(response))
+            .await
+            .unwrap();
+        (to, report)
+    }
+
+    #[async_std::test]
+    async fn test_graphsync() {
+        let builder = BlockBuilder::new(MemStore::default(), Codec::new());
+        let leaf = builder.insert(&ipld!({"leaf": true})).await.unwrap();
+        let a = builder.insert(&ipld!({"a": leaf.clone()})).await.unwrap();
+        let b = builder.insert(&ipld!({"b": leaf.clone()})).await.unwrap();
+        let root = builder
+            .insert(&ipld!({"a": a.clone(), "b": [b.clone(), leaf.clone()]}))
+            .await
+            .unwrap();
+
+        let request = GraphsyncRequest::new(root.clone(), Selector::All);
+        let (to, report) = sync(&builder, &request).await;
+        assert_eq!(report.blocks, 4);
+        assert!(report.missing.is_empty());
+        assert!(to.get_ipld(&leaf).await.is_ok());
+
+        let request = GraphsyncRequest::new(root.clone(), Selector::Depth(1));
+        let (_, report) = sync(&builder, &request).await;
+        assert_eq!(report.blocks, 4);
+
+        let request = GraphsyncRequest::new(root.clone(), Selector::Depth(0));
+        let (to, report) = sync(&builder, &request).await;
+        assert_eq!(report.blocks, 1);
+        assert!(to.get_ipld(&a).await.is_err());
+
+        let selector = Selector::Path("a".into(), Box::new(Selector::Depth(0)));
+        let request = GraphsyncRequest::new(root.clone(), selector);
+        let (to, report) = sync(&builder, &request).await;
+        assert_eq!(report.blocks, 2);
+        assert!(to.get_ipld(&a).await.is_ok());
+        assert!(to.get_ipld(&b).await.is_err());
+    }
+
+    #[async_std::test]
+    async fn test_graphsync_missing() {
+        let partial = BlockBuilder::new(MemStore::default(), Codec::new());
+        let missing = partial.codec().encode(&ipld!("missing")).unwrap().cid;
+        let root = partial.insert(&ipld!([missing.clone()])).await.unwrap();
+        let request = GraphsyncRequest::new(root, Selector::All);
+        let (_, report) = sync(&partial, &request).await;
+        assert_eq!(report.blocks, 1);
+        assert_eq!(report.missing, vec![missing]);
+    }
+
+    #[async_std::test]
+    async fn test_graphsync_invalid_response() {
+        let builder = BlockBuilder::new(MemStore::default(), Codec::new());
+        let a = builder.insert(&ipld!("a")).await.unwrap();
+        let b = builder.insert(&ipld!("b")).await.unwrap();
+        let mut response = vec![];
+        let request = GraphsyncRequest::new(b.clone(), Selector::All);
+        builder
+            .serve_graphsync(&request, &mut response)
+            .await
+            .unwrap();
+
+        let request = GraphsyncRequest::new(a, Selector::All);
+        let res = builder
+            .receive_graphsync(&request, futures::io::Cursor::new(&response[..]))
+            .await;
+        assert!(matches!(res, Err(Error::InvalidGraphsync(_))));
+        let res = builder
+            .receive_graphsync(&request, futures::io::Cursor::new(&response[..2]))
+            .await;
+        assert!(matches!(res, Err(Error::InvalidGraphsync(_))));
+    }
+}
@@ -0,0 +1,128 @@
+use crate::builder::BlockBuilder;
+use crate::error::{verify, Result};
+use crate::rt::Instant;
+use crate::timeout::timeout;
+use libipld::cid::{Cid, Codec as CidCodec};
+use libipld::multihash::Identity;
+use libipld::store::{Store, Visibility};
+use std::time::Duration;
+
+/// Data of the canary block written by `BlockBuilder::health`.
+const CANARY: &[u8] = b"ipld-block-builder/health";
+
+/// Latencies of a health check round-trip.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Health {
+    /// Time to insert the canary block.
+    pub insert: Duration,
+    /// Time to read the canary block back.
+    pub get: Duration,
+    /// Time to unpin the canary block.
+    pub unpin: Duration,
+}
+
+impl Health {
+    /// Returns the total latency of the round-trip.
+    pub fn latency(&self) -> Duration {
+        self.insert + self.get + self.unpin
+    }
+}
+
+impl<S: Store, C> BlockBuilder<S, C> {
+    /// Checks that the store is writable and readable by inserting, reading
+    /// and unpinning an identity-hashed canary block.
+    ///
+    /// The canary is tiny and always the same block, so the check is cheap
+    /// enough for readiness and liveness probes. Every step is bounded by the
+    /// `get` timeout.
+    pub async fn health(&self) -> Result<Health> {
+        let cid = Cid::new_v1(CidCodec::Raw, Identity::digest(CANARY));
+        let limit = self.timeouts().get;
+
+        let start = Instant::now();
+        timeout("health", limit, async {
+            Ok(self
+                .store()
+                .insert(&cid, CANARY.into(), Visibility::Public)
+                .await?)
+        })
+        .await?;
+        let insert = start.elapsed();
+
+        let start = Instant::now();
+        let data = timeout("health", limit, async { Ok(self.store().get(&cid).await?) }).await?;
+        verify(&cid, &data)?;
+        let get = start.elapsed();
+
+        let start = Instant::now();
+        timeout("health", limit, async {
+            Ok(self.store().unpin(&cid).await?)
+        })
+        .await?;
+        let unpin = start.elapsed();
+
+        Ok(Health { insert, get, unpin })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Codec, Timeouts};
+    use libipld::block::Block;
+    use libipld::mem::MemStore;
+    use libipld::store::{ReadonlyStore, StoreResult};
+
+    #[derive(Clone, Default)]
+    struct BrokenStore(MemStore);
+
+    impl ReadonlyStore for BrokenStore {
+        fn get<'a>(&'a self, _: &'a Cid) -> StoreResult<'a, Box<[u8]>> {
+            Box::pin(futures::future::pending())
+        }
+    }
+
+    impl Store for BrokenStore {
+        fn insert<'a>(
+            &'a self,
+            cid: &'a Cid,
+            data: Box<[u8]>,
+            visibility: Visibility,
+        ) -> StoreResult<'a, ()> {
+            self.0.insert(cid, data, visibility)
+        }
+
+        fn insert_batch<'a>(
+            &'a self,
+            batch: Vec<Block>,
+            visibility: Visibility,
+        ) -> StoreResult<'a, Cid> {
+            self.0.insert_batch(batch, visibility)
+        }
+
+        fn flush(&self) -> StoreResult<'_, ()> {
+            self.0.flush()
+        }
+
+        fn unpin<'a>(&'a self, cid: &'a Cid) -> StoreResult<'a, ()> {
+            self.0.unpin(cid)
+        }
+    }
+
+    #[cfg_attr(not(feature = "tokio"), async_std::test)]
+    #[cfg_attr(feature = "tokio", tokio::test)]
+    async fn test_health() {
+        let builder = BlockBuilder::new(MemStore::default(), Codec::new());
+        let health = builder.health().await.unwrap();
+        assert_eq!(health.latency(), health.insert + health.get + health.unpin);
+        // the canary doesn't stay pinned
+        builder.health().await.unwrap();
+
+        let mut builder = BlockBuilder::new(BrokenStore::default(), Codec::new());
+        builder.set_timeouts(Timeouts {
+            get: Some(Duration::from_millis(10)),
+            ..Default::default()
+        });
+        assert!(builder.health().await.is_err());
+    }
+}
@@ -0,0 +1,276 @@
+use crate::batch::Batch;
+use crate::builder::BlockBuilder;
+use crate::codec::{Encoder, IpldDecoder};
+use crate::error::{Error, Result};
+use crate::path::IpldPath;
+use async_trait::async_trait;
+use futures::lock::Mutex;
+use libipld::cbor::DagCborCodec;
+use libipld::cid::Cid;
+use libipld::codec::{Codec, Encode};
+use libipld::ipld::Ipld;
+use libipld::store::{AliasStore, Store};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+/// Extracts the indexed values of a document.
+pub type Extract = dyn Fn(&Ipld) -> Vec<Ipld> + Send + Sync;
+
+struct Index {
+    name: String,
+    extract: Box<Extract>,
+}
+
+/// Secondary indexes of the documents inserted through an `IpldCache`.
+///
+/// Every index maps the values extracted from a document to the set of
+/// documents containing them. An index is a root block referenced by the
+/// alias `index/<name>`, linking every value to a block listing the
+/// documents. The index blocks are written in the same batch as the
+/// documents.
+#[derive(Default)]
+pub struct IndexManager {
+    indexes: Vec<Index>,
+}
+
+impl IndexManager {
+    /// Creates an index manager without indexes.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an index of the values returned by `extract`.
+    pub fn add_index<F>(&mut self, name: &str, extract: F)
+    where
+        F: Fn(&Ipld) -> Vec<Ipld> + Send + Sync + 'static,
+    {
+        self.indexes.push(Index {
+            name: name.to_string(),
+            extract: Box::new(extract),
+        });
+    }
+
+    /// Adds an index of the value at `path` inside the document.
+    ///
+    /// Links are not followed. Documents without the path are not indexed.
+    pub fn add_path_index<P: Into<IpldPath>>(&mut self, name: &str, path: P) {
+        let path = path.into();
+        self.add_index(name, move |doc| {
+            let mut ipld = doc;
+            for segment in path.iter() {
+                match ipld.get(segment) {
+                    Ok(next) => ipld = next,
+                    Err(_) => return vec![],
+                }
+            }
+            vec![ipld.clone()]
+        });
+    }
+
+    /// Returns the alias of the index `name`.
+    pub fn alias(name: &str) -> Vec<u8> {
+        format!("index/{}", name).into_bytes()
+    }
+
+    fn contains(&self, name: &str) -> bool {
+        self.indexes.iter().any(|index| index.name == name)
+    }
+}
+
+/// Returns the key of an indexed value in the index root.
+fn key(value: &Ipld) -> Result<String> {
+    let bytes = DagCborCodec::encode(value)
+        .map_err(|err| Error::encode(libipld::error::Error::CodecError(Box::new(err))))?;
+    Ok(bytes.iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+fn links(cid: &Cid, ipld: Ipld) -> Result<Vec<Cid>> {
+    match ipld {
+        Ipld::List(links) => links
+            .into_iter()
+            .map(|link| match link {
+                Ipld::Link(link) => Ok(link),
+                _ => Err(Error::InvalidCollection(cid.clone())),
+            })
+            .collect(),
+        _ => Err(Error::InvalidCollection(cid.clone())),
+    }
+}
+
+/// Indexes bound to the builder of a cache.
+#[async_trait]
+pub(crate) trait Indexer<C>: Send + Sync {
+    /// Indexes the documents of the batch and inserts them together with
+    /// the index blocks.
+    async fn insert_batch(&self, batch: Batch<C>) -> Result<Cid>;
+
+    /// Returns the documents containing `value` in the index `name`.
+    async fn find(&self, name: &str, value: &Ipld) -> Result<Vec<Cid>>;
+}
+
+pub(crate) struct BoundIndexes<S, C> {
+    builder: Arc<BlockBuilder<S, C>>,
+    manager: IndexManager,
+    lock: Mutex<()>,
+}
+
+impl<S, C> BoundIndexes<S, C> {
+    pub fn new(builder: Arc<BlockBuilder<S, C>>, manager: IndexManager) -> Self {
+        Self {
+            builder,
+            manager,
+            lock: Mutex::new(()),
+        }
+    }
+}
+
+impl<S, C> BoundIndexes<S, C>
+where
+    S: Store + AliasStore,
+    C: Encoder + IpldDecoder + Clone,
+    Ipld: Encode<C::Codec>,
+{
+    async fn root(&self, name: &str) -> Result<Option<(Cid, BTreeMap<String, Ipld>)>> {
+        match self.builder.resolve(&IndexManager::alias(name)).await? {
+            Some(cid) => match self.builder.get_ipld(&cid).await? {
+                Ipld::Map(map) => Ok(Some((cid, map))),
+                _ => Err(Error::InvalidCollection(cid)),
+            },
+            None => Ok(None),
+        }
+    }
+}
+
+#[async_trait]
+impl<S, C> Indexer<C> for BoundIndexes<S, C>
+where
+    S: Store + AliasStore + Send + Sync,
+    C: Encoder + IpldDecoder + Clone + Send + Sync,
+    Ipld: Encode<C::Codec>,
+{
+    async fn insert_batch(&self, docs: Batch<C>) -> Result<Cid> {
+        let _guard = self.lock.lock().await;
+        let codec = self.builder.codec();
+        let mut batch = self.builder.create_batch();
+        let mut roots = vec![];
+        for index in &self.manager.indexes {
+            let mut added: BTreeMap<String, Vec<Cid>> = BTreeMap::new();
+            for block in docs.blocks() {
+                let doc = codec.decode_ipld(&block.cid, &block.data)?;
+                for value in (index.extract)(&doc) {
+                    let cids = added.entry(key(&value)?).or_default();
+                    if !cids.contains(&block.cid) {
+                        cids.push(block.cid.clone());
+                    }
+                }
+            }
+            if added.is_empty() {
+                continue;
+            }
+            let (old, mut map) = match self.root(&index.name).await? {
+                Some((cid, map)) => (Some(cid), map),
+                None => (None, BTreeMap::new()),
+            };
+            for (key, cids) in added {
+                let mut docs = match map.get(&key) {
+                    Some(Ipld::Link(cid)) => links(cid, self.builder.get_ipld(cid).await?)?,
+                    _ => vec![],
+                };
+                for cid in cids {
+                    if !docs.contains(&cid) {
+                        docs.push(cid);
+                    }
+                }
+                let docs = Ipld::List(docs.into_iter().map(Ipld::Link).collect());
+                let cid = batch.insert(&docs)?.clone();
+                map.insert(key, Ipld::Link(cid));
+            }
+            let root = Ipld::Map(map);
+            batch.insert(&root)?;
+            roots.push((IndexManager::alias(&index.name), old, root));
+        }
+        // the last document is pinned by the batch
+        for block in docs.into_vec() {
+            batch.push(block);
+        }
+        let cid = self.builder.insert_batch(batch).await?;
+        for (alias, old, root) in roots {
+            // pins the index root
+            let root = self.builder.insert(&root).await?;
+            self.builder.alias(&alias, &root).await?;
+            if let Some(old) = old {
+                self.builder.unpin(&old).await?;
+            }
+        }
+        Ok(cid)
+    }
+
+    async fn find(&self, name: &str, value: &Ipld) -> Result<Vec<Cid>> {
+        if !self.manager.contains(name) {
+            return Err(Error::UnknownIndex(name.to_string()));
+        }
+        let map = match self.root(name).await? {
+            Some((_, map)) => map,
+            None => return Ok(vec![]),
+        };
+        match map.get(&key(value)?) {
+            Some(Ipld::Link(cid)) => links(cid, self.builder.get_ipld(cid).await?),
+            _ => Ok(vec![]),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::{Cache, IpldCache};
+    use crate::Codec;
+    use libipld::ipld;
+    use libipld::mem::MemStore;
+
+    #[async_std::test]
+    async fn test_indexes() {
+        let store = MemStore::default();
+        let mut cache = IpldCache::<_, _, Ipld>::new(store.clone(), Codec::new(), 4);
+        let mut indexes = IndexManager::new();
+        indexes.add_path_index("author", "meta/author");
+        indexes.add_index("tags", |doc| match doc.get("tags") {
+            Ok(Ipld::List(tags)) => tags.clone(),
+            _ => vec![],
+        });
+        cache.set_indexes(indexes);
+
+        let a = cache
+            .insert(ipld!({ "meta": { "author": "alice" }, "tags": ["a", "b"] }))
+            .await
+            .unwrap();
+        let mut batch = cache.create_batch();
+        let b = batch
+            .insert(ipld!({ "meta": { "author": "bob" }, "tags": ["b"] }))
+            .unwrap()
+            .clone();
+        let c = batch
+            .insert(ipld!({ "meta": { "author": "alice" } }))
+            .unwrap()
+            .clone();
+        assert_eq!(cache.insert_batch(batch).await.unwrap(), c);
+
+        let alice = Ipld::String("alice".into());
+        assert_eq!(
+            cache.find("author", &alice).await.unwrap(),
+            vec![a.clone(), c]
+        );
+        let bob = Ipld::String("bob".into());
+        assert_eq!(cache.find("author", &bob).await.unwrap(), vec![b.clone()]);
+        let tag = Ipld::String("b".into());
+        assert_eq!(cache.find("tags", &tag).await.unwrap(), vec![a, b]);
+        let carol = Ipld::String("carol".into());
+        assert!(cache.find("author", &carol).await.unwrap().is_empty());
+        assert!(cache.find("title", &carol).await.is_err());
+        assert!(store
+            .resolve(&IndexManager::alias("author"))
+            .await
+            .unwrap()
+            .is_some());
+    }
+}
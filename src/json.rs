@@ -0,0 +1,113 @@
+use crate::builder::BlockBuilder;
+use crate::codec::Encoder;
+use crate::error::{Error, Result};
+use core::convert::TryFrom;
+use libipld::cid::Cid;
+use libipld::codec::Encode;
+use libipld::ipld::Ipld;
+use libipld::multibase::Base;
+use libipld::store::Store;
+use serde_json::{Map, Value};
+
+fn invalid(msg: impl Into<String>) -> Error {
+    Error::InvalidJson(msg.into())
+}
+
+fn from_map(mut map: Map<String, Value>) -> Result<Ipld> {
+    if map.len() == 1 {
+        match map.remove("/") {
+            Some(Value::String(cid)) => {
+                let cid = Cid::try_from(cid.as_str())
+                    .map_err(|err| invalid(format!("invalid link {}: {}", cid, err)))?;
+                return Ok(Ipld::Link(cid));
+            }
+            Some(Value::Object(mut inner)) if inner.len() == 1 => match inner.remove("bytes") {
+                Some(Value::String(bytes)) => {
+                    let bytes = Base::Base64
+                        .decode(bytes.trim_end_matches('='))
+                        .map_err(|err| invalid(format!("invalid bytes: {}", err)))?;
+                    return Ok(Ipld::Bytes(bytes));
+                }
+                _ => return Err(invalid("expected {\"/\": {\"bytes\": \"...\"}}")),
+            },
+            Some(_) => return Err(invalid("reserved key \"/\"")),
+            None => {}
+        }
+    }
+    map.into_iter()
+        .map(|(key, value)| Ok((key, from_json(value)?)))
+        .collect::<Result<_>>()
+        .map(Ipld::Map)
+}
+
+fn from_json(value: Value) -> Result<Ipld> {
+    Ok(match value {
+        Value::Null => Ipld::Null,
+        Value::Bool(b) => Ipld::Bool(b),
+        Value::Number(n) => {
+            if let Some(i) = n.as_i64() {
+                Ipld::Integer(i.into())
+            } else if let Some(u) = n.as_u64() {
+                Ipld::Integer(u.into())
+            } else {
+                Ipld::Float(n.as_f64().unwrap_or_default())
+            }
+        }
+        Value::String(s) => Ipld::String(s),
+        Value::Array(list) => Ipld::List(list.into_iter().map(from_json).collect::<Result<_>>()?),
+        Value::Object(map) => from_map(map)?,
+    })
+}
+
+/// Parses dag-json into ipld.
+pub fn parse_json(json: &str) -> Result<Ipld> {
+    let value = serde_json::from_str(json).map_err(|err| invalid(err.to_string()))?;
+    from_json(value)
+}
+
+impl<S, C> BlockBuilder<S, C>
+where
+    S: Store,
+    C: Encoder + Clone,
+    Ipld: Encode<C::Codec>,
+{
+    /// Parses dag-json and inserts it as a block encoded with the codec.
+    pub async fn insert_json(&self, json: &str) -> Result<Cid> {
+        self.insert(&parse_json(json)?).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Codec;
+    use libipld::ipld;
+    use libipld::mem::MemStore;
+
+    #[async_std::test]
+    async fn test_insert_json() {
+        let builder = BlockBuilder::new(MemStore::default(), Codec::new());
+        let leaf = builder.insert(&ipld!({"a": 1})).await.unwrap();
+        let json = format!(
+            r#"{{"leaf": {{"/": "{}"}}, "bytes": {{"/": {{"bytes": "AQID"}}}}, "l": [-1, 1.5, null, "s"]}}"#,
+            leaf
+        );
+        let root = builder.insert_json(&json).await.unwrap();
+        let ipld = builder.get_ipld(&root).await.unwrap();
+        assert_eq!(
+            ipld,
+            ipld!({
+                "leaf": leaf,
+                "bytes": vec![1u8, 2, 3],
+                "l": [-1, 1.5, null, "s"],
+            })
+        );
+
+        let dump = builder.dump(&root, 0).await.unwrap();
+        let again = builder.insert_json(&dump).await.unwrap();
+        assert_eq!(again, root);
+
+        assert!(builder.insert_json(r#"{"/": "notacid"}"#).await.is_err());
+        assert!(builder.insert_json("{").await.is_err());
+    }
+}
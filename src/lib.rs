@@ -2,27 +2,162 @@
 #![deny(missing_docs)]
 #![deny(warnings)]
 
+extern crate alloc;
+
+mod announce;
+#[cfg(feature = "audit")]
+mod audit;
 mod batch;
+#[cfg(feature = "bincode")]
+mod bincode_codec;
+mod budget;
 mod builder;
 mod cache;
+#[cfg(feature = "signing")]
+mod capability;
+mod car;
+mod check;
 mod codec;
+pub mod collections;
+#[cfg(feature = "concurrent")]
+mod concurrent;
+#[cfg(feature = "crdt")]
+mod crdt;
 #[cfg(feature = "crypto")]
 mod crypto;
+mod dedup;
+#[cfg(feature = "signing")]
+mod did;
+mod docstore;
+mod dump;
+mod error;
+mod eviction;
+mod expiry;
+mod graphsync;
+mod health;
+mod index;
+#[cfg(feature = "json")]
+mod json;
+mod merge;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+mod observer;
 mod path;
+mod pinset;
+#[cfg(feature = "json")]
+mod plain_json;
+mod pool;
+mod prefetch;
+mod project;
+mod prune;
+mod query;
+#[cfg(feature = "crypto")]
+mod ratchet;
+mod rekey;
+mod root;
+mod rt;
+#[cfg(feature = "serde")]
+mod serde_codec;
+#[cfg(feature = "crypto")]
+mod share;
+#[cfg(feature = "signing")]
+mod signed_head;
+mod store;
+#[cfg(feature = "sync")]
+mod sync;
+#[cfg(feature = "crypto")]
+mod tenant;
+mod timeout;
+mod versioning;
+#[cfg(feature = "fs")]
+mod wal;
+mod walk;
 
+pub use announce::{HeadUpdate, Signer, Verifier};
+#[cfg(feature = "audit")]
+pub use audit::{AuditEntry, AuditOp, AuditStore, AUDIT_LOG};
 pub use batch::Batch;
+#[cfg(feature = "bincode")]
+pub use bincode_codec::{Bin, Bincode};
+pub use budget::{Acquire, MemoryBudget, Reservation};
 pub use builder::BlockBuilder;
-pub use cache::{Cache, CacheBatch, IpldCache, ReadonlyCache};
+pub use cache::{Cache, CacheBatch, CacheKey, IpldCache, ReadonlyCache};
+#[cfg(feature = "signing")]
+pub use capability::{Ability, AuthorizedStore, CapabilityToken};
+pub use car::CarProgress;
+pub use check::{CheckReport, Damage, DamagedBlock, MissingBlock, MissingReport};
 pub use codec::*;
+#[cfg(feature = "concurrent")]
+pub use concurrent::ConcurrentIpldCache;
+#[cfg(feature = "crdt")]
+pub use crdt::{Crdt, GCounter, LwwRegister, OrSet};
+#[cfg(feature = "crypto")]
+pub use crypto::{decode_codec, decode_header, Error as CryptoError, Key, MAX_HEADER_LEN};
+pub use dedup::{DedupReport, DuplicatedSubtree};
+#[cfg(feature = "signing")]
+pub use did::DidKey;
+pub use docstore::DocStore;
+pub use dump::to_json;
+#[cfg(feature = "signing")]
+pub use ed25519_dalek::{SigningKey, VerifyingKey};
+pub use error::{Error, IntegrityError, Result};
+pub use eviction::EvictionPolicy;
+pub use expiry::ExpiringPins;
+pub use graphsync::{GraphsyncReport, GraphsyncRequest, Selector};
+pub use health::Health;
+pub use index::{Extract, IndexManager};
+#[cfg(feature = "json")]
+pub use json::parse_json;
+pub use merge::Resolver;
+pub use observer::Observer;
+pub use path::{DagPath, IpldPath, IpldView, PathStats};
+pub use pinset::PinSet;
+#[cfg(feature = "json")]
+pub use plain_json::{Json, PlainJson};
+pub use pool::{BufferPool, PooledBuffer};
+pub use prefetch::Prefetcher;
+pub use prune::PrunePolicy;
+pub use query::{Predicate, Query};
+#[cfg(feature = "crypto")]
+pub use ratchet::{Ratchet, RatchetLog};
+pub use root::Root;
+#[cfg(feature = "serde")]
+pub use serde_codec::{from_ipld, to_ipld, Serde, SerdeCodec, SerdeError};
+#[cfg(feature = "crypto")]
+pub use share::SharedSubtree;
+#[cfg(feature = "signing")]
+pub use signed_head::{SignedHead, DEFAULT_VALIDITY};
+pub use store::*;
+#[cfg(feature = "sync")]
+pub use sync::{SyncBlockBuilder, SyncIpldCache};
 #[cfg(feature = "crypto")]
-pub use crypto::{Error, Key};
-pub use path::DagPath;
+pub use tenant::{TenantRouter, TenantStore};
+pub use timeout::Timeouts;
+pub use versioning::{Commit, History, RetentionPolicy, RetentionReport};
+#[cfg(feature = "fs")]
+pub use wal::Wal;
+pub use walk::{Emission, Traversal, Visitor, WalkControl, WalkOptions};
 
 use libipld::cbor::DagCborCodec;
 use libipld::multihash::Blake2b256;
 
 /// Default codec.
+///
+/// Blocks are hashed with `Blake2b256`, which is backed by `blake2b_simd` and
+/// picks the fastest SIMD implementation at runtime. Other hashers are
+/// selected with `GenericCodec<DagCborCodec, H>`, limited to the codes known
+/// to multihash 0.11, which has no BLAKE3 or parallel Blake2bp code. Large
+/// batches are hashed on all cores with `BlockBuilder::insert_many_owned`.
 pub type Codec = GenericCodec<DagCborCodec, Blake2b256>;
+/// Bincode codec.
+#[cfg(feature = "bincode")]
+pub type BincodeCodec = GenericCodec<Bincode, Blake2b256>;
+/// Plain json codec.
+#[cfg(feature = "json")]
+pub type JsonCodec = GenericCodec<PlainJson, Blake2b256>;
+/// Codec for serde types encoded as dag-cbor.
+#[cfg(feature = "serde")]
+pub type SerdeCborCodec = GenericCodec<SerdeCodec<DagCborCodec>, Blake2b256>;
 /// Default encrypted codec.
 #[cfg(feature = "crypto")]
 pub type StrobeCodec = GenericStrobeCodec<DagCborCodec, Blake2b256>;
@@ -0,0 +1,209 @@
+use crate::batch::Batch;
+use crate::builder::BlockBuilder;
+use crate::codec::{Encoder, IpldDecoder};
+use crate::error::Result;
+use futures::future::{FutureExt, LocalBoxFuture};
+use libipld::cid::Cid;
+use libipld::codec::Encode;
+use libipld::ipld::Ipld;
+use libipld::store::Store;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Resolves conflicting changes during a merge.
+pub trait Resolver {
+    /// Returns the merged value at `path`, or `None` to remove it.
+    ///
+    /// A value is `None` if it doesn't exist in that version.
+    fn resolve(
+        &mut self,
+        path: &str,
+        base: Option<&Ipld>,
+        ours: Option<&Ipld>,
+        theirs: Option<&Ipld>,
+    ) -> Result<Option<Ipld>>;
+}
+
+impl<F> Resolver for F
+where
+    F: FnMut(&str, Option<&Ipld>, Option<&Ipld>, Option<&Ipld>) -> Result<Option<Ipld>>,
+{
+    fn resolve(
+        &mut self,
+        path: &str,
+        base: Option<&Ipld>,
+        ours: Option<&Ipld>,
+        theirs: Option<&Ipld>,
+    ) -> Result<Option<Ipld>> {
+        self(path, base, ours, theirs)
+    }
+}
+
+fn join(path: &str, segment: &str) -> String {
+    if path.is_empty() {
+        segment.to_string()
+    } else {
+        format!("{}/{}", path, segment)
+    }
+}
+
+struct Merger<'a, S, C, R> {
+    builder: &'a BlockBuilder<S, C>,
+    batch: Batch<C>,
+    resolver: R,
+}
+
+impl<'a, S, C, R> Merger<'a, S, C, R>
+where
+    S: Store,
+    C: Encoder + IpldDecoder + Clone,
+    Ipld: Encode<C::Codec>,
+    R: Resolver,
+{
+    fn merge(
+        &mut self,
+        path: String,
+        base: Option<Ipld>,
+        ours: Option<Ipld>,
+        theirs: Option<Ipld>,
+    ) -> LocalBoxFuture<'_, Result<Option<Ipld>>> {
+        async move {
+            if ours == theirs || base == theirs {
+                return Ok(ours);
+            }
+            if base == ours {
+                return Ok(theirs);
+            }
+            match (base, ours, theirs) {
+                (Some(Ipld::Map(mut b)), Some(Ipld::Map(mut o)), Some(Ipld::Map(mut t))) => {
+                    let keys: BTreeSet<String> =
+                        b.keys().chain(o.keys()).chain(t.keys()).cloned().collect();
+                    let mut merged = BTreeMap::new();
+                    for key in keys {
+                        let (bv, ov, tv) = (b.remove(&key), o.remove(&key), t.remove(&key));
+                        if let Some(value) = self.merge(join(&path, &key), bv, ov, tv).await? {
+                            merged.insert(key, value);
+                        }
+                    }
+                    Ok(Some(Ipld::Map(merged)))
+                }
+                (Some(Ipld::List(b)), Some(Ipld::List(o)), Some(Ipld::List(t)))
+                    if b.len() == o.len() && o.len() == t.len() =>
+                {
+                    let mut merged = Vec::with_capacity(o.len());
+                    let items = b.into_iter().zip(o.into_iter().zip(t));
+                    for (i, (bv, (ov, tv))) in items.enumerate() {
+                        let path = join(&path, &i.to_string());
+                        if let Some(value) = self.merge(path, Some(bv), Some(ov), Some(tv)).await? {
+                            merged.push(value);
+                        }
+                    }
+                    Ok(Some(Ipld::List(merged)))
+                }
+                (Some(Ipld::Link(b)), Some(Ipld::Link(o)), Some(Ipld::Link(t))) => {
+                    let b = self.builder.get_ipld(&b).await?;
+                    let o = self.builder.get_ipld(&o).await?;
+                    let t = self.builder.get_ipld(&t).await?;
+                    match self.merge(path, Some(b), Some(o), Some(t)).await? {
+                        Some(ipld) => Ok(Some(Ipld::Link(self.batch.insert(&ipld)?.clone()))),
+                        None => Ok(None),
+                    }
+                }
+                (b, o, t) => self
+                    .resolver
+                    .resolve(&path, b.as_ref(), o.as_ref(), t.as_ref()),
+            }
+        }
+        .boxed_local()
+    }
+}
+
+impl<S, C> BlockBuilder<S, C>
+where
+    S: Store,
+    C: Encoder + IpldDecoder + Clone,
+    Ipld: Encode<C::Codec>,
+{
+    /// Merges the changes from `base` to `ours` and `theirs`.
+    ///
+    /// Maps are merged key by key and lists of equal length element by
+    /// element, following links into other blocks. Values changed on both
+    /// sides are passed to the `resolver`. The merged blocks are inserted as
+    /// a batch pinning the merged root.
+    pub async fn merge<R: Resolver>(
+        &self,
+        base: &Cid,
+        ours: &Cid,
+        theirs: &Cid,
+        resolver: R,
+    ) -> Result<Cid> {
+        let base = self.get_ipld(base).await?;
+        let ours = self.get_ipld(ours).await?;
+        let theirs = self.get_ipld(theirs).await?;
+        let mut merger = Merger {
+            builder: self,
+            batch: self.create_batch(),
+            resolver,
+        };
+        let root = merger
+            .merge(String::new(), Some(base), Some(ours), Some(theirs))
+            .await?
+            .unwrap_or(Ipld::Null);
+        let mut batch = merger.batch;
+        batch.insert(&root)?;
+        self.insert_batch(batch).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Codec;
+    use libipld::ipld;
+    use libipld::mem::MemStore;
+
+    #[async_std::test]
+    async fn test_merge() {
+        let builder = BlockBuilder::new(MemStore::default(), Codec::new());
+        let child = builder.insert(&ipld!({"x": 1})).await.unwrap();
+        let base = builder
+            .insert(&ipld!({"a": 1, "b": 1, "c": child, "l": [1, 2]}))
+            .await
+            .unwrap();
+        let child = builder.insert(&ipld!({"x": 1, "y": 2})).await.unwrap();
+        let ours = builder
+            .insert(&ipld!({"a": 2, "b": 1, "c": child, "l": [3, 2]}))
+            .await
+            .unwrap();
+        let child = builder.insert(&ipld!({"x": 1, "z": 3})).await.unwrap();
+        let theirs = builder
+            .insert(&ipld!({"a": 3, "b": 5, "c": child, "l": [1, 4], "d": true}))
+            .await
+            .unwrap();
+
+        let mut conflicts = vec![];
+        let merged = builder
+            .merge(
+                &base,
+                &ours,
+                &theirs,
+                |path: &str, _: Option<&Ipld>, _: Option<&Ipld>, theirs: Option<&Ipld>| {
+                    conflicts.push(path.to_string());
+                    Ok(theirs.cloned())
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(conflicts, vec!["a".to_string()]);
+
+        let root = builder.get_ipld(&merged).await.unwrap();
+        let child = match root.get("c").unwrap() {
+            Ipld::Link(cid) => builder.get_ipld(cid).await.unwrap(),
+            _ => panic!(),
+        };
+        assert_eq!(child, ipld!({"x": 1, "y": 2, "z": 3}));
+        assert_eq!(root.get("a").unwrap(), &ipld!(3));
+        assert_eq!(root.get("b").unwrap(), &ipld!(5));
+        assert_eq!(root.get("d").unwrap(), &ipld!(true));
+        assert_eq!(root.get("l").unwrap(), &ipld!([3, 4]));
+    }
+}
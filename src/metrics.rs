@@ -0,0 +1,102 @@
+//! Metrics reported through the `metrics` facade.
+//!
+//! Install any `metrics` compatible recorder (for example a prometheus
+//! exporter) to collect them.
+use ::metrics::{counter, describe_counter, describe_histogram, histogram, Label, Unit};
+use std::time::Duration;
+
+/// Number of blocks fetched from the store.
+pub const STORE_GETS: &str = "ipld_block_builder_store_gets_total";
+/// Number of bytes fetched from the store.
+pub const STORE_GET_BYTES: &str = "ipld_block_builder_store_get_bytes_total";
+/// Latency of fetching a block from the store.
+pub const STORE_GET_LATENCY: &str = "ipld_block_builder_store_get_seconds";
+/// Number of batches inserted into the store.
+pub const STORE_INSERTS: &str = "ipld_block_builder_store_inserts_total";
+/// Number of bytes inserted into the store.
+pub const STORE_INSERT_BYTES: &str = "ipld_block_builder_store_insert_bytes_total";
+/// Latency of inserting a batch into the store.
+pub const STORE_INSERT_LATENCY: &str = "ipld_block_builder_store_insert_seconds";
+/// Number of blocks per inserted batch.
+pub const BATCH_SIZE: &str = "ipld_block_builder_batch_blocks";
+/// Number of cache hits.
+pub const CACHE_HITS: &str = "ipld_block_builder_cache_hits_total";
+/// Number of cache misses.
+pub const CACHE_MISSES: &str = "ipld_block_builder_cache_misses_total";
+/// Number of values inserted through a cache.
+pub const CACHE_INSERTS: &str = "ipld_block_builder_cache_inserts_total";
+/// Latency of decoding a block on a cache miss.
+pub const CACHE_DECODE_LATENCY: &str = "ipld_block_builder_cache_decode_seconds";
+/// Label with the field name of caches derived with `derive_cache!(.., metrics)`.
+pub const CACHE_LABEL: &str = "cache";
+
+/// Registers the descriptions of all metrics with the installed recorder.
+pub fn describe() {
+    describe_counter!(STORE_GETS, "Number of blocks fetched from the store.");
+    describe_counter!(
+        STORE_GET_BYTES,
+        Unit::Bytes,
+        "Number of bytes fetched from the store."
+    );
+    describe_histogram!(
+        STORE_GET_LATENCY,
+        Unit::Seconds,
+        "Latency of fetching a block from the store."
+    );
+    describe_counter!(STORE_INSERTS, "Number of batches inserted into the store.");
+    describe_counter!(
+        STORE_INSERT_BYTES,
+        Unit::Bytes,
+        "Number of bytes inserted into the store."
+    );
+    describe_histogram!(
+        STORE_INSERT_LATENCY,
+        Unit::Seconds,
+        "Latency of inserting a batch into the store."
+    );
+    describe_histogram!(BATCH_SIZE, "Number of blocks per inserted batch.");
+    describe_counter!(CACHE_HITS, "Number of cache hits.");
+    describe_counter!(CACHE_MISSES, "Number of cache misses.");
+    describe_counter!(CACHE_INSERTS, "Number of values inserted through a cache.");
+    describe_histogram!(
+        CACHE_DECODE_LATENCY,
+        Unit::Seconds,
+        "Latency of decoding a block on a cache miss."
+    );
+}
+
+fn cache_labels(cache: Option<&'static str>) -> Vec<Label> {
+    cache
+        .map(|cache| Label::from_static_parts(CACHE_LABEL, cache))
+        .into_iter()
+        .collect()
+}
+
+pub(crate) fn store_get(bytes: usize, latency: Duration) {
+    counter!(STORE_GETS).increment(1);
+    counter!(STORE_GET_BYTES).increment(bytes as u64);
+    histogram!(STORE_GET_LATENCY).record(latency);
+}
+
+pub(crate) fn store_insert(blocks: usize, bytes: usize, latency: Duration) {
+    counter!(STORE_INSERTS).increment(1);
+    counter!(STORE_INSERT_BYTES).increment(bytes as u64);
+    histogram!(STORE_INSERT_LATENCY).record(latency);
+    histogram!(BATCH_SIZE).record(blocks as f64);
+}
+
+pub(crate) fn cache_hit(cache: Option<&'static str>) {
+    counter!(CACHE_HITS, cache_labels(cache)).increment(1);
+}
+
+pub(crate) fn cache_miss(cache: Option<&'static str>) {
+    counter!(CACHE_MISSES, cache_labels(cache)).increment(1);
+}
+
+pub(crate) fn cache_insert(cache: Option<&'static str>, values: usize) {
+    counter!(CACHE_INSERTS, cache_labels(cache)).increment(values as u64);
+}
+
+pub(crate) fn cache_decode(cache: Option<&'static str>, latency: Duration) {
+    histogram!(CACHE_DECODE_LATENCY, cache_labels(cache)).record(latency);
+}
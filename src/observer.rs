@@ -0,0 +1,22 @@
+use libipld::block::Block;
+use libipld::cid::Cid;
+
+/// Observer of block builder operations.
+///
+/// Callbacks are invoked after the operation succeeded.
+pub trait Observer: Send + Sync {
+    /// Called when a block was inserted.
+    fn on_insert(&self, _block: &Block) {}
+
+    /// Called with the links of a block inserted with `insert_with_refs`.
+    fn on_refs(&self, _cid: &Cid, _refs: &[Cid]) {}
+
+    /// Called when a block was fetched.
+    fn on_get(&self, _cid: &Cid) {}
+
+    /// Called when a block was unpinned.
+    fn on_unpin(&self, _cid: &Cid) {}
+
+    /// Called when an alias was set to a cid or removed.
+    fn on_alias(&self, _alias: &[u8], _cid: Option<&Cid>) {}
+}
@@ -1,4 +1,7 @@
+use core::ops::Deref;
+use core::time::Duration;
 use libipld::cid::Cid;
+use libipld::ipld::Ipld;
 pub use libipld::path::Path as IpldPath;
 
 /// Path in a dag.
@@ -22,8 +25,71 @@ impl<'a> DagPath<'a> {
     }
 }
 
+/// Statistics of a path resolution.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct PathStats {
+    /// Number of blocks fetched.
+    pub blocks: usize,
+    /// Number of bytes decoded.
+    pub bytes: usize,
+    /// Number of blocks served by the prefetcher.
+    pub cache_hits: usize,
+    /// Time spent resolving the path.
+    pub elapsed: Duration,
+}
+
 impl<'a> From<&'a Cid> for DagPath<'a> {
     fn from(cid: &'a Cid) -> Self {
         Self(cid, Default::default())
     }
 }
+
+/// Ipld resolved by a path, borrowed from the last block the path crosses.
+///
+/// Dereferencing walks the remainder of the path inside the block without
+/// cloning, `into_ipld` moves the value out of the block.
+#[derive(Clone, Debug, PartialEq)]
+pub struct IpldView {
+    block: Ipld,
+    path: IpldPath,
+}
+
+impl IpldView {
+    /// Creates a view, `path` needs to resolve inside `block`.
+    pub(crate) fn new(block: Ipld, path: IpldPath) -> Self {
+        Self { block, path }
+    }
+
+    /// Returns the last block crossed by the path.
+    pub fn block(&self) -> &Ipld {
+        &self.block
+    }
+
+    /// Returns the remainder of the path inside the block.
+    pub fn path(&self) -> &IpldPath {
+        &self.path
+    }
+
+    /// Returns the resolved ipld.
+    pub fn into_ipld(self) -> Ipld {
+        let mut ipld = self.block;
+        for segment in self.path.iter() {
+            ipld = match ipld {
+                Ipld::List(mut list) => list.swap_remove(segment.parse().unwrap()),
+                Ipld::Map(mut map) => map.remove(segment).unwrap(),
+                _ => unreachable!("path resolves inside the block"),
+            };
+        }
+        ipld
+    }
+}
+
+impl Deref for IpldView {
+    type Target = Ipld;
+
+    fn deref(&self) -> &Ipld {
+        self.path.iter().fold(&self.block, |ipld, segment| {
+            ipld.get(segment).expect("path resolves inside the block")
+        })
+    }
+}
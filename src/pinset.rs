@@ -0,0 +1,144 @@
+use crate::builder::BlockBuilder;
+use crate::codec::{Encoder, IpldDecoder};
+use crate::error::Result;
+use futures::lock::Mutex;
+use libipld::cid::Cid;
+use libipld::codec::Encode;
+use libipld::ipld::Ipld;
+use libipld::store::{AliasStore, Store};
+use std::collections::BTreeSet;
+
+/// Named set of pinned roots.
+///
+/// The set is stored as a list of links in a single block, which is
+/// referenced by the alias `pinset/<name>`. Every change writes a new block
+/// and unpins the previous one.
+pub struct PinSet<S, C> {
+    builder: BlockBuilder<S, C>,
+    alias: Vec<u8>,
+    lock: Mutex<()>,
+}
+
+impl<S, C> PinSet<S, C> {
+    /// Creates a pin set named `name`.
+    pub fn new(store: S, codec: C, name: &str) -> Self {
+        Self {
+            builder: BlockBuilder::new(store, codec),
+            alias: format!("pinset/{}", name).into_bytes(),
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// Returns the alias of the pin set.
+    pub fn alias(&self) -> &[u8] {
+        &self.alias
+    }
+}
+
+impl<S, C> PinSet<S, C>
+where
+    S: Store + AliasStore,
+    C: Encoder + IpldDecoder + Clone,
+    Ipld: Encode<C::Codec>,
+{
+    async fn read(&self) -> Result<(Option<Cid>, BTreeSet<Cid>)> {
+        let root = match self.builder.resolve(&self.alias).await? {
+            Some(root) => root,
+            None => return Ok((None, Default::default())),
+        };
+        let cids = match self.builder.get_ipld(&root).await? {
+            Ipld::List(links) => links
+                .into_iter()
+                .filter_map(|ipld| match ipld {
+                    Ipld::Link(cid) => Some(cid),
+                    _ => None,
+                })
+                .collect(),
+            _ => Default::default(),
+        };
+        Ok((Some(root), cids))
+    }
+
+    async fn write(&self, old: Option<Cid>, cids: BTreeSet<Cid>) -> Result<()> {
+        if cids.is_empty() {
+            self.builder.unalias(&self.alias).await?;
+        } else {
+            let ipld = Ipld::List(cids.into_iter().map(Ipld::Link).collect());
+            let root = self.builder.insert(&ipld).await?;
+            if Some(&root) == old.as_ref() {
+                return self.builder.unpin(&root).await;
+            }
+            self.builder.alias(&self.alias, &root).await?;
+        }
+        if let Some(old) = old {
+            self.builder.unpin(&old).await?;
+        }
+        Ok(())
+    }
+
+    /// Adds a root to the pin set.
+    pub async fn add(&self, cid: &Cid) -> Result<()> {
+        let _guard = self.lock.lock().await;
+        let (root, mut cids) = self.read().await?;
+        if !cids.insert(cid.clone()) {
+            return Ok(());
+        }
+        self.write(root, cids).await
+    }
+
+    /// Removes a root from the pin set.
+    pub async fn remove(&self, cid: &Cid) -> Result<()> {
+        let _guard = self.lock.lock().await;
+        let (root, mut cids) = self.read().await?;
+        if !cids.remove(cid) {
+            return Ok(());
+        }
+        self.write(root, cids).await
+    }
+
+    /// Returns if the pin set contains a root.
+    pub async fn contains(&self, cid: &Cid) -> Result<bool> {
+        Ok(self.read().await?.1.contains(cid))
+    }
+
+    /// Lists the roots of the pin set.
+    pub async fn ls(&self) -> Result<Vec<Cid>> {
+        Ok(self.read().await?.1.into_iter().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Codec;
+    use libipld::ipld;
+    use libipld::mem::MemStore;
+
+    #[async_std::test]
+    async fn test_pin_set() {
+        let store = MemStore::default();
+        let builder = BlockBuilder::new(store.clone(), Codec::new());
+        let a = builder.insert(&ipld!({"a": 1})).await.unwrap();
+        let b = builder.insert(&ipld!({"b": 2})).await.unwrap();
+
+        let backups = PinSet::new(store.clone(), Codec::new(), "backups");
+        let uploads = PinSet::new(store.clone(), Codec::new(), "uploads");
+        backups.add(&a).await.unwrap();
+        backups.add(&b).await.unwrap();
+        backups.add(&a).await.unwrap();
+        uploads.add(&b).await.unwrap();
+
+        let mut expected = vec![a.clone(), b.clone()];
+        expected.sort();
+        assert_eq!(backups.ls().await.unwrap(), expected);
+        assert_eq!(uploads.ls().await.unwrap(), vec![b.clone()]);
+
+        let backups = PinSet::new(store.clone(), Codec::new(), "backups");
+        backups.remove(&a).await.unwrap();
+        assert!(!backups.contains(&a).await.unwrap());
+        assert_eq!(backups.ls().await.unwrap(), vec![b.clone()]);
+        backups.remove(&b).await.unwrap();
+        assert!(backups.ls().await.unwrap().is_empty());
+        assert_eq!(builder.resolve(backups.alias()).await.unwrap(), None);
+    }
+}
@@ -0,0 +1,59 @@
+use libipld::cid::Codec as Code;
+use libipld::codec::{Codec, Decode, Encode};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::io::{Read, Write};
+
+/// Plain json codec for serde types.
+///
+/// Unlike dag-json values aren't converted to ipld, so links aren't
+/// supported. There is no multicodec for plain json, blocks are tagged `Raw`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PlainJson;
+
+impl Codec for PlainJson {
+    const CODE: Code = Code::Raw;
+
+    type Error = serde_json::Error;
+}
+
+/// Wraps a serde type to encode it with `PlainJson`.
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+pub struct Json<T>(pub T);
+
+impl<T: Serialize> Encode<PlainJson> for Json<T> {
+    fn encode<W: Write>(&self, w: &mut W) -> Result<(), serde_json::Error> {
+        serde_json::to_writer(w, &self.0)
+    }
+}
+
+impl<T: DeserializeOwned> Decode<PlainJson> for Json<T> {
+    fn decode<R: Read>(r: &mut R) -> Result<Self, serde_json::Error> {
+        serde_json::from_reader(r).map(Json)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BlockBuilder, JsonCodec};
+    use libipld::mem::MemStore;
+    use std::collections::BTreeMap;
+
+    #[async_std::test]
+    async fn test_plain_json() {
+        let builder = BlockBuilder::new(MemStore::default(), JsonCodec::new());
+        let value = serde_json::json!({"user": "alice", "ids": [1, 2]});
+        let cid = builder.insert(&Json(value.clone())).await.unwrap();
+        assert_eq!(cid.codec(), Code::Raw);
+        let Json(value2): Json<serde_json::Value> = builder.get(&cid).await.unwrap();
+        assert_eq!(value2, value);
+
+        let mut map = BTreeMap::new();
+        map.insert("a".to_string(), 1u32);
+        let cid = builder.insert(&Json(map.clone())).await.unwrap();
+        let map2: Json<BTreeMap<String, u32>> = builder.get(&cid).await.unwrap();
+        assert_eq!(map2, Json(map));
+        assert!(builder.get::<Json<u32>>(&cid).await.is_err());
+    }
+}
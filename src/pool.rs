@@ -0,0 +1,134 @@
+use core::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex, OnceLock};
+
+/// Default number of idle buffers kept by the global pool.
+const MAX_BUFFERS: usize = 64;
+
+/// Default capacity above which buffers are freed instead of pooled.
+const MAX_CAPACITY: usize = libipld::MAX_BLOCK_SIZE;
+
+/// Pool of reusable byte buffers.
+///
+/// Encoders serialize values into pooled scratch buffers, so bulk
+/// ingestion doesn't grow and free a fresh `Vec` for every block. Only the
+/// final block data, which the block owns, is allocated once at its final
+/// size. Buffers holding plaintext of encrypted blocks are taken with
+/// `get_secret` and wiped before they are pooled again.
+#[derive(Clone)]
+pub struct BufferPool {
+    buffers: Arc<Mutex<Vec<Vec<u8>>>>,
+    max_buffers: usize,
+    max_capacity: usize,
+}
+
+impl BufferPool {
+    /// Creates a pool keeping up to `max_buffers` idle buffers of at most
+    /// `max_capacity` bytes.
+    pub fn new(max_buffers: usize, max_capacity: usize) -> Self {
+        Self {
+            buffers: Default::default(),
+            max_buffers,
+            max_capacity,
+        }
+    }
+
+    /// Returns the pool shared by the encoders.
+    pub fn global() -> &'static Self {
+        static POOL: OnceLock<BufferPool> = OnceLock::new();
+        POOL.get_or_init(|| Self::new(MAX_BUFFERS, MAX_CAPACITY))
+    }
+
+    /// Takes an empty buffer from the pool, allocating one if the pool is
+    /// empty.
+    pub fn get(&self) -> PooledBuffer<'_> {
+        let buf = self.buffers.lock().unwrap().pop().unwrap_or_default();
+        PooledBuffer {
+            buf,
+            pool: self,
+            #[cfg(feature = "crypto")]
+            secret: false,
+        }
+    }
+
+    /// Takes an empty buffer like `get`, which is zeroized before it is
+    /// returned to the pool.
+    #[cfg(feature = "crypto")]
+    pub fn get_secret(&self) -> PooledBuffer<'_> {
+        let mut buf = self.get();
+        buf.secret = true;
+        buf
+    }
+
+    /// Returns the number of idle buffers.
+    pub fn idle(&self) -> usize {
+        self.buffers.lock().unwrap().len()
+    }
+
+    fn put(&self, mut buf: Vec<u8>) {
+        if buf.capacity() == 0 || buf.capacity() > self.max_capacity {
+            return;
+        }
+        buf.clear();
+        let mut buffers = self.buffers.lock().unwrap();
+        if buffers.len() < self.max_buffers {
+            buffers.push(buf);
+        }
+    }
+}
+
+/// Buffer returned to its pool when dropped.
+pub struct PooledBuffer<'a> {
+    buf: Vec<u8>,
+    pool: &'a BufferPool,
+    #[cfg(feature = "crypto")]
+    secret: bool,
+}
+
+impl Deref for PooledBuffer<'_> {
+    type Target = Vec<u8>;
+
+    fn deref(&self) -> &Vec<u8> {
+        &self.buf
+    }
+}
+
+impl DerefMut for PooledBuffer<'_> {
+    fn deref_mut(&mut self) -> &mut Vec<u8> {
+        &mut self.buf
+    }
+}
+
+impl Drop for PooledBuffer<'_> {
+    fn drop(&mut self) {
+        #[cfg(feature = "crypto")]
+        if self.secret {
+            // wipes the whole capacity
+            zeroize::Zeroize::zeroize(&mut self.buf);
+        }
+        self.pool.put(core::mem::take(&mut self.buf));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_buffer_pool() {
+        let pool = BufferPool::new(1, 16);
+        let mut a = pool.get();
+        a.extend_from_slice(b"hello");
+        let ptr = a.as_ptr();
+        let mut b = pool.get();
+        b.extend_from_slice(&[0; 32]);
+        drop(a);
+        // too large to be pooled
+        drop(b);
+        assert_eq!(pool.idle(), 1);
+
+        let a = pool.get();
+        assert!(a.is_empty());
+        assert_eq!(a.as_ptr(), ptr);
+        assert_eq!(pool.idle(), 0);
+    }
+}
@@ -0,0 +1,203 @@
+use crate::budget::{MemoryBudget, Reclaim, Reservation};
+use crate::eviction::{EvictionCache, EvictionPolicy};
+use futures::channel::mpsc;
+use futures::future::{AbortHandle, Abortable};
+use futures::{Future, StreamExt};
+use libipld::cid::Cid;
+use libipld::store::ReadonlyStore;
+use std::sync::{Arc, Mutex, Weak};
+
+type BlockCache = Arc<Mutex<EvictionCache<Cid, (Box<[u8]>, Option<Reservation>)>>>;
+
+struct Inner {
+    cache: BlockCache,
+    queue: Mutex<mpsc::Sender<Cid>>,
+    abort: AbortHandle,
+}
+
+impl Drop for Inner {
+    fn drop(&mut self) {
+        self.abort.abort();
+    }
+}
+
+/// Speculatively fetches links of decoded blocks into a raw block cache.
+///
+/// Links are queued in a bounded queue, when the queue is full new links are
+/// dropped. The fetches are performed by the future returned from
+/// `Prefetcher::new`, which must be spawned on an executor and completes when
+/// the last clone of the prefetcher is dropped.
+#[derive(Clone)]
+pub struct Prefetcher {
+    inner: Arc<Inner>,
+}
+
+impl Prefetcher {
+    /// Creates a prefetcher caching up to `cache_size` blocks from `store`,
+    /// with at most `queue_size` pending links fetched `parallelism` at a
+    /// time.
+    pub fn new<S>(
+        store: S,
+        cache_size: usize,
+        queue_size: usize,
+        parallelism: usize,
+    ) -> (Self, impl Future<Output = ()> + Send + 'static)
+    where
+        S: ReadonlyStore + Send + Sync + 'static,
+    {
+        Self::build(store, cache_size, queue_size, parallelism, None)
+    }
+
+    /// Creates a prefetcher like `new`, whose cached blocks hold a
+    /// reservation of `budget`.
+    ///
+    /// Prefetched blocks that don't fit into the budget are dropped, cached
+    /// blocks are evicted while a batch waits for the budget.
+    pub fn with_budget<S>(
+        store: S,
+        cache_size: usize,
+        queue_size: usize,
+        parallelism: usize,
+        budget: MemoryBudget,
+    ) -> (Self, impl Future<Output = ()> + Send + 'static)
+    where
+        S: ReadonlyStore + Send + Sync + 'static,
+    {
+        Self::build(store, cache_size, queue_size, parallelism, Some(budget))
+    }
+
+    fn build<S>(
+        store: S,
+        cache_size: usize,
+        queue_size: usize,
+        parallelism: usize,
+        budget: Option<MemoryBudget>,
+    ) -> (Self, impl Future<Output = ()> + Send + 'static)
+    where
+        S: ReadonlyStore + Send + Sync + 'static,
+    {
+        let cache: BlockCache = Arc::new(Mutex::new(EvictionCache::new(
+            EvictionPolicy::Lru,
+            cache_size,
+        )));
+        if let Some(budget) = &budget {
+            let blocks: Weak<dyn Reclaim> = Arc::downgrade(&cache) as _;
+            budget.add_reclaimer(blocks);
+        }
+        let (queue, links) = mpsc::channel(queue_size);
+        let (abort, registration) = AbortHandle::new_pair();
+        let blocks = cache.clone();
+        let task = links.for_each_concurrent(parallelism.max(1), move |cid: Cid| {
+            let store = store.clone();
+            let blocks = blocks.clone();
+            let budget = budget.clone();
+            async move {
+                if blocks.lock().unwrap().contains(&cid) {
+                    return;
+                }
+                if let Ok(data) = store.get(&cid).await {
+                    let reservation = match &budget {
+                        Some(budget) => match budget.try_acquire(data.len()) {
+                            Some(reservation) => Some(reservation),
+                            None => return,
+                        },
+                        None => None,
+                    };
+                    blocks.lock().unwrap().insert(cid, (data, reservation));
+                }
+            }
+        });
+        let task = Abortable::new(task, registration);
+        let prefetcher = Self {
+            inner: Arc::new(Inner {
+                cache,
+                queue: Mutex::new(queue),
+                abort,
+            }),
+        };
+        (prefetcher, async move {
+            task.await.ok();
+        })
+    }
+
+    /// Queues a block for prefetching.
+    pub fn prefetch(&self, cid: &Cid) {
+        if self.contains(cid) {
+            return;
+        }
+        self.inner.queue.lock().unwrap().try_send(cid.clone()).ok();
+    }
+
+    /// Returns if the block is in the cache.
+    pub fn contains(&self, cid: &Cid) -> bool {
+        self.inner.cache.lock().unwrap().contains(cid)
+    }
+
+    /// Takes a block out of the cache.
+    ///
+    /// Prefetched blocks are handed out once, so they can be decoded without
+    /// copying them.
+    pub(crate) fn get(&self, cid: &Cid) -> Option<Box<[u8]>> {
+        let mut cache = self.inner.cache.lock().unwrap();
+        cache.remove(cid).map(|(data, _)| data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BlockBuilder, Codec};
+    use libipld::ipld;
+    use libipld::ipld::Ipld;
+    use libipld::mem::MemStore;
+    use libipld::store::StoreResult;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    #[derive(Clone, Default)]
+    struct CountingStore {
+        store: MemStore,
+        gets: Arc<AtomicUsize>,
+    }
+
+    impl ReadonlyStore for CountingStore {
+        fn get<'a>(&'a self, cid: &'a Cid) -> StoreResult<'a, Box<[u8]>> {
+            self.gets.fetch_add(1, Ordering::SeqCst);
+            self.store.get(cid)
+        }
+    }
+
+    #[async_std::test]
+    async fn test_prefetcher() {
+        let store = MemStore::default();
+        let builder = BlockBuilder::new(store.clone(), Codec::new());
+        let a = builder.insert(&ipld!({"a": 1})).await.unwrap();
+        let b = builder.insert(&ipld!({"b": 2})).await.unwrap();
+        let root = builder
+            .insert(&ipld!([a.clone(), b.clone()]))
+            .await
+            .unwrap();
+
+        let counting = CountingStore {
+            store: store.clone(),
+            gets: Default::default(),
+        };
+        let (prefetcher, task) = Prefetcher::new(store, 16, 16, 2);
+        let task = async_std::task::spawn(task);
+        let mut builder = BlockBuilder::new(counting.clone(), Codec::new());
+        builder.set_prefetcher(prefetcher.clone());
+
+        builder.get_ipld(&root).await.unwrap();
+        while !prefetcher.contains(&a) || !prefetcher.contains(&b) {
+            futures_timer::Delay::new(Duration::from_millis(1)).await;
+        }
+        let ipld: Ipld = builder.get(&b).await.unwrap();
+        assert_eq!(ipld, ipld!({"b": 2}));
+        assert_eq!(counting.gets.load(Ordering::SeqCst), 1);
+        assert!(!prefetcher.contains(&b));
+
+        drop(prefetcher);
+        drop(builder);
+        task.await;
+    }
+}
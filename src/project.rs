@@ -0,0 +1,158 @@
+use crate::builder::BlockBuilder;
+use crate::codec::IpldDecoder;
+use crate::error::{Error, Result};
+use libipld::cbor::{DagCborCodec, Error as CborError};
+use libipld::cid::{Cid, Codec as CidCodec};
+use libipld::codec::Decode;
+use libipld::error::{TypeError, TypeErrorType};
+use libipld::ipld::Ipld;
+use libipld::store::ReadonlyStore;
+use std::collections::BTreeMap;
+
+/// Reads the header of a data item, returning the major type and argument.
+fn header(buf: &mut &[u8]) -> core::result::Result<(u8, u64), CborError> {
+    let (&byte, rest) = buf.split_first().ok_or(CborError::UnexpectedEof)?;
+    let len = match byte & 0x1f {
+        info @ 0..=23 => {
+            *buf = rest;
+            return Ok((byte >> 5, u64::from(info)));
+        }
+        24 => 1,
+        25 => 2,
+        26 => 4,
+        27 => 8,
+        _ => return Err(CborError::UnexpectedCode),
+    };
+    if rest.len() < len {
+        return Err(CborError::UnexpectedEof);
+    }
+    let arg = rest[..len]
+        .iter()
+        .fold(0, |arg, b| (arg << 8) | u64::from(*b));
+    *buf = &rest[len..];
+    Ok((byte >> 5, arg))
+}
+
+fn advance<'a>(buf: &mut &'a [u8], len: usize) -> core::result::Result<&'a [u8], CborError> {
+    if buf.len() < len {
+        return Err(CborError::UnexpectedEof);
+    }
+    let (item, rest) = buf.split_at(len);
+    *buf = rest;
+    Ok(item)
+}
+
+/// Skips a data item without decoding it.
+fn skip(buf: &mut &[u8]) -> core::result::Result<(), CborError> {
+    let (major, arg) = header(buf)?;
+    match major {
+        2 | 3 => {
+            advance(buf, arg as usize)?;
+        }
+        4 => (0..arg).try_for_each(|_| skip(buf))?,
+        5 => (0..arg * 2).try_for_each(|_| skip(buf))?,
+        6 => skip(buf)?,
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Decodes the `fields` of a dag-cbor map, skipping the other entries.
+///
+/// Returns `None` if the data item isn't a map.
+fn project(mut buf: &[u8], fields: &[&str]) -> core::result::Result<Option<Ipld>, CborError> {
+    let (major, len) = header(&mut buf)?;
+    if major != 5 {
+        return Ok(None);
+    }
+    let mut map = BTreeMap::new();
+    for _ in 0..len {
+        if map.len() == fields.len() {
+            break;
+        }
+        let (major, key_len) = header(&mut buf)?;
+        if major != 3 {
+            return Err(CborError::UnexpectedCode);
+        }
+        let key = advance(&mut buf, key_len as usize)?;
+        match fields.iter().find(|field| field.as_bytes() == key) {
+            Some(field) => {
+                let value = Decode::<DagCborCodec>::decode(&mut buf)?;
+                map.insert(field.to_string(), value);
+            }
+            None => skip(&mut buf)?,
+        }
+    }
+    Ok(Some(Ipld::Map(map)))
+}
+
+impl<S: ReadonlyStore, C: IpldDecoder> BlockBuilder<S, C> {
+    /// Returns a map with the requested top-level `fields` of a block.
+    ///
+    /// For dag-cbor blocks only the requested fields are decoded and the rest
+    /// of the block is skipped. Other blocks, including encrypted ones, are
+    /// decoded completely. Fields missing in the block are missing in the
+    /// result, a block that isn't a map returns a path error.
+    pub async fn get_project(&self, cid: &Cid, fields: &[&str]) -> Result<Ipld> {
+        let data = self.get_verified(cid).await?;
+        if cid.codec() == CidCodec::DagCBOR {
+            let decode = |e| Error::decode(cid, libipld::error::Error::CodecError(Box::new(e)));
+            if let Some(ipld) = project(&data, fields).map_err(decode)? {
+                return Ok(ipld);
+            }
+        }
+        match self.codec().decode_ipld_owned(cid, data)? {
+            Ipld::Map(mut map) => Ok(Ipld::Map(
+                fields
+                    .iter()
+                    .filter_map(|field| Some((field.to_string(), map.remove(*field)?)))
+                    .collect(),
+            )),
+            ipld => Err(Error::Path {
+                path: String::new(),
+                source: TypeError::new(TypeErrorType::Map, &ipld),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Codec;
+    use libipld::ipld;
+    use libipld::mem::MemStore;
+
+    #[async_std::test]
+    async fn test_get_project() {
+        let builder = BlockBuilder::new(MemStore::default(), Codec::new());
+        let link = builder.insert(&ipld!(null)).await.unwrap();
+        let ipld = ipld!({
+            "bytes": vec![0u8; 300],
+            "float": 1.5,
+            "id": 7,
+            "link": &link,
+            "list": [1, -1000000, "s", {"nested": [true, null]}],
+            "name": "index",
+        });
+        let cid = builder.insert(&ipld).await.unwrap();
+
+        let projected = builder
+            .get_project(&cid, &["name", "link", "missing"])
+            .await
+            .unwrap();
+        assert_eq!(projected, ipld!({"link": &link, "name": "index"}));
+        let projected = builder.get_project(&cid, &["id", "float"]).await.unwrap();
+        assert_eq!(projected, ipld!({"float": 1.5, "id": 7}));
+        let projected = builder.get_project(&cid, &["list"]).await.unwrap();
+        assert_eq!(
+            &projected,
+            &ipld!({"list": ipld.get("list").unwrap().clone()})
+        );
+
+        match builder.get_project(&link, &["a"]).await {
+            Err(Error::Path { .. }) => {}
+            _ => panic!("expected path error"),
+        }
+    }
+}
@@ -0,0 +1,174 @@
+use crate::builder::BlockBuilder;
+use crate::codec::{Encoder, IpldDecoder};
+use crate::error::Result;
+use crate::walk::{links, Traversal, WalkControl, WalkOptions};
+use libipld::cid::Cid;
+use libipld::codec::Encode;
+use libipld::ipld::Ipld;
+use libipld::store::Store;
+use std::collections::HashMap;
+
+/// Policy deciding which blocks `prune` drops.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PrunePolicy {
+    /// Drops the links of blocks `max_depth` links from the root.
+    pub max_depth: Option<usize>,
+    /// Drops blocks with a `timestamp_field` lower than `older_than`.
+    pub older_than: Option<i128>,
+    /// Name of the timestamp field of a block.
+    pub timestamp_field: String,
+}
+
+impl Default for PrunePolicy {
+    fn default() -> Self {
+        Self {
+            max_depth: None,
+            older_than: None,
+            timestamp_field: "timestamp".to_string(),
+        }
+    }
+}
+
+impl PrunePolicy {
+    /// Returns if the block is older than the cutoff.
+    fn expired(&self, ipld: &Ipld) -> bool {
+        match (self.older_than, ipld.get(self.timestamp_field.as_str())) {
+            (Some(cutoff), Ok(Ipld::Integer(timestamp))) => *timestamp < cutoff,
+            _ => false,
+        }
+    }
+}
+
+/// Replaces the links in `ipld` with their rewritten cids, or with null if
+/// they were dropped.
+fn prune_links(ipld: &Ipld, cids: &HashMap<Cid, Cid>) -> Ipld {
+    match ipld {
+        Ipld::List(list) => Ipld::List(list.iter().map(|ipld| prune_links(ipld, cids)).collect()),
+        Ipld::Map(map) => Ipld::Map(
+            map.iter()
+                .map(|(key, ipld)| (key.clone(), prune_links(ipld, cids)))
+                .collect(),
+        ),
+        Ipld::Link(cid) => cids.get(cid).cloned().map(Ipld::Link).unwrap_or(Ipld::Null),
+        ipld => ipld.clone(),
+    }
+}
+
+impl<S: Store, C: IpldDecoder + Encoder + Clone> BlockBuilder<S, C>
+where
+    Ipld: Encode<C::Codec>,
+{
+    /// Rewrites the dag of `root` without the blocks dropped by `policy`,
+    /// pins the new root and unpins `root`.
+    ///
+    /// Links to dropped blocks are replaced with null. The root is always
+    /// kept and blocks linked from several places are kept at their
+    /// shallowest depth.
+    pub async fn prune(&self, root: &Cid, policy: &PrunePolicy) -> Result<Cid> {
+        let mut blocks = HashMap::new();
+        let options = WalkOptions {
+            traversal: Traversal::BreadthFirst,
+            ..Default::default()
+        };
+        self.walk_with(root, options, |depth: usize, cid: &Cid, ipld: &Ipld| {
+            if depth > 0 && policy.expired(ipld) {
+                return WalkControl::Skip;
+            }
+            blocks.insert(cid.clone(), ipld.clone());
+            match policy.max_depth {
+                Some(max_depth) if depth >= max_depth => WalkControl::Skip,
+                _ => WalkControl::Descend,
+            }
+        })
+        .await?;
+
+        let mut cids = HashMap::new();
+        let mut batch = self.create_batch();
+        let mut stack = vec![(root.clone(), false)];
+        while let Some((cid, visited)) = stack.pop() {
+            if cids.contains_key(&cid) {
+                continue;
+            }
+            let ipld = &blocks[&cid];
+            if visited {
+                let new = batch.insert(&prune_links(ipld, &cids))?.clone();
+                cids.insert(cid, new);
+            } else {
+                stack.push((cid, true));
+                for link in links(ipld) {
+                    if blocks.contains_key(&link) && !cids.contains_key(&link) {
+                        stack.push((link, false));
+                    }
+                }
+            }
+        }
+        let new = self.insert_batch(batch).await?;
+        self.unpin(root).await?;
+        Ok(new)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Codec, DagPath};
+    use libipld::ipld;
+    use libipld::mem::MemStore;
+
+    async fn log(builder: &BlockBuilder<MemStore, Codec>, len: i128) -> Cid {
+        let mut prev = Ipld::Null;
+        for timestamp in 0..len {
+            let entry = ipld!({"timestamp": timestamp, "prev": prev});
+            prev = Ipld::Link(builder.insert(&entry).await.unwrap());
+        }
+        match prev {
+            Ipld::Link(cid) => cid,
+            _ => unreachable!(),
+        }
+    }
+
+    async fn len(builder: &BlockBuilder<MemStore, Codec>, root: &Cid) -> usize {
+        let mut len = 0;
+        builder
+            .walk(root, |_: usize, _: &Cid, _: &Ipld| {
+                len += 1;
+                WalkControl::Descend
+            })
+            .await
+            .unwrap();
+        len
+    }
+
+    #[async_std::test]
+    async fn test_prune_depth() {
+        let builder = BlockBuilder::new(MemStore::default(), Codec::new());
+        let root = log(&builder, 10).await;
+        let policy = PrunePolicy {
+            max_depth: Some(2),
+            ..Default::default()
+        };
+        let pruned = builder.prune(&root, &policy).await.unwrap();
+        assert_eq!(len(&builder, &pruned).await, 3);
+        let tail = builder
+            .get_path(&DagPath::new(&pruned, "prev/prev"))
+            .await
+            .unwrap();
+        assert_eq!(tail, ipld!({"timestamp": 7, "prev": null}));
+
+        // nothing to prune
+        assert_eq!(builder.prune(&pruned, &policy).await.unwrap(), pruned);
+    }
+
+    #[async_std::test]
+    async fn test_prune_age() {
+        let builder = BlockBuilder::new(MemStore::default(), Codec::new());
+        let root = log(&builder, 10).await;
+        let policy = PrunePolicy {
+            older_than: Some(6),
+            ..Default::default()
+        };
+        let pruned = builder.prune(&root, &policy).await.unwrap();
+        assert_eq!(len(&builder, &pruned).await, 4);
+        assert_eq!(len(&builder, &root).await, 10);
+    }
+}
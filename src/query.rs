@@ -0,0 +1,239 @@
+use crate::builder::BlockBuilder;
+use crate::codec::IpldDecoder;
+use crate::error::Result;
+use crate::graphsync::Selector;
+use crate::path::IpldPath;
+use core::cmp::Ordering;
+use core::ops::Bound;
+use futures::stream::{self, Stream};
+use libipld::cid::Cid;
+use libipld::ipld::Ipld;
+use libipld::store::ReadonlyStore;
+use std::collections::HashSet;
+
+/// Condition on the value at the path of a filter.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Predicate {
+    /// The path exists.
+    Exists,
+    /// The value is equal to the ipld.
+    Equals(Ipld),
+    /// The value is within the bounds.
+    ///
+    /// Integers and floats are compared numerically, strings and bytes
+    /// lexicographically. Other values are never within the bounds.
+    Range(Bound<Ipld>, Bound<Ipld>),
+    /// The value is a list containing the ipld, a map containing the key or
+    /// a string containing the substring.
+    Contains(Ipld),
+    /// The predicate doesn't hold, or the path doesn't exist.
+    Not(Box<Predicate>),
+}
+
+/// Compares two ipld values of the same kind.
+fn compare(a: &Ipld, b: &Ipld) -> Option<Ordering> {
+    match (a, b) {
+        (Ipld::Integer(a), Ipld::Integer(b)) => Some(a.cmp(b)),
+        (Ipld::Integer(a), Ipld::Float(b)) => (*a as f64).partial_cmp(b),
+        (Ipld::Float(a), Ipld::Integer(b)) => a.partial_cmp(&(*b as f64)),
+        (Ipld::Float(a), Ipld::Float(b)) => a.partial_cmp(b),
+        (Ipld::String(a), Ipld::String(b)) => Some(a.cmp(b)),
+        (Ipld::Bytes(a), Ipld::Bytes(b)) => Some(a.cmp(b)),
+        _ => None,
+    }
+}
+
+impl Predicate {
+    /// Evaluates the predicate on the value at the path, `None` if the path
+    /// doesn't exist.
+    pub fn eval(&self, value: Option<&Ipld>) -> bool {
+        let value = match (self, value) {
+            (Self::Not(inner), value) => return !inner.eval(value),
+            (_, None) => return false,
+            (_, Some(value)) => value,
+        };
+        match self {
+            Self::Exists => true,
+            Self::Equals(ipld) => value == ipld,
+            Self::Range(start, end) => {
+                let above_start = match start {
+                    Bound::Included(start) => compare(value, start).map(Ordering::is_ge),
+                    Bound::Excluded(start) => compare(value, start).map(Ordering::is_gt),
+                    Bound::Unbounded => Some(true),
+                };
+                let below_end = match end {
+                    Bound::Included(end) => compare(value, end).map(Ordering::is_le),
+                    Bound::Excluded(end) => compare(value, end).map(Ordering::is_lt),
+                    Bound::Unbounded => Some(true),
+                };
+                above_start == Some(true) && below_end == Some(true)
+            }
+            Self::Contains(ipld) => match (value, ipld) {
+                (Ipld::List(list), ipld) => list.contains(ipld),
+                (Ipld::Map(map), Ipld::String(key)) => map.contains_key(key),
+                (Ipld::String(string), Ipld::String(sub)) => string.contains(sub.as_str()),
+                _ => false,
+            },
+            Self::Not(_) => unreachable!(),
+        }
+    }
+}
+
+/// Query matching the blocks of a dag.
+///
+/// The blocks selected by the selector are matched if every filter holds.
+/// The path of a filter is resolved inside the block, links are compared as
+/// values and not followed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Query {
+    selector: Selector,
+    filters: Vec<(IpldPath, Predicate)>,
+}
+
+impl Default for Query {
+    fn default() -> Self {
+        Self::new(Selector::All)
+    }
+}
+
+impl Query {
+    /// Creates a query matching every block selected by `selector`.
+    pub fn new(selector: Selector) -> Self {
+        Self {
+            selector,
+            filters: vec![],
+        }
+    }
+
+    /// Adds a filter on the value at `path`.
+    pub fn filter<P: Into<IpldPath>>(mut self, path: P, predicate: Predicate) -> Self {
+        self.filters.push((path.into(), predicate));
+        self
+    }
+
+    /// Adds a filter requiring the value at `path` to equal `value`.
+    pub fn equals<P: Into<IpldPath>>(self, path: P, value: Ipld) -> Self {
+        self.filter(path, Predicate::Equals(value))
+    }
+
+    /// Adds a filter requiring the value at `path` to be within `start` and
+    /// `end`.
+    pub fn range<P: Into<IpldPath>>(self, path: P, start: Bound<Ipld>, end: Bound<Ipld>) -> Self {
+        self.filter(path, Predicate::Range(start, end))
+    }
+
+    /// Adds a filter requiring the value at `path` to contain `value`.
+    pub fn contains<P: Into<IpldPath>>(self, path: P, value: Ipld) -> Self {
+        self.filter(path, Predicate::Contains(value))
+    }
+
+    /// Returns if the block matches every filter.
+    pub fn matches(&self, ipld: &Ipld) -> bool {
+        self.filters.iter().all(|(path, predicate)| {
+            let mut value = Some(ipld);
+            for segment in path.iter() {
+                value = value.and_then(|ipld| ipld.get(segment).ok());
+            }
+            predicate.eval(value)
+        })
+    }
+}
+
+impl<S: ReadonlyStore, C: IpldDecoder> BlockBuilder<S, C> {
+    /// Returns the blocks of the dag of `root` matching `query` in depth
+    /// first pre-order.
+    ///
+    /// The blocks are fetched while the stream is consumed, each matching
+    /// block is returned once.
+    pub fn query<'a>(
+        &'a self,
+        root: &Cid,
+        query: &'a Query,
+    ) -> impl Stream<Item = Result<(Cid, Ipld)>> + 'a {
+        let stack = vec![(root.clone(), query.selector.clone())];
+        let state = (stack, HashSet::new(), HashSet::new());
+        stream::try_unfold(
+            state,
+            move |(mut stack, mut visited, mut matched)| async move {
+                while let Some((cid, selector)) = stack.pop() {
+                    if !visited.insert((cid.clone(), selector.clone())) {
+                        continue;
+                    }
+                    let ipld = self.get_ipld(&cid).await?;
+                    stack.extend(selector.select(&ipld).into_iter().rev());
+                    if query.matches(&ipld) && matched.insert(cid.clone()) {
+                        return Ok(Some(((cid, ipld), (stack, visited, matched))));
+                    }
+                }
+                Result::Ok(None)
+            },
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Codec;
+    use futures::stream::TryStreamExt;
+    use libipld::ipld;
+    use libipld::mem::MemStore;
+
+    #[async_std::test]
+    async fn test_query() {
+        let builder = BlockBuilder::new(MemStore::default(), Codec::new());
+        let a = builder
+            .insert(&ipld!({ "name": "a", "size": 3, "tags": ["x"] }))
+            .await
+            .unwrap();
+        let b = builder
+            .insert(&ipld!({ "name": "b", "size": 7.5, "tags": ["x", "y"] }))
+            .await
+            .unwrap();
+        let c = builder
+            .insert(&ipld!({ "name": "c", "child": b.clone() }))
+            .await
+            .unwrap();
+        let root = builder
+            .insert(&ipld!({ "entries": [a.clone(), c.clone()] }))
+            .await
+            .unwrap();
+
+        let run = |query: Query| {
+            let builder = &builder;
+            let root = &root;
+            async move {
+                builder
+                    .query(root, &query)
+                    .map_ok(|(cid, _)| cid)
+                    .try_collect::<Vec<_>>()
+                    .await
+                    .unwrap()
+            }
+        };
+        assert_eq!(
+            run(Query::default()).await,
+            vec![root.clone(), a.clone(), c.clone(), b.clone()]
+        );
+        assert_eq!(
+            run(Query::default().equals("name", ipld!("c"))).await,
+            vec![c.clone()]
+        );
+        let range = Query::default().range(
+            "size",
+            Bound::Excluded(ipld!(3)),
+            Bound::Included(ipld!(10)),
+        );
+        assert_eq!(run(range).await, vec![b.clone()]);
+        assert_eq!(
+            run(Query::default().contains("tags", ipld!("x"))).await,
+            vec![a.clone(), b.clone()]
+        );
+        let query = Query::default()
+            .filter("name", Predicate::Exists)
+            .filter("tags", Predicate::Not(Box::new(Predicate::Exists)));
+        assert_eq!(run(query).await, vec![c.clone()]);
+        let query = Query::new(Selector::Depth(1)).filter("size", Predicate::Exists);
+        assert_eq!(run(query).await, vec![a]);
+    }
+}
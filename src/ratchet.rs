@@ -0,0 +1,187 @@
+use crate::builder::BlockBuilder;
+use crate::crypto::Key;
+use crate::error::{Error, Result};
+use crate::StrobeCodec;
+use core::convert::TryFrom;
+use libipld::cbor::DagCborCodec;
+use libipld::cid::Cid;
+use libipld::codec::{Codec, Encode};
+use libipld::ipld::Ipld;
+use libipld::store::Store;
+use std::collections::BTreeMap;
+use strobe_rs::{SecParam, Strobe};
+
+/// Key ratchet of an encrypted log.
+///
+/// Every entry is encrypted with the next key derived from the previous
+/// one. Keys can't be derived backwards, so discarding old keys protects
+/// the entries they encrypted if a later key leaks.
+pub struct Ratchet {
+    key: Key,
+    index: u64,
+}
+
+impl Ratchet {
+    /// Creates a ratchet starting at index 0.
+    pub fn new(key: Key) -> Self {
+        Self::at(key, 0)
+    }
+
+    /// Restores a ratchet from its key and index.
+    pub fn at(key: Key, index: u64) -> Self {
+        Self { key, index }
+    }
+
+    /// Returns the current key.
+    pub fn key(&self) -> &Key {
+        &self.key
+    }
+
+    /// Returns the index of the current key.
+    pub fn index(&self) -> u64 {
+        self.index
+    }
+
+    /// Replaces the current key with the next key, discarding it.
+    pub fn advance(&mut self) {
+        let mut s = Strobe::new(b"ipld-block-builder/ratchet", SecParam::B128);
+        s.ad(&self.key, false);
+        let mut next = vec![0; 32];
+        s.prf(&mut next, false);
+        self.key = Key::from(next);
+        self.index += 1;
+    }
+
+    fn codec(&self) -> StrobeCodec {
+        StrobeCodec::new(Key::from(self.key.to_vec()))
+    }
+}
+
+/// Append-only log encrypted with a `Ratchet`.
+///
+/// Entries link to their predecessor and carry their index in the plaintext
+/// header, so readers know which key decrypts them. The head is the only
+/// pinned entry. The caller persists the head and the ratchet.
+pub struct RatchetLog<S> {
+    store: S,
+    ratchet: Ratchet,
+    head: Option<Cid>,
+}
+
+impl<S: Store + Clone> RatchetLog<S> {
+    /// Creates a log that appends after `head` with the key of `ratchet`.
+    pub fn new(store: S, ratchet: Ratchet, head: Option<Cid>) -> Self {
+        Self {
+            store,
+            ratchet,
+            head,
+        }
+    }
+
+    /// Returns the ratchet for the next entry.
+    pub fn ratchet(&self) -> &Ratchet {
+        &self.ratchet
+    }
+
+    /// Returns the last entry.
+    pub fn head(&self) -> Option<&Cid> {
+        self.head.as_ref()
+    }
+
+    /// Appends an entry and advances the ratchet.
+    pub async fn append<E: Encode<DagCborCodec>>(&mut self, entry: &E) -> Result<Cid> {
+        let builder = BlockBuilder::new_private(self.store.clone(), self.ratchet.codec());
+        let mut block = BTreeMap::new();
+        let codec_error = |err| Error::encode(libipld::error::Error::CodecError(Box::new(err)));
+        let entry = DagCborCodec::encode(entry).map_err(codec_error)?;
+        let entry: Ipld = DagCborCodec::decode(&entry).map_err(codec_error)?;
+        block.insert("entry".to_string(), entry);
+        let prev = self.head.clone().map(Ipld::Link).unwrap_or(Ipld::Null);
+        block.insert("prev".to_string(), prev);
+        let header = self.ratchet.index.to_be_bytes();
+        let cid = builder
+            .insert_with_header(&Ipld::Map(block), &header)
+            .await?;
+        if let Some(head) = self.head.replace(cid.clone()) {
+            builder.unpin(&head).await?;
+        }
+        self.ratchet.advance();
+        Ok(cid)
+    }
+
+    /// Returns the entries up to `head` that `ratchet` can decrypt, oldest
+    /// first, together with their index.
+    pub async fn read(store: &S, ratchet: &Ratchet, head: &Cid) -> Result<Vec<(u64, Ipld)>> {
+        let mut keys = Ratchet::at(Key::from(ratchet.key.to_vec()), ratchet.index);
+        // builder of the entry at ratchet.index + i
+        let mut builders = vec![BlockBuilder::new_private(store.clone(), keys.codec())];
+        let mut entries = vec![];
+        let mut next = Some(head.clone());
+        while let Some(cid) = next {
+            let header = builders[0].get_header(&cid).await?.unwrap_or_default();
+            let index = <[u8; 8]>::try_from(header.as_slice())
+                .map(u64::from_be_bytes)
+                .map_err(|_| Error::InvalidLogEntry(cid.clone()))?;
+            if index < ratchet.index {
+                break;
+            }
+            let offset = (index - ratchet.index) as usize;
+            while builders.len() <= offset {
+                keys.advance();
+                builders.push(BlockBuilder::new_private(store.clone(), keys.codec()));
+            }
+            let mut block = match builders[offset].get_ipld(&cid).await? {
+                Ipld::Map(block) => block,
+                _ => return Err(Error::InvalidLogEntry(cid)),
+            };
+            next = match block.remove("prev") {
+                Some(Ipld::Link(prev)) => Some(prev),
+                Some(Ipld::Null) => None,
+                _ => return Err(Error::InvalidLogEntry(cid)),
+            };
+            let entry = block
+                .remove("entry")
+                .ok_or_else(|| Error::InvalidLogEntry(cid.clone()))?;
+            entries.push((index, entry));
+        }
+        entries.reverse();
+        Ok(entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libipld::ipld;
+    use libipld::mem::MemStore;
+
+    #[async_std::test]
+    async fn test_ratchet_log() {
+        let store = MemStore::default();
+        let mut log = RatchetLog::new(store.clone(), Ratchet::new(Key::from(vec![1; 32])), None);
+        let mut cids = vec![];
+        for i in 0..4 {
+            cids.push(log.append(&ipld!({ "n": i })).await.unwrap());
+        }
+        assert_eq!(log.ratchet().index(), 4);
+        let head = log.head().unwrap().clone();
+
+        let entries = RatchetLog::read(&store, &Ratchet::new(Key::from(vec![1; 32])), &head)
+            .await
+            .unwrap();
+        let indices: Vec<_> = entries.iter().map(|(index, _)| *index).collect();
+        assert_eq!(indices, vec![0, 1, 2, 3]);
+        assert_eq!(entries[3].1, ipld!({ "n": 3 }));
+
+        // a later key doesn't decrypt earlier entries
+        let mut ratchet = Ratchet::new(Key::from(vec![1; 32]));
+        ratchet.advance();
+        ratchet.advance();
+        let entries = RatchetLog::read(&store, &ratchet, &head).await.unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].1, ipld!({ "n": 2 }));
+        let builder = BlockBuilder::new_private(store, ratchet.codec());
+        assert!(builder.get_ipld(&cids[2]).await.is_ok());
+        assert!(builder.get_ipld(&cids[1]).await.is_err());
+    }
+}
@@ -0,0 +1,215 @@
+use crate::builder::BlockBuilder;
+use crate::codec::{Encoder, IpldDecoder};
+use crate::error::{Error, Result};
+use crate::walk::{links, Emission, WalkControl, WalkOptions};
+use libipld::cbor::DagCborCodec;
+use libipld::cid::{Cid, Codec as CidCodec};
+use libipld::codec::Encode;
+use libipld::ipld::Ipld;
+use libipld::multihash::{Code, Multihasher};
+use libipld::raw::RawCodec;
+use libipld::store::{ReadonlyStore, Store};
+use std::collections::HashMap;
+
+/// Replaces the links in `ipld` with their re-encoded cids.
+fn relink(ipld: &Ipld, cids: &HashMap<Cid, Cid>) -> Ipld {
+    match ipld {
+        Ipld::List(list) => Ipld::List(list.iter().map(|ipld| relink(ipld, cids)).collect()),
+        Ipld::Map(map) => Ipld::Map(
+            map.iter()
+                .map(|(key, ipld)| (key.clone(), relink(ipld, cids)))
+                .collect(),
+        ),
+        Ipld::Link(cid) => Ipld::Link(cids.get(cid).unwrap_or(cid).clone()),
+        ipld => ipld.clone(),
+    }
+}
+
+impl<S: ReadonlyStore, C: IpldDecoder> BlockBuilder<S, C> {
+    /// Re-encodes the dag of `root` with the codec of `target` and returns
+    /// the new root.
+    ///
+    /// Used to rotate the key of an encrypted dag, or to encrypt or decrypt
+    /// a dag. Since cids change, links are rewritten bottom up. The new
+    /// blocks are inserted into `target` as a batch pinning the new root.
+    pub async fn rekey<T, D>(&self, root: &Cid, target: &BlockBuilder<T, D>) -> Result<Cid>
+    where
+        T: Store,
+        D: Encoder + Clone,
+        Ipld: Encode<D::Codec>,
+    {
+        let mut cids = HashMap::new();
+        let mut batch = target.create_batch();
+        let mut error = None;
+        let options = WalkOptions {
+            emission: Emission::PostOrder,
+            ..Default::default()
+        };
+        self.walk_with(
+            root,
+            options,
+            |_: usize, cid: &Cid, ipld: &Ipld| match batch.insert(&relink(ipld, &cids)) {
+                Ok(new) => {
+                    cids.insert(cid.clone(), new.clone());
+                    WalkControl::Descend
+                }
+                Err(err) => {
+                    error = Some(err);
+                    WalkControl::Stop
+                }
+            },
+        )
+        .await?;
+        if let Some(err) = error {
+            return Err(err);
+        }
+        target.insert_batch(batch).await
+    }
+
+    /// Re-encodes the dag of `root` with `codec` into the same store and
+    /// returns the new root.
+    ///
+    /// Like `rekey` links are rewritten bottom up, so a dag can be migrated
+    /// to another codec or hasher. The new blocks are inserted as public
+    /// blocks, encrypted dags are migrated with `rekey` and a private target.
+    pub async fn transcode<D>(&self, root: &Cid, codec: D) -> Result<Cid>
+    where
+        S: Store + Clone,
+        D: Encoder + Clone,
+        Ipld: Encode<D::Codec>,
+    {
+        let target = BlockBuilder::new(self.store().clone(), codec);
+        self.rekey(root, &target).await
+    }
+}
+
+enum Visit {
+    Enter(Cid),
+    Exit(Cid, Ipld),
+}
+
+impl<S: Store, C: Encoder + Clone> BlockBuilder<S, C> {
+    /// Rehashes the dag of `root` with the hasher `H` and returns the new
+    /// root together with the mapping of old to new cids.
+    ///
+    /// Blocks are rehashed without going through the builder's codec, links
+    /// in dag-cbor blocks are rewritten bottom up. Encrypted blocks are
+    /// rehashed as opaque raw blocks and their links aren't followed, use
+    /// `rekey` to migrate encrypted dags. The new blocks are inserted as a
+    /// batch pinning the new root.
+    pub async fn rehash<H: Multihasher<Code>>(
+        &self,
+        root: &Cid,
+    ) -> Result<(Cid, HashMap<Cid, Cid>)> {
+        let mut cids = HashMap::new();
+        let mut batch = self.create_batch();
+        let mut stack = vec![Visit::Enter(root.clone())];
+        while let Some(visit) = stack.pop() {
+            match visit {
+                Visit::Enter(cid) => {
+                    if cids.contains_key(&cid) {
+                        continue;
+                    }
+                    let data = self.get_verified(&cid).await?;
+                    let ipld = libipld::block::decode_ipld(&cid, &data)
+                        .map_err(|err| Error::decode(&cid, err))?;
+                    let children = links(&ipld);
+                    stack.push(Visit::Exit(cid, ipld));
+                    stack.extend(children.into_iter().map(Visit::Enter));
+                }
+                Visit::Exit(cid, ipld) => {
+                    if cids.contains_key(&cid) {
+                        continue;
+                    }
+                    let block = match cid.codec() {
+                        CidCodec::DagCBOR => {
+                            libipld::block::encode::<DagCborCodec, H, _>(&relink(&ipld, &cids))
+                        }
+                        CidCodec::Raw => libipld::block::encode::<RawCodec, H, _>(&ipld),
+                        codec => Err(libipld::error::Error::UnsupportedCodec(codec)),
+                    }
+                    .map_err(Error::encode)?;
+                    cids.insert(cid, block.cid.clone());
+                    batch.push(block);
+                }
+            }
+        }
+        let root = self.insert_batch(batch).await?;
+        Ok((root, cids))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Codec, GenericCodec};
+    #[cfg(feature = "crypto")]
+    use crate::{Key, StrobeCodec};
+    use libipld::cbor::DagCborCodec;
+    use libipld::ipld;
+    use libipld::mem::MemStore;
+    use libipld::multihash::{Code, Sha2_256};
+
+    #[async_std::test]
+    async fn test_transcode() {
+        let builder = BlockBuilder::new(MemStore::default(), Codec::new());
+        let leaf = builder.insert(&ipld!({"n": 42})).await.unwrap();
+        let root = builder.insert(&ipld!({"leaf": &leaf})).await.unwrap();
+
+        let codec = GenericCodec::<DagCborCodec, Sha2_256>::new();
+        let transcoded = builder.transcode(&root, codec).await.unwrap();
+        assert_eq!(transcoded.hash().algorithm(), Code::Sha2_256);
+        let leaf = match builder.get_ipld(&transcoded).await.unwrap().get("leaf") {
+            Ok(Ipld::Link(cid)) => cid.clone(),
+            _ => panic!("expected link"),
+        };
+        assert_eq!(leaf.hash().algorithm(), Code::Sha2_256);
+        assert_eq!(builder.get_ipld(&leaf).await.unwrap(), ipld!({"n": 42}));
+    }
+
+    #[async_std::test]
+    async fn test_rehash() {
+        let builder = BlockBuilder::new(MemStore::default(), Codec::new());
+        let leaf = builder.insert(&ipld!({"n": 42})).await.unwrap();
+        let root = builder
+            .insert(&ipld!({"a": &leaf, "b": &leaf}))
+            .await
+            .unwrap();
+
+        let (rehashed, cids) = builder.rehash::<Sha2_256>(&root).await.unwrap();
+        assert_eq!(cids.len(), 2);
+        assert_eq!(cids[&root], rehashed);
+        assert_eq!(rehashed.hash().algorithm(), Code::Sha2_256);
+        let ipld = builder.get_ipld(&rehashed).await.unwrap();
+        assert_eq!(ipld, ipld!({"a": &cids[&leaf], "b": &cids[&leaf]}));
+        assert_eq!(
+            builder.get_ipld(&cids[&leaf]).await.unwrap(),
+            ipld!({"n": 42})
+        );
+        // rehashing leaves the encoding unchanged
+        let data = builder.store().get(&cids[&leaf]).await.unwrap();
+        assert_eq!(data, builder.store().get(&leaf).await.unwrap());
+    }
+
+    #[cfg(feature = "crypto")]
+    #[async_std::test]
+    async fn test_rekey() {
+        let store = MemStore::default();
+        let old =
+            BlockBuilder::new_private(store.clone(), StrobeCodec::new(Key::from(vec![1; 32])));
+        let new =
+            BlockBuilder::new_private(store.clone(), StrobeCodec::new(Key::from(vec![2; 32])));
+        let leaf = old.insert(&ipld!({"secret": 42})).await.unwrap();
+        let root = old.insert(&ipld!({"leaf": leaf})).await.unwrap();
+
+        let rekeyed = old.rekey(&root, &new).await.unwrap();
+        assert_ne!(rekeyed, root);
+        assert!(old.get_ipld(&rekeyed).await.is_err());
+        let ipld = new.get_ipld(&rekeyed).await.unwrap();
+        let leaf = match ipld.get("leaf").unwrap() {
+            Ipld::Link(cid) => cid.clone(),
+            _ => panic!(),
+        };
+        assert_eq!(new.get_ipld(&leaf).await.unwrap(), ipld!({"secret": 42}));
+    }
+}
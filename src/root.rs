@@ -0,0 +1,258 @@
+use crate::cache::{Cache, ReadonlyCache};
+use crate::codec::{Decoder, Encoder};
+use crate::error::Result;
+use core::fmt;
+use core::hash::{Hash, Hasher};
+use core::marker::PhantomData;
+use libipld::cbor::decode::TryReadCbor;
+use libipld::cbor::{DagCborCodec, Error as CborError};
+use libipld::cid::Cid;
+use libipld::codec::{Decode, Encode};
+use std::io::{Read, Write};
+
+/// Typed pointer to a block.
+///
+/// A root only holds the cid of the block, the value is loaded and
+/// persisted through a cache with the codec of `T`. Roots are encoded as
+/// links, so they can be embedded in other blocks and are followed by walks
+/// and garbage collection.
+pub struct Root<T> {
+    cid: Cid,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Root<T> {
+    /// Creates a root pointing to `cid`.
+    pub fn new(cid: Cid) -> Self {
+        Self {
+            cid,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Returns the cid of the block.
+    pub fn cid(&self) -> &Cid {
+        &self.cid
+    }
+
+    /// Returns the cid of the block.
+    pub fn into_cid(self) -> Cid {
+        self.cid
+    }
+
+    /// Inserts `value` into `cache` and returns its root.
+    pub async fn insert<C, K>(cache: &K, value: T) -> Result<Self>
+    where
+        K: Cache<C, T> + ?Sized,
+        C: Decoder + Encoder + Clone + Send + Sync,
+        T: Decode<<C as Decoder>::Codec> + Encode<<C as Encoder>::Codec> + Clone + Send + Sync,
+    {
+        Ok(Self::new(cache.insert(value).await?))
+    }
+
+    /// Loads the value from `cache`.
+    pub async fn load<C, K>(&self, cache: &K) -> Result<T>
+    where
+        K: ReadonlyCache<C, T> + ?Sized,
+        C: Decoder + Clone + Send + Sync,
+        T: Decode<<C as Decoder>::Codec> + Clone + Send + Sync,
+    {
+        cache.get(&self.cid).await
+    }
+
+    /// Loads the value, applies `f` and returns the root of the inserted
+    /// result.
+    ///
+    /// The old value stays pinned.
+    pub async fn map<C, K, F>(&self, cache: &K, f: F) -> Result<Self>
+    where
+        K: Cache<C, T> + ?Sized,
+        C: Decoder + Encoder + Clone + Send + Sync,
+        T: Decode<<C as Decoder>::Codec> + Encode<<C as Encoder>::Codec> + Clone + Send + Sync,
+        F: FnOnce(T) -> T,
+    {
+        let value = f(self.load(cache).await?);
+        Self::insert(cache, value).await
+    }
+}
+
+impl<T> Clone for Root<T> {
+    fn clone(&self) -> Self {
+        Self::new(self.cid.clone())
+    }
+}
+
+impl<T> fmt::Debug for Root<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Root").field(&self.cid).finish()
+    }
+}
+
+impl<T> PartialEq for Root<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cid == other.cid
+    }
+}
+
+impl<T> Eq for Root<T> {}
+
+impl<T> Hash for Root<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        Hash::hash(&self.cid, state);
+    }
+}
+
+impl<T> From<Root<T>> for Cid {
+    fn from(root: Root<T>) -> Self {
+        root.cid
+    }
+}
+
+impl<T> Encode<DagCborCodec> for Root<T> {
+    fn encode<W: Write>(&self, w: &mut W) -> core::result::Result<(), CborError> {
+        self.cid.encode(w)
+    }
+}
+
+impl<T> Decode<DagCborCodec> for Root<T> {
+    fn decode<R: Read>(r: &mut R) -> core::result::Result<Self, CborError> {
+        Cid::decode(r).map(Self::new)
+    }
+}
+
+impl<T> TryReadCbor for Root<T> {
+    fn try_read_cbor<R: Read>(
+        r: &mut R,
+        major: u8,
+    ) -> core::result::Result<Option<Self>, CborError> {
+        Ok(Cid::try_read_cbor(r, major)?.map(Self::new))
+    }
+}
+
+#[cfg(feature = "serde")]
+mod serde_impl {
+    use super::*;
+    use crate::serde_codec::LINK;
+    use core::convert::TryFrom;
+    use serde::de::{self, Deserialize, Deserializer, Visitor};
+    use serde::ser::{Serialize, Serializer};
+
+    struct Bytes<'a>(&'a [u8]);
+
+    impl Serialize for Bytes<'_> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> core::result::Result<S::Ok, S::Error> {
+            serializer.serialize_bytes(self.0)
+        }
+    }
+
+    /// Serialized as a link by `to_ipld` and as bytes by other serializers.
+    impl<T> Serialize for Root<T> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> core::result::Result<S::Ok, S::Error> {
+            serializer.serialize_newtype_struct(LINK, &Bytes(&self.cid.to_bytes()))
+        }
+    }
+
+    struct RootVisitor<T>(PhantomData<fn() -> T>);
+
+    impl<'de, T> Visitor<'de> for RootVisitor<T> {
+        type Value = Root<T>;
+
+        fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("a link")
+        }
+
+        fn visit_newtype_struct<D: Deserializer<'de>>(
+            self,
+            deserializer: D,
+        ) -> core::result::Result<Root<T>, D::Error> {
+            deserializer.deserialize_bytes(self)
+        }
+
+        fn visit_bytes<E: de::Error>(self, bytes: &[u8]) -> core::result::Result<Root<T>, E> {
+            Cid::try_from(bytes).map(Root::new).map_err(E::custom)
+        }
+
+        fn visit_seq<A: de::SeqAccess<'de>>(
+            self,
+            mut seq: A,
+        ) -> core::result::Result<Root<T>, A::Error> {
+            let mut bytes = vec![];
+            while let Some(byte) = seq.next_element::<u8>()? {
+                bytes.push(byte);
+            }
+            self.visit_bytes(&bytes)
+        }
+    }
+
+    impl<'de, T> Deserialize<'de> for Root<T> {
+        fn deserialize<D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> core::result::Result<Self, D::Error> {
+            deserializer.deserialize_newtype_struct(LINK, RootVisitor(PhantomData))
+        }
+    }
+}
+
+#[cfg(test)]
+#[allow(non_local_definitions)]
+mod tests {
+    use super::*;
+    use crate::{Codec, IpldCache};
+    use libipld::ipld;
+    use libipld::ipld::Ipld;
+    use libipld::mem::MemStore;
+    use libipld::DagCbor;
+
+    #[derive(Clone, DagCbor, Debug, Eq, PartialEq)]
+    struct Parent {
+        name: String,
+        child: Root<Ipld>,
+    }
+
+    #[async_std::test]
+    async fn test_root() {
+        let cache = IpldCache::new(MemStore::default(), Codec::new(), 16);
+        let root = Root::insert(&cache, ipld!({"n": 1})).await.unwrap();
+        assert_eq!(root.load(&cache).await.unwrap(), ipld!({"n": 1}));
+
+        let mapped = root
+            .map(&cache, |ipld| match ipld.get("n") {
+                Ok(Ipld::Integer(n)) => ipld!({ "n": n + 1 }),
+                _ => ipld,
+            })
+            .await
+            .unwrap();
+        assert_ne!(mapped, root);
+        assert_eq!(mapped.load(&cache).await.unwrap(), ipld!({"n": 2}));
+
+        // roots are embedded as links
+        let parent = Parent {
+            name: "parent".into(),
+            child: mapped.clone(),
+        };
+        let builder = cache.builder();
+        let cid = builder.insert(&parent).await.unwrap();
+        let ipld = builder.get_ipld(&cid).await.unwrap();
+        assert_eq!(ipld, ipld!({"name": "parent", "child": mapped.cid()}));
+        assert_eq!(builder.get::<Parent>(&cid).await.unwrap(), parent);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_root_serde() {
+        use crate::{from_ipld, to_ipld};
+
+        #[derive(Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+        struct Parent {
+            child: Root<Ipld>,
+        }
+
+        let cid = Codec::new().encode(&ipld!(1)).unwrap().cid;
+        let parent = Parent {
+            child: Root::new(cid.clone()),
+        };
+        let ipld = to_ipld(&parent).unwrap();
+        assert_eq!(ipld, ipld!({ "child": &cid }));
+        assert_eq!(from_ipld::<Parent>(ipld).unwrap(), parent);
+    }
+}
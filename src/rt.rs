@@ -0,0 +1,77 @@
+//! Runtime specific primitives.
+//!
+//! Everything else in the crate only uses executor agnostic `futures`
+//! primitives. With the `tokio` feature blocking work and timers are
+//! driven by the tokio runtime instead of a helper thread pool. On wasm32
+//! there are no threads and no system clock, blocking work runs inline and
+//! time is read from the browser.
+#[cfg(feature = "sync")]
+use std::future::Future;
+use std::time::Duration;
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) use std::time::{Instant, SystemTime, UNIX_EPOCH};
+#[cfg(target_arch = "wasm32")]
+pub(crate) use web_time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// Waits for `duration` to elapse.
+#[cfg(not(feature = "tokio"))]
+pub(crate) async fn sleep(duration: Duration) {
+    futures_timer::Delay::new(duration).await
+}
+
+/// Waits for `duration` to elapse.
+#[cfg(feature = "tokio")]
+pub(crate) async fn sleep(duration: Duration) {
+    tokio::time::sleep(duration).await
+}
+
+/// Runs blocking work off the executor.
+#[cfg(all(not(feature = "tokio"), not(target_arch = "wasm32")))]
+pub(crate) async fn unblock<T, F>(f: F) -> T
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    blocking::unblock(f).await
+}
+
+/// Runs blocking work on the only thread.
+#[cfg(all(not(feature = "tokio"), target_arch = "wasm32"))]
+pub(crate) async fn unblock<T, F>(f: F) -> T
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    f()
+}
+
+/// Runs blocking work off the executor.
+#[cfg(feature = "tokio")]
+pub(crate) async fn unblock<T, F>(f: F) -> T
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    match tokio::task::spawn_blocking(f).await {
+        Ok(res) => res,
+        Err(err) => std::panic::resume_unwind(err.into_panic()),
+    }
+}
+
+/// Runs a future to completion on the current thread.
+#[cfg(all(feature = "sync", not(feature = "tokio")))]
+pub(crate) fn block_on<F: Future>(future: F) -> F::Output {
+    futures::executor::block_on(future)
+}
+
+/// Runs a future to completion on the current thread.
+#[cfg(all(feature = "sync", feature = "tokio"))]
+pub(crate) fn block_on<F: Future>(future: F) -> F::Output {
+    thread_local! {
+        static RUNTIME: tokio::runtime::Runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .expect("failed to build tokio runtime");
+    }
+    RUNTIME.with(|rt| rt.block_on(future))
+}
@@ -0,0 +1,743 @@
+use core::convert::TryFrom;
+use core::fmt::Display;
+use core::marker::PhantomData;
+use libipld::cid::{Cid, Codec as Code};
+use libipld::codec::{Codec, Decode, Encode};
+use libipld::ipld::Ipld;
+use serde::de::{self, DeserializeOwned, IntoDeserializer, Unexpected, Visitor};
+use serde::ser::{self, Serialize};
+use std::collections::{btree_map, BTreeMap};
+use std::io::{Read, Write};
+use thiserror::Error;
+
+/// Serde error.
+#[derive(Debug, Error)]
+pub enum SerdeError {
+    /// The value doesn't match the ipld.
+    #[error("{0}")]
+    Custom(String),
+    /// The ipld codec failed.
+    #[error("{0}")]
+    Codec(Box<dyn std::error::Error + Send>),
+}
+
+impl ser::Error for SerdeError {
+    fn custom<T: Display>(msg: T) -> Self {
+        Self::Custom(msg.to_string())
+    }
+}
+
+impl de::Error for SerdeError {
+    fn custom<T: Display>(msg: T) -> Self {
+        Self::Custom(msg.to_string())
+    }
+}
+
+/// Name of the newtype struct carrying the bytes of a link.
+///
+/// `to_ipld` converts it to `Ipld::Link` and `from_ipld` converts links back,
+/// other serializers see a newtype struct of bytes.
+pub(crate) const LINK: &str = "$__ipld_link";
+
+/// Converts a serde type to ipld.
+///
+/// Enums are externally tagged like in serde_json. Map keys need to be
+/// strings.
+pub fn to_ipld<T: Serialize + ?Sized>(value: &T) -> Result<Ipld, SerdeError> {
+    value.serialize(Serializer)
+}
+
+/// Converts ipld to a serde type.
+pub fn from_ipld<T: DeserializeOwned>(ipld: Ipld) -> Result<T, SerdeError> {
+    T::deserialize(Deserializer(ipld))
+}
+
+/// Codec adapter encoding serde types with the ipld codec `C`.
+///
+/// Values are converted to ipld and encoded with `C`, so the blocks are
+/// the same as the ones of `C`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SerdeCodec<C>(PhantomData<C>);
+
+impl<C: Codec> Codec for SerdeCodec<C> {
+    const CODE: Code = C::CODE;
+
+    type Error = SerdeError;
+}
+
+/// Wraps a serde type to encode it with `SerdeCodec`.
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+pub struct Serde<T>(pub T);
+
+impl<C: Codec, T: Serialize> Encode<SerdeCodec<C>> for Serde<T>
+where
+    Ipld: Encode<C>,
+{
+    fn encode<W: Write>(&self, w: &mut W) -> Result<(), SerdeError> {
+        let ipld = to_ipld(&self.0)?;
+        Encode::<C>::encode(&ipld, w).map_err(|e| SerdeError::Codec(Box::new(e)))
+    }
+}
+
+impl<C: Codec, T: DeserializeOwned> Decode<SerdeCodec<C>> for Serde<T>
+where
+    Ipld: Decode<C>,
+{
+    fn decode<R: Read>(r: &mut R) -> Result<Self, SerdeError> {
+        let ipld = Decode::<C>::decode(r).map_err(|e| SerdeError::Codec(Box::new(e)))?;
+        from_ipld(ipld).map(Serde)
+    }
+}
+
+/// Wraps ipld in a single entry map if it's the value of an enum variant.
+fn tagged(variant: Option<&'static str>, ipld: Ipld) -> Ipld {
+    match variant {
+        Some(variant) => {
+            let mut map = BTreeMap::new();
+            map.insert(variant.to_string(), ipld);
+            Ipld::Map(map)
+        }
+        None => ipld,
+    }
+}
+
+struct Serializer;
+
+impl ser::Serializer for Serializer {
+    type Ok = Ipld;
+    type Error = SerdeError;
+    type SerializeSeq = SerializeList;
+    type SerializeTuple = SerializeList;
+    type SerializeTupleStruct = SerializeList;
+    type SerializeTupleVariant = SerializeList;
+    type SerializeMap = SerializeMap;
+    type SerializeStruct = SerializeMap;
+    type SerializeStructVariant = SerializeMap;
+
+    fn serialize_bool(self, v: bool) -> Result<Ipld, SerdeError> {
+        Ok(Ipld::Bool(v))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Ipld, SerdeError> {
+        Ok(Ipld::Integer(v.into()))
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Ipld, SerdeError> {
+        Ok(Ipld::Integer(v.into()))
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Ipld, SerdeError> {
+        Ok(Ipld::Integer(v.into()))
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Ipld, SerdeError> {
+        Ok(Ipld::Integer(v.into()))
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<Ipld, SerdeError> {
+        Ok(Ipld::Integer(v))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Ipld, SerdeError> {
+        Ok(Ipld::Integer(v.into()))
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Ipld, SerdeError> {
+        Ok(Ipld::Integer(v.into()))
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Ipld, SerdeError> {
+        Ok(Ipld::Integer(v.into()))
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Ipld, SerdeError> {
+        Ok(Ipld::Integer(v.into()))
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<Ipld, SerdeError> {
+        let v = i128::try_from(v).map_err(ser::Error::custom)?;
+        Ok(Ipld::Integer(v))
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Ipld, SerdeError> {
+        Ok(Ipld::Float(v.into()))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Ipld, SerdeError> {
+        Ok(Ipld::Float(v))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Ipld, SerdeError> {
+        Ok(Ipld::String(v.to_string()))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Ipld, SerdeError> {
+        Ok(Ipld::String(v.to_string()))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Ipld, SerdeError> {
+        Ok(Ipld::Bytes(v.to_vec()))
+    }
+
+    fn serialize_none(self) -> Result<Ipld, SerdeError> {
+        Ok(Ipld::Null)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Ipld, SerdeError> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Ipld, SerdeError> {
+        Ok(Ipld::Null)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Ipld, SerdeError> {
+        Ok(Ipld::Null)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+    ) -> Result<Ipld, SerdeError> {
+        Ok(Ipld::String(variant.to_string()))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        name: &'static str,
+        value: &T,
+    ) -> Result<Ipld, SerdeError> {
+        match (name, value.serialize(self)?) {
+            (LINK, Ipld::Bytes(bytes)) => Cid::try_from(bytes)
+                .map(Ipld::Link)
+                .map_err(ser::Error::custom),
+            (_, ipld) => Ok(ipld),
+        }
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Ipld, SerdeError> {
+        Ok(tagged(Some(variant), value.serialize(self)?))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SerializeList, SerdeError> {
+        Ok(SerializeList::new(None, len.unwrap_or_default()))
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SerializeList, SerdeError> {
+        Ok(SerializeList::new(None, len))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<SerializeList, SerdeError> {
+        Ok(SerializeList::new(None, len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<SerializeList, SerdeError> {
+        Ok(SerializeList::new(Some(variant), len))
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<SerializeMap, SerdeError> {
+        Ok(SerializeMap::new(None))
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<SerializeMap, SerdeError> {
+        Ok(SerializeMap::new(None))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<SerializeMap, SerdeError> {
+        Ok(SerializeMap::new(Some(variant)))
+    }
+}
+
+struct SerializeList {
+    variant: Option<&'static str>,
+    list: Vec<Ipld>,
+}
+
+impl SerializeList {
+    fn new(variant: Option<&'static str>, len: usize) -> Self {
+        Self {
+            variant,
+            list: Vec::with_capacity(len),
+        }
+    }
+
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        self.list.push(value.serialize(Serializer)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Ipld, SerdeError> {
+        Ok(tagged(self.variant, Ipld::List(self.list)))
+    }
+}
+
+impl ser::SerializeSeq for SerializeList {
+    type Ok = Ipld;
+    type Error = SerdeError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Ipld, SerdeError> {
+        SerializeList::end(self)
+    }
+}
+
+impl ser::SerializeTuple for SerializeList {
+    type Ok = Ipld;
+    type Error = SerdeError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Ipld, SerdeError> {
+        SerializeList::end(self)
+    }
+}
+
+impl ser::SerializeTupleStruct for SerializeList {
+    type Ok = Ipld;
+    type Error = SerdeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Ipld, SerdeError> {
+        SerializeList::end(self)
+    }
+}
+
+impl ser::SerializeTupleVariant for SerializeList {
+    type Ok = Ipld;
+    type Error = SerdeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Ipld, SerdeError> {
+        SerializeList::end(self)
+    }
+}
+
+struct SerializeMap {
+    variant: Option<&'static str>,
+    map: BTreeMap<String, Ipld>,
+    key: Option<String>,
+}
+
+impl SerializeMap {
+    fn new(variant: Option<&'static str>) -> Self {
+        Self {
+            variant,
+            map: Default::default(),
+            key: None,
+        }
+    }
+
+    fn insert<T: Serialize + ?Sized>(&mut self, key: String, value: &T) -> Result<(), SerdeError> {
+        self.map.insert(key, value.serialize(Serializer)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Ipld, SerdeError> {
+        Ok(tagged(self.variant, Ipld::Map(self.map)))
+    }
+}
+
+impl ser::SerializeMap for SerializeMap {
+    type Ok = Ipld;
+    type Error = SerdeError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), SerdeError> {
+        match key.serialize(Serializer)? {
+            Ipld::String(key) => {
+                self.key = Some(key);
+                Ok(())
+            }
+            _ => Err(ser::Error::custom("map keys need to be strings")),
+        }
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SerdeError> {
+        let key = self
+            .key
+            .take()
+            .ok_or_else(|| ser::Error::custom("value without a key"))?;
+        self.insert(key, value)
+    }
+
+    fn end(self) -> Result<Ipld, SerdeError> {
+        SerializeMap::end(self)
+    }
+}
+
+impl ser::SerializeStruct for SerializeMap {
+    type Ok = Ipld;
+    type Error = SerdeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), SerdeError> {
+        self.insert(key.to_string(), value)
+    }
+
+    fn end(self) -> Result<Ipld, SerdeError> {
+        SerializeMap::end(self)
+    }
+}
+
+impl ser::SerializeStructVariant for SerializeMap {
+    type Ok = Ipld;
+    type Error = SerdeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), SerdeError> {
+        self.insert(key.to_string(), value)
+    }
+
+    fn end(self) -> Result<Ipld, SerdeError> {
+        SerializeMap::end(self)
+    }
+}
+
+fn unexpected(ipld: &Ipld) -> Unexpected<'_> {
+    match ipld {
+        Ipld::Null => Unexpected::Unit,
+        Ipld::Bool(b) => Unexpected::Bool(*b),
+        Ipld::Integer(i) => match i64::try_from(*i) {
+            Ok(i) => Unexpected::Signed(i),
+            Err(_) => Unexpected::Other("integer"),
+        },
+        Ipld::Float(f) => Unexpected::Float(*f),
+        Ipld::String(s) => Unexpected::Str(s),
+        Ipld::Bytes(b) => Unexpected::Bytes(b),
+        Ipld::List(_) => Unexpected::Seq,
+        Ipld::Map(_) => Unexpected::Map,
+        Ipld::Link(_) => Unexpected::Other("link"),
+    }
+}
+
+struct Deserializer(Ipld);
+
+impl<'de> de::Deserializer<'de> for Deserializer {
+    type Error = SerdeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        match self.0 {
+            Ipld::Null => visitor.visit_unit(),
+            Ipld::Bool(b) => visitor.visit_bool(b),
+            Ipld::Integer(i) => {
+                if let Ok(i) = u64::try_from(i) {
+                    visitor.visit_u64(i)
+                } else if let Ok(i) = i64::try_from(i) {
+                    visitor.visit_i64(i)
+                } else {
+                    visitor.visit_i128(i)
+                }
+            }
+            Ipld::Float(f) => visitor.visit_f64(f),
+            Ipld::String(s) => visitor.visit_string(s),
+            Ipld::Bytes(b) => visitor.visit_byte_buf(b),
+            Ipld::List(list) => visitor.visit_seq(SeqAccess(list.into_iter())),
+            Ipld::Map(map) => visitor.visit_map(MapAccess {
+                iter: map.into_iter(),
+                value: None,
+            }),
+            ipld => Err(de::Error::invalid_type(unexpected(&ipld), &visitor)),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SerdeError> {
+        match self.0 {
+            Ipld::Null => visitor.visit_none(),
+            ipld => visitor.visit_some(Deserializer(ipld)),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, SerdeError> {
+        match (name, self.0) {
+            (LINK, Ipld::Link(cid)) => {
+                visitor.visit_newtype_struct(Deserializer(Ipld::Bytes(cid.to_bytes())))
+            }
+            (_, ipld) => visitor.visit_newtype_struct(Deserializer(ipld)),
+        }
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, SerdeError> {
+        match self.0 {
+            Ipld::String(variant) => visitor.visit_enum(EnumAccess {
+                variant,
+                value: None,
+            }),
+            Ipld::Map(map) if map.len() == 1 => {
+                let (variant, value) = map.into_iter().next().unwrap();
+                visitor.visit_enum(EnumAccess {
+                    variant,
+                    value: Some(value),
+                })
+            }
+            ipld => Err(de::Error::invalid_type(unexpected(&ipld), &"enum")),
+        }
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple tuple_struct map struct
+        identifier ignored_any
+    }
+}
+
+struct SeqAccess(std::vec::IntoIter<Ipld>);
+
+impl<'de> de::SeqAccess<'de> for SeqAccess {
+    type Error = SerdeError;
+
+    fn next_element_seed<T: de::DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, SerdeError> {
+        match self.0.next() {
+            Some(ipld) => seed.deserialize(Deserializer(ipld)).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.0.len())
+    }
+}
+
+struct MapAccess {
+    iter: btree_map::IntoIter<String, Ipld>,
+    value: Option<Ipld>,
+}
+
+impl<'de> de::MapAccess<'de> for MapAccess {
+    type Error = SerdeError;
+
+    fn next_key_seed<K: de::DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, SerdeError> {
+        match self.iter.next() {
+            Some((key, value)) => {
+                self.value = Some(value);
+                seed.deserialize(key.into_deserializer()).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: de::DeserializeSeed<'de>>(
+        &mut self,
+        seed: V,
+    ) -> Result<V::Value, SerdeError> {
+        let value = self
+            .value
+            .take()
+            .ok_or_else(|| de::Error::custom("value without a key"))?;
+        seed.deserialize(Deserializer(value))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.iter.len())
+    }
+}
+
+struct EnumAccess {
+    variant: String,
+    value: Option<Ipld>,
+}
+
+impl<'de> de::EnumAccess<'de> for EnumAccess {
+    type Error = SerdeError;
+    type Variant = VariantAccess;
+
+    fn variant_seed<V: de::DeserializeSeed<'de>>(
+        self,
+        seed: V,
+    ) -> Result<(V::Value, VariantAccess), SerdeError> {
+        let variant = seed.deserialize(self.variant.into_deserializer())?;
+        Ok((variant, VariantAccess(self.value)))
+    }
+}
+
+struct VariantAccess(Option<Ipld>);
+
+impl<'de> de::VariantAccess<'de> for VariantAccess {
+    type Error = SerdeError;
+
+    fn unit_variant(self) -> Result<(), SerdeError> {
+        match self.0 {
+            None | Some(Ipld::Null) => Ok(()),
+            Some(ipld) => Err(de::Error::invalid_type(unexpected(&ipld), &"unit variant")),
+        }
+    }
+
+    fn newtype_variant_seed<T: de::DeserializeSeed<'de>>(
+        self,
+        seed: T,
+    ) -> Result<T::Value, SerdeError> {
+        match self.0 {
+            Some(ipld) => seed.deserialize(Deserializer(ipld)),
+            None => Err(de::Error::invalid_type(
+                Unexpected::UnitVariant,
+                &"newtype variant",
+            )),
+        }
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, SerdeError> {
+        match self.0 {
+            Some(Ipld::List(list)) => visitor.visit_seq(SeqAccess(list.into_iter())),
+            Some(ipld) => Err(de::Error::invalid_type(unexpected(&ipld), &"tuple variant")),
+            None => Err(de::Error::invalid_type(
+                Unexpected::UnitVariant,
+                &"tuple variant",
+            )),
+        }
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, SerdeError> {
+        match self.0 {
+            Some(Ipld::Map(map)) => visitor.visit_map(MapAccess {
+                iter: map.into_iter(),
+                value: None,
+            }),
+            Some(ipld) => Err(de::Error::invalid_type(
+                unexpected(&ipld),
+                &"struct variant",
+            )),
+            None => Err(de::Error::invalid_type(
+                Unexpected::UnitVariant,
+                &"struct variant",
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BlockBuilder, Cache, Codec, IpldCache, ReadonlyCache, SerdeCborCodec};
+    use libipld::ipld;
+    use libipld::mem::MemStore;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+    enum Role {
+        Admin,
+        Member { since: u32 },
+        Guest(String),
+    }
+
+    #[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+    struct User {
+        name: String,
+        age: Option<u8>,
+        roles: Vec<Role>,
+        scores: BTreeMap<String, f64>,
+    }
+
+    fn user() -> User {
+        let mut scores = BTreeMap::new();
+        scores.insert("chess".to_string(), 1.5);
+        User {
+            name: "alice".into(),
+            age: None,
+            roles: vec![
+                Role::Admin,
+                Role::Member { since: 2019 },
+                Role::Guest("bob".into()),
+            ],
+            scores,
+        }
+    }
+
+    #[test]
+    fn test_ipld_roundtrip() {
+        let ipld = to_ipld(&user()).unwrap();
+        let expected = ipld!({
+            "name": "alice",
+            "age": null,
+            "roles": ["Admin", {"Member": {"since": 2019}}, {"Guest": "bob"}],
+            "scores": {"chess": 1.5},
+        });
+        assert_eq!(ipld, expected);
+        assert_eq!(from_ipld::<User>(ipld).unwrap(), user());
+        assert!(from_ipld::<User>(ipld!({"name": 1})).is_err());
+    }
+
+    #[async_std::test]
+    async fn test_serde_codec() {
+        let store = MemStore::default();
+        let builder = BlockBuilder::new(store.clone(), SerdeCborCodec::new());
+        let cid = builder.insert(&Serde(user())).await.unwrap();
+        let Serde(user2): Serde<User> = builder.get(&cid).await.unwrap();
+        assert_eq!(user2, user());
+
+        // the blocks are plain dag-cbor
+        let builder = BlockBuilder::new(store.clone(), Codec::new());
+        let ipld = builder.get_ipld(&cid).await.unwrap();
+        assert_eq!(ipld, to_ipld(&user()).unwrap());
+
+        let cache =
+            IpldCache::<_, SerdeCborCodec, Serde<User>>::new(store, SerdeCborCodec::new(), 1);
+        let cid = cache.insert(Serde(user())).await.unwrap();
+        assert_eq!(cache.get(&cid).await.unwrap(), Serde(user()));
+    }
+}
@@ -0,0 +1,84 @@
+use crate::builder::BlockBuilder;
+use crate::codec::{Encoder, GenericStrobeCodec};
+use crate::crypto::Key;
+use crate::error::Result;
+use libipld::cid::Cid;
+use libipld::codec::Encode;
+use libipld::ipld::Ipld;
+use libipld::store::Store;
+
+/// Subtree re-encrypted for a recipient.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SharedSubtree {
+    /// Root of the re-encrypted subtree.
+    pub root: Cid,
+    /// Key of the subtree wrapped with the key of the recipient.
+    pub wrapped_key: Box<[u8]>,
+}
+
+impl<S, C, H> BlockBuilder<S, GenericStrobeCodec<C, H>>
+where
+    S: Store + Clone,
+    GenericStrobeCodec<C, H>: Encoder + Clone,
+    Ipld: Encode<<GenericStrobeCodec<C, H> as Encoder>::Codec>,
+{
+    /// Re-encrypts the subtree of `root` under a fresh key wrapped for the
+    /// holder of `recipient_key`.
+    ///
+    /// The blocks of the rest of the dag are left untouched, the recipient
+    /// can only read the subtree. The new root is pinned. The recipient
+    /// recovers the key with `Key::unwrap`.
+    pub async fn export_encrypted_subtree(
+        &self,
+        root: &Cid,
+        recipient_key: &Key,
+    ) -> Result<SharedSubtree> {
+        let key = Key::generate();
+        let wrapped_key = key.wrap(recipient_key)?;
+        let target = BlockBuilder::new_private(self.store().clone(), self.codec().with_key(key));
+        let root = self.rekey(root, &target).await?;
+        Ok(SharedSubtree { root, wrapped_key })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::StrobeCodec;
+    use libipld::ipld;
+    use libipld::mem::MemStore;
+
+    #[async_std::test]
+    async fn test_export_encrypted_subtree() {
+        let store = MemStore::default();
+        let owner = BlockBuilder::new_private(store.clone(), StrobeCodec::new(Key::generate()));
+        let leaf = owner.insert(&ipld!({"shared": 42})).await.unwrap();
+        let subtree = owner.insert(&ipld!({"leaf": leaf})).await.unwrap();
+        let secret = owner.insert(&ipld!("secret")).await.unwrap();
+        let root = owner
+            .insert(&ipld!({"subtree": &subtree, "secret": &secret}))
+            .await
+            .unwrap();
+
+        let recipient_key = Key::from(vec![7; 32]);
+        let shared = owner
+            .export_encrypted_subtree(&subtree, &recipient_key)
+            .await
+            .unwrap();
+        assert!(Key::unwrap(&shared.wrapped_key, &Key::from(vec![8; 32])).is_err());
+        let key = Key::unwrap(&shared.wrapped_key, &recipient_key).unwrap();
+        let recipient = BlockBuilder::new_private(store, StrobeCodec::new(key));
+        let ipld = recipient.get_ipld(&shared.root).await.unwrap();
+        let leaf = match ipld.get("leaf").unwrap() {
+            Ipld::Link(cid) => cid.clone(),
+            _ => panic!(),
+        };
+        assert_eq!(
+            recipient.get_ipld(&leaf).await.unwrap(),
+            ipld!({"shared": 42})
+        );
+        assert!(recipient.get_ipld(&root).await.is_err());
+        assert!(recipient.get_ipld(&secret).await.is_err());
+        assert!(owner.get_ipld(&root).await.is_ok());
+    }
+}
@@ -0,0 +1,277 @@
+use crate::builder::BlockBuilder;
+use crate::error::{verify, Error, Result};
+use crate::rt::{SystemTime, UNIX_EPOCH};
+use core::convert::TryFrom;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use libipld::cbor::DagCborCodec;
+use libipld::cid::{Cid, Codec as CidCodec};
+use libipld::codec::Codec;
+use libipld::ipld::Ipld;
+use libipld::multihash::Blake2b256;
+use libipld::store::{AliasStore, Store, Visibility};
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// Validity of heads created with `publish`.
+pub const DEFAULT_VALIDITY: Duration = Duration::from_secs(24 * 60 * 60);
+
+fn invalid(msg: impl Into<String>) -> Error {
+    Error::InvalidRecord(msg.into())
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Returns the alias of the heads signed by `public_key`.
+fn alias(public_key: &VerifyingKey) -> Vec<u8> {
+    [&b"signed-head/"[..], public_key.as_bytes()].concat()
+}
+
+/// Mutable pointer to a cid signed with an ed25519 key.
+///
+/// Like an ipns record the head with the highest sequence number wins and
+/// expires after its validity.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SignedHead {
+    /// Key that signed the head.
+    pub public_key: VerifyingKey,
+    /// Sequence number, incremented with every update.
+    pub sequence: u64,
+    /// The cid the head points to.
+    pub cid: Cid,
+    /// Expiry of the head in seconds since the unix epoch.
+    pub validity: u64,
+    /// Signature of the sequence, cid and validity.
+    pub signature: Signature,
+}
+
+fn fields(sequence: u64, cid: &Cid, validity: u64) -> BTreeMap<String, Ipld> {
+    let mut map = BTreeMap::new();
+    map.insert("sequence".to_string(), Ipld::Integer(sequence.into()));
+    map.insert("cid".to_string(), Ipld::Link(cid.clone()));
+    map.insert("validity".to_string(), Ipld::Integer(validity.into()));
+    map
+}
+
+fn encode(map: BTreeMap<String, Ipld>) -> Result<Box<[u8]>> {
+    DagCborCodec::encode(&Ipld::Map(map)).map_err(|err| invalid(err.to_string()))
+}
+
+fn integer(ipld: &Ipld, key: &str) -> Result<u64> {
+    match ipld.get(key) {
+        Ok(Ipld::Integer(n)) => u64::try_from(*n).map_err(|_| invalid(format!("invalid {}", key))),
+        _ => Err(invalid(format!("missing {}", key))),
+    }
+}
+
+fn bytes<'a>(ipld: &'a Ipld, key: &str) -> Result<&'a [u8]> {
+    match ipld.get(key) {
+        Ok(Ipld::Bytes(bytes)) => Ok(bytes),
+        _ => Err(invalid(format!("missing {}", key))),
+    }
+}
+
+impl SignedHead {
+    /// Creates a head signed with `key`.
+    pub fn new(key: &SigningKey, sequence: u64, cid: Cid, validity: u64) -> Result<Self> {
+        let msg = encode(fields(sequence, &cid, validity))?;
+        Ok(Self {
+            public_key: key.verifying_key(),
+            sequence,
+            cid,
+            validity,
+            signature: key.sign(&msg),
+        })
+    }
+
+    /// Verifies the signature and the validity of the head.
+    pub fn verify(&self) -> Result<()> {
+        let msg = encode(fields(self.sequence, &self.cid, self.validity))?;
+        self.public_key
+            .verify(&msg, &self.signature)
+            .map_err(|_| invalid("invalid signature"))?;
+        if self.validity < now() {
+            return Err(invalid("expired"));
+        }
+        Ok(())
+    }
+
+    /// Encodes the head as a dag-cbor block.
+    pub fn encode(&self) -> Result<Box<[u8]>> {
+        let mut map = fields(self.sequence, &self.cid, self.validity);
+        let public_key = self.public_key.as_bytes().to_vec();
+        map.insert("public_key".to_string(), Ipld::Bytes(public_key));
+        let signature = self.signature.to_bytes().to_vec();
+        map.insert("signature".to_string(), Ipld::Bytes(signature));
+        encode(map)
+    }
+
+    /// Decodes a head without verifying it.
+    pub fn decode(data: &[u8]) -> Result<Self> {
+        let ipld: Ipld = DagCborCodec::decode(data).map_err(|err| invalid(err.to_string()))?;
+        let public_key = <[u8; 32]>::try_from(bytes(&ipld, "public_key")?)
+            .ok()
+            .and_then(|key| VerifyingKey::from_bytes(&key).ok())
+            .ok_or_else(|| invalid("invalid public key"))?;
+        let signature = Signature::from_slice(bytes(&ipld, "signature")?)
+            .map_err(|_| invalid("invalid signature"))?;
+        let cid = match ipld.get("cid") {
+            Ok(Ipld::Link(cid)) => cid.clone(),
+            _ => return Err(invalid("missing cid")),
+        };
+        Ok(Self {
+            public_key,
+            sequence: integer(&ipld, "sequence")?,
+            cid,
+            validity: integer(&ipld, "validity")?,
+            signature,
+        })
+    }
+
+    /// Returns the cid of the encoded head.
+    fn block(&self) -> Result<(Cid, Box<[u8]>)> {
+        let data = self.encode()?;
+        let hash = Blake2b256::digest(&data);
+        Ok((Cid::new_v1(CidCodec::DagCBOR, hash), data))
+    }
+}
+
+impl<S: Store + AliasStore, C> BlockBuilder<S, C> {
+    /// Points the head of `key` to `cid`, valid for `DEFAULT_VALIDITY`.
+    pub async fn publish(&self, key: &SigningKey, cid: &Cid) -> Result<SignedHead> {
+        self.publish_with_validity(key, cid, DEFAULT_VALIDITY).await
+    }
+
+    /// Points the head of `key` to `cid`, valid for `validity`.
+    ///
+    /// The head is stored as a public block aliased by the public key, so
+    /// it can be exchanged with untrusted parties.
+    pub async fn publish_with_validity(
+        &self,
+        key: &SigningKey,
+        cid: &Cid,
+        validity: Duration,
+    ) -> Result<SignedHead> {
+        let sequence = match self.get_head(&key.verifying_key()).await? {
+            Some(head) => head.sequence + 1,
+            None => 0,
+        };
+        let validity = now().saturating_add(validity.as_secs());
+        let head = SignedHead::new(key, sequence, cid.clone(), validity)?;
+        if !self.accept_head(&head).await? {
+            return Err(invalid("concurrent publish"));
+        }
+        Ok(head)
+    }
+
+    /// Stores a head received from another party if it is valid and newer
+    /// than the current head of its key, returning if it was stored.
+    pub async fn accept_head(&self, head: &SignedHead) -> Result<bool> {
+        head.verify()?;
+        let current = self.store().resolve(&alias(&head.public_key)).await?;
+        let prev = match &current {
+            Some(cid) => Some(self.load_head(cid).await?),
+            None => None,
+        };
+        if let Some(prev) = &prev {
+            if prev.sequence >= head.sequence {
+                return Ok(false);
+            }
+        }
+        let (cid, data) = head.block()?;
+        self.store().insert(&cid, data, Visibility::Public).await?;
+        if !self
+            .alias_cas(&alias(&head.public_key), current.as_ref(), &cid)
+            .await?
+        {
+            self.store().unpin(&cid).await?;
+            return Ok(false);
+        }
+        if let Some(current) = current {
+            self.store().unpin(&current).await?;
+        }
+        Ok(true)
+    }
+
+    /// Returns the cid the head of `public_key` points to after verifying
+    /// its signature and validity.
+    pub async fn resolve_verified(&self, public_key: &VerifyingKey) -> Result<Option<Cid>> {
+        match self.get_head(public_key).await? {
+            Some(head) => {
+                head.verify()?;
+                Ok(Some(head.cid))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the stored head of `public_key` without verifying it.
+    pub async fn get_head(&self, public_key: &VerifyingKey) -> Result<Option<SignedHead>> {
+        match self.store().resolve(&alias(public_key)).await? {
+            Some(cid) => {
+                let head = self.load_head(&cid).await?;
+                if &head.public_key != public_key {
+                    return Err(invalid("public key mismatch"));
+                }
+                Ok(Some(head))
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn load_head(&self, cid: &Cid) -> Result<SignedHead> {
+        let data = self.store().get(cid).await?;
+        verify(cid, &data)?;
+        SignedHead::decode(&data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Codec;
+    use libipld::ipld;
+    use libipld::mem::MemStore;
+
+    #[async_std::test]
+    async fn test_signed_head() {
+        let key = SigningKey::from_bytes(&[1; 32]);
+        let public_key = key.verifying_key();
+        let a = BlockBuilder::new(MemStore::default(), Codec::new());
+        let v1 = a.insert(&ipld!(1)).await.unwrap();
+        let v2 = a.insert(&ipld!(2)).await.unwrap();
+        assert_eq!(a.resolve_verified(&public_key).await.unwrap(), None);
+
+        let h1 = a.publish(&key, &v1).await.unwrap();
+        let h2 = a.publish(&key, &v2).await.unwrap();
+        assert_eq!((h1.sequence, h2.sequence), (0, 1));
+        assert_eq!(
+            a.resolve_verified(&public_key).await.unwrap(),
+            Some(v2.clone())
+        );
+
+        // records can be exchanged with untrusted parties
+        let b = BlockBuilder::new(MemStore::default(), Codec::new());
+        let h2 = SignedHead::decode(&h2.encode().unwrap()).unwrap();
+        assert!(b.accept_head(&h2).await.unwrap());
+        assert!(!b.accept_head(&h1).await.unwrap());
+        assert_eq!(b.resolve_verified(&public_key).await.unwrap(), Some(v2));
+
+        let mut forged = h1.clone();
+        forged.sequence = 5;
+        assert!(matches!(
+            b.accept_head(&forged).await,
+            Err(Error::InvalidRecord(_))
+        ));
+
+        let expired = SignedHead::new(&key, 6, v1, 0).unwrap();
+        assert!(matches!(
+            b.accept_head(&expired).await,
+            Err(Error::InvalidRecord(_))
+        ));
+    }
+}
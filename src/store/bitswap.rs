@@ -0,0 +1,178 @@
+use crate::store::RemoteStore;
+use libipld::block::Block;
+use libipld::cid::Cid;
+use libipld::error::StoreError;
+use libipld::store::{ReadonlyStore, Store, StoreResult, Visibility};
+
+/// Network half of a bitswap integration.
+///
+/// The crate doesn't depend on libp2p, the trait is implemented on top of
+/// the bitswap behaviour of the application's swarm. Wanted blocks of other
+/// peers are served from the local store of the `BitswapStore`.
+pub trait Bitswap: Clone + Send + Sync {
+    /// Requests a block from the connected peers.
+    fn want<'a>(&'a self, cid: &'a Cid) -> StoreResult<'a, Box<[u8]>>;
+
+    /// Announces that a block is available from the local store.
+    fn provide<'a>(&'a self, cid: &'a Cid) -> StoreResult<'a, ()>;
+}
+
+/// A store fetching missing blocks from connected peers with bitswap.
+///
+/// Blocks missing from the local store are requested from the network and
+/// verified against their cid. Fetched blocks are not written to the local
+/// store. Public blocks inserted into the store are provided to the
+/// network, private blocks are never announced.
+#[derive(Clone)]
+pub struct BitswapStore<S, B> {
+    store: S,
+    bitswap: B,
+}
+
+impl<S, B> BitswapStore<S, B> {
+    /// Creates a new bitswap store.
+    pub fn new(store: S, bitswap: B) -> Self {
+        Self { store, bitswap }
+    }
+
+    /// Returns the local store.
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    /// Returns the bitswap network.
+    pub fn bitswap(&self) -> &B {
+        &self.bitswap
+    }
+}
+
+impl<S: ReadonlyStore + Send + Sync, B: Bitswap> ReadonlyStore for BitswapStore<S, B> {
+    fn get<'a>(&'a self, cid: &'a Cid) -> StoreResult<'a, Box<[u8]>> {
+        Box::pin(async move {
+            match self.store.get(cid).await {
+                Err(StoreError::BlockNotFound(_)) => {
+                    let data = self.bitswap.want(cid).await?;
+                    crate::error::verify(cid, &data).map_err(|e| StoreError::Other(Box::new(e)))?;
+                    Ok(data)
+                }
+                res => res,
+            }
+        })
+    }
+}
+
+impl<S: ReadonlyStore + Send + Sync, B: Bitswap> RemoteStore for BitswapStore<S, B> {
+    fn get_local<'a>(&'a self, cid: &'a Cid) -> StoreResult<'a, Box<[u8]>> {
+        self.store.get(cid)
+    }
+}
+
+impl<S: Store + Send + Sync, B: Bitswap> Store for BitswapStore<S, B> {
+    fn insert<'a>(
+        &'a self,
+        cid: &'a Cid,
+        data: Box<[u8]>,
+        visibility: Visibility,
+    ) -> StoreResult<'a, ()> {
+        Box::pin(async move {
+            self.store.insert(cid, data, visibility).await?;
+            if visibility == Visibility::Public {
+                self.bitswap.provide(cid).await?;
+            }
+            Ok(())
+        })
+    }
+
+    fn insert_batch<'a>(
+        &'a self,
+        batch: Vec<Block>,
+        visibility: Visibility,
+    ) -> StoreResult<'a, Cid> {
+        Box::pin(async move {
+            let cids: Vec<Cid> = batch.iter().map(|block| block.cid.clone()).collect();
+            let cid = self.store.insert_batch(batch, visibility).await?;
+            if visibility == Visibility::Public {
+                for cid in &cids {
+                    self.bitswap.provide(cid).await?;
+                }
+            }
+            Ok(cid)
+        })
+    }
+
+    fn flush(&self) -> StoreResult<'_, ()> {
+        self.store.flush()
+    }
+
+    fn unpin<'a>(&'a self, cid: &'a Cid) -> StoreResult<'a, ()> {
+        self.store.unpin(cid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BlockBuilder, Codec, Encoder, Error};
+    use futures::lock::Mutex;
+    use libipld::ipld;
+    use libipld::mem::MemStore;
+    use std::sync::Arc;
+
+    /// Peers sharing the blocks of a `MemStore`.
+    #[derive(Clone, Default)]
+    struct Peers {
+        store: MemStore,
+        provided: Arc<Mutex<Vec<Cid>>>,
+    }
+
+    impl Bitswap for Peers {
+        fn want<'a>(&'a self, cid: &'a Cid) -> StoreResult<'a, Box<[u8]>> {
+            self.store.get(cid)
+        }
+
+        fn provide<'a>(&'a self, cid: &'a Cid) -> StoreResult<'a, ()> {
+            Box::pin(async move {
+                self.provided.lock().await.push(cid.clone());
+                Ok(())
+            })
+        }
+    }
+
+    #[async_std::test]
+    async fn test_bitswap_store() {
+        let peers = Peers::default();
+        let remote = BlockBuilder::new(peers.store.clone(), Codec::new());
+        let remote = remote.insert(&ipld!("remote")).await.unwrap();
+
+        let store = BitswapStore::new(MemStore::default(), peers.clone());
+        let builder = BlockBuilder::new(store.clone(), Codec::new());
+        assert_eq!(builder.get_ipld(&remote).await.unwrap(), ipld!("remote"));
+
+        let local = builder.insert(&ipld!("local")).await.unwrap();
+        assert_eq!(*peers.provided.lock().await, vec![local.clone()]);
+
+        let missing = builder.codec().encode(&ipld!("missing")).unwrap().cid;
+        let res = builder.get_ipld(&missing).await;
+        assert!(matches!(
+            res,
+            Err(Error::Store(StoreError::BlockNotFound(_)))
+        ));
+
+        let mut builder = BlockBuilder::new(store, Codec::new());
+        builder.set_offline(true);
+        assert!(builder.get_ipld(&local).await.is_ok());
+        let res = builder.get_ipld(&remote).await;
+        assert!(matches!(res, Err(Error::NotFoundLocally(_))));
+    }
+
+    #[cfg(feature = "crypto")]
+    #[async_std::test]
+    async fn test_bitswap_store_private() {
+        let peers = Peers::default();
+        let store = BitswapStore::new(MemStore::default(), peers.clone());
+        let key = crate::Key::from(b"private encryption key".to_vec());
+        let builder = BlockBuilder::new_private(store, crate::StrobeCodec::new(key));
+        builder.insert(&ipld!("private")).await.unwrap();
+        assert!(peers.provided.lock().await.is_empty());
+    }
+}
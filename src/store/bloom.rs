@@ -0,0 +1,262 @@
+use libipld::block::Block;
+use libipld::cid::Cid;
+use libipld::error::StoreError;
+use libipld::store::{AliasStore, MultiUserStore, ReadonlyStore, Store, StoreResult, Visibility};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+struct BloomFilter {
+    bits: Vec<u64>,
+    hashes: u64,
+}
+
+impl BloomFilter {
+    fn new(items: usize, fp_rate: f64) -> Self {
+        let items = items.max(1) as f64;
+        let ln2 = std::f64::consts::LN_2;
+        let bits = (-items * fp_rate.ln() / (ln2 * ln2)).ceil().max(64.0);
+        let hashes = (bits / items * ln2).round().max(1.0) as u64;
+        Self {
+            bits: vec![0; (bits as usize).div_ceil(64)],
+            hashes,
+        }
+    }
+
+    fn indices(&self, cid: &Cid) -> impl Iterator<Item = usize> {
+        let mut hasher = DefaultHasher::new();
+        Hash::hash(cid, &mut hasher);
+        let h1 = hasher.finish();
+        1u8.hash(&mut hasher);
+        let h2 = hasher.finish();
+        let len = self.bits.len() as u64 * 64;
+        (0..self.hashes).map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % len) as usize)
+    }
+
+    fn insert(&mut self, cid: &Cid) {
+        for i in self.indices(cid).collect::<Vec<_>>() {
+            self.bits[i / 64] |= 1 << (i % 64);
+        }
+    }
+
+    fn contains(&self, cid: &Cid) -> bool {
+        self.indices(cid)
+            .all(|i| self.bits[i / 64] & (1 << (i % 64)) != 0)
+    }
+}
+
+/// A store wrapper tracking the blocks of the store in a bloom filter.
+///
+/// The filter only knows about blocks that were inserted or fetched through
+/// the wrapper, or added with `add`. Blocks already present in the wrapped
+/// store need to be added before `contains` can be trusted.
+#[derive(Clone)]
+pub struct BloomStore<S> {
+    store: S,
+    filter: Arc<Mutex<BloomFilter>>,
+    skip_existing: bool,
+    skipped: Arc<AtomicUsize>,
+}
+
+impl<S> BloomStore<S> {
+    /// Creates a wrapper sized for `items` blocks with a false positive rate
+    /// of `fp_rate`.
+    pub fn new(store: S, items: usize, fp_rate: f64) -> Self {
+        Self {
+            store,
+            filter: Arc::new(Mutex::new(BloomFilter::new(items, fp_rate))),
+            skip_existing: false,
+            skipped: Default::default(),
+        }
+    }
+
+    /// Skips the blocks of a batch that the store already contains.
+    ///
+    /// The last block of a batch is always written, so that it is pinned.
+    /// Only blocks that may be in the store are looked up.
+    pub fn set_skip_existing(&mut self, skip_existing: bool) {
+        self.skip_existing = skip_existing;
+    }
+
+    /// Returns the number of blocks that were skipped.
+    pub fn skipped(&self) -> usize {
+        self.skipped.load(Ordering::Relaxed)
+    }
+
+    /// Returns the wrapped store.
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    /// Adds a block known to be in the store to the filter.
+    pub fn add(&self, cid: &Cid) {
+        self.filter.lock().unwrap().insert(cid);
+    }
+
+    /// Returns `false` if the block is definitely not in the store.
+    pub fn may_contain(&self, cid: &Cid) -> bool {
+        self.filter.lock().unwrap().contains(cid)
+    }
+}
+
+impl<S: ReadonlyStore> BloomStore<S> {
+    /// Returns if the store contains a block.
+    ///
+    /// Only blocks that may be in the store are looked up in the wrapped store.
+    pub async fn contains(&self, cid: &Cid) -> Result<bool, StoreError> {
+        if !self.may_contain(cid) {
+            return Ok(false);
+        }
+        match self.store.get(cid).await {
+            Ok(_) => Ok(true),
+            Err(StoreError::BlockNotFound(_)) => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Returns the blocks that are not in the store.
+    pub async fn missing<'a, I>(&self, cids: I) -> Result<Vec<Cid>, StoreError>
+    where
+        I: IntoIterator<Item = &'a Cid>,
+    {
+        let mut missing = vec![];
+        for cid in cids {
+            if !self.contains(cid).await? {
+                missing.push(cid.clone());
+            }
+        }
+        Ok(missing)
+    }
+}
+
+impl<S: ReadonlyStore + Send + Sync> ReadonlyStore for BloomStore<S> {
+    fn get<'a>(&'a self, cid: &'a Cid) -> StoreResult<'a, Box<[u8]>> {
+        Box::pin(async move {
+            let data = self.store.get(cid).await?;
+            self.add(cid);
+            Ok(data)
+        })
+    }
+}
+
+impl<S: Store + Send + Sync> Store for BloomStore<S> {
+    fn insert<'a>(
+        &'a self,
+        cid: &'a Cid,
+        data: Box<[u8]>,
+        visibility: Visibility,
+    ) -> StoreResult<'a, ()> {
+        Box::pin(async move {
+            self.store.insert(cid, data, visibility).await?;
+            self.add(cid);
+            Ok(())
+        })
+    }
+
+    fn insert_batch<'a>(
+        &'a self,
+        batch: Vec<Block>,
+        visibility: Visibility,
+    ) -> StoreResult<'a, Cid> {
+        Box::pin(async move {
+            let cids: Vec<Cid> = batch.iter().map(|block| block.cid.clone()).collect();
+            let batch = if self.skip_existing {
+                let len = batch.len();
+                let mut blocks = Vec::with_capacity(len);
+                for (i, block) in batch.into_iter().enumerate() {
+                    if i + 1 < len && self.contains(&block.cid).await? {
+                        self.skipped.fetch_add(1, Ordering::Relaxed);
+                    } else {
+                        blocks.push(block);
+                    }
+                }
+                blocks
+            } else {
+                batch
+            };
+            let cid = self.store.insert_batch(batch, visibility).await?;
+            let mut filter = self.filter.lock().unwrap();
+            for cid in &cids {
+                filter.insert(cid);
+            }
+            Ok(cid)
+        })
+    }
+
+    fn flush(&self) -> StoreResult<'_, ()> {
+        self.store.flush()
+    }
+
+    fn unpin<'a>(&'a self, cid: &'a Cid) -> StoreResult<'a, ()> {
+        self.store.unpin(cid)
+    }
+}
+
+impl<S: MultiUserStore + Send + Sync> MultiUserStore for BloomStore<S> {
+    fn pin<'a>(&'a self, cid: &'a Cid, path: &'a Path) -> StoreResult<'a, ()> {
+        self.store.pin(cid, path)
+    }
+}
+
+impl<S: AliasStore + Send + Sync> AliasStore for BloomStore<S> {
+    fn alias<'a>(
+        &'a self,
+        alias: &'a [u8],
+        cid: &'a Cid,
+        visibility: Visibility,
+    ) -> StoreResult<'a, ()> {
+        self.store.alias(alias, cid, visibility)
+    }
+
+    fn unalias<'a>(&'a self, alias: &'a [u8]) -> StoreResult<'a, ()> {
+        self.store.unalias(alias)
+    }
+
+    fn resolve<'a>(&'a self, alias: &'a [u8]) -> StoreResult<'a, Option<Cid>> {
+        self.store.resolve(alias)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BlockBuilder, Codec, Encoder};
+    use libipld::ipld;
+    use libipld::mem::MemStore;
+
+    #[async_std::test]
+    async fn test_bloom_store() {
+        let store = BloomStore::new(MemStore::default(), 1000, 0.01);
+        let builder = BlockBuilder::new(store.clone(), Codec::new());
+        let a = builder.insert(&ipld!({"a": 3})).await.unwrap();
+        let b = Codec::new().encode(&ipld!({"b": 3})).unwrap().cid;
+        assert!(store.may_contain(&a));
+        assert!(!store.may_contain(&b));
+        assert!(store.contains(&a).await.unwrap());
+        assert!(!store.contains(&b).await.unwrap());
+        assert_eq!(store.missing(&[a, b.clone()]).await.unwrap(), vec![b]);
+    }
+
+    #[async_std::test]
+    async fn test_skip_existing() {
+        let mut store = BloomStore::new(MemStore::default(), 1000, 0.01);
+        store.set_skip_existing(true);
+        let builder = BlockBuilder::new(store.clone(), Codec::new());
+        let values: Vec<_> = (0..10).map(|i| ipld!({ "row": i })).collect();
+        builder.insert_many(&values[..5]).await.unwrap();
+        assert_eq!(store.skipped(), 0);
+
+        let cids = builder.insert_many(&values).await.unwrap();
+        assert_eq!(store.skipped(), 5);
+        for cid in &cids {
+            assert!(store.contains(cid).await.unwrap());
+        }
+        // the root is written again to pin it
+        assert_eq!(builder.insert_many(&values).await.unwrap(), cids);
+        assert_eq!(store.skipped(), 14);
+        builder.unpin(cids.last().unwrap()).await.unwrap();
+        assert!(store.contains(cids.last().unwrap()).await.unwrap());
+    }
+}
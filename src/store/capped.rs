@@ -0,0 +1,251 @@
+use futures::lock::Mutex;
+use libipld::block::Block;
+use libipld::cid::Cid;
+use libipld::error::StoreError;
+use libipld::store::{AliasStore, ReadonlyStore, Store, StoreResult, Visibility};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+struct Entry {
+    data: Box<[u8]>,
+    pins: usize,
+    tick: u64,
+}
+
+struct InnerStore {
+    budget: usize,
+    size: usize,
+    tick: u64,
+    blocks: HashMap<Cid, Entry>,
+    lru: BTreeMap<u64, Cid>,
+}
+
+impl InnerStore {
+    fn new(budget: usize) -> Self {
+        Self {
+            budget,
+            size: 0,
+            tick: 0,
+            blocks: Default::default(),
+            lru: Default::default(),
+        }
+    }
+
+    fn get(&mut self, cid: &Cid) -> Result<Box<[u8]>, StoreError> {
+        self.touch(cid);
+        if let Some(entry) = self.blocks.get(cid) {
+            Ok(entry.data.clone())
+        } else {
+            Err(StoreError::BlockNotFound(cid.clone()))
+        }
+    }
+
+    fn touch(&mut self, cid: &Cid) {
+        if let Some(entry) = self.blocks.get_mut(cid) {
+            if entry.pins == 0 {
+                self.lru.remove(&entry.tick);
+                self.tick += 1;
+                entry.tick = self.tick;
+                self.lru.insert(self.tick, cid.clone());
+            }
+        }
+    }
+
+    fn insert_block(&mut self, cid: &Cid, data: Box<[u8]>) {
+        if self.blocks.contains_key(cid) {
+            self.touch(cid);
+            return;
+        }
+        self.tick += 1;
+        self.size += data.len();
+        self.lru.insert(self.tick, cid.clone());
+        let entry = Entry {
+            data,
+            pins: 0,
+            tick: self.tick,
+        };
+        self.blocks.insert(cid.clone(), entry);
+    }
+
+    fn pin(&mut self, cid: &Cid) {
+        if let Some(entry) = self.blocks.get_mut(cid) {
+            if entry.pins == 0 {
+                self.lru.remove(&entry.tick);
+            }
+            entry.pins += 1;
+        }
+    }
+
+    fn unpin(&mut self, cid: &Cid) {
+        if let Some(entry) = self.blocks.get_mut(cid) {
+            if entry.pins == 1 {
+                self.tick += 1;
+                entry.tick = self.tick;
+                self.lru.insert(self.tick, cid.clone());
+            }
+            entry.pins = entry.pins.saturating_sub(1);
+        }
+        self.evict();
+    }
+
+    fn evict(&mut self) {
+        while self.size > self.budget {
+            if let Some((_, cid)) = self.lru.pop_first() {
+                if let Some(entry) = self.blocks.remove(&cid) {
+                    self.size -= entry.data.len();
+                }
+            } else {
+                break;
+            }
+        }
+    }
+}
+
+/// A memory backed store with a byte budget.
+///
+/// When the budget is exceeded the least recently used unpinned blocks are
+/// evicted. Blocks that are only reachable through a pinned block are not
+/// protected from eviction.
+#[derive(Clone)]
+pub struct CappedMemStore {
+    inner: Arc<Mutex<InnerStore>>,
+    aliases: Arc<Mutex<HashMap<Box<[u8]>, Cid>>>,
+}
+
+impl CappedMemStore {
+    /// Creates a new store that holds up to `budget` bytes of blocks.
+    pub fn new(budget: usize) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(InnerStore::new(budget))),
+            aliases: Default::default(),
+        }
+    }
+
+    /// Returns the number of bytes stored.
+    pub async fn size(&self) -> usize {
+        self.inner.lock().await.size
+    }
+
+    /// Returns the number of pins on a block.
+    pub async fn pins(&self, cid: &Cid) -> usize {
+        let inner = self.inner.lock().await;
+        inner
+            .blocks
+            .get(cid)
+            .map(|entry| entry.pins)
+            .unwrap_or_default()
+    }
+
+    /// Returns the byte budget of the store.
+    pub async fn budget(&self) -> usize {
+        self.inner.lock().await.budget
+    }
+}
+
+impl ReadonlyStore for CappedMemStore {
+    fn get<'a>(&'a self, cid: &'a Cid) -> StoreResult<'a, Box<[u8]>> {
+        Box::pin(async move { self.inner.lock().await.get(cid) })
+    }
+}
+
+impl Store for CappedMemStore {
+    fn insert<'a>(
+        &'a self,
+        cid: &'a Cid,
+        data: Box<[u8]>,
+        _visibility: Visibility,
+    ) -> StoreResult<'a, ()> {
+        Box::pin(async move {
+            let mut inner = self.inner.lock().await;
+            inner.insert_block(cid, data);
+            inner.pin(cid);
+            inner.evict();
+            Ok(())
+        })
+    }
+
+    fn insert_batch<'a>(
+        &'a self,
+        batch: Vec<Block>,
+        _visibility: Visibility,
+    ) -> StoreResult<'a, Cid> {
+        Box::pin(async move {
+            let mut inner = self.inner.lock().await;
+            let mut last_cid = None;
+            for Block { cid, data } in batch.into_iter() {
+                inner.insert_block(&cid, data);
+                last_cid = Some(cid);
+            }
+            let cid = last_cid.ok_or(StoreError::EmptyBatch)?;
+            inner.pin(&cid);
+            inner.evict();
+            Ok(cid)
+        })
+    }
+
+    fn flush(&self) -> StoreResult<'_, ()> {
+        Box::pin(async move { Ok(()) })
+    }
+
+    fn unpin<'a>(&'a self, cid: &'a Cid) -> StoreResult<'a, ()> {
+        Box::pin(async move {
+            self.inner.lock().await.unpin(cid);
+            Ok(())
+        })
+    }
+}
+
+impl AliasStore for CappedMemStore {
+    fn alias<'a>(
+        &'a self,
+        alias: &'a [u8],
+        cid: &'a Cid,
+        _visibility: Visibility,
+    ) -> StoreResult<'a, ()> {
+        Box::pin(async move {
+            self.aliases
+                .lock()
+                .await
+                .insert(alias.to_vec().into_boxed_slice(), cid.clone());
+            Ok(())
+        })
+    }
+
+    fn unalias<'a>(&'a self, alias: &'a [u8]) -> StoreResult<'a, ()> {
+        Box::pin(async move {
+            self.aliases.lock().await.remove(alias);
+            Ok(())
+        })
+    }
+
+    fn resolve<'a>(&'a self, alias: &'a [u8]) -> StoreResult<'a, Option<Cid>> {
+        Box::pin(async move { Ok(self.aliases.lock().await.get(alias).cloned()) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BlockBuilder, Codec};
+    use libipld::ipld;
+
+    #[async_std::test]
+    async fn test_capped_store() {
+        let store = CappedMemStore::new(10);
+        let builder = BlockBuilder::new(store.clone(), Codec::new());
+        let a = builder.insert(&ipld!({"a": 3})).await.unwrap();
+        let b = builder.insert(&ipld!({"b": 3})).await.unwrap();
+        let c = builder.insert(&ipld!({"c": 3})).await.unwrap();
+        assert!(store.size().await > store.budget().await);
+
+        builder.unpin(&a).await.unwrap();
+        assert!(store.get(&a).await.is_err());
+        assert!(store.get(&b).await.is_ok());
+
+        builder.unpin(&b).await.unwrap();
+        builder.unpin(&c).await.unwrap();
+        assert!(store.get(&b).await.is_ok());
+        assert!(store.get(&c).await.is_ok());
+        assert!(store.size().await <= store.budget().await);
+    }
+}
@@ -0,0 +1,211 @@
+use libipld::block::Block;
+use libipld::cid::Cid;
+use libipld::store::{AliasStore, ReadonlyStore, Store, StoreResult, Visibility};
+
+/// Object safe version of `Store`.
+///
+/// `Store` requires `Clone` and can't be made into a trait object.
+/// `Box<dyn DynStore>` implements `Store`, so the store backend of a
+/// `BlockBuilder` can be chosen at runtime.
+pub trait DynStore: Send + Sync {
+    /// Returns a block from the store.
+    fn get<'a>(&'a self, cid: &'a Cid) -> StoreResult<'a, Box<[u8]>>;
+
+    /// Inserts and pins a block into the store.
+    fn insert<'a>(
+        &'a self,
+        cid: &'a Cid,
+        data: Box<[u8]>,
+        visibility: Visibility,
+    ) -> StoreResult<'a, ()>;
+
+    /// Inserts a batch of blocks atomically pinning the last one.
+    fn insert_batch<'a>(
+        &'a self,
+        batch: Vec<Block>,
+        visibility: Visibility,
+    ) -> StoreResult<'a, Cid>;
+
+    /// Flushes the write buffer.
+    fn flush(&self) -> StoreResult<'_, ()>;
+
+    /// Decreases the ref count on a cid.
+    fn unpin<'a>(&'a self, cid: &'a Cid) -> StoreResult<'a, ()>;
+
+    /// Clones the store into a box.
+    fn box_clone(&self) -> Box<dyn DynStore>;
+}
+
+impl<S: Store + Send + Sync + 'static> DynStore for S {
+    fn get<'a>(&'a self, cid: &'a Cid) -> StoreResult<'a, Box<[u8]>> {
+        ReadonlyStore::get(self, cid)
+    }
+
+    fn insert<'a>(
+        &'a self,
+        cid: &'a Cid,
+        data: Box<[u8]>,
+        visibility: Visibility,
+    ) -> StoreResult<'a, ()> {
+        Store::insert(self, cid, data, visibility)
+    }
+
+    fn insert_batch<'a>(
+        &'a self,
+        batch: Vec<Block>,
+        visibility: Visibility,
+    ) -> StoreResult<'a, Cid> {
+        Store::insert_batch(self, batch, visibility)
+    }
+
+    fn flush(&self) -> StoreResult<'_, ()> {
+        Store::flush(self)
+    }
+
+    fn unpin<'a>(&'a self, cid: &'a Cid) -> StoreResult<'a, ()> {
+        Store::unpin(self, cid)
+    }
+
+    fn box_clone(&self) -> Box<dyn DynStore> {
+        Box::new(self.clone())
+    }
+}
+
+/// Object safe version of `Store` and `AliasStore`.
+pub trait DynAliasStore: DynStore {
+    /// Creates an alias for a `Cid`.
+    fn alias<'a>(
+        &'a self,
+        alias: &'a [u8],
+        cid: &'a Cid,
+        visibility: Visibility,
+    ) -> StoreResult<'a, ()>;
+
+    /// Removes an alias for a `Cid`.
+    fn unalias<'a>(&'a self, alias: &'a [u8]) -> StoreResult<'a, ()>;
+
+    /// Resolves an alias for a `Cid`.
+    fn resolve<'a>(&'a self, alias: &'a [u8]) -> StoreResult<'a, Option<Cid>>;
+
+    /// Clones the store into a box.
+    fn box_clone_alias(&self) -> Box<dyn DynAliasStore>;
+}
+
+impl<S: Store + AliasStore + Send + Sync + 'static> DynAliasStore for S {
+    fn alias<'a>(
+        &'a self,
+        alias: &'a [u8],
+        cid: &'a Cid,
+        visibility: Visibility,
+    ) -> StoreResult<'a, ()> {
+        AliasStore::alias(self, alias, cid, visibility)
+    }
+
+    fn unalias<'a>(&'a self, alias: &'a [u8]) -> StoreResult<'a, ()> {
+        AliasStore::unalias(self, alias)
+    }
+
+    fn resolve<'a>(&'a self, alias: &'a [u8]) -> StoreResult<'a, Option<Cid>> {
+        AliasStore::resolve(self, alias)
+    }
+
+    fn box_clone_alias(&self) -> Box<dyn DynAliasStore> {
+        Box::new(self.clone())
+    }
+}
+
+macro_rules! impl_store {
+    ($ty:ty, $clone:ident) => {
+        impl Clone for Box<$ty> {
+            fn clone(&self) -> Self {
+                (**self).$clone()
+            }
+        }
+
+        impl ReadonlyStore for Box<$ty> {
+            fn get<'a>(&'a self, cid: &'a Cid) -> StoreResult<'a, Box<[u8]>> {
+                DynStore::get(&**self, cid)
+            }
+        }
+
+        impl Store for Box<$ty> {
+            fn insert<'a>(
+                &'a self,
+                cid: &'a Cid,
+                data: Box<[u8]>,
+                visibility: Visibility,
+            ) -> StoreResult<'a, ()> {
+                DynStore::insert(&**self, cid, data, visibility)
+            }
+
+            fn insert_batch<'a>(
+                &'a self,
+                batch: Vec<Block>,
+                visibility: Visibility,
+            ) -> StoreResult<'a, Cid> {
+                DynStore::insert_batch(&**self, batch, visibility)
+            }
+
+            fn flush(&self) -> StoreResult<'_, ()> {
+                DynStore::flush(&**self)
+            }
+
+            fn unpin<'a>(&'a self, cid: &'a Cid) -> StoreResult<'a, ()> {
+                DynStore::unpin(&**self, cid)
+            }
+        }
+    };
+}
+
+impl_store!(dyn DynStore, box_clone);
+impl_store!(dyn DynAliasStore, box_clone_alias);
+
+impl AliasStore for Box<dyn DynAliasStore> {
+    fn alias<'a>(
+        &'a self,
+        alias: &'a [u8],
+        cid: &'a Cid,
+        visibility: Visibility,
+    ) -> StoreResult<'a, ()> {
+        DynAliasStore::alias(&**self, alias, cid, visibility)
+    }
+
+    fn unalias<'a>(&'a self, alias: &'a [u8]) -> StoreResult<'a, ()> {
+        DynAliasStore::unalias(&**self, alias)
+    }
+
+    fn resolve<'a>(&'a self, alias: &'a [u8]) -> StoreResult<'a, Option<Cid>> {
+        DynAliasStore::resolve(&**self, alias)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BlockBuilder, CappedMemStore, Codec};
+    use libipld::ipld;
+    use libipld::mem::MemStore;
+
+    fn open(capped: bool) -> Box<dyn DynAliasStore> {
+        if capped {
+            Box::new(CappedMemStore::new(1024))
+        } else {
+            Box::new(MemStore::default())
+        }
+    }
+
+    #[async_std::test]
+    async fn test_dyn_store() {
+        for capped in &[false, true] {
+            let builder = BlockBuilder::new(open(*capped), Codec::new());
+            let cid = builder.insert(&ipld!({"a": 3})).await.unwrap();
+            builder.alias(b"root", &cid).await.unwrap();
+            assert_eq!(builder.resolve(b"root").await.unwrap(), Some(cid.clone()));
+            assert_eq!(builder.get_ipld(&cid).await.unwrap(), ipld!({"a": 3}));
+
+            let store: Box<dyn DynStore> = Box::new(builder.store().clone());
+            let builder = BlockBuilder::new(store, Codec::new());
+            assert_eq!(builder.get_ipld(&cid).await.unwrap(), ipld!({"a": 3}));
+        }
+    }
+}
@@ -0,0 +1,210 @@
+use crate::store::RemoteStore;
+use libipld::block::Block;
+use libipld::cid::Cid;
+use libipld::error::StoreError;
+use libipld::store::{AliasStore, ReadonlyStore, Store, StoreResult, Visibility};
+
+/// Api of an embedded ipfs node like `ipfs-embed`.
+///
+/// The node keeps blocks reachable from an alias and garbage collects the
+/// rest. The crate doesn't depend on `ipfs-embed`, implementations for
+/// `ipfs_embed::Ipfs` delegate every method and convert cids through their
+/// byte representation.
+pub trait EmbeddedNode: Clone + Send + Sync {
+    /// Returns a block if it is in the local store.
+    fn get(&self, cid: &Cid) -> Result<Option<Box<[u8]>>, StoreError>;
+
+    /// Fetches a block from the network.
+    fn fetch<'a>(&'a self, cid: &'a Cid) -> StoreResult<'a, Box<[u8]>>;
+
+    /// Inserts a block, providing public blocks to the network.
+    fn insert(&self, cid: &Cid, data: Box<[u8]>, visibility: Visibility) -> Result<(), StoreError>;
+
+    /// Points an alias to a cid or removes it.
+    fn alias(&self, alias: &[u8], cid: Option<&Cid>) -> Result<(), StoreError>;
+
+    /// Resolves an alias.
+    fn resolve(&self, alias: &[u8]) -> Result<Option<Cid>, StoreError>;
+
+    /// Flushes the local store to disk.
+    fn flush(&self) -> StoreResult<'_, ()>;
+}
+
+/// Returns the alias pinning a cid.
+fn pin(cid: &Cid) -> Vec<u8> {
+    [&b"ipld-block-builder/pin/"[..], &cid.to_bytes()].concat()
+}
+
+/// A store on top of an embedded ipfs node.
+///
+/// Blocks missing from the node are fetched from the network. Pins are
+/// stored as aliases of the node, so they survive restarts but are not
+/// reference counted: a single `unpin` releases a block pinned multiple
+/// times.
+#[derive(Clone)]
+pub struct EmbedStore<N> {
+    node: N,
+}
+
+impl<N> EmbedStore<N> {
+    /// Creates a new store.
+    pub fn new(node: N) -> Self {
+        Self { node }
+    }
+
+    /// Returns the node.
+    pub fn node(&self) -> &N {
+        &self.node
+    }
+}
+
+impl<N: EmbeddedNode> ReadonlyStore for EmbedStore<N> {
+    fn get<'a>(&'a self, cid: &'a Cid) -> StoreResult<'a, Box<[u8]>> {
+        Box::pin(async move {
+            match self.node.get(cid)? {
+                Some(data) => Ok(data),
+                None => self.node.fetch(cid).await,
+            }
+        })
+    }
+}
+
+impl<N: EmbeddedNode> RemoteStore for EmbedStore<N> {
+    fn get_local<'a>(&'a self, cid: &'a Cid) -> StoreResult<'a, Box<[u8]>> {
+        Box::pin(async move {
+            self.node
+                .get(cid)?
+                .ok_or_else(|| StoreError::BlockNotFound(cid.clone()))
+        })
+    }
+}
+
+impl<N: EmbeddedNode> Store for EmbedStore<N> {
+    fn insert<'a>(
+        &'a self,
+        cid: &'a Cid,
+        data: Box<[u8]>,
+        visibility: Visibility,
+    ) -> StoreResult<'a, ()> {
+        Box::pin(async move {
+            self.node.insert(cid, data, visibility)?;
+            self.node.alias(&pin(cid), Some(cid))
+        })
+    }
+
+    fn insert_batch<'a>(
+        &'a self,
+        batch: Vec<Block>,
+        visibility: Visibility,
+    ) -> StoreResult<'a, Cid> {
+        Box::pin(async move {
+            let mut last = None;
+            for block in batch {
+                self.node.insert(&block.cid, block.data, visibility)?;
+                last = Some(block.cid);
+            }
+            let last = last.ok_or(StoreError::EmptyBatch)?;
+            self.node.alias(&pin(&last), Some(&last))?;
+            Ok(last)
+        })
+    }
+
+    fn flush(&self) -> StoreResult<'_, ()> {
+        self.node.flush()
+    }
+
+    fn unpin<'a>(&'a self, cid: &'a Cid) -> StoreResult<'a, ()> {
+        Box::pin(async move { self.node.alias(&pin(cid), None) })
+    }
+}
+
+impl<N: EmbeddedNode> AliasStore for EmbedStore<N> {
+    fn alias<'a>(
+        &'a self,
+        alias: &'a [u8],
+        cid: &'a Cid,
+        _visibility: Visibility,
+    ) -> StoreResult<'a, ()> {
+        Box::pin(async move { self.node.alias(alias, Some(cid)) })
+    }
+
+    fn unalias<'a>(&'a self, alias: &'a [u8]) -> StoreResult<'a, ()> {
+        Box::pin(async move { self.node.alias(alias, None) })
+    }
+
+    fn resolve<'a>(&'a self, alias: &'a [u8]) -> StoreResult<'a, Option<Cid>> {
+        Box::pin(async move { self.node.resolve(alias) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BlockBuilder, Cache, Codec, IpldCache, ReadonlyCache};
+    use libipld::ipld;
+    use libipld::ipld::Ipld;
+    use libipld::mem::MemStore;
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Clone, Default)]
+    struct Node {
+        blocks: Arc<Mutex<HashMap<Cid, Box<[u8]>>>>,
+        aliases: Arc<Mutex<HashMap<Vec<u8>, Cid>>>,
+        network: MemStore,
+    }
+
+    impl EmbeddedNode for Node {
+        fn get(&self, cid: &Cid) -> Result<Option<Box<[u8]>>, StoreError> {
+            Ok(self.blocks.lock().unwrap().get(cid).cloned())
+        }
+
+        fn fetch<'a>(&'a self, cid: &'a Cid) -> StoreResult<'a, Box<[u8]>> {
+            self.network.get(cid)
+        }
+
+        fn insert(&self, cid: &Cid, data: Box<[u8]>, _: Visibility) -> Result<(), StoreError> {
+            self.blocks.lock().unwrap().insert(cid.clone(), data);
+            Ok(())
+        }
+
+        fn alias(&self, alias: &[u8], cid: Option<&Cid>) -> Result<(), StoreError> {
+            let mut aliases = self.aliases.lock().unwrap();
+            match cid {
+                Some(cid) => aliases.insert(alias.to_vec(), cid.clone()),
+                None => aliases.remove(alias),
+            };
+            Ok(())
+        }
+
+        fn resolve(&self, alias: &[u8]) -> Result<Option<Cid>, StoreError> {
+            Ok(self.aliases.lock().unwrap().get(alias).cloned())
+        }
+
+        fn flush(&self) -> StoreResult<'_, ()> {
+            Box::pin(async { Ok(()) })
+        }
+    }
+
+    #[async_std::test]
+    async fn test_embed_store() {
+        let node = Node::default();
+        let remote = BlockBuilder::new(node.network.clone(), Codec::new());
+        let remote = remote.insert(&ipld!("remote")).await.unwrap();
+
+        let store = EmbedStore::new(node.clone());
+        let cache = IpldCache::<_, _, Ipld>::new(store.clone(), Codec::new(), 16);
+        let cid = cache
+            .insert(ipld!({"remote": remote.clone()}))
+            .await
+            .unwrap();
+        assert_eq!(cache.get(&remote).await.unwrap(), ipld!("remote"));
+        assert!(node.resolve(&pin(&cid)).unwrap().is_some());
+
+        let builder = BlockBuilder::new(store, Codec::new());
+        builder.alias(b"root", &cid).await.unwrap();
+        assert_eq!(builder.resolve(b"root").await.unwrap(), Some(cid.clone()));
+        builder.unpin(&cid).await.unwrap();
+        assert!(node.resolve(&pin(&cid)).unwrap().is_none());
+    }
+}
@@ -0,0 +1,302 @@
+use crate::rt::unblock;
+use futures::lock::Mutex;
+use libipld::block::Block;
+use libipld::cid::Cid;
+use libipld::error::StoreError;
+use libipld::store::{AliasStore, ReadonlyStore, Store, StoreResult, Visibility};
+use std::convert::TryFrom;
+use std::fs;
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+fn other<E: std::error::Error + Send + 'static>(err: E) -> StoreError {
+    StoreError::Other(Box::new(err))
+}
+
+/// Syncs a directory, so the entries renamed into or removed from it
+/// survive a crash.
+#[cfg(unix)]
+fn sync_dir(dir: &Path) -> std::io::Result<()> {
+    fs::File::open(dir)?.sync_all()
+}
+
+/// Directories can't be opened for syncing, renames are durable once the
+/// file system commits its metadata.
+#[cfg(not(unix))]
+fn sync_dir(_dir: &Path) -> std::io::Result<()> {
+    Ok(())
+}
+
+struct Inner {
+    root: PathBuf,
+    tmp: AtomicU64,
+    pins: Mutex<()>,
+}
+
+/// A file system backed store.
+///
+/// Blocks are stored in directories sharded by the next to last two characters
+/// of the cid. Pins and aliases are stored as files next to the blocks. All
+/// files are written to a temporary location and moved into place, so a crash
+/// never leaves a partially written file behind. Every write syncs the file
+/// and its directory before it returns, flushing syncs the store
+/// directories once more.
+#[derive(Clone)]
+pub struct FsStore {
+    inner: Arc<Inner>,
+}
+
+impl FsStore {
+    /// Opens the store at `root`, creating the directories if needed.
+    pub async fn open<P: AsRef<Path>>(root: P) -> Result<Self, StoreError> {
+        let root = root.as_ref().to_path_buf();
+        let dirs = root.clone();
+        unblock(move || {
+            for dir in &["blocks", "pins", "aliases", "tmp"] {
+                fs::create_dir_all(dirs.join(dir))?;
+            }
+            Ok::<_, std::io::Error>(())
+        })
+        .await
+        .map_err(other)?;
+        Ok(Self {
+            inner: Arc::new(Inner {
+                root,
+                tmp: AtomicU64::new(0),
+                pins: Mutex::new(()),
+            }),
+        })
+    }
+
+    /// Returns the root directory of the store.
+    pub fn root(&self) -> &Path {
+        &self.inner.root
+    }
+
+    fn block_path(&self, cid: &Cid) -> PathBuf {
+        let name = cid.to_string();
+        let shard = &name[(name.len() - 3)..(name.len() - 1)];
+        self.inner.root.join("blocks").join(shard).join(name)
+    }
+
+    fn pin_path(&self, cid: &Cid) -> PathBuf {
+        self.inner.root.join("pins").join(cid.to_string())
+    }
+
+    fn alias_path(&self, alias: &[u8]) -> PathBuf {
+        self.inner.root.join("aliases").join(super::hex(alias))
+    }
+
+    async fn write_atomic(&self, path: PathBuf, data: Box<[u8]>) -> Result<(), StoreError> {
+        let n = self.inner.tmp.fetch_add(1, Ordering::SeqCst);
+        let tmp = self
+            .inner
+            .root
+            .join("tmp")
+            .join(format!("{}.{}", std::process::id(), n));
+        unblock(move || {
+            let mut file = fs::File::create(&tmp)?;
+            file.write_all(&data)?;
+            file.sync_all()?;
+            fs::rename(&tmp, &path)?;
+            sync_dir(path.parent().unwrap())
+        })
+        .await
+        .map_err(other)
+    }
+
+    async fn remove(&self, path: PathBuf) -> Result<(), StoreError> {
+        let res = unblock(move || {
+            fs::remove_file(&path)?;
+            sync_dir(path.parent().unwrap())
+        })
+        .await;
+        match res {
+            Err(err) if err.kind() != ErrorKind::NotFound => Err(other(err)),
+            _ => Ok(()),
+        }
+    }
+
+    async fn insert_block(&self, cid: &Cid, data: Box<[u8]>) -> Result<(), StoreError> {
+        let path = self.block_path(cid);
+        let dir = path.parent().unwrap().to_path_buf();
+        let exists = unblock(move || {
+            if fs::metadata(&path).is_ok() {
+                return Ok(true);
+            }
+            if fs::metadata(&dir).is_err() {
+                fs::create_dir_all(&dir)?;
+                sync_dir(dir.parent().unwrap())?;
+            }
+            Ok::<_, std::io::Error>(false)
+        })
+        .await
+        .map_err(other)?;
+        if exists {
+            return Ok(());
+        }
+        self.write_atomic(self.block_path(cid), data).await
+    }
+
+    async fn read_pins(&self, cid: &Cid) -> Result<u64, StoreError> {
+        let path = self.pin_path(cid);
+        match unblock(move || fs::read_to_string(path)).await {
+            Ok(pins) => pins.trim().parse().map_err(other),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(0),
+            Err(err) => Err(other(err)),
+        }
+    }
+
+    async fn pin(&self, cid: &Cid) -> Result<(), StoreError> {
+        let _guard = self.inner.pins.lock().await;
+        let pins = self.read_pins(cid).await?;
+        self.write_atomic(
+            self.pin_path(cid),
+            (pins + 1).to_string().into_bytes().into(),
+        )
+        .await
+    }
+
+    /// Returns the number of pins on a block.
+    pub async fn pins(&self, cid: &Cid) -> Result<u64, StoreError> {
+        let _guard = self.inner.pins.lock().await;
+        self.read_pins(cid).await
+    }
+}
+
+impl ReadonlyStore for FsStore {
+    fn get<'a>(&'a self, cid: &'a Cid) -> StoreResult<'a, Box<[u8]>> {
+        Box::pin(async move {
+            let path = self.block_path(cid);
+            match unblock(move || fs::read(path)).await {
+                Ok(data) => Ok(data.into_boxed_slice()),
+                Err(err) if err.kind() == ErrorKind::NotFound => {
+                    Err(StoreError::BlockNotFound(cid.clone()))
+                }
+                Err(err) => Err(other(err)),
+            }
+        })
+    }
+}
+
+impl Store for FsStore {
+    fn insert<'a>(
+        &'a self,
+        cid: &'a Cid,
+        data: Box<[u8]>,
+        _visibility: Visibility,
+    ) -> StoreResult<'a, ()> {
+        Box::pin(async move {
+            self.insert_block(cid, data).await?;
+            self.pin(cid).await
+        })
+    }
+
+    fn insert_batch<'a>(
+        &'a self,
+        batch: Vec<Block>,
+        _visibility: Visibility,
+    ) -> StoreResult<'a, Cid> {
+        Box::pin(async move {
+            let mut last_cid = None;
+            for Block { cid, data } in batch.into_iter() {
+                self.insert_block(&cid, data).await?;
+                last_cid = Some(cid);
+            }
+            let cid = last_cid.ok_or(StoreError::EmptyBatch)?;
+            self.pin(&cid).await?;
+            Ok(cid)
+        })
+    }
+
+    fn flush(&self) -> StoreResult<'_, ()> {
+        Box::pin(async move {
+            let root = self.inner.root.clone();
+            unblock(move || {
+                for dir in &["blocks", "pins", "aliases"] {
+                    sync_dir(&root.join(dir))?;
+                }
+                sync_dir(&root)
+            })
+            .await
+            .map_err(other)
+        })
+    }
+
+    fn unpin<'a>(&'a self, cid: &'a Cid) -> StoreResult<'a, ()> {
+        Box::pin(async move {
+            let _guard = self.inner.pins.lock().await;
+            let pins = self.read_pins(cid).await?;
+            if pins > 1 {
+                self.write_atomic(
+                    self.pin_path(cid),
+                    (pins - 1).to_string().into_bytes().into(),
+                )
+                .await
+            } else {
+                self.remove(self.pin_path(cid)).await
+            }
+        })
+    }
+}
+
+impl AliasStore for FsStore {
+    fn alias<'a>(
+        &'a self,
+        alias: &'a [u8],
+        cid: &'a Cid,
+        _visibility: Visibility,
+    ) -> StoreResult<'a, ()> {
+        Box::pin(async move {
+            self.write_atomic(self.alias_path(alias), cid.to_string().into_bytes().into())
+                .await
+        })
+    }
+
+    fn unalias<'a>(&'a self, alias: &'a [u8]) -> StoreResult<'a, ()> {
+        Box::pin(async move { self.remove(self.alias_path(alias)).await })
+    }
+
+    fn resolve<'a>(&'a self, alias: &'a [u8]) -> StoreResult<'a, Option<Cid>> {
+        Box::pin(async move {
+            let path = self.alias_path(alias);
+            match unblock(move || fs::read_to_string(path)).await {
+                Ok(cid) => Ok(Some(Cid::try_from(cid.trim()).map_err(other)?)),
+                Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+                Err(err) => Err(other(err)),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BlockBuilder, Codec};
+    use libipld::ipld;
+    use libipld::ipld::Ipld;
+
+    #[cfg_attr(not(feature = "tokio"), async_std::test)]
+    #[cfg_attr(feature = "tokio", tokio::test)]
+    async fn test_fs_store() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = FsStore::open(dir.path()).await.unwrap();
+        let builder = BlockBuilder::new(store.clone(), Codec::new());
+        let cid = builder.insert(&ipld!({"a": 3})).await.unwrap();
+        assert_eq!(store.pins(&cid).await.unwrap(), 1);
+        builder.alias(b"root", &cid).await.unwrap();
+        builder.flush().await.unwrap();
+
+        let store = FsStore::open(dir.path()).await.unwrap();
+        let builder = BlockBuilder::new(store.clone(), Codec::new());
+        assert_eq!(builder.resolve(b"root").await.unwrap(), Some(cid.clone()));
+        let ipld: Ipld = builder.get(&cid).await.unwrap();
+        assert_eq!(ipld, ipld!({"a": 3}));
+        builder.unpin(&cid).await.unwrap();
+        assert_eq!(store.pins(&cid).await.unwrap(), 0);
+        builder.unalias(b"root").await.unwrap();
+        assert_eq!(builder.resolve(b"root").await.unwrap(), None);
+    }
+}
@@ -0,0 +1,186 @@
+use crate::error::Error;
+use crate::store::{RemoteStore, Transient};
+use futures::io::AsyncReadExt;
+use libipld::cid::Cid;
+use libipld::error::StoreError;
+use libipld::store::{ReadonlyStore, StoreResult};
+use libipld::MAX_BLOCK_SIZE;
+use std::sync::Arc;
+use surf::StatusCode;
+
+fn other(err: surf::Error) -> StoreError {
+    let err: Box<dyn std::error::Error + Send + Sync> = err.into();
+    StoreError::Other(err)
+}
+
+/// Network failures and server errors are transient, client errors are not.
+fn transient(err: surf::Error) -> StoreError {
+    if err.status().is_server_error() || err.status() == StatusCode::TooManyRequests {
+        Transient::store_error(err)
+    } else {
+        other(err)
+    }
+}
+
+/// A read-only store fetching blocks from an http ipfs gateway.
+///
+/// Blocks are requested as raw blocks and verified against their cid, so an
+/// untrusted gateway can't return forged data. Responses larger than
+/// `MAX_BLOCK_SIZE` are rejected without being read to the end.
+#[derive(Clone)]
+pub struct GatewayStore {
+    client: surf::Client,
+    url: Arc<str>,
+}
+
+impl GatewayStore {
+    /// Creates a store for the gateway at `url`, for example `https://ipfs.io`.
+    pub fn new(url: &str) -> Self {
+        Self {
+            client: surf::Client::new(),
+            url: url.trim_end_matches('/').into(),
+        }
+    }
+
+    /// Returns the url of the gateway.
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    fn block_url(&self, cid: &Cid) -> String {
+        format!("{}/ipfs/{}?format=raw", self.url, cid)
+    }
+}
+
+impl ReadonlyStore for GatewayStore {
+    fn get<'a>(&'a self, cid: &'a Cid) -> StoreResult<'a, Box<[u8]>> {
+        Box::pin(async move {
+            let mut res = self
+                .client
+                .get(self.block_url(cid))
+                .header("Accept", "application/vnd.ipld.raw")
+                .await
+                .map_err(Transient::store_error)?;
+            if res.status() == StatusCode::NotFound {
+                return Err(StoreError::BlockNotFound(cid.clone()));
+            }
+            if !res.status().is_success() {
+                return Err(transient(surf::Error::from_str(
+                    res.status(),
+                    format!("gateway returned {}", res.status()),
+                )));
+            }
+            let too_large = |len| StoreError::Other(Box::new(Error::BlockTooLarge(len)));
+            if let Some(len) = res.len().filter(|len| *len > MAX_BLOCK_SIZE) {
+                return Err(too_large(len));
+            }
+            let mut data = vec![];
+            res.take_body()
+                .take(MAX_BLOCK_SIZE as u64 + 1)
+                .read_to_end(&mut data)
+                .await
+                .map_err(Transient::store_error)?;
+            if data.len() > MAX_BLOCK_SIZE {
+                return Err(too_large(data.len()));
+            }
+            crate::error::verify(cid, &data).map_err(|e| StoreError::Other(Box::new(e)))?;
+            Ok(data.into_boxed_slice())
+        })
+    }
+}
+
+impl RemoteStore for GatewayStore {
+    fn get_local<'a>(&'a self, cid: &'a Cid) -> StoreResult<'a, Box<[u8]>> {
+        Box::pin(async move { Err(StoreError::BlockNotFound(cid.clone())) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BlockBuilder, Codec, Encoder, Error};
+    use async_std::io::WriteExt;
+    use async_std::net::{TcpListener, TcpStream};
+    use libipld::ipld;
+
+    type Blocks = Arc<Vec<(Cid, Box<[u8]>)>>;
+
+    async fn serve(listener: TcpListener, blocks: Vec<(Cid, Box<[u8]>)>) {
+        let blocks = Arc::new(blocks);
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            async_std::task::spawn(respond(stream, blocks.clone()));
+        }
+    }
+
+    /// Answers requests until the client closes the pooled connection.
+    async fn respond(mut stream: TcpStream, blocks: Blocks) -> std::io::Result<()> {
+        let mut buf = [0; 4096];
+        loop {
+            let mut req = vec![];
+            while !req.ends_with(b"\r\n\r\n") {
+                let n = stream.read(&mut buf).await?;
+                if n == 0 {
+                    return Ok(());
+                }
+                req.extend_from_slice(&buf[..n]);
+            }
+            let req = String::from_utf8_lossy(&req).to_string();
+            let block = blocks
+                .iter()
+                .find(|(cid, _)| req.contains(&cid.to_string()));
+            if let Some((_, data)) = block.filter(|(_, data)| data.len() > MAX_BLOCK_SIZE) {
+                // oversized blocks are streamed without a content length
+                let head = format!(
+                    "HTTP/1.1 200 OK\r\ntransfer-encoding: chunked\r\n\r\n{:x}\r\n",
+                    data.len()
+                );
+                stream.write_all(head.as_bytes()).await?;
+                stream.write_all(data).await?;
+                stream.write_all(b"\r\n0\r\n\r\n").await?;
+            } else if let Some((_, data)) = block {
+                let head = format!("HTTP/1.1 200 OK\r\ncontent-length: {}\r\n\r\n", data.len());
+                stream.write_all(head.as_bytes()).await?;
+                stream.write_all(data).await?;
+            } else {
+                let head = "HTTP/1.1 404 Not Found\r\ncontent-length: 0\r\n\r\n";
+                stream.write_all(head.as_bytes()).await?;
+            }
+        }
+    }
+
+    #[async_std::test]
+    async fn test_gateway_store() {
+        let codec = Codec::new();
+        let a = codec.encode(&ipld!({"a": 3})).unwrap();
+        let b = codec.encode(&ipld!({"b": 3})).unwrap();
+        let c = codec.encode(&ipld!({"c": 3})).unwrap();
+        let d = codec.encode(&ipld!({"d": 3})).unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let large = vec![0; MAX_BLOCK_SIZE + 1].into_boxed_slice();
+        let blocks = vec![
+            (a.cid.clone(), a.data),
+            (b.cid.clone(), c.data),
+            (d.cid.clone(), large),
+        ];
+        async_std::task::spawn(serve(listener, blocks));
+
+        let builder = BlockBuilder::new(GatewayStore::new(&url), codec);
+        assert_eq!(builder.get_ipld(&a.cid).await.unwrap(), ipld!({"a": 3}));
+        match builder.get_ipld(&b.cid).await {
+            Err(Error::Store(StoreError::Other(_))) => {}
+            _ => panic!("expected integrity error"),
+        }
+        match builder.get_ipld(&c.cid).await {
+            Err(Error::Store(StoreError::BlockNotFound(cid))) => assert_eq!(cid, c.cid),
+            _ => panic!("expected block not found"),
+        }
+        match builder.get_ipld(&d.cid).await {
+            Err(Error::Store(StoreError::Other(err))) => {
+                assert!(err.to_string().contains("exceeds MAX_BLOCK_SIZE"))
+            }
+            _ => panic!("expected block too large"),
+        }
+    }
+}
@@ -0,0 +1,135 @@
+use super::object::{parse_count, ObjectBlockStore, ObjectStore};
+use async_trait::async_trait;
+use indexed_db_futures::js_sys::Uint8Array;
+use indexed_db_futures::prelude::*;
+use indexed_db_futures::web_sys::DomException;
+use libipld::error::StoreError;
+use send_wrapper::SendWrapper;
+use std::sync::Arc;
+use thiserror::Error;
+
+/// Name of the object store holding the blocks, pins and aliases.
+const OBJECTS: &str = "objects";
+
+/// Error returned by indexed db.
+#[derive(Debug, Error)]
+#[error("indexed db: {0}")]
+pub struct IdbError(String);
+
+fn other(err: DomException) -> StoreError {
+    StoreError::Other(Box::new(IdbError(err.message())))
+}
+
+/// A store persisting blocks in the indexed db of a browser.
+pub type IndexedDbStore = ObjectBlockStore<IdbObjects>;
+
+/// Objects kept in the indexed db of a browser.
+///
+/// The database handle is only accessed from the thread that opened it,
+/// which on wasm is the only thread.
+#[derive(Clone)]
+pub struct IdbObjects {
+    db: Arc<SendWrapper<IdbDatabase>>,
+}
+
+impl IdbObjects {
+    /// Opens the database `name`, creating it if needed.
+    pub async fn open(name: &str) -> Result<Self, StoreError> {
+        let mut request = IdbDatabase::open_u32(name, 1).map_err(other)?;
+        request.set_on_upgrade_needed(Some(|event: &IdbVersionChangeEvent| {
+            if !event.db().object_store_names().any(|name| name == OBJECTS) {
+                event.db().create_object_store(OBJECTS)?;
+            }
+            Ok(())
+        }));
+        let db = request.await.map_err(other)?;
+        Ok(Self {
+            db: Arc::new(SendWrapper::new(db)),
+        })
+    }
+
+    /// Returns a store keeping its objects under `prefix` in the database
+    /// `name`.
+    pub async fn open_store(name: &str, prefix: &str) -> Result<IndexedDbStore, StoreError> {
+        Ok(ObjectBlockStore::new(Self::open(name).await?, prefix))
+    }
+
+    /// Puts or deletes objects in a single transaction.
+    async fn write(&self, objects: Vec<(String, Option<Vec<u8>>)>) -> Result<(), DomException> {
+        let tx = self
+            .db
+            .transaction_on_one_with_mode(OBJECTS, IdbTransactionMode::Readwrite)?;
+        let store = tx.object_store(OBJECTS)?;
+        for (key, data) in objects {
+            match data {
+                Some(data) => store.put_key_val_owned(key, &Uint8Array::from(&data[..]))?,
+                None => store.delete_owned(key)?,
+            };
+        }
+        tx.await.into_result()
+    }
+
+    /// Updates a counter in a single read-write transaction. Indexed db
+    /// doesn't run read-write transactions on the same object store
+    /// concurrently, so updates from other tabs can't be lost.
+    async fn add_count(&self, key: &str, delta: i64) -> Result<u64, StoreError> {
+        let tx = self
+            .db
+            .transaction_on_one_with_mode(OBJECTS, IdbTransactionMode::Readwrite)
+            .map_err(other)?;
+        let store = tx.object_store(OBJECTS).map_err(other)?;
+        let count = match store.get_owned(key).map_err(other)?.await.map_err(other)? {
+            Some(count) => parse_count(&Uint8Array::new(&count).to_vec())?,
+            None => 0,
+        };
+        let count = count.saturating_add_signed(delta);
+        if count == 0 {
+            store.delete_owned(key).map_err(other)?;
+        } else {
+            let data = count.to_string().into_bytes();
+            store
+                .put_key_val_owned(key, &Uint8Array::from(&data[..]))
+                .map_err(other)?;
+        }
+        tx.await.into_result().map_err(other)?;
+        Ok(count)
+    }
+
+    async fn read(&self, key: &str) -> Result<Option<Vec<u8>>, DomException> {
+        let tx = self.db.transaction_on_one(OBJECTS)?;
+        let store = tx.object_store(OBJECTS)?;
+        let value = store.get_owned(key)?.await?;
+        Ok(value.map(|value| Uint8Array::new(&value).to_vec()))
+    }
+}
+
+#[async_trait]
+impl ObjectStore for IdbObjects {
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, StoreError> {
+        SendWrapper::new(self.read(key)).await.map_err(other)
+    }
+
+    async fn put(&self, key: &str, data: Vec<u8>) -> Result<(), StoreError> {
+        let objects = vec![(key.to_string(), Some(data))];
+        SendWrapper::new(self.write(objects)).await.map_err(other)
+    }
+
+    /// Stores the objects in a single transaction, so batches are
+    /// inserted atomically.
+    async fn put_many(&self, objects: Vec<(String, Vec<u8>)>) -> Result<(), StoreError> {
+        let objects = objects
+            .into_iter()
+            .map(|(key, data)| (key, Some(data)))
+            .collect();
+        SendWrapper::new(self.write(objects)).await.map_err(other)
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), StoreError> {
+        let objects = vec![(key.to_string(), None)];
+        SendWrapper::new(self.write(objects)).await.map_err(other)
+    }
+
+    async fn add(&self, key: &str, delta: i64) -> Result<u64, StoreError> {
+        SendWrapper::new(self.add_count(key, delta)).await
+    }
+}
@@ -0,0 +1,242 @@
+use crate::store::RemoteStore;
+use libipld::block::Block;
+use libipld::cid::Cid;
+use libipld::error::StoreError;
+use libipld::store::{AliasStore, MultiUserStore, ReadonlyStore, Store, StoreResult, Visibility};
+use std::path::Path;
+
+/// How failures of the secondary store are handled.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum MirrorMode {
+    /// Errors of the secondary store are returned.
+    FailFast,
+    /// Errors of the secondary store are ignored.
+    BestEffort,
+}
+
+/// A store that mirrors all writes to a secondary store.
+///
+/// Reads are served by the primary store. Writes go to the primary store
+/// first and are then replicated to the secondary store.
+#[derive(Clone)]
+pub struct MirrorStore<A, B> {
+    primary: A,
+    secondary: B,
+    mode: MirrorMode,
+}
+
+impl<A, B> MirrorStore<A, B> {
+    /// Creates a new mirror store.
+    pub fn new(primary: A, secondary: B, mode: MirrorMode) -> Self {
+        Self {
+            primary,
+            secondary,
+            mode,
+        }
+    }
+
+    /// Returns the primary store.
+    pub fn primary(&self) -> &A {
+        &self.primary
+    }
+
+    /// Returns the secondary store.
+    pub fn secondary(&self) -> &B {
+        &self.secondary
+    }
+
+    /// Returns the mirror mode.
+    pub fn mode(&self) -> MirrorMode {
+        self.mode
+    }
+
+    fn secondary_result<T: Default>(&self, res: Result<T, StoreError>) -> Result<T, StoreError> {
+        match (res, self.mode) {
+            (Err(err), MirrorMode::FailFast) => Err(err),
+            (Err(_err), MirrorMode::BestEffort) => {
+                #[cfg(feature = "tracing")]
+                tracing::warn!("secondary store failed: {}", _err);
+                Ok(Default::default())
+            }
+            (Ok(res), _) => Ok(res),
+        }
+    }
+}
+
+impl<A: ReadonlyStore + Send + Sync, B: Clone + Send + Sync> ReadonlyStore for MirrorStore<A, B> {
+    fn get<'a>(&'a self, cid: &'a Cid) -> StoreResult<'a, Box<[u8]>> {
+        self.primary.get(cid)
+    }
+}
+
+impl<A: RemoteStore + Send + Sync, B: Clone + Send + Sync> RemoteStore for MirrorStore<A, B> {
+    fn get_local<'a>(&'a self, cid: &'a Cid) -> StoreResult<'a, Box<[u8]>> {
+        self.primary.get_local(cid)
+    }
+}
+
+impl<A: Store + Send + Sync, B: Store + Send + Sync> Store for MirrorStore<A, B> {
+    fn insert<'a>(
+        &'a self,
+        cid: &'a Cid,
+        data: Box<[u8]>,
+        visibility: Visibility,
+    ) -> StoreResult<'a, ()> {
+        Box::pin(async move {
+            self.primary.insert(cid, data.clone(), visibility).await?;
+            let res = self.secondary.insert(cid, data, visibility).await;
+            self.secondary_result(res)
+        })
+    }
+
+    fn insert_batch<'a>(
+        &'a self,
+        batch: Vec<Block>,
+        visibility: Visibility,
+    ) -> StoreResult<'a, Cid> {
+        Box::pin(async move {
+            let copy = batch.iter().map(super::clone_block).collect();
+            let cid = self.primary.insert_batch(batch, visibility).await?;
+            let res = self
+                .secondary
+                .insert_batch(copy, visibility)
+                .await
+                .map(|_| ());
+            self.secondary_result(res)?;
+            Ok(cid)
+        })
+    }
+
+    fn flush(&self) -> StoreResult<'_, ()> {
+        Box::pin(async move {
+            self.primary.flush().await?;
+            let res = self.secondary.flush().await;
+            self.secondary_result(res)
+        })
+    }
+
+    fn unpin<'a>(&'a self, cid: &'a Cid) -> StoreResult<'a, ()> {
+        Box::pin(async move {
+            self.primary.unpin(cid).await?;
+            let res = self.secondary.unpin(cid).await;
+            self.secondary_result(res)
+        })
+    }
+}
+
+impl<A, B> MultiUserStore for MirrorStore<A, B>
+where
+    A: MultiUserStore + Send + Sync,
+    B: MultiUserStore + Send + Sync,
+{
+    fn pin<'a>(&'a self, cid: &'a Cid, path: &'a Path) -> StoreResult<'a, ()> {
+        Box::pin(async move {
+            self.primary.pin(cid, path).await?;
+            let res = self.secondary.pin(cid, path).await;
+            self.secondary_result(res)
+        })
+    }
+}
+
+impl<A, B> AliasStore for MirrorStore<A, B>
+where
+    A: AliasStore + Send + Sync,
+    B: AliasStore + Send + Sync,
+{
+    fn alias<'a>(
+        &'a self,
+        alias: &'a [u8],
+        cid: &'a Cid,
+        visibility: Visibility,
+    ) -> StoreResult<'a, ()> {
+        Box::pin(async move {
+            self.primary.alias(alias, cid, visibility).await?;
+            let res = self.secondary.alias(alias, cid, visibility).await;
+            self.secondary_result(res)
+        })
+    }
+
+    fn unalias<'a>(&'a self, alias: &'a [u8]) -> StoreResult<'a, ()> {
+        Box::pin(async move {
+            self.primary.unalias(alias).await?;
+            let res = self.secondary.unalias(alias).await;
+            self.secondary_result(res)
+        })
+    }
+
+    fn resolve<'a>(&'a self, alias: &'a [u8]) -> StoreResult<'a, Option<Cid>> {
+        self.primary.resolve(alias)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BlockBuilder, Codec};
+    use libipld::ipld;
+    use libipld::mem::MemStore;
+
+    #[async_std::test]
+    async fn test_mirror_store() {
+        let primary = MemStore::default();
+        let secondary = MemStore::default();
+        let store = MirrorStore::new(primary, secondary.clone(), MirrorMode::FailFast);
+        let builder = BlockBuilder::new(store, Codec::new());
+        let cid = builder.insert(&ipld!({"a": 3})).await.unwrap();
+        builder.alias(b"root", &cid).await.unwrap();
+        assert!(secondary.get(&cid).await.is_ok());
+        assert_eq!(secondary.resolve(b"root").await.unwrap(), Some(cid));
+    }
+
+    #[derive(Clone)]
+    struct OfflineStore;
+
+    fn offline() -> StoreError {
+        StoreError::Other(Box::new(std::io::Error::other("offline")))
+    }
+
+    impl ReadonlyStore for OfflineStore {
+        fn get<'a>(&'a self, _cid: &'a Cid) -> StoreResult<'a, Box<[u8]>> {
+            Box::pin(async move { Err(offline()) })
+        }
+    }
+
+    impl Store for OfflineStore {
+        fn insert<'a>(
+            &'a self,
+            _cid: &'a Cid,
+            _data: Box<[u8]>,
+            _visibility: Visibility,
+        ) -> StoreResult<'a, ()> {
+            Box::pin(async move { Err(offline()) })
+        }
+
+        fn insert_batch<'a>(
+            &'a self,
+            _batch: Vec<Block>,
+            _visibility: Visibility,
+        ) -> StoreResult<'a, Cid> {
+            Box::pin(async move { Err(offline()) })
+        }
+
+        fn flush(&self) -> StoreResult<'_, ()> {
+            Box::pin(async move { Err(offline()) })
+        }
+
+        fn unpin<'a>(&'a self, _cid: &'a Cid) -> StoreResult<'a, ()> {
+            Box::pin(async move { Err(offline()) })
+        }
+    }
+
+    #[async_std::test]
+    async fn test_mirror_store_mode() {
+        let store = MirrorStore::new(MemStore::default(), OfflineStore, MirrorMode::FailFast);
+        let builder = BlockBuilder::new(store, Codec::new());
+        assert!(builder.insert(&ipld!({"a": 3})).await.is_err());
+
+        let store = MirrorStore::new(MemStore::default(), OfflineStore, MirrorMode::BestEffort);
+        let builder = BlockBuilder::new(store, Codec::new());
+        let cid = builder.insert(&ipld!({"a": 3})).await.unwrap();
+        assert!(builder.get_ipld(&cid).await.is_ok());
+    }
+}
@@ -0,0 +1,65 @@
+//! Store implementations.
+#[cfg(feature = "bitswap")]
+mod bitswap;
+mod bloom;
+mod capped;
+mod dynamic;
+#[cfg(feature = "embed")]
+mod embed;
+#[cfg(feature = "fs")]
+mod fs;
+#[cfg(feature = "gateway")]
+mod gateway;
+#[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+mod indexed_db;
+mod mirror;
+mod object;
+mod overlay;
+mod rate_limit;
+mod read_through;
+mod remote;
+#[cfg(feature = "repo")]
+mod repo;
+mod retry;
+#[cfg(feature = "sled")]
+mod sled;
+
+#[cfg(feature = "sled")]
+pub use self::sled::SledStore;
+#[cfg(feature = "bitswap")]
+pub use bitswap::{Bitswap, BitswapStore};
+pub use bloom::BloomStore;
+pub use capped::CappedMemStore;
+pub use dynamic::{DynAliasStore, DynStore};
+#[cfg(feature = "embed")]
+pub use embed::{EmbedStore, EmbeddedNode};
+#[cfg(feature = "fs")]
+pub use fs::FsStore;
+#[cfg(feature = "gateway")]
+pub use gateway::GatewayStore;
+#[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+pub use indexed_db::{IdbError, IdbObjects, IndexedDbStore};
+pub use mirror::{MirrorMode, MirrorStore};
+pub use object::{ObjectBlockStore, ObjectStore};
+pub use overlay::OverlayStore;
+pub use rate_limit::{RateLimit, RateLimitStore};
+pub use read_through::{ReadThroughPolicy, ReadThroughStore};
+pub use remote::RemoteStore;
+#[cfg(feature = "repo")]
+pub use repo::{IpfsRepo, RepoStore};
+pub use retry::{is_transient, RetryPolicy, RetryStore, StoreOperation, Transient};
+
+use libipld::block::Block;
+
+/// Hex encodes bytes.
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Copies a block.
+pub(crate) fn clone_block(block: &Block) -> Block {
+    Block {
+        cid: block.cid.clone(),
+        data: block.data.clone(),
+    }
+}
@@ -0,0 +1,255 @@
+use async_trait::async_trait;
+use libipld::block::Block;
+use libipld::cid::Cid;
+use libipld::error::StoreError;
+use libipld::store::{AliasStore, ReadonlyStore, Store, StoreResult, Visibility};
+use std::convert::TryFrom;
+
+/// Object storage service like S3, GCS or Azure blob storage.
+#[async_trait]
+pub trait ObjectStore: Clone + Send + Sync {
+    /// Returns the object stored at `key`.
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, StoreError>;
+
+    /// Stores an object at `key`.
+    async fn put(&self, key: &str, data: Vec<u8>) -> Result<(), StoreError>;
+
+    /// Stores many objects.
+    ///
+    /// Backends should override this to use multipart or concurrent uploads.
+    async fn put_many(&self, objects: Vec<(String, Vec<u8>)>) -> Result<(), StoreError> {
+        for (key, data) in objects {
+            self.put(&key, data).await?;
+        }
+        Ok(())
+    }
+
+    /// Deletes the object at `key`.
+    async fn delete(&self, key: &str) -> Result<(), StoreError>;
+
+    /// Adds `delta` to the counter at `key` and returns the new count.
+    ///
+    /// Counters are stored as decimal strings and deleted when they drop to
+    /// zero. The default implementation reads and writes the object, so
+    /// concurrent writers can lose updates. Backends with transactions or
+    /// conditional writes should override it with an atomic update.
+    async fn add(&self, key: &str, delta: i64) -> Result<u64, StoreError> {
+        let count = match self.get(key).await? {
+            Some(count) => parse_count(&count)?,
+            None => 0,
+        };
+        let count = count.saturating_add_signed(delta);
+        if count == 0 {
+            self.delete(key).await?;
+        } else {
+            self.put(key, count.to_string().into_bytes()).await?;
+        }
+        Ok(count)
+    }
+}
+
+/// Parses a counter written by `ObjectStore::add`.
+pub(crate) fn parse_count(count: &[u8]) -> Result<u64, StoreError> {
+    String::from_utf8_lossy(count)
+        .parse()
+        .map_err(|e| StoreError::Other(Box::new(e)))
+}
+
+/// A store keeping blocks in an object storage service.
+///
+/// Blocks are stored as objects keyed by their cid, pins and aliases as small
+/// metadata objects. Pin counts are updated with `ObjectStore::add`, a store
+/// can only be shared by multiple writers if the backend updates counters
+/// atomically.
+#[derive(Clone)]
+pub struct ObjectBlockStore<O> {
+    objects: O,
+    prefix: String,
+}
+
+impl<O: ObjectStore> ObjectBlockStore<O> {
+    /// Creates a store keeping its objects under `prefix`.
+    pub fn new(objects: O, prefix: &str) -> Self {
+        Self {
+            objects,
+            prefix: prefix.into(),
+        }
+    }
+
+    /// Returns the object store.
+    pub fn objects(&self) -> &O {
+        &self.objects
+    }
+
+    fn block_key(&self, cid: &Cid) -> String {
+        format!("{}blocks/{}", self.prefix, cid)
+    }
+
+    fn pin_key(&self, cid: &Cid) -> String {
+        format!("{}pins/{}", self.prefix, cid)
+    }
+
+    fn alias_key(&self, alias: &[u8]) -> String {
+        format!("{}aliases/{}", self.prefix, super::hex(alias))
+    }
+
+    /// Returns the number of pins on a block.
+    pub async fn pins(&self, cid: &Cid) -> Result<u64, StoreError> {
+        match self.objects.get(&self.pin_key(cid)).await? {
+            Some(pins) => parse_count(&pins),
+            None => Ok(0),
+        }
+    }
+
+    async fn pin(&self, cid: &Cid) -> Result<(), StoreError> {
+        self.objects.add(&self.pin_key(cid), 1).await?;
+        Ok(())
+    }
+}
+
+impl<O: ObjectStore> ReadonlyStore for ObjectBlockStore<O> {
+    fn get<'a>(&'a self, cid: &'a Cid) -> StoreResult<'a, Box<[u8]>> {
+        Box::pin(async move {
+            match self.objects.get(&self.block_key(cid)).await? {
+                Some(data) => Ok(data.into_boxed_slice()),
+                None => Err(StoreError::BlockNotFound(cid.clone())),
+            }
+        })
+    }
+}
+
+impl<O: ObjectStore> Store for ObjectBlockStore<O> {
+    fn insert<'a>(
+        &'a self,
+        cid: &'a Cid,
+        data: Box<[u8]>,
+        _visibility: Visibility,
+    ) -> StoreResult<'a, ()> {
+        Box::pin(async move {
+            self.objects.put(&self.block_key(cid), data.into()).await?;
+            self.pin(cid).await
+        })
+    }
+
+    fn insert_batch<'a>(
+        &'a self,
+        batch: Vec<Block>,
+        _visibility: Visibility,
+    ) -> StoreResult<'a, Cid> {
+        Box::pin(async move {
+            let cid = batch.last().ok_or(StoreError::EmptyBatch)?.cid.clone();
+            let objects = batch
+                .into_iter()
+                .map(|block| (self.block_key(&block.cid), block.data.into()))
+                .collect();
+            self.objects.put_many(objects).await?;
+            self.pin(&cid).await?;
+            Ok(cid)
+        })
+    }
+
+    fn flush(&self) -> StoreResult<'_, ()> {
+        Box::pin(async move { Ok(()) })
+    }
+
+    fn unpin<'a>(&'a self, cid: &'a Cid) -> StoreResult<'a, ()> {
+        Box::pin(async move {
+            self.objects.add(&self.pin_key(cid), -1).await?;
+            Ok(())
+        })
+    }
+}
+
+impl<O: ObjectStore> AliasStore for ObjectBlockStore<O> {
+    fn alias<'a>(
+        &'a self,
+        alias: &'a [u8],
+        cid: &'a Cid,
+        _visibility: Visibility,
+    ) -> StoreResult<'a, ()> {
+        Box::pin(async move {
+            self.objects
+                .put(&self.alias_key(alias), cid.to_bytes())
+                .await
+        })
+    }
+
+    fn unalias<'a>(&'a self, alias: &'a [u8]) -> StoreResult<'a, ()> {
+        Box::pin(async move { self.objects.delete(&self.alias_key(alias)).await })
+    }
+
+    fn resolve<'a>(&'a self, alias: &'a [u8]) -> StoreResult<'a, Option<Cid>> {
+        Box::pin(async move {
+            match self.objects.get(&self.alias_key(alias)).await? {
+                Some(cid) => Ok(Some(
+                    Cid::try_from(cid).map_err(|e| StoreError::Other(Box::new(e)))?,
+                )),
+                None => Ok(None),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BlockBuilder, Codec};
+    use futures::lock::Mutex;
+    use libipld::ipld;
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    #[derive(Clone, Default)]
+    struct Bucket {
+        objects: Arc<Mutex<HashMap<String, Vec<u8>>>>,
+        uploads: Arc<Mutex<usize>>,
+    }
+
+    #[async_trait]
+    impl ObjectStore for Bucket {
+        async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, StoreError> {
+            Ok(self.objects.lock().await.get(key).cloned())
+        }
+
+        async fn put(&self, key: &str, data: Vec<u8>) -> Result<(), StoreError> {
+            self.objects.lock().await.insert(key.into(), data);
+            Ok(())
+        }
+
+        async fn put_many(&self, objects: Vec<(String, Vec<u8>)>) -> Result<(), StoreError> {
+            *self.uploads.lock().await += 1;
+            self.objects.lock().await.extend(objects);
+            Ok(())
+        }
+
+        async fn delete(&self, key: &str) -> Result<(), StoreError> {
+            self.objects.lock().await.remove(key);
+            Ok(())
+        }
+    }
+
+    #[async_std::test]
+    async fn test_object_store() {
+        let bucket = Bucket::default();
+        let store = ObjectBlockStore::new(bucket.clone(), "dags/");
+        let builder = BlockBuilder::new(store.clone(), Codec::new());
+        let a = builder.insert(&ipld!({"a": 3})).await.unwrap();
+        let mut batch = builder.create_batch();
+        batch.insert(&ipld!({"b": &a})).unwrap();
+        let b = builder.insert_batch(batch).await.unwrap();
+        assert_eq!(*bucket.uploads.lock().await, 2);
+        assert_eq!(store.pins(&b).await.unwrap(), 1);
+        assert_eq!(builder.get_ipld(&a).await.unwrap(), ipld!({"a": 3}));
+
+        builder.alias(b"root", &b).await.unwrap();
+        assert_eq!(builder.resolve(b"root").await.unwrap(), Some(b.clone()));
+        builder.unpin(&b).await.unwrap();
+        assert_eq!(store.pins(&b).await.unwrap(), 0);
+        assert!(bucket
+            .objects
+            .lock()
+            .await
+            .keys()
+            .all(|k| k.starts_with("dags/")));
+    }
+}
@@ -0,0 +1,272 @@
+use crate::store::RemoteStore;
+use futures::lock::Mutex;
+use libipld::block::Block;
+use libipld::cid::{Cid, Codec as CidCodec};
+use libipld::error::StoreError;
+use libipld::multihash::Identity;
+use libipld::store::{AliasStore, ReadonlyStore, Store, StoreResult, Visibility};
+use std::collections::HashSet;
+use std::sync::Arc;
+
+#[derive(Default)]
+struct Written {
+    order: Vec<Cid>,
+    cids: HashSet<Cid>,
+}
+
+impl Written {
+    fn insert(&mut self, cid: &Cid) {
+        if self.cids.insert(cid.clone()) {
+            self.order.push(cid.clone());
+        }
+    }
+
+    fn retain(&mut self, gone: &HashSet<Cid>) {
+        self.order.retain(|cid| !gone.contains(cid));
+        self.cids.retain(|cid| !gone.contains(cid));
+    }
+}
+
+/// Alias of the tombstone hiding `alias` of the base store.
+fn tombstone(alias: &[u8]) -> Vec<u8> {
+    let mut tombstone = b"overlay/unaliased/".to_vec();
+    tombstone.extend_from_slice(alias);
+    tombstone
+}
+
+fn tombstone_cid() -> Cid {
+    Cid::new_v1(CidCodec::Raw, Identity::digest(&[]))
+}
+
+/// A copy-on-write store layering a writable store over a read-only base.
+///
+/// Reads are served by the upper store and fall back to the base store. All
+/// writes land in the upper store, so the base store is never modified.
+/// Removing an alias stores a tombstone alias under `overlay/unaliased/` in
+/// the upper store, hiding the alias of the base store across restarts. The
+/// list of written blocks returned by `export` is kept in memory only.
+#[derive(Clone)]
+pub struct OverlayStore<B, U> {
+    base: B,
+    upper: U,
+    written: Arc<Mutex<Written>>,
+}
+
+impl<B, U> OverlayStore<B, U> {
+    /// Creates a new overlay store.
+    pub fn new(base: B, upper: U) -> Self {
+        Self {
+            base,
+            upper,
+            written: Default::default(),
+        }
+    }
+
+    /// Returns the base store.
+    pub fn base(&self) -> &B {
+        &self.base
+    }
+
+    /// Returns the upper store.
+    pub fn upper(&self) -> &U {
+        &self.upper
+    }
+}
+
+impl<B, U: ReadonlyStore> OverlayStore<B, U> {
+    /// Exports the blocks written to the overlay in insertion order.
+    ///
+    /// Blocks that were garbage collected since they were written are
+    /// skipped. The result can be inserted into another store as a batch.
+    pub async fn export(&self) -> Result<Vec<Block>, StoreError> {
+        let order = self.written.lock().await.order.clone();
+        let mut blocks = Vec::with_capacity(order.len());
+        let mut gone = HashSet::new();
+        for cid in order {
+            match self.upper.get(&cid).await {
+                Ok(data) => blocks.push(Block { cid, data }),
+                Err(StoreError::BlockNotFound(_)) => {
+                    gone.insert(cid);
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        if !gone.is_empty() {
+            self.written.lock().await.retain(&gone);
+        }
+        Ok(blocks)
+    }
+}
+
+impl<B, U> ReadonlyStore for OverlayStore<B, U>
+where
+    B: ReadonlyStore + Send + Sync,
+    U: ReadonlyStore + Send + Sync,
+{
+    fn get<'a>(&'a self, cid: &'a Cid) -> StoreResult<'a, Box<[u8]>> {
+        Box::pin(async move {
+            match self.upper.get(cid).await {
+                Err(StoreError::BlockNotFound(_)) => self.base.get(cid).await,
+                res => res,
+            }
+        })
+    }
+}
+
+impl<B, U> RemoteStore for OverlayStore<B, U>
+where
+    B: RemoteStore + Send + Sync,
+    U: ReadonlyStore + Send + Sync,
+{
+    fn get_local<'a>(&'a self, cid: &'a Cid) -> StoreResult<'a, Box<[u8]>> {
+        Box::pin(async move {
+            match self.upper.get(cid).await {
+                Err(StoreError::BlockNotFound(_)) => self.base.get_local(cid).await,
+                res => res,
+            }
+        })
+    }
+}
+
+impl<B, U> Store for OverlayStore<B, U>
+where
+    B: ReadonlyStore + Send + Sync,
+    U: Store + Send + Sync,
+{
+    fn insert<'a>(
+        &'a self,
+        cid: &'a Cid,
+        data: Box<[u8]>,
+        visibility: Visibility,
+    ) -> StoreResult<'a, ()> {
+        Box::pin(async move {
+            self.upper.insert(cid, data, visibility).await?;
+            self.written.lock().await.insert(cid);
+            Ok(())
+        })
+    }
+
+    fn insert_batch<'a>(
+        &'a self,
+        batch: Vec<Block>,
+        visibility: Visibility,
+    ) -> StoreResult<'a, Cid> {
+        Box::pin(async move {
+            let cids: Vec<Cid> = batch.iter().map(|block| block.cid.clone()).collect();
+            let cid = self.upper.insert_batch(batch, visibility).await?;
+            let mut written = self.written.lock().await;
+            for cid in &cids {
+                written.insert(cid);
+            }
+            Ok(cid)
+        })
+    }
+
+    fn flush(&self) -> StoreResult<'_, ()> {
+        self.upper.flush()
+    }
+
+    fn unpin<'a>(&'a self, cid: &'a Cid) -> StoreResult<'a, ()> {
+        self.upper.unpin(cid)
+    }
+}
+
+impl<B, U> AliasStore for OverlayStore<B, U>
+where
+    B: AliasStore + Send + Sync,
+    U: AliasStore + Send + Sync,
+{
+    fn alias<'a>(
+        &'a self,
+        alias: &'a [u8],
+        cid: &'a Cid,
+        visibility: Visibility,
+    ) -> StoreResult<'a, ()> {
+        Box::pin(async move {
+            self.upper.alias(alias, cid, visibility).await?;
+            self.upper.unalias(&tombstone(alias)).await
+        })
+    }
+
+    fn unalias<'a>(&'a self, alias: &'a [u8]) -> StoreResult<'a, ()> {
+        Box::pin(async move {
+            self.upper.unalias(alias).await?;
+            let cid = tombstone_cid();
+            self.upper
+                .alias(&tombstone(alias), &cid, Visibility::Private)
+                .await
+        })
+    }
+
+    fn resolve<'a>(&'a self, alias: &'a [u8]) -> StoreResult<'a, Option<Cid>> {
+        Box::pin(async move {
+            if let Some(cid) = self.upper.resolve(alias).await? {
+                return Ok(Some(cid));
+            }
+            if self.upper.resolve(&tombstone(alias)).await?.is_some() {
+                return Ok(None);
+            }
+            self.base.resolve(alias).await
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BlockBuilder, Codec, Encoder};
+    use libipld::ipld;
+    use libipld::mem::MemStore;
+
+    #[async_std::test]
+    async fn test_overlay_store() {
+        let base = BlockBuilder::new(MemStore::default(), Codec::new());
+        let a = base.insert(&ipld!({"a": 3})).await.unwrap();
+        base.alias(b"root", &a).await.unwrap();
+
+        let store = OverlayStore::new(base.store().clone(), MemStore::default());
+        let overlay = BlockBuilder::new(store.clone(), Codec::new());
+        let b = overlay.insert(&ipld!({"b": &a})).await.unwrap();
+        overlay.alias(b"root", &b).await.unwrap();
+        assert!(overlay.get_ipld(&a).await.is_ok());
+        assert!(base.get_ipld(&b).await.is_err());
+        assert_eq!(base.resolve(b"root").await.unwrap(), Some(a.clone()));
+        overlay.unalias(b"root").await.unwrap();
+        assert_eq!(overlay.resolve(b"root").await.unwrap(), None);
+
+        let blocks = store.export().await.unwrap();
+        assert_eq!(blocks.len(), 1);
+        assert_eq!(blocks[0].cid, b);
+
+        // tombstones are persisted in the upper store
+        let reopened = OverlayStore::new(base.store().clone(), store.upper().clone());
+        assert_eq!(reopened.resolve(b"root").await.unwrap(), None);
+        reopened
+            .alias(b"root", &a, Visibility::Public)
+            .await
+            .unwrap();
+        assert_eq!(reopened.resolve(b"root").await.unwrap(), Some(a.clone()));
+        reopened.unalias(b"root").await.unwrap();
+        assert_eq!(reopened.resolve(b"root").await.unwrap(), None);
+    }
+
+    #[async_std::test]
+    async fn test_overlay_export_collected() {
+        let store = OverlayStore::new(MemStore::default(), MemStore::default());
+        let a = Codec::new().encode(&ipld!({"a": 1})).unwrap();
+        let b = Codec::new().encode(&ipld!({"b": 2})).unwrap();
+        store
+            .insert(&a.cid, a.data, Visibility::Public)
+            .await
+            .unwrap();
+        store
+            .insert(&b.cid, b.data, Visibility::Public)
+            .await
+            .unwrap();
+        store.unpin(&a.cid).await.unwrap();
+        let blocks = store.export().await.unwrap();
+        assert_eq!(blocks.len(), 1);
+        assert_eq!(blocks[0].cid, b.cid);
+        assert_eq!(store.written.lock().await.order, vec![b.cid]);
+    }
+}
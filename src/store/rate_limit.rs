@@ -0,0 +1,239 @@
+use crate::budget::{MemoryBudget, Reservation};
+use crate::error::{Error, Result};
+use crate::rt::Instant;
+use crate::store::RemoteStore;
+use futures::lock::Mutex;
+use libipld::block::Block;
+use libipld::cid::Cid;
+use libipld::error::StoreError;
+use libipld::store::{AliasStore, MultiUserStore, ReadonlyStore, Store, StoreResult, Visibility};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Limits of a `RateLimitStore`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RateLimit {
+    /// Maximum number of requests started per second.
+    pub requests_per_second: Option<f64>,
+    /// Maximum number of requests in flight.
+    pub max_concurrent: Option<usize>,
+}
+
+/// A store limiting the rate and concurrency of requests to the wrapped
+/// store.
+///
+/// Requests wait for the previous request to be admitted, a request that is
+/// dropped while waiting doesn't use up a time slot. Reads of local blocks
+/// through `RemoteStore::get_local` are not limited.
+#[derive(Clone)]
+pub struct RateLimitStore<S> {
+    store: S,
+    limit: RateLimit,
+    interval: Option<Duration>,
+    concurrency: Option<MemoryBudget>,
+    next: Arc<Mutex<Option<Instant>>>,
+}
+
+impl<S> RateLimitStore<S> {
+    /// Creates a store enforcing `limit`.
+    ///
+    /// Fails if the requests per second are not positive and finite, or so
+    /// small that the interval between requests overflows.
+    pub fn new(store: S, limit: RateLimit) -> Result<Self> {
+        let interval = match limit.requests_per_second {
+            Some(rps) if rps > 0.0 && rps.is_finite() => Some(
+                Duration::try_from_secs_f64(1.0 / rps).map_err(|_| Error::InvalidRateLimit(rps))?,
+            ),
+            Some(rps) => return Err(Error::InvalidRateLimit(rps)),
+            None => None,
+        };
+        Ok(Self {
+            store,
+            limit,
+            interval,
+            concurrency: limit
+                .max_concurrent
+                .map(|max| MemoryBudget::new(max.max(1))),
+            next: Default::default(),
+        })
+    }
+
+    /// Returns the limits.
+    pub fn limit(&self) -> RateLimit {
+        self.limit
+    }
+
+    /// Returns the wrapped store.
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    /// Waits for a concurrency slot and the next free time slot.
+    async fn admit(&self) -> Option<Reservation> {
+        let permit = match &self.concurrency {
+            Some(concurrency) => Some(concurrency.acquire(1).await),
+            None => None,
+        };
+        if let Some(interval) = self.interval {
+            // the lock is held while sleeping, dropping the future releases
+            // it without taking the slot
+            let mut next = self.next.lock().await;
+            if let Some(slot) = *next {
+                let now = Instant::now();
+                if slot > now {
+                    crate::rt::sleep(slot - now).await;
+                }
+            }
+            *next = Some(Instant::now() + interval);
+        }
+        permit
+    }
+
+    async fn limited<'a, T, F>(&'a self, f: F) -> core::result::Result<T, StoreError>
+    where
+        F: FnOnce() -> StoreResult<'a, T>,
+    {
+        let _permit = self.admit().await;
+        f().await
+    }
+}
+
+impl<S: ReadonlyStore + Send + Sync> ReadonlyStore for RateLimitStore<S> {
+    fn get<'a>(&'a self, cid: &'a Cid) -> StoreResult<'a, Box<[u8]>> {
+        Box::pin(self.limited(move || self.store.get(cid)))
+    }
+}
+
+impl<S: RemoteStore + Send + Sync> RemoteStore for RateLimitStore<S> {
+    fn get_local<'a>(&'a self, cid: &'a Cid) -> StoreResult<'a, Box<[u8]>> {
+        self.store.get_local(cid)
+    }
+}
+
+impl<S: Store + Send + Sync> Store for RateLimitStore<S> {
+    fn insert<'a>(
+        &'a self,
+        cid: &'a Cid,
+        data: Box<[u8]>,
+        visibility: Visibility,
+    ) -> StoreResult<'a, ()> {
+        Box::pin(self.limited(move || self.store.insert(cid, data, visibility)))
+    }
+
+    fn insert_batch<'a>(
+        &'a self,
+        batch: Vec<Block>,
+        visibility: Visibility,
+    ) -> StoreResult<'a, Cid> {
+        Box::pin(self.limited(move || self.store.insert_batch(batch, visibility)))
+    }
+
+    fn flush(&self) -> StoreResult<'_, ()> {
+        Box::pin(self.limited(move || self.store.flush()))
+    }
+
+    fn unpin<'a>(&'a self, cid: &'a Cid) -> StoreResult<'a, ()> {
+        Box::pin(self.limited(move || self.store.unpin(cid)))
+    }
+}
+
+impl<S: MultiUserStore + Send + Sync> MultiUserStore for RateLimitStore<S> {
+    fn pin<'a>(&'a self, cid: &'a Cid, path: &'a Path) -> StoreResult<'a, ()> {
+        Box::pin(self.limited(move || self.store.pin(cid, path)))
+    }
+}
+
+impl<S: AliasStore + Send + Sync> AliasStore for RateLimitStore<S> {
+    fn alias<'a>(
+        &'a self,
+        alias: &'a [u8],
+        cid: &'a Cid,
+        visibility: Visibility,
+    ) -> StoreResult<'a, ()> {
+        Box::pin(self.limited(move || self.store.alias(alias, cid, visibility)))
+    }
+
+    fn unalias<'a>(&'a self, alias: &'a [u8]) -> StoreResult<'a, ()> {
+        Box::pin(self.limited(move || self.store.unalias(alias)))
+    }
+
+    fn resolve<'a>(&'a self, alias: &'a [u8]) -> StoreResult<'a, Option<Cid>> {
+        Box::pin(self.limited(move || self.store.resolve(alias)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BlockBuilder, Codec};
+    use futures::future::join_all;
+    use libipld::ipld;
+    use libipld::mem::MemStore;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[derive(Clone, Default)]
+    struct SlowStore {
+        store: MemStore,
+        in_flight: Arc<AtomicUsize>,
+        max_in_flight: Arc<AtomicUsize>,
+    }
+
+    impl ReadonlyStore for SlowStore {
+        fn get<'a>(&'a self, cid: &'a Cid) -> StoreResult<'a, Box<[u8]>> {
+            Box::pin(async move {
+                let in_flight = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                self.max_in_flight.fetch_max(in_flight, Ordering::SeqCst);
+                crate::rt::sleep(Duration::from_millis(10)).await;
+                self.in_flight.fetch_sub(1, Ordering::SeqCst);
+                self.store.get(cid).await
+            })
+        }
+    }
+
+    #[cfg_attr(not(feature = "tokio"), async_std::test)]
+    #[cfg_attr(feature = "tokio", tokio::test)]
+    async fn test_rate_limit_store() {
+        let slow = SlowStore::default();
+        let builder = BlockBuilder::new(slow.store.clone(), Codec::new());
+        let cid = builder.insert(&ipld!({ "a": 1 })).await.unwrap();
+
+        let limit = RateLimit {
+            requests_per_second: None,
+            max_concurrent: Some(2),
+        };
+        let store = RateLimitStore::new(slow.clone(), limit).unwrap();
+        join_all((0..8).map(|_| store.get(&cid))).await;
+        assert_eq!(slow.max_in_flight.load(Ordering::SeqCst), 2);
+
+        let limit = RateLimit {
+            requests_per_second: Some(100.0),
+            max_concurrent: None,
+        };
+        let store = RateLimitStore::new(slow.store.clone(), limit).unwrap();
+        let start = Instant::now();
+        for res in join_all((0..6).map(|_| store.get(&cid))).await {
+            res.unwrap();
+        }
+        assert!(start.elapsed() >= Duration::from_millis(50));
+
+        // dropped requests don't use up slots
+        for _ in 0..50 {
+            let _ = futures::poll!(Box::pin(store.get(&cid)));
+        }
+        let start = Instant::now();
+        store.get(&cid).await.unwrap();
+        assert!(start.elapsed() < Duration::from_millis(100));
+
+        for rps in [0.0, -1.0, f64::NAN, f64::INFINITY, f64::MIN_POSITIVE] {
+            let limit = RateLimit {
+                requests_per_second: Some(rps),
+                max_concurrent: None,
+            };
+            assert!(matches!(
+                RateLimitStore::new(slow.store.clone(), limit),
+                Err(Error::InvalidRateLimit(_))
+            ));
+        }
+    }
+}
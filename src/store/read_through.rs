@@ -0,0 +1,216 @@
+use crate::error::verify;
+use crate::store::RemoteStore;
+use futures::lock::Mutex;
+use libipld::block::Block;
+use libipld::cid::Cid;
+use libipld::error::StoreError;
+use libipld::store::{AliasStore, ReadonlyStore, Store, StoreResult, Visibility};
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+/// Replication policy of a `ReadThroughStore`.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct ReadThroughPolicy {
+    /// Maximum number of replicated bytes kept in the local store.
+    pub max_bytes: Option<usize>,
+    /// Keeps replicated blocks pinned forever instead of evicting them.
+    ///
+    /// Once `max_bytes` is reached no further blocks are replicated.
+    pub pin: bool,
+}
+
+#[derive(Default)]
+struct Replicated {
+    bytes: usize,
+    order: VecDeque<(Cid, usize)>,
+}
+
+/// A store replicating blocks fetched from a remote store into a local
+/// store, so they stay available offline.
+///
+/// Reads are served by the local store and fall back to the remote store.
+/// Fetched blocks are verified and inserted into the local store. Unless
+/// the policy pins them, the least recently replicated blocks are unpinned
+/// when `max_bytes` is exceeded, leaving them to the local store's garbage
+/// collection. Writes and aliases only go to the local store.
+#[derive(Clone)]
+pub struct ReadThroughStore<R, L> {
+    remote: R,
+    local: L,
+    policy: ReadThroughPolicy,
+    replicated: Arc<Mutex<Replicated>>,
+}
+
+impl<R, L> ReadThroughStore<R, L> {
+    /// Creates a store replicating from `remote` into `local`.
+    pub fn new(remote: R, local: L, policy: ReadThroughPolicy) -> Self {
+        Self {
+            remote,
+            local,
+            policy,
+            replicated: Default::default(),
+        }
+    }
+
+    /// Returns the remote store.
+    pub fn remote(&self) -> &R {
+        &self.remote
+    }
+
+    /// Returns the local store.
+    pub fn local(&self) -> &L {
+        &self.local
+    }
+
+    /// Returns the replication policy.
+    pub fn policy(&self) -> ReadThroughPolicy {
+        self.policy
+    }
+
+    /// Returns the number of replicated bytes kept in the local store.
+    pub async fn replicated_bytes(&self) -> usize {
+        self.replicated.lock().await.bytes
+    }
+}
+
+impl<R: ReadonlyStore, L: Store> ReadThroughStore<R, L> {
+    async fn replicate(&self, cid: &Cid, data: Box<[u8]>) -> Result<(), StoreError> {
+        let mut replicated = self.replicated.lock().await;
+        let max = self.policy.max_bytes.unwrap_or(usize::MAX);
+        if data.len() > max || (self.policy.pin && replicated.bytes + data.len() > max) {
+            return Ok(());
+        }
+        replicated.bytes += data.len();
+        replicated.order.push_back((cid.clone(), data.len()));
+        self.local.insert(cid, data, Visibility::Public).await?;
+        while replicated.bytes > max {
+            let (cid, len) = replicated.order.pop_front().unwrap();
+            replicated.bytes -= len;
+            self.local.unpin(&cid).await?;
+        }
+        Ok(())
+    }
+}
+
+impl<R, L> ReadonlyStore for ReadThroughStore<R, L>
+where
+    R: ReadonlyStore + Send + Sync,
+    L: Store + Send + Sync,
+{
+    fn get<'a>(&'a self, cid: &'a Cid) -> StoreResult<'a, Box<[u8]>> {
+        Box::pin(async move {
+            match self.local.get(cid).await {
+                Err(StoreError::BlockNotFound(_)) => {}
+                res => return res,
+            }
+            let data = self.remote.get(cid).await?;
+            verify(cid, &data).map_err(|err| StoreError::Other(Box::new(err)))?;
+            self.replicate(cid, data.clone()).await?;
+            Ok(data)
+        })
+    }
+}
+
+impl<R, L> RemoteStore for ReadThroughStore<R, L>
+where
+    R: ReadonlyStore + Send + Sync,
+    L: Store + Send + Sync,
+{
+    fn get_local<'a>(&'a self, cid: &'a Cid) -> StoreResult<'a, Box<[u8]>> {
+        self.local.get(cid)
+    }
+}
+
+impl<R, L> Store for ReadThroughStore<R, L>
+where
+    R: ReadonlyStore + Send + Sync,
+    L: Store + Send + Sync,
+{
+    fn insert<'a>(
+        &'a self,
+        cid: &'a Cid,
+        data: Box<[u8]>,
+        visibility: Visibility,
+    ) -> StoreResult<'a, ()> {
+        self.local.insert(cid, data, visibility)
+    }
+
+    fn insert_batch<'a>(
+        &'a self,
+        batch: Vec<Block>,
+        visibility: Visibility,
+    ) -> StoreResult<'a, Cid> {
+        self.local.insert_batch(batch, visibility)
+    }
+
+    fn flush(&self) -> StoreResult<'_, ()> {
+        self.local.flush()
+    }
+
+    fn unpin<'a>(&'a self, cid: &'a Cid) -> StoreResult<'a, ()> {
+        self.local.unpin(cid)
+    }
+}
+
+impl<R, L> AliasStore for ReadThroughStore<R, L>
+where
+    R: ReadonlyStore + Send + Sync,
+    L: Store + AliasStore + Send + Sync,
+{
+    fn alias<'a>(
+        &'a self,
+        alias: &'a [u8],
+        cid: &'a Cid,
+        visibility: Visibility,
+    ) -> StoreResult<'a, ()> {
+        self.local.alias(alias, cid, visibility)
+    }
+
+    fn unalias<'a>(&'a self, alias: &'a [u8]) -> StoreResult<'a, ()> {
+        self.local.unalias(alias)
+    }
+
+    fn resolve<'a>(&'a self, alias: &'a [u8]) -> StoreResult<'a, Option<Cid>> {
+        self.local.resolve(alias)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BlockBuilder, Codec};
+    use libipld::ipld;
+    use libipld::mem::MemStore;
+
+    #[async_std::test]
+    async fn test_read_through_store() {
+        let remote = BlockBuilder::new(MemStore::default(), Codec::new());
+        let a = remote.insert(&ipld!({ "a": 1 })).await.unwrap();
+        let b = remote.insert(&ipld!({ "b": 2 })).await.unwrap();
+        let len = remote.store().get(&a).await.unwrap().len();
+
+        let policy = ReadThroughPolicy {
+            max_bytes: Some(len),
+            pin: false,
+        };
+        let store = ReadThroughStore::new(remote.store().clone(), MemStore::default(), policy);
+        let builder = BlockBuilder::new(store.clone(), Codec::new());
+        builder.get_ipld(&a).await.unwrap();
+        assert!(store.get_local(&a).await.is_ok());
+        assert_eq!(store.replicated_bytes().await, len);
+        // replicating b evicts a
+        builder.get_ipld(&b).await.unwrap();
+        assert!(store.get_local(&a).await.is_err());
+        assert!(store.get_local(&b).await.is_ok());
+
+        let policy = ReadThroughPolicy {
+            max_bytes: Some(len),
+            pin: true,
+        };
+        let store = ReadThroughStore::new(remote.store().clone(), MemStore::default(), policy);
+        store.get(&a).await.unwrap();
+        store.get(&b).await.unwrap();
+        assert!(store.get_local(&a).await.is_ok());
+        assert!(store.get_local(&b).await.is_err());
+    }
+}
@@ -0,0 +1,12 @@
+use libipld::cid::Cid;
+use libipld::store::{ReadonlyStore, StoreResult};
+
+/// A store that resolves missing blocks over the network.
+///
+/// Used by `BlockBuilder::set_offline` to read blocks without going to the
+/// network.
+pub trait RemoteStore: ReadonlyStore {
+    /// Returns a block if it is available locally, failing with
+    /// `StoreError::BlockNotFound` instead of fetching it.
+    fn get_local<'a>(&'a self, cid: &'a Cid) -> StoreResult<'a, Box<[u8]>>;
+}
@@ -0,0 +1,209 @@
+use crate::store::RemoteStore;
+use libipld::block::Block;
+use libipld::cid::Cid;
+use libipld::error::StoreError;
+use libipld::store::{ReadonlyStore, Store, StoreResult, Visibility};
+
+/// Api of a `rust-ipfs` repo.
+///
+/// The crate doesn't depend on `ipfs`, implementations for `ipfs::Repo`
+/// delegate every method and convert cids through their byte
+/// representation.
+pub trait IpfsRepo: Clone + Send + Sync {
+    /// Returns a block if it is in the blockstore.
+    fn get_block_now<'a>(&'a self, cid: &'a Cid) -> StoreResult<'a, Option<Box<[u8]>>>;
+
+    /// Returns a block, waiting for it to be fetched by bitswap.
+    fn get_block<'a>(&'a self, cid: &'a Cid) -> StoreResult<'a, Box<[u8]>>;
+
+    /// Puts a block into the blockstore.
+    fn put_block(&self, block: Block) -> StoreResult<'_, ()>;
+
+    /// Pins a block directly.
+    fn insert_direct_pin<'a>(&'a self, cid: &'a Cid) -> StoreResult<'a, ()>;
+
+    /// Removes a direct pin.
+    fn remove_direct_pin<'a>(&'a self, cid: &'a Cid) -> StoreResult<'a, ()>;
+
+    /// Returns if a block is pinned.
+    fn is_pinned<'a>(&'a self, cid: &'a Cid) -> StoreResult<'a, bool>;
+}
+
+/// A store on top of a `rust-ipfs` repo.
+///
+/// The repo serves every block it holds to its peers, so private data
+/// should be inserted with an encrypted codec. Pins map to direct pins of
+/// the repo and are not reference counted.
+#[derive(Clone)]
+pub struct RepoStore<R> {
+    repo: R,
+}
+
+impl<R> RepoStore<R> {
+    /// Creates a new store.
+    pub fn new(repo: R) -> Self {
+        Self { repo }
+    }
+
+    /// Returns the repo.
+    pub fn repo(&self) -> &R {
+        &self.repo
+    }
+}
+
+impl<R: IpfsRepo> RepoStore<R> {
+    async fn pin(&self, cid: &Cid) -> Result<(), StoreError> {
+        if !self.repo.is_pinned(cid).await? {
+            self.repo.insert_direct_pin(cid).await?;
+        }
+        Ok(())
+    }
+}
+
+impl<R: IpfsRepo> ReadonlyStore for RepoStore<R> {
+    fn get<'a>(&'a self, cid: &'a Cid) -> StoreResult<'a, Box<[u8]>> {
+        self.repo.get_block(cid)
+    }
+}
+
+impl<R: IpfsRepo> RemoteStore for RepoStore<R> {
+    fn get_local<'a>(&'a self, cid: &'a Cid) -> StoreResult<'a, Box<[u8]>> {
+        Box::pin(async move {
+            self.repo
+                .get_block_now(cid)
+                .await?
+                .ok_or_else(|| StoreError::BlockNotFound(cid.clone()))
+        })
+    }
+}
+
+impl<R: IpfsRepo> Store for RepoStore<R> {
+    fn insert<'a>(
+        &'a self,
+        cid: &'a Cid,
+        data: Box<[u8]>,
+        _visibility: Visibility,
+    ) -> StoreResult<'a, ()> {
+        Box::pin(async move {
+            let block = Block {
+                cid: cid.clone(),
+                data,
+            };
+            self.repo.put_block(block).await?;
+            self.pin(cid).await
+        })
+    }
+
+    fn insert_batch<'a>(
+        &'a self,
+        batch: Vec<Block>,
+        _visibility: Visibility,
+    ) -> StoreResult<'a, Cid> {
+        Box::pin(async move {
+            let mut last = None;
+            for block in batch {
+                last = Some(block.cid.clone());
+                self.repo.put_block(block).await?;
+            }
+            let last = last.ok_or(StoreError::EmptyBatch)?;
+            self.pin(&last).await?;
+            Ok(last)
+        })
+    }
+
+    fn flush(&self) -> StoreResult<'_, ()> {
+        Box::pin(async { Ok(()) })
+    }
+
+    fn unpin<'a>(&'a self, cid: &'a Cid) -> StoreResult<'a, ()> {
+        Box::pin(async move {
+            if self.repo.is_pinned(cid).await? {
+                self.repo.remove_direct_pin(cid).await?;
+            }
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BlockBuilder, Cache, Codec, IpldCache, ReadonlyCache};
+    use libipld::ipld;
+    use libipld::ipld::Ipld;
+    use libipld::mem::MemStore;
+    use std::collections::{HashMap, HashSet};
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Clone, Default)]
+    struct Repo {
+        blocks: Arc<Mutex<HashMap<Cid, Box<[u8]>>>>,
+        pins: Arc<Mutex<HashSet<Cid>>>,
+        network: MemStore,
+    }
+
+    impl IpfsRepo for Repo {
+        fn get_block_now<'a>(&'a self, cid: &'a Cid) -> StoreResult<'a, Option<Box<[u8]>>> {
+            let block = self.blocks.lock().unwrap().get(cid).cloned();
+            Box::pin(async move { Ok(block) })
+        }
+
+        fn get_block<'a>(&'a self, cid: &'a Cid) -> StoreResult<'a, Box<[u8]>> {
+            Box::pin(async move {
+                match self.get_block_now(cid).await? {
+                    Some(data) => Ok(data),
+                    None => self.network.get(cid).await,
+                }
+            })
+        }
+
+        fn put_block(&self, block: Block) -> StoreResult<'_, ()> {
+            self.blocks.lock().unwrap().insert(block.cid, block.data);
+            Box::pin(async { Ok(()) })
+        }
+
+        fn insert_direct_pin<'a>(&'a self, cid: &'a Cid) -> StoreResult<'a, ()> {
+            self.pins.lock().unwrap().insert(cid.clone());
+            Box::pin(async { Ok(()) })
+        }
+
+        fn remove_direct_pin<'a>(&'a self, cid: &'a Cid) -> StoreResult<'a, ()> {
+            let res = if self.pins.lock().unwrap().remove(cid) {
+                Ok(())
+            } else {
+                Err(StoreError::BlockNotFound(cid.clone()))
+            };
+            Box::pin(async move { res })
+        }
+
+        fn is_pinned<'a>(&'a self, cid: &'a Cid) -> StoreResult<'a, bool> {
+            let pinned = self.pins.lock().unwrap().contains(cid);
+            Box::pin(async move { Ok(pinned) })
+        }
+    }
+
+    #[async_std::test]
+    async fn test_repo_store() {
+        let repo = Repo::default();
+        let remote = BlockBuilder::new(repo.network.clone(), Codec::new());
+        let remote = remote.insert(&ipld!("remote")).await.unwrap();
+
+        let store = RepoStore::new(repo.clone());
+        let cache = IpldCache::<_, _, Ipld>::new(store.clone(), Codec::new(), 16);
+        let cid = cache
+            .insert(ipld!({"remote": remote.clone()}))
+            .await
+            .unwrap();
+        cache
+            .insert(ipld!({"remote": remote.clone()}))
+            .await
+            .unwrap();
+        assert_eq!(cache.get(&remote).await.unwrap(), ipld!("remote"));
+        assert!(repo.is_pinned(&cid).await.unwrap());
+        assert!(store.get_local(&remote).await.is_err());
+
+        store.unpin(&cid).await.unwrap();
+        store.unpin(&cid).await.unwrap();
+        assert!(!repo.is_pinned(&cid).await.unwrap());
+    }
+}
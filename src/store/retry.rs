@@ -0,0 +1,387 @@
+use crate::store::RemoteStore;
+use libipld::block::Block;
+use libipld::cid::Cid;
+use libipld::error::StoreError;
+use libipld::store::{AliasStore, MultiUserStore, ReadonlyStore, Store, StoreResult, Visibility};
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::hash::{BuildHasher, Hasher};
+use std::io::ErrorKind;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Store operation.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum StoreOperation {
+    /// Fetching a block.
+    Get,
+    /// Inserting a block or a batch.
+    Insert,
+    /// Flushing the store.
+    Flush,
+    /// Pinning or unpinning a block.
+    Pin,
+    /// Creating, removing or resolving an alias.
+    Alias,
+}
+
+/// Exponential backoff retry policy.
+#[derive(Clone, Copy, Debug)]
+pub struct RetryPolicy {
+    /// Maximum number of retries.
+    pub max_retries: usize,
+    /// Backoff before the first retry.
+    pub initial_backoff: Duration,
+    /// Upper bound of the backoff.
+    pub max_backoff: Duration,
+    /// Randomize the backoff to avoid synchronized retries.
+    pub jitter: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(10),
+            jitter: true,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// A policy that never retries.
+    pub fn never() -> Self {
+        Self {
+            max_retries: 0,
+            ..Default::default()
+        }
+    }
+
+    fn backoff(&self, retry: usize) -> Duration {
+        let backoff = self
+            .initial_backoff
+            .checked_mul(1 << retry.min(31) as u32)
+            .unwrap_or(self.max_backoff)
+            .min(self.max_backoff);
+        if self.jitter {
+            let random = RandomState::new().build_hasher().finish();
+            backoff / 2 + (backoff / 2).mul_f64((random % 1024) as f64 / 1024.0)
+        } else {
+            backoff
+        }
+    }
+}
+
+/// Marks a store error as transient.
+///
+/// Backends wrap errors that may succeed when retried, like timeouts or
+/// server errors, in a `Transient` before returning them as
+/// `StoreError::Other`.
+#[derive(Debug)]
+pub struct Transient(pub Box<dyn Error + Send + Sync>);
+
+impl Transient {
+    /// Wraps `err` in a transient store error.
+    pub fn store_error(err: impl Into<Box<dyn Error + Send + Sync>>) -> StoreError {
+        StoreError::Other(Box::new(Self(err.into())))
+    }
+}
+
+impl fmt::Display for Transient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl Error for Transient {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&*self.0)
+    }
+}
+
+/// Returns if an error is transient.
+///
+/// Only errors marked as `Transient` and io errors caused by timeouts,
+/// interruptions or dropped connections are transient. Everything else,
+/// including missing blocks and failed verifications, is permanent.
+pub fn is_transient(err: &StoreError) -> bool {
+    let err = match err {
+        StoreError::Other(err) => err,
+        _ => return false,
+    };
+    if err.downcast_ref::<Transient>().is_some() {
+        return true;
+    }
+    match err.downcast_ref::<std::io::Error>() {
+        Some(err) => matches!(
+            err.kind(),
+            ErrorKind::TimedOut
+                | ErrorKind::Interrupted
+                | ErrorKind::WouldBlock
+                | ErrorKind::ConnectionRefused
+                | ErrorKind::ConnectionReset
+                | ErrorKind::ConnectionAborted
+                | ErrorKind::UnexpectedEof
+        ),
+        None => false,
+    }
+}
+
+type Classifier = Arc<dyn Fn(&StoreError) -> bool + Send + Sync>;
+
+/// A store retrying transient failures with exponential backoff.
+///
+/// Inserts and pins change the pin count of a block, so retrying one that
+/// timed out after it was applied pins or unpins the block twice. They are
+/// only retried when a policy was set for them with `set_policy`, which
+/// declares that the backend applies them idempotently.
+#[derive(Clone)]
+pub struct RetryStore<S> {
+    store: S,
+    default: RetryPolicy,
+    policies: Arc<HashMap<StoreOperation, RetryPolicy>>,
+    classifier: Classifier,
+}
+
+impl<S> RetryStore<S> {
+    /// Creates a retry store using `policy` for all idempotent operations.
+    pub fn new(store: S, policy: RetryPolicy) -> Self {
+        Self {
+            store,
+            default: policy,
+            policies: Default::default(),
+            classifier: Arc::new(is_transient),
+        }
+    }
+
+    /// Sets the policy of an operation.
+    pub fn set_policy(&mut self, op: StoreOperation, policy: RetryPolicy) {
+        Arc::make_mut(&mut self.policies).insert(op, policy);
+    }
+
+    /// Sets the function deciding which errors are retried, `is_transient` by
+    /// default.
+    pub fn set_classifier<F>(&mut self, classifier: F)
+    where
+        F: Fn(&StoreError) -> bool + Send + Sync + 'static,
+    {
+        self.classifier = Arc::new(classifier);
+    }
+
+    /// Returns the policy of an operation.
+    pub fn policy(&self, op: StoreOperation) -> RetryPolicy {
+        match (self.policies.get(&op), op) {
+            (Some(policy), _) => *policy,
+            (None, StoreOperation::Insert) | (None, StoreOperation::Pin) => RetryPolicy::never(),
+            (None, _) => self.default,
+        }
+    }
+
+    /// Returns the wrapped store.
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    async fn retry<'a, T, F>(&'a self, op: StoreOperation, mut f: F) -> Result<T, StoreError>
+    where
+        F: FnMut() -> StoreResult<'a, T>,
+    {
+        let policy = self.policy(op);
+        let mut retry = 0;
+        loop {
+            match f().await {
+                Err(err) if retry < policy.max_retries && (self.classifier)(&err) => {
+                    #[cfg(feature = "tracing")]
+                    tracing::debug!("retrying {:?} after error: {}", op, err);
+                    crate::rt::sleep(policy.backoff(retry)).await;
+                    retry += 1;
+                }
+                res => return res,
+            }
+        }
+    }
+}
+
+impl<S: ReadonlyStore + Send + Sync> ReadonlyStore for RetryStore<S> {
+    fn get<'a>(&'a self, cid: &'a Cid) -> StoreResult<'a, Box<[u8]>> {
+        Box::pin(self.retry(StoreOperation::Get, move || self.store.get(cid)))
+    }
+}
+
+impl<S: RemoteStore + Send + Sync> RemoteStore for RetryStore<S> {
+    fn get_local<'a>(&'a self, cid: &'a Cid) -> StoreResult<'a, Box<[u8]>> {
+        Box::pin(self.retry(StoreOperation::Get, move || self.store.get_local(cid)))
+    }
+}
+
+impl<S: Store + Send + Sync> Store for RetryStore<S> {
+    fn insert<'a>(
+        &'a self,
+        cid: &'a Cid,
+        data: Box<[u8]>,
+        visibility: Visibility,
+    ) -> StoreResult<'a, ()> {
+        Box::pin(self.retry(StoreOperation::Insert, move || {
+            self.store.insert(cid, data.clone(), visibility)
+        }))
+    }
+
+    fn insert_batch<'a>(
+        &'a self,
+        batch: Vec<Block>,
+        visibility: Visibility,
+    ) -> StoreResult<'a, Cid> {
+        Box::pin(self.retry(StoreOperation::Insert, move || {
+            let batch = batch.iter().map(super::clone_block).collect();
+            self.store.insert_batch(batch, visibility)
+        }))
+    }
+
+    fn flush(&self) -> StoreResult<'_, ()> {
+        Box::pin(self.retry(StoreOperation::Flush, move || self.store.flush()))
+    }
+
+    fn unpin<'a>(&'a self, cid: &'a Cid) -> StoreResult<'a, ()> {
+        Box::pin(self.retry(StoreOperation::Pin, move || self.store.unpin(cid)))
+    }
+}
+
+impl<S: MultiUserStore + Send + Sync> MultiUserStore for RetryStore<S> {
+    fn pin<'a>(&'a self, cid: &'a Cid, path: &'a Path) -> StoreResult<'a, ()> {
+        Box::pin(self.retry(StoreOperation::Pin, move || self.store.pin(cid, path)))
+    }
+}
+
+impl<S: AliasStore + Send + Sync> AliasStore for RetryStore<S> {
+    fn alias<'a>(
+        &'a self,
+        alias: &'a [u8],
+        cid: &'a Cid,
+        visibility: Visibility,
+    ) -> StoreResult<'a, ()> {
+        Box::pin(self.retry(StoreOperation::Alias, move || {
+            self.store.alias(alias, cid, visibility)
+        }))
+    }
+
+    fn unalias<'a>(&'a self, alias: &'a [u8]) -> StoreResult<'a, ()> {
+        Box::pin(self.retry(StoreOperation::Alias, move || self.store.unalias(alias)))
+    }
+
+    fn resolve<'a>(&'a self, alias: &'a [u8]) -> StoreResult<'a, Option<Cid>> {
+        Box::pin(self.retry(StoreOperation::Alias, move || self.store.resolve(alias)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BlockBuilder, Codec, Encoder};
+    use libipld::ipld;
+    use libipld::mem::MemStore;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[derive(Clone, Default)]
+    struct FlakyStore {
+        store: MemStore,
+        failures: Arc<AtomicUsize>,
+    }
+
+    impl FlakyStore {
+        fn fail(&self) -> Option<StoreError> {
+            if self.failures.fetch_sub(1, Ordering::SeqCst) > 0 {
+                return Some(Transient::store_error("timeout"));
+            }
+            self.failures.store(0, Ordering::SeqCst);
+            None
+        }
+    }
+
+    impl ReadonlyStore for FlakyStore {
+        fn get<'a>(&'a self, cid: &'a Cid) -> StoreResult<'a, Box<[u8]>> {
+            match self.fail() {
+                Some(err) => Box::pin(async move { Err(err) }),
+                None => self.store.get(cid),
+            }
+        }
+    }
+
+    impl Store for FlakyStore {
+        fn insert<'a>(
+            &'a self,
+            cid: &'a Cid,
+            data: Box<[u8]>,
+            visibility: Visibility,
+        ) -> StoreResult<'a, ()> {
+            match self.fail() {
+                Some(err) => Box::pin(async move { Err(err) }),
+                None => self.store.insert(cid, data, visibility),
+            }
+        }
+
+        fn insert_batch<'a>(
+            &'a self,
+            batch: Vec<Block>,
+            visibility: Visibility,
+        ) -> StoreResult<'a, Cid> {
+            self.store.insert_batch(batch, visibility)
+        }
+
+        fn flush(&self) -> StoreResult<'_, ()> {
+            self.store.flush()
+        }
+
+        fn unpin<'a>(&'a self, cid: &'a Cid) -> StoreResult<'a, ()> {
+            self.store.unpin(cid)
+        }
+    }
+
+    #[test]
+    fn test_is_transient() {
+        let timeout = std::io::Error::from(ErrorKind::TimedOut);
+        assert!(is_transient(&StoreError::Other(Box::new(timeout))));
+        assert!(is_transient(&Transient::store_error("503")));
+        let denied = std::io::Error::from(ErrorKind::PermissionDenied);
+        assert!(!is_transient(&StoreError::Other(Box::new(denied))));
+        let invalid = crate::Error::InvalidRecord("bad hash".into());
+        assert!(!is_transient(&StoreError::Other(Box::new(invalid))));
+        assert!(!is_transient(&StoreError::EmptyBatch));
+    }
+
+    #[cfg_attr(not(feature = "tokio"), async_std::test)]
+    #[cfg_attr(feature = "tokio", tokio::test)]
+    async fn test_retry_store() {
+        let flaky = FlakyStore::default();
+        let builder = BlockBuilder::new(flaky.store.clone(), Codec::new());
+        let cid = builder.insert(&ipld!({"a": 3})).await.unwrap();
+
+        let policy = RetryPolicy {
+            initial_backoff: Duration::from_millis(1),
+            ..Default::default()
+        };
+        let mut store = RetryStore::new(flaky.clone(), policy);
+        let builder = BlockBuilder::new(store.clone(), Codec::new());
+        flaky.failures.store(3, Ordering::SeqCst);
+        assert!(builder.get_ipld(&cid).await.is_ok());
+
+        store.set_policy(StoreOperation::Get, RetryPolicy::never());
+        let builder = BlockBuilder::new(store.clone(), Codec::new());
+        flaky.failures.store(1, Ordering::SeqCst);
+        assert!(builder.get_ipld(&cid).await.is_err());
+        assert!(builder.get_ipld(&cid).await.is_ok());
+
+        // inserts are not retried unless declared idempotent
+        let block = Codec::new().encode(&ipld!("block")).unwrap();
+        flaky.failures.store(1, Ordering::SeqCst);
+        let res = store.insert(&block.cid, block.data.clone(), Visibility::Public);
+        assert!(res.await.is_err());
+        store.set_policy(StoreOperation::Insert, policy);
+        flaky.failures.store(1, Ordering::SeqCst);
+        let res = store.insert(&block.cid, block.data, Visibility::Public);
+        assert!(res.await.is_ok());
+    }
+}
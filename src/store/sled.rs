@@ -0,0 +1,240 @@
+use ::sled::transaction::{ConflictableTransactionError, TransactionError, TransactionalTree};
+use ::sled::{Db, Transactional, Tree};
+use libipld::block::Block;
+use libipld::cid::Cid;
+use libipld::error::StoreError;
+use libipld::store::{AliasStore, MultiUserStore, ReadonlyStore, Store, StoreResult, Visibility};
+use std::convert::TryFrom;
+use std::path::Path;
+
+fn other<E: std::error::Error + Send + 'static>(err: E) -> StoreError {
+    StoreError::Other(Box::new(err))
+}
+
+fn tx_error(err: TransactionError) -> StoreError {
+    match err {
+        TransactionError::Abort(err) | TransactionError::Storage(err) => other(err),
+    }
+}
+
+fn decode_pins(pins: Option<&[u8]>) -> Result<u64, ::sled::Error> {
+    match pins {
+        Some(pins) => <[u8; 8]>::try_from(pins)
+            .map(u64::from_be_bytes)
+            .map_err(|_| ::sled::Error::Unsupported(format!("invalid pin count {:?}", pins))),
+        None => Ok(0),
+    }
+}
+
+fn decode_cid(cid: &[u8]) -> Result<Cid, StoreError> {
+    Cid::try_from(cid).map_err(other)
+}
+
+fn pin(pins: &TransactionalTree, key: &[u8]) -> Result<(), ConflictableTransactionError> {
+    let n = decode_pins(pins.get(key)?.as_deref())?;
+    pins.insert(key, &(n + 1).to_be_bytes())?;
+    Ok(())
+}
+
+fn unpin(pins: &TransactionalTree, key: &[u8]) -> Result<(), ConflictableTransactionError> {
+    match decode_pins(pins.get(key)?.as_deref())? {
+        0 => {}
+        1 => {
+            pins.remove(key)?;
+        }
+        n => {
+            pins.insert(key, &(n - 1).to_be_bytes())?;
+        }
+    }
+    Ok(())
+}
+
+/// A sled backed store.
+///
+/// Blocks, pin counts, named pins and aliases are kept in separate trees of
+/// the same database. Inserting a batch is a single transaction.
+#[derive(Clone)]
+pub struct SledStore {
+    db: Db,
+    blocks: Tree,
+    pins: Tree,
+    paths: Tree,
+    aliases: Tree,
+}
+
+impl SledStore {
+    /// Creates a store from a sled database.
+    pub fn new(db: Db) -> Result<Self, StoreError> {
+        Ok(Self {
+            blocks: db.open_tree("blocks").map_err(other)?,
+            pins: db.open_tree("pins").map_err(other)?,
+            paths: db.open_tree("paths").map_err(other)?,
+            aliases: db.open_tree("aliases").map_err(other)?,
+            db,
+        })
+    }
+
+    /// Opens a sled database at `path` and creates a store from it.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, StoreError> {
+        Self::new(::sled::open(path).map_err(other)?)
+    }
+
+    /// Returns the number of pins on a block.
+    pub fn pins(&self, cid: &Cid) -> Result<u64, StoreError> {
+        let pins = self.pins.get(cid.to_bytes()).map_err(other)?;
+        decode_pins(pins.as_deref()).map_err(other)
+    }
+}
+
+impl ReadonlyStore for SledStore {
+    fn get<'a>(&'a self, cid: &'a Cid) -> StoreResult<'a, Box<[u8]>> {
+        Box::pin(async move {
+            match self.blocks.get(cid.to_bytes()).map_err(other)? {
+                Some(data) => Ok(data.to_vec().into_boxed_slice()),
+                None => Err(StoreError::BlockNotFound(cid.clone())),
+            }
+        })
+    }
+}
+
+impl Store for SledStore {
+    fn insert<'a>(
+        &'a self,
+        cid: &'a Cid,
+        data: Box<[u8]>,
+        _visibility: Visibility,
+    ) -> StoreResult<'a, ()> {
+        Box::pin(async move {
+            let key = cid.to_bytes();
+            (&self.blocks, &self.pins)
+                .transaction(|(blocks, pins)| {
+                    blocks.insert(key.as_slice(), &*data)?;
+                    pin(pins, &key)
+                })
+                .map_err(tx_error)
+        })
+    }
+
+    fn insert_batch<'a>(
+        &'a self,
+        batch: Vec<Block>,
+        _visibility: Visibility,
+    ) -> StoreResult<'a, Cid> {
+        Box::pin(async move {
+            let last = batch.last().ok_or(StoreError::EmptyBatch)?.cid.clone();
+            let key = last.to_bytes();
+            (&self.blocks, &self.pins)
+                .transaction(|(blocks, pins)| {
+                    for block in &batch {
+                        blocks.insert(block.cid.to_bytes(), &*block.data)?;
+                    }
+                    pin(pins, &key)
+                })
+                .map_err(tx_error)?;
+            Ok(last)
+        })
+    }
+
+    fn flush(&self) -> StoreResult<'_, ()> {
+        Box::pin(async move {
+            self.db.flush_async().await.map_err(other)?;
+            Ok(())
+        })
+    }
+
+    fn unpin<'a>(&'a self, cid: &'a Cid) -> StoreResult<'a, ()> {
+        Box::pin(async move {
+            let key = cid.to_bytes();
+            self.pins
+                .transaction(|pins| unpin(pins, &key))
+                .map_err(tx_error)
+        })
+    }
+}
+
+impl MultiUserStore for SledStore {
+    fn pin<'a>(&'a self, cid: &'a Cid, path: &'a Path) -> StoreResult<'a, ()> {
+        Box::pin(async move {
+            let key = cid.to_bytes();
+            let path = path.to_string_lossy();
+            (&self.pins, &self.paths)
+                .transaction(|(pins, paths)| {
+                    if let Some(old) = paths.insert(path.as_bytes(), key.as_slice())? {
+                        unpin(pins, &old)?;
+                    }
+                    pin(pins, &key)
+                })
+                .map_err(tx_error)
+        })
+    }
+}
+
+impl AliasStore for SledStore {
+    fn alias<'a>(
+        &'a self,
+        alias: &'a [u8],
+        cid: &'a Cid,
+        _visibility: Visibility,
+    ) -> StoreResult<'a, ()> {
+        Box::pin(async move {
+            self.aliases.insert(alias, cid.to_bytes()).map_err(other)?;
+            Ok(())
+        })
+    }
+
+    fn unalias<'a>(&'a self, alias: &'a [u8]) -> StoreResult<'a, ()> {
+        Box::pin(async move {
+            self.aliases.remove(alias).map_err(other)?;
+            Ok(())
+        })
+    }
+
+    fn resolve<'a>(&'a self, alias: &'a [u8]) -> StoreResult<'a, Option<Cid>> {
+        Box::pin(async move {
+            match self.aliases.get(alias).map_err(other)? {
+                Some(cid) => Ok(Some(decode_cid(&cid)?)),
+                None => Ok(None),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BlockBuilder, Codec};
+    use libipld::ipld;
+    use libipld::ipld::Ipld;
+
+    #[async_std::test]
+    async fn test_sled_store() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = SledStore::open(dir.path()).unwrap();
+        let builder = BlockBuilder::new(store.clone(), Codec::new());
+        let a = builder.insert(&ipld!({"a": 3})).await.unwrap();
+        let b = builder.insert(&ipld!({"b": &a})).await.unwrap();
+        builder.pin(&a, Path::new("user/a")).await.unwrap();
+        assert_eq!(store.pins(&a).unwrap(), 2);
+        builder.pin(&b, Path::new("user/a")).await.unwrap();
+        assert_eq!(store.pins(&a).unwrap(), 1);
+        assert_eq!(store.pins(&b).unwrap(), 2);
+        builder.alias(b"root", &b).await.unwrap();
+        builder.flush().await.unwrap();
+        assert_eq!(builder.resolve(b"root").await.unwrap(), Some(b.clone()));
+        let ipld: Ipld = builder.get(&a).await.unwrap();
+        assert_eq!(ipld, ipld!({"a": 3}));
+        builder.unpin(&a).await.unwrap();
+        assert_eq!(store.pins(&a).unwrap(), 0);
+    }
+
+    #[async_std::test]
+    async fn test_sled_invalid_pins() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = SledStore::open(dir.path()).unwrap();
+        let builder = BlockBuilder::new(store.clone(), Codec::new());
+        let a = builder.insert(&ipld!({"a": 3})).await.unwrap();
+        store.pins.insert(a.to_bytes(), &[1, 2, 3]).unwrap();
+        assert!(store.pins(&a).is_err());
+        assert!(builder.unpin(&a).await.is_err());
+    }
+}
@@ -0,0 +1,246 @@
+//! Blocking wrappers around the async api.
+//!
+//! The async operations are driven to completion on the calling thread,
+//! so they must not be used from within an async context.
+use crate::batch::Batch;
+use crate::builder::BlockBuilder;
+use crate::cache::{Cache, CacheBatch, IpldCache, ReadonlyCache};
+use crate::codec::{Decoder, Encoder, IpldDecoder};
+use crate::error::Result;
+use crate::path::{DagPath, IpldPath, IpldView, PathStats};
+use crate::rt::block_on;
+use libipld::cid::Cid;
+use libipld::codec::{Decode, Encode};
+use libipld::ipld::Ipld;
+use libipld::store::{AliasStore, MultiUserStore, ReadonlyStore, Store};
+use std::path::Path;
+
+/// Blocking block builder.
+pub struct SyncBlockBuilder<S, C> {
+    builder: BlockBuilder<S, C>,
+}
+
+impl<S, C> SyncBlockBuilder<S, C> {
+    /// Creates a new blocking block builder.
+    pub fn new(store: S, codec: C) -> Self {
+        BlockBuilder::new(store, codec).into()
+    }
+
+    /// Returns the wrapped async block builder.
+    pub fn builder(&self) -> &BlockBuilder<S, C> {
+        &self.builder
+    }
+
+    /// Returns the wrapped async block builder.
+    pub fn into_inner(self) -> BlockBuilder<S, C> {
+        self.builder
+    }
+}
+
+impl<S, C> From<BlockBuilder<S, C>> for SyncBlockBuilder<S, C> {
+    fn from(builder: BlockBuilder<S, C>) -> Self {
+        Self { builder }
+    }
+}
+
+impl<S: ReadonlyStore, C: Decoder> SyncBlockBuilder<S, C> {
+    /// Returns the decoded block with cid.
+    pub fn get<D: Decode<C::Codec>>(&self, cid: &Cid) -> Result<D> {
+        block_on(self.builder.get(cid))
+    }
+}
+
+impl<S: ReadonlyStore, C: IpldDecoder> SyncBlockBuilder<S, C> {
+    /// Returns the ipld representation of a block with cid.
+    pub fn get_ipld(&self, cid: &Cid) -> Result<Ipld> {
+        block_on(self.builder.get_ipld(cid))
+    }
+
+    /// Resolves a path recursively and returns the ipld.
+    pub fn get_path(&self, path: &DagPath<'_>) -> Result<Ipld> {
+        block_on(self.builder.get_path(path))
+    }
+
+    /// Resolves a path recursively and returns a view into its last block.
+    pub fn get_path_view(&self, path: &DagPath<'_>) -> Result<IpldView> {
+        block_on(self.builder.get_path_view(path))
+    }
+
+    /// Resolves a path recursively and returns the ipld with statistics.
+    pub fn get_path_with_stats(&self, path: &DagPath<'_>) -> Result<(Ipld, PathStats)> {
+        block_on(self.builder.get_path_with_stats(path))
+    }
+
+    /// Resolves a path to its last block and the remainder inside it.
+    pub fn resolve_path(&self, path: &DagPath<'_>) -> Result<(Cid, IpldPath)> {
+        block_on(self.builder.resolve_path(path))
+    }
+}
+
+impl<S: Store, C: Encoder + Clone> SyncBlockBuilder<S, C> {
+    /// Creates a new batch.
+    pub fn create_batch(&self) -> Batch<C> {
+        self.builder.create_batch()
+    }
+
+    /// Creates a new batch with capacity.
+    pub fn create_batch_with_capacity(&self, capacity: usize) -> Batch<C> {
+        self.builder.create_batch_with_capacity(capacity)
+    }
+
+    /// Encodes and inserts a block into the store.
+    pub fn insert<E: Encode<C::Codec>>(&self, e: &E) -> Result<Cid> {
+        block_on(self.builder.insert(e))
+    }
+
+    /// Inserts a batch of blocks atomically pinning the last one.
+    pub fn insert_batch<T>(&self, batch: Batch<T>) -> Result<Cid> {
+        block_on(self.builder.insert_batch(batch))
+    }
+}
+
+impl<S: Store, C> SyncBlockBuilder<S, C> {
+    /// Flushes the store to disk.
+    pub fn flush(&self) -> Result<()> {
+        block_on(self.builder.flush())
+    }
+
+    /// Unpins a block from the store marking it ready for garbage collection.
+    pub fn unpin(&self, cid: &Cid) -> Result<()> {
+        block_on(self.builder.unpin(cid))
+    }
+}
+
+impl<S: MultiUserStore, C> SyncBlockBuilder<S, C> {
+    /// Pins a block in the store.
+    pub fn pin(&self, cid: &Cid, path: &Path) -> Result<()> {
+        block_on(self.builder.pin(cid, path))
+    }
+}
+
+impl<S: AliasStore, C> SyncBlockBuilder<S, C> {
+    /// Creates an alias for a cid.
+    pub fn alias(&self, alias: &[u8], cid: &Cid) -> Result<()> {
+        block_on(self.builder.alias(alias, cid))
+    }
+
+    /// Removes an alias.
+    pub fn unalias(&self, alias: &[u8]) -> Result<()> {
+        block_on(self.builder.unalias(alias))
+    }
+
+    /// Resolves an alias.
+    pub fn resolve(&self, alias: &[u8]) -> Result<Option<Cid>> {
+        block_on(self.builder.resolve(alias))
+    }
+}
+
+/// Blocking cache for ipld blocks.
+pub struct SyncIpldCache<S, C, T> {
+    cache: IpldCache<S, C, T>,
+}
+
+impl<S, C, T> SyncIpldCache<S, C, T> {
+    /// Creates a new cache of size `size`.
+    pub fn new(store: S, codec: C, size: usize) -> Self {
+        IpldCache::new(store, codec, size).into()
+    }
+
+    /// Returns the wrapped async cache.
+    pub fn cache(&self) -> &IpldCache<S, C, T> {
+        &self.cache
+    }
+
+    /// Returns the wrapped async cache.
+    pub fn into_inner(self) -> IpldCache<S, C, T> {
+        self.cache
+    }
+}
+
+impl<S, C, T> From<IpldCache<S, C, T>> for SyncIpldCache<S, C, T> {
+    fn from(cache: IpldCache<S, C, T>) -> Self {
+        Self { cache }
+    }
+}
+
+impl<S: ReadonlyStore + Send + Sync, C, T> SyncIpldCache<S, C, T>
+where
+    C: Decoder + Clone + Send + Sync,
+    T: Decode<<C as Decoder>::Codec> + Clone + Send + Sync + 'static,
+{
+    /// Returns a decoded block.
+    pub fn get(&self, cid: &Cid) -> Result<T> {
+        block_on(ReadonlyCache::get(&self.cache, cid))
+    }
+}
+
+impl<S: Store + Send + Sync, C, T> SyncIpldCache<S, C, T>
+where
+    C: Decoder + Encoder + Clone + Send + Sync,
+    T: Decode<<C as Decoder>::Codec>
+        + Encode<<C as Encoder>::Codec>
+        + Clone
+        + Send
+        + Sync
+        + 'static,
+{
+    /// Creates a typed batch.
+    pub fn create_batch(&self) -> CacheBatch<C, T> {
+        self.cache.create_batch()
+    }
+
+    /// Creates a typed batch.
+    pub fn create_batch_with_capacity(&self, capacity: usize) -> CacheBatch<C, T> {
+        self.cache.create_batch_with_capacity(capacity)
+    }
+
+    /// Inserts a batch into the store.
+    pub fn insert_batch(&self, batch: CacheBatch<C, T>) -> Result<Cid> {
+        block_on(self.cache.insert_batch(batch))
+    }
+
+    /// Encodes and inserts a block.
+    pub fn insert(&self, value: T) -> Result<Cid> {
+        block_on(self.cache.insert(value))
+    }
+
+    /// Flushes all buffers.
+    pub fn flush(&self) -> Result<()> {
+        block_on(self.cache.flush())
+    }
+
+    /// Unpins a block.
+    pub fn unpin(&self, cid: &Cid) -> Result<()> {
+        block_on(self.cache.unpin(cid))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Codec;
+    use libipld::ipld;
+    use libipld::mem::MemStore;
+
+    #[test]
+    fn test_sync_block_builder() {
+        let builder = SyncBlockBuilder::new(MemStore::default(), Codec::new());
+        let cid = builder.insert(&ipld!({"a": 3})).unwrap();
+        builder.alias(b"root", &cid).unwrap();
+        assert_eq!(builder.resolve(b"root").unwrap(), Some(cid.clone()));
+        assert_eq!(builder.get_ipld(&cid).unwrap(), ipld!({"a": 3}));
+        builder.unpin(&cid).unwrap();
+    }
+
+    #[test]
+    fn test_sync_ipld_cache() {
+        let cache = SyncIpldCache::new(MemStore::default(), Codec::new(), 1);
+        let cid = cache.insert(42u64).unwrap();
+        assert_eq!(cache.get(&cid).unwrap(), 42);
+        let mut batch = cache.create_batch();
+        batch.insert(1).unwrap();
+        let cid = batch.insert(2).unwrap().clone();
+        assert_eq!(cache.insert_batch(batch).unwrap(), cid);
+        assert_eq!(cache.get(&cid).unwrap(), 2);
+    }
+}
@@ -0,0 +1,259 @@
+use crate::builder::BlockBuilder;
+use crate::cache::IpldCache;
+use crate::crypto::Key;
+use crate::error::{Error, Result};
+use crate::StrobeCodec;
+use libipld::block::Block;
+use libipld::cid::Cid;
+use libipld::store::{AliasStore, ReadonlyStore, Store, StoreResult, Visibility};
+use std::collections::HashMap;
+
+/// A store scoping the aliases of a tenant to its namespace prefix.
+///
+/// Blocks are shared with the other tenants of the store, and so are their
+/// pins: unpinning a cid releases a pin of whichever tenant inserted it.
+/// Tenants can't read each other's blocks, but one that learns the cid of
+/// another tenant's block can unpin it.
+#[derive(Clone)]
+pub struct TenantStore<S> {
+    store: S,
+    prefix: Vec<u8>,
+}
+
+impl<S> TenantStore<S> {
+    /// Returns the shared store.
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    /// Returns the alias prefix of the tenant.
+    pub fn prefix(&self) -> &[u8] {
+        &self.prefix
+    }
+
+    fn scoped(&self, alias: &[u8]) -> Vec<u8> {
+        let mut scoped = self.prefix.clone();
+        scoped.extend_from_slice(alias);
+        scoped
+    }
+}
+
+impl<S: ReadonlyStore> ReadonlyStore for TenantStore<S> {
+    fn get<'a>(&'a self, cid: &'a Cid) -> StoreResult<'a, Box<[u8]>> {
+        self.store.get(cid)
+    }
+}
+
+impl<S: Store> Store for TenantStore<S> {
+    fn insert<'a>(
+        &'a self,
+        cid: &'a Cid,
+        data: Box<[u8]>,
+        visibility: Visibility,
+    ) -> StoreResult<'a, ()> {
+        self.store.insert(cid, data, visibility)
+    }
+
+    fn insert_batch<'a>(
+        &'a self,
+        batch: Vec<Block>,
+        visibility: Visibility,
+    ) -> StoreResult<'a, Cid> {
+        self.store.insert_batch(batch, visibility)
+    }
+
+    fn flush(&self) -> StoreResult<'_, ()> {
+        self.store.flush()
+    }
+
+    fn unpin<'a>(&'a self, cid: &'a Cid) -> StoreResult<'a, ()> {
+        self.store.unpin(cid)
+    }
+}
+
+impl<S: AliasStore + Send + Sync> AliasStore for TenantStore<S> {
+    fn alias<'a>(
+        &'a self,
+        alias: &'a [u8],
+        cid: &'a Cid,
+        visibility: Visibility,
+    ) -> StoreResult<'a, ()> {
+        Box::pin(async move {
+            let alias = self.scoped(alias);
+            self.store.alias(&alias, cid, visibility).await
+        })
+    }
+
+    fn unalias<'a>(&'a self, alias: &'a [u8]) -> StoreResult<'a, ()> {
+        Box::pin(async move { self.store.unalias(&self.scoped(alias)).await })
+    }
+
+    fn resolve<'a>(&'a self, alias: &'a [u8]) -> StoreResult<'a, Option<Cid>> {
+        Box::pin(async move { self.store.resolve(&self.scoped(alias)).await })
+    }
+}
+
+struct Tenant {
+    codec: StrobeCodec,
+    visibility: Visibility,
+    prefix: Vec<u8>,
+}
+
+/// Routes tenants to builders and caches over one shared store.
+///
+/// Every tenant encrypts its blocks with its own key, inserts them with its
+/// own visibility and keeps its aliases under its own prefix.
+pub struct TenantRouter<S> {
+    store: S,
+    tenants: HashMap<String, Tenant>,
+}
+
+impl<S: Clone> TenantRouter<S> {
+    /// Creates a router without tenants.
+    pub fn new(store: S) -> Self {
+        Self {
+            store,
+            tenants: Default::default(),
+        }
+    }
+
+    /// Returns the shared store.
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    /// Adds or replaces the tenant `id`.
+    ///
+    /// Fails if `prefix` is a prefix of the prefix of another tenant or the
+    /// other way around, as the tenants could then resolve each other's
+    /// aliases.
+    pub fn add_tenant(
+        &mut self,
+        id: &str,
+        key: Key,
+        visibility: Visibility,
+        prefix: &str,
+    ) -> Result<()> {
+        let overlapping = self.tenants.iter().find(|(other, tenant)| {
+            other.as_str() != id
+                && (tenant.prefix.starts_with(prefix.as_bytes())
+                    || prefix.as_bytes().starts_with(&tenant.prefix))
+        });
+        if let Some((other, _)) = overlapping {
+            return Err(Error::OverlappingTenant(id.to_string(), other.clone()));
+        }
+        let tenant = Tenant {
+            codec: StrobeCodec::new(key),
+            visibility,
+            prefix: prefix.as_bytes().to_vec(),
+        };
+        self.tenants.insert(id.to_string(), tenant);
+        Ok(())
+    }
+
+    /// Removes the tenant `id`, returning if it existed.
+    ///
+    /// The blocks and aliases of the tenant are kept in the store.
+    pub fn remove_tenant(&mut self, id: &str) -> bool {
+        self.tenants.remove(id).is_some()
+    }
+
+    /// Returns the ids of the tenants.
+    pub fn tenants(&self) -> impl Iterator<Item = &str> {
+        self.tenants.keys().map(|id| id.as_str())
+    }
+
+    fn tenant(&self, id: &str) -> Result<&Tenant> {
+        self.tenants
+            .get(id)
+            .ok_or_else(|| Error::UnknownTenant(id.to_string()))
+    }
+
+    /// Returns a builder scoped to the tenant `id`.
+    pub fn builder(&self, id: &str) -> Result<BlockBuilder<TenantStore<S>, StrobeCodec>> {
+        let tenant = self.tenant(id)?;
+        let store = TenantStore {
+            store: self.store.clone(),
+            prefix: tenant.prefix.clone(),
+        };
+        let codec = tenant.codec.clone();
+        Ok(match tenant.visibility {
+            Visibility::Public => BlockBuilder::new(store, codec),
+            Visibility::Private => BlockBuilder::new_private(store, codec),
+        })
+    }
+
+    /// Returns a cache of size `size` scoped to the tenant `id`.
+    pub fn cache<T>(
+        &self,
+        id: &str,
+        size: usize,
+    ) -> Result<IpldCache<TenantStore<S>, StrobeCodec, T>>
+    where
+        T: Clone,
+    {
+        Ok(IpldCache::with_builder(self.builder(id)?, size))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::ReadonlyCache;
+    use libipld::ipld;
+    use libipld::ipld::Ipld;
+    use libipld::mem::MemStore;
+
+    #[async_std::test]
+    async fn test_tenant_router() {
+        let store = MemStore::default();
+        let mut router = TenantRouter::new(store.clone());
+        router
+            .add_tenant("a", Key::generate(), Visibility::Private, "tenant/a/")
+            .unwrap();
+        router
+            .add_tenant("b", Key::generate(), Visibility::Public, "tenant/b/")
+            .unwrap();
+        assert!(router.builder("c").is_err());
+
+        let a = router.builder("a").unwrap();
+        let b = router.builder("b").unwrap();
+        assert_eq!(a.visibility(), Visibility::Private);
+        assert_eq!(b.visibility(), Visibility::Public);
+
+        let value = ipld!({ "tenant": "a" });
+        let cid = a.insert(&value).await.unwrap();
+        a.alias(b"head", &cid).await.unwrap();
+        assert_eq!(a.resolve(b"head").await.unwrap(), Some(cid.clone()));
+        assert_eq!(b.resolve(b"head").await.unwrap(), None);
+        assert_eq!(
+            store.resolve(b"tenant/a/head").await.unwrap(),
+            Some(cid.clone())
+        );
+        // blocks of other tenants can't be decrypted
+        assert!(b.get_ipld(&cid).await.is_err());
+
+        let cache = router.cache::<Ipld>("a", 4).unwrap();
+        assert_eq!(cache.get(&cid).await.unwrap(), value);
+    }
+
+    #[test]
+    fn test_overlapping_prefix() {
+        let mut router = TenantRouter::new(MemStore::default());
+        router
+            .add_tenant("a", Key::generate(), Visibility::Private, "t/")
+            .unwrap();
+        for prefix in &["t/x", "t/", ""] {
+            let res = router.add_tenant("b", Key::generate(), Visibility::Private, prefix);
+            assert!(matches!(res, Err(Error::OverlappingTenant(_, _))));
+        }
+        router
+            .add_tenant("b", Key::generate(), Visibility::Private, "u/")
+            .unwrap();
+        // replacing a tenant only checks the other tenants
+        router
+            .add_tenant("a", Key::generate(), Visibility::Private, "t/x/")
+            .unwrap();
+        assert_eq!(router.tenants().count(), 2);
+    }
+}
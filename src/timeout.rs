@@ -0,0 +1,40 @@
+use crate::error::{Error, Result};
+use futures::future::{self, Either};
+use std::future::Future;
+use std::time::Duration;
+
+/// Per-operation timeouts of a `BlockBuilder`.
+///
+/// Operations without a timeout wait for the store indefinitely.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Timeouts {
+    /// Maximum time to fetch a single block from the store.
+    pub get: Option<Duration>,
+    /// Maximum time to resolve a path with `get_path` or `resolve_path`.
+    pub get_path: Option<Duration>,
+    /// Maximum time to insert a batch, including waiting for the memory
+    /// budget and the write-ahead log.
+    pub insert_batch: Option<Duration>,
+}
+
+/// Runs `future`, failing with `Error::Timeout` if it does not complete
+/// within `duration`.
+pub(crate) async fn timeout<T, F>(
+    op: &'static str,
+    duration: Option<Duration>,
+    future: F,
+) -> Result<T>
+where
+    F: Future<Output = Result<T>>,
+{
+    let duration = match duration {
+        Some(duration) => duration,
+        None => return future.await,
+    };
+    let sleep = crate::rt::sleep(duration);
+    futures::pin_mut!(future, sleep);
+    match future::select(future, sleep).await {
+        Either::Left((res, _)) => res,
+        Either::Right(_) => Err(Error::Timeout(op, duration)),
+    }
+}
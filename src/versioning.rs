@@ -0,0 +1,376 @@
+use crate::builder::BlockBuilder;
+use crate::codec::{Decoder, Encoder, IpldDecoder};
+use crate::error::{Error, Result};
+use crate::rt::{SystemTime, UNIX_EPOCH};
+use futures::lock::Mutex;
+use libipld::cid::Cid;
+use libipld::codec::{Decode, Encode};
+use libipld::ipld::Ipld;
+use libipld::store::{AliasStore, Store};
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// Commit node linking a value to its history.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Commit {
+    /// Cid of the committed value.
+    pub value: Cid,
+    /// Cids of the parent commits.
+    pub parents: Vec<Cid>,
+    /// Seconds since the unix epoch.
+    pub timestamp: u64,
+    /// Commit message.
+    pub message: String,
+}
+
+impl Commit {
+    fn to_ipld(&self) -> Ipld {
+        let mut map = BTreeMap::new();
+        map.insert("value".to_string(), Ipld::Link(self.value.clone()));
+        map.insert(
+            "parents".to_string(),
+            Ipld::List(self.parents.iter().cloned().map(Ipld::Link).collect()),
+        );
+        map.insert(
+            "timestamp".to_string(),
+            Ipld::Integer(self.timestamp as i128),
+        );
+        map.insert("message".to_string(), Ipld::String(self.message.clone()));
+        Ipld::Map(map)
+    }
+
+    fn from_ipld(cid: &Cid, ipld: Ipld) -> Result<Self> {
+        let invalid = || Error::InvalidCommit(cid.clone());
+        let mut map = match ipld {
+            Ipld::Map(map) => map,
+            _ => return Err(invalid()),
+        };
+        let value = match map.remove("value") {
+            Some(Ipld::Link(value)) => value,
+            _ => return Err(invalid()),
+        };
+        let parents = match map.remove("parents") {
+            Some(Ipld::List(parents)) => parents
+                .into_iter()
+                .map(|parent| match parent {
+                    Ipld::Link(parent) => Ok(parent),
+                    _ => Err(invalid()),
+                })
+                .collect::<Result<_>>()?,
+            _ => return Err(invalid()),
+        };
+        let timestamp = match map.remove("timestamp") {
+            Some(Ipld::Integer(timestamp)) if timestamp >= 0 => timestamp as u64,
+            _ => return Err(invalid()),
+        };
+        let message = match map.remove("message") {
+            Some(Ipld::String(message)) => message,
+            _ => return Err(invalid()),
+        };
+        Ok(Self {
+            value,
+            parents,
+            timestamp,
+            message,
+        })
+    }
+}
+
+/// Policy deciding which commits of a history `retain` keeps.
+///
+/// A commit is kept if it matches any of the rules. The head is always
+/// kept.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct RetentionPolicy {
+    /// Keeps the last `keep_last` commits.
+    pub keep_last: Option<usize>,
+    /// Keeps the commits that were the head within `keep_within`.
+    pub keep_within: Option<Duration>,
+    /// Reports what would be removed without changing the history.
+    pub dry_run: bool,
+}
+
+/// Result of applying a `RetentionPolicy`.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct RetentionReport {
+    /// The head after applying the policy.
+    pub head: Option<Cid>,
+    /// Commits that are kept, newest first.
+    pub kept: Vec<Cid>,
+    /// Commits that are no longer referenced, newest first.
+    pub removed: Vec<Cid>,
+}
+
+/// Versioned value with a git like history.
+///
+/// The head commit is referenced by the alias `versioning/<name>` and is the
+/// only pinned commit, older commits and values are reachable through the
+/// parent links.
+pub struct History<S, C> {
+    builder: BlockBuilder<S, C>,
+    alias: Vec<u8>,
+    lock: Mutex<()>,
+}
+
+impl<S, C> History<S, C> {
+    /// Creates a history named `name`.
+    pub fn new(store: S, codec: C, name: &str) -> Self {
+        Self {
+            builder: BlockBuilder::new(store, codec),
+            alias: format!("versioning/{}", name).into_bytes(),
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// Returns the alias of the head commit.
+    pub fn alias(&self) -> &[u8] {
+        &self.alias
+    }
+
+    /// Returns the block builder.
+    pub fn builder(&self) -> &BlockBuilder<S, C> {
+        &self.builder
+    }
+}
+
+impl<S, C> History<S, C>
+where
+    S: Store + AliasStore,
+    C: Encoder + Decoder + IpldDecoder + Clone,
+    Ipld: Encode<<C as Encoder>::Codec>,
+{
+    /// Returns the head commit.
+    pub async fn head(&self) -> Result<Option<Cid>> {
+        self.builder.resolve(&self.alias).await
+    }
+
+    /// Returns a commit.
+    pub async fn commit_at(&self, cid: &Cid) -> Result<Commit> {
+        let ipld = self.builder.get_ipld(cid).await?;
+        Commit::from_ipld(cid, ipld)
+    }
+
+    /// Returns the value of a commit.
+    pub async fn value<T: Decode<<C as Decoder>::Codec>>(&self, cid: &Cid) -> Result<T> {
+        let commit = self.commit_at(cid).await?;
+        self.builder.get(&commit.value).await
+    }
+
+    /// Moves the head to `cid`, pinning the new and unpinning the old head.
+    async fn set_head(&self, cid: &Cid) -> Result<()> {
+        let old = self.head().await?;
+        self.builder.alias(&self.alias, cid).await?;
+        if let Some(old) = old {
+            self.builder.unpin(&old).await?;
+        }
+        Ok(())
+    }
+
+    async fn write<T: Encode<<C as Encoder>::Codec>>(
+        &self,
+        value: &T,
+        parents: Vec<Cid>,
+        message: &str,
+    ) -> Result<Cid> {
+        let mut batch = self.builder.create_batch();
+        let value = batch.insert(value)?.clone();
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|time| time.as_secs())
+            .unwrap_or_default();
+        let commit = Commit {
+            value,
+            parents,
+            timestamp,
+            message: message.to_string(),
+        };
+        batch.insert(&commit.to_ipld())?;
+        let cid = self.builder.insert_batch(batch).await?;
+        self.set_head(&cid).await?;
+        Ok(cid)
+    }
+
+    /// Applies a retention policy to the history.
+    pub async fn retain(&self, policy: &RetentionPolicy) -> Result<RetentionReport> {
+        self.retain_at(policy, SystemTime::now()).await
+    }
+
+    /// Applies a retention policy to the history at `now`.
+    ///
+    /// The kept commits are rewritten on top of the oldest kept commit, so
+    /// their cids change. Removed commits and values only they reference
+    /// are unpinned and left to the garbage collector of the store.
+    pub async fn retain_at(
+        &self,
+        policy: &RetentionPolicy,
+        now: SystemTime,
+    ) -> Result<RetentionReport> {
+        let _guard = self.lock.lock().await;
+        let mut log = self.log().await?;
+        let cutoff = policy.keep_within.map(|within| {
+            now.checked_sub(within)
+                .unwrap_or(UNIX_EPOCH)
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs()
+        });
+        // a commit was the head until its successor was committed
+        let mut until = None;
+        let keep = log
+            .iter()
+            .enumerate()
+            .take_while(|(i, (_, commit))| {
+                let keep = *i == 0
+                    || policy.keep_last.is_some_and(|n| *i < n)
+                    || matches!((cutoff, until), (Some(cutoff), Some(until)) if until >= cutoff);
+                until = Some(commit.timestamp);
+                keep
+            })
+            .count();
+        let removed = log.split_off(keep);
+        let mut report = RetentionReport {
+            head: log.first().map(|(cid, _)| cid.clone()),
+            kept: log.iter().map(|(cid, _)| cid.clone()).collect(),
+            removed: removed.into_iter().map(|(cid, _)| cid).collect(),
+        };
+        if policy.dry_run || report.removed.is_empty() {
+            return Ok(report);
+        }
+        let mut batch = self.builder.create_batch();
+        let mut parent = None;
+        report.kept.clear();
+        for (_, mut commit) in log.into_iter().rev() {
+            commit.parents = parent.into_iter().collect();
+            let cid = batch.insert(&commit.to_ipld())?.clone();
+            report.kept.insert(0, cid.clone());
+            parent = Some(cid);
+        }
+        let head = self.builder.insert_batch(batch).await?;
+        self.set_head(&head).await?;
+        report.head = Some(head);
+        Ok(report)
+    }
+
+    /// Commits a value on top of `parent` and makes it the new head.
+    pub async fn commit<T: Encode<<C as Encoder>::Codec>>(
+        &self,
+        value: &T,
+        parent: Option<&Cid>,
+        message: &str,
+    ) -> Result<Cid> {
+        let _guard = self.lock.lock().await;
+        self.write(value, parent.into_iter().cloned().collect(), message)
+            .await
+    }
+
+    /// Returns the commits from the head following the first parent.
+    pub async fn log(&self) -> Result<Vec<(Cid, Commit)>> {
+        let mut log = vec![];
+        let mut next = self.head().await?;
+        while let Some(cid) = next {
+            let commit = self.commit_at(&cid).await?;
+            next = commit.parents.first().cloned();
+            log.push((cid, commit));
+        }
+        Ok(log)
+    }
+
+    /// Moves the head to a previous commit.
+    pub async fn checkout(&self, cid: &Cid) -> Result<Commit> {
+        let _guard = self.lock.lock().await;
+        let commit = self.commit_at(cid).await?;
+        if self.head().await?.as_ref() == Some(cid) {
+            return Ok(commit);
+        }
+        self.builder.insert(&commit.to_ipld()).await?;
+        self.set_head(cid).await?;
+        Ok(commit)
+    }
+
+    /// Commits the value of a previous commit on top of the head.
+    pub async fn revert(&self, cid: &Cid, message: &str) -> Result<Cid> {
+        let _guard = self.lock.lock().await;
+        let value = self
+            .builder
+            .get_ipld(&self.commit_at(cid).await?.value)
+            .await?;
+        let parents = self.head().await?.into_iter().collect();
+        self.write(&value, parents, message).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Codec;
+    use libipld::mem::MemStore;
+
+    #[async_std::test]
+    async fn test_history() {
+        let history = History::new(MemStore::default(), Codec::new(), "counter");
+        assert_eq!(history.head().await.unwrap(), None);
+        let v1 = history.commit(&1u64, None, "one").await.unwrap();
+        let v2 = history.commit(&2u64, Some(&v1), "two").await.unwrap();
+        assert_eq!(history.head().await.unwrap(), Some(v2.clone()));
+
+        let log = history.log().await.unwrap();
+        let messages: Vec<_> = log.iter().map(|(_, c)| c.message.as_str()).collect();
+        assert_eq!(messages, vec!["two", "one"]);
+        assert_eq!(log[0].1.parents, vec![v1.clone()]);
+
+        let v3 = history.revert(&v1, "revert").await.unwrap();
+        assert_eq!(history.value::<u64>(&v3).await.unwrap(), 1);
+        assert_eq!(history.log().await.unwrap().len(), 3);
+
+        let commit = history.checkout(&v2).await.unwrap();
+        assert_eq!(commit.message, "two");
+        assert_eq!(history.head().await.unwrap(), Some(v2.clone()));
+        assert_eq!(history.value::<u64>(&v2).await.unwrap(), 2);
+
+        let value = history.commit_at(&v2).await.unwrap().value;
+        assert!(history.commit_at(&value).await.is_err());
+    }
+
+    #[async_std::test]
+    async fn test_retention() {
+        let history = History::new(MemStore::default(), Codec::new(), "counter");
+        let mut head = None;
+        for i in 0..4u64 {
+            let message = i.to_string();
+            head = Some(history.commit(&i, head.as_ref(), &message).await.unwrap());
+        }
+        let policy = RetentionPolicy {
+            keep_last: Some(2),
+            dry_run: true,
+            ..Default::default()
+        };
+        let report = history.retain(&policy).await.unwrap();
+        assert_eq!((report.kept.len(), report.removed.len()), (2, 2));
+        assert_eq!(report.head, head);
+        assert_eq!(history.log().await.unwrap().len(), 4);
+
+        let policy = RetentionPolicy {
+            dry_run: false,
+            ..policy
+        };
+        let report = history.retain(&policy).await.unwrap();
+        assert_eq!(history.head().await.unwrap(), report.head);
+        let log = history.log().await.unwrap();
+        let messages: Vec<_> = log.iter().map(|(_, c)| c.message.as_str()).collect();
+        assert_eq!(messages, vec!["3", "2"]);
+        let head = report.head.unwrap();
+        assert_eq!(history.value::<u64>(&head).await.unwrap(), 3);
+
+        // every commit was the head within the last hour
+        let policy = RetentionPolicy {
+            keep_within: Some(Duration::from_secs(3600)),
+            ..Default::default()
+        };
+        let report = history.retain(&policy).await.unwrap();
+        assert_eq!((report.kept.len(), report.removed.len()), (2, 0));
+        let later = SystemTime::now() + Duration::from_secs(7200);
+        let report = history.retain_at(&policy, later).await.unwrap();
+        assert_eq!((report.kept.len(), report.removed.len()), (1, 1));
+        assert_eq!(history.log().await.unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,203 @@
+use crate::error::{verify, Result};
+use crate::rt::unblock;
+use futures::lock::{Mutex, MutexGuard};
+use libipld::block::Block;
+use libipld::cid::Cid;
+use std::convert::TryFrom;
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+struct Inner {
+    path: PathBuf,
+    lock: Mutex<()>,
+    /// Serializes file writes, which keep running when their future is
+    /// dropped.
+    file: std::sync::Mutex<()>,
+}
+
+/// Write-ahead log of inserted batches.
+///
+/// The encoded blocks of a batch are appended to the log before the batch is
+/// inserted into the store, and the log is truncated after the store was
+/// flushed successfully. Batches left in the log after a crash are replayed
+/// with `BlockBuilder::recover`.
+#[derive(Clone)]
+pub struct Wal {
+    inner: Arc<Inner>,
+}
+
+fn put_u32(buf: &mut Vec<u8>, n: usize) {
+    buf.extend_from_slice(&(n as u32).to_be_bytes());
+}
+
+fn take_u32(buf: &mut &[u8]) -> Option<usize> {
+    if buf.len() < 4 {
+        return None;
+    }
+    let (n, rest) = buf.split_at(4);
+    *buf = rest;
+    Some(u32::from_be_bytes([n[0], n[1], n[2], n[3]]) as usize)
+}
+
+fn take<'a>(buf: &mut &'a [u8], len: usize) -> Option<&'a [u8]> {
+    if buf.len() < len {
+        return None;
+    }
+    let (bytes, rest) = buf.split_at(len);
+    *buf = rest;
+    Some(bytes)
+}
+
+/// Decodes a batch, returning `None` if the record is incomplete or corrupt.
+fn decode_batch(buf: &mut &[u8]) -> Option<Vec<Block>> {
+    let len = take_u32(buf)?;
+    let mut blocks = Vec::with_capacity(len);
+    for _ in 0..len {
+        let cid_len = take_u32(buf)?;
+        let cid = Cid::try_from(take(buf, cid_len)?).ok()?;
+        let data_len = take_u32(buf)?;
+        let data = take(buf, data_len)?;
+        verify(&cid, data).ok()?;
+        blocks.push(Block {
+            cid,
+            data: data.to_vec().into_boxed_slice(),
+        });
+    }
+    Some(blocks)
+}
+
+impl Wal {
+    /// Opens the log at `path`, creating it if needed.
+    pub async fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let file = path.clone();
+        unblock(move || {
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(file)
+                .map(|_| ())
+        })
+        .await?;
+        Ok(Self {
+            inner: Arc::new(Inner {
+                path,
+                lock: Mutex::new(()),
+                file: std::sync::Mutex::new(()),
+            }),
+        })
+    }
+
+    /// Returns the path of the log.
+    pub fn path(&self) -> &Path {
+        &self.inner.path
+    }
+
+    /// Locks the log, appends and truncates must hold the lock.
+    pub(crate) async fn lock(&self) -> MutexGuard<'_, ()> {
+        self.inner.lock.lock().await
+    }
+
+    /// Appends a batch to the log.
+    pub(crate) async fn append(&self, blocks: &[Block]) -> Result<()> {
+        let mut buf = vec![];
+        put_u32(&mut buf, blocks.len());
+        for block in blocks {
+            let cid = block.cid.to_bytes();
+            put_u32(&mut buf, cid.len());
+            buf.extend_from_slice(&cid);
+            put_u32(&mut buf, block.data.len());
+            buf.extend_from_slice(&block.data);
+        }
+        let inner = self.inner.clone();
+        unblock(move || {
+            let _file = inner.file.lock().unwrap_or_else(|err| err.into_inner());
+            let mut file = OpenOptions::new().append(true).open(&inner.path)?;
+            file.write_all(&buf)?;
+            file.sync_data()
+        })
+        .await?;
+        Ok(())
+    }
+
+    /// Removes all batches from the log.
+    pub(crate) async fn truncate(&self) -> Result<()> {
+        let inner = self.inner.clone();
+        unblock(move || {
+            let _file = inner.file.lock().unwrap_or_else(|err| err.into_inner());
+            let file = OpenOptions::new().write(true).open(&inner.path)?;
+            file.set_len(0)?;
+            file.sync_all()
+        })
+        .await?;
+        Ok(())
+    }
+
+    /// Returns the batches in the log.
+    ///
+    /// A partially written batch at the end of the log is ignored.
+    pub async fn batches(&self) -> Result<Vec<Vec<Block>>> {
+        let path = self.inner.path.clone();
+        let data = match unblock(move || fs::read(path)).await {
+            Ok(data) => data,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(vec![]),
+            Err(err) => return Err(err.into()),
+        };
+        let mut buf = &data[..];
+        let mut batches = vec![];
+        while !buf.is_empty() {
+            match decode_batch(&mut buf) {
+                Some(batch) => batches.push(batch),
+                None => break,
+            }
+        }
+        Ok(batches)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BlockBuilder, Codec};
+    use libipld::ipld;
+    use libipld::ipld::Ipld;
+    use libipld::mem::MemStore;
+
+    #[cfg_attr(not(feature = "tokio"), async_std::test)]
+    #[cfg_attr(feature = "tokio", tokio::test)]
+    async fn test_wal() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("wal");
+
+        let mut builder = BlockBuilder::new(MemStore::default(), Codec::new());
+        builder.set_wal(Wal::open(&path).await.unwrap());
+        let mut batch = builder.create_batch();
+        batch.insert(&ipld!({"a": 1})).unwrap();
+        let cid = batch.insert(&ipld!({"b": 2})).unwrap().clone();
+        builder.insert_batch(batch).await.unwrap();
+        drop(builder);
+
+        let wal = Wal::open(&path).await.unwrap();
+        assert_eq!(wal.batches().await.unwrap().len(), 1);
+        let mut builder = BlockBuilder::new(MemStore::default(), Codec::new());
+        builder.set_wal(wal.clone());
+        assert_eq!(builder.recover().await.unwrap(), 1);
+        let ipld: Ipld = builder.get(&cid).await.unwrap();
+        assert_eq!(ipld, ipld!({"b": 2}));
+        assert!(wal.batches().await.unwrap().is_empty());
+
+        builder.insert(&ipld!({"c": 3})).await.unwrap();
+        OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(&[0, 0, 0, 1, 0])
+            .unwrap();
+        assert_eq!(wal.batches().await.unwrap().len(), 1);
+        builder.flush().await.unwrap();
+        assert!(wal.batches().await.unwrap().is_empty());
+        assert_eq!(builder.recover().await.unwrap(), 0);
+    }
+}
@@ -0,0 +1,436 @@
+use crate::builder::BlockBuilder;
+use crate::codec::IpldDecoder;
+use crate::error::Result;
+use futures::stream::{self, StreamExt, TryStreamExt};
+use libipld::cid::Cid;
+use libipld::ipld::Ipld;
+use libipld::store::ReadonlyStore;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+/// Decision returned by a `Visitor`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum WalkControl {
+    /// Visit the links of the block.
+    Descend,
+    /// Don't visit the links of the block.
+    Skip,
+    /// Stop the walk.
+    Stop,
+}
+
+/// Order in which the blocks of a dag are discovered.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Traversal {
+    /// Follow the first link of a block before its siblings.
+    #[default]
+    DepthFirst,
+    /// Visit all blocks at one depth before the next depth.
+    BreadthFirst,
+}
+
+/// Order in which blocks are passed to the visitor.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Emission {
+    /// Blocks are visited before their links.
+    #[default]
+    PreOrder,
+    /// Blocks are visited after all of their links.
+    ///
+    /// The whole dag is fetched before the first block is visited, and
+    /// `WalkControl::Skip` behaves like `WalkControl::Descend`.
+    PostOrder,
+}
+
+/// Options of a dag walk.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct WalkOptions {
+    /// Traversal order.
+    pub traversal: Traversal,
+    /// Emission order.
+    pub emission: Emission,
+    /// Maximum number of blocks fetched concurrently.
+    pub parallelism: usize,
+}
+
+impl Default for WalkOptions {
+    fn default() -> Self {
+        Self {
+            traversal: Traversal::default(),
+            emission: Emission::default(),
+            parallelism: 1,
+        }
+    }
+}
+
+/// Visitor called for each block of a dag.
+pub trait Visitor {
+    /// Visits the block `cid` at `depth` links from the root.
+    fn visit(&mut self, depth: usize, cid: &Cid, ipld: &Ipld) -> WalkControl;
+}
+
+impl<F: FnMut(usize, &Cid, &Ipld) -> WalkControl> Visitor for F {
+    fn visit(&mut self, depth: usize, cid: &Cid, ipld: &Ipld) -> WalkControl {
+        self(depth, cid, ipld)
+    }
+}
+
+/// Returns the links of a block in order of appearance.
+pub(crate) fn links(ipld: &Ipld) -> Vec<Cid> {
+    let mut seen = HashSet::new();
+    ipld.iter()
+        .filter_map(|ipld| match ipld {
+            Ipld::Link(cid) if seen.insert(cid.clone()) => Some(cid.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+struct Node {
+    depth: usize,
+    cid: Cid,
+    ipld: Ipld,
+    links: Vec<Cid>,
+}
+
+impl<S: ReadonlyStore, C: IpldDecoder> BlockBuilder<S, C> {
+    /// Walks the dag depth first in pre-order starting at `root`.
+    ///
+    /// Each block is visited once, even if it is linked multiple times.
+    pub async fn walk<V: Visitor>(&self, root: &Cid, visitor: V) -> Result<()> {
+        self.walk_with(root, WalkOptions::default(), visitor).await
+    }
+
+    /// Walks the dag starting at `root` in the order given by `options`.
+    ///
+    /// Each block is visited once, even if it is linked multiple times. Up to
+    /// `options.parallelism` blocks that are next in line are fetched
+    /// concurrently, the order of the visits is not affected.
+    pub async fn walk_with<V: Visitor>(
+        &self,
+        root: &Cid,
+        options: WalkOptions,
+        mut visitor: V,
+    ) -> Result<()> {
+        match options.emission {
+            Emission::PreOrder => self.walk_pre_order(root, options, visitor).await,
+            Emission::PostOrder => {
+                for node in self.post_order(root, options).await? {
+                    if visitor.visit(node.depth, &node.cid, &node.ipld) == WalkControl::Stop {
+                        break;
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Fetches the blocks concurrently, returning them in order.
+    async fn fetch_all(&self, cids: Vec<Cid>, parallelism: usize) -> Result<Vec<(Cid, Ipld)>> {
+        stream::iter(cids)
+            .map(|cid| async move {
+                let ipld = self.get_ipld(&cid).await?;
+                Ok((cid, ipld))
+            })
+            .buffered(parallelism.max(1))
+            .try_collect()
+            .await
+    }
+
+    /// Returns `cid` or fetches it together with the next `parallelism - 1`
+    /// blocks of `pending` that haven't been fetched or visited yet.
+    async fn fetch_ahead<'a>(
+        &self,
+        cid: &Cid,
+        pending: impl Iterator<Item = &'a Cid>,
+        visited: &HashSet<Cid>,
+        fetched: &mut HashMap<Cid, Ipld>,
+        parallelism: usize,
+    ) -> Result<Ipld> {
+        if let Some(ipld) = fetched.remove(cid) {
+            return Ok(ipld);
+        }
+        let mut batch = vec![cid.clone()];
+        for cid in pending {
+            if batch.len() >= parallelism {
+                break;
+            }
+            if !visited.contains(cid) && !fetched.contains_key(cid) && !batch.contains(cid) {
+                batch.push(cid.clone());
+            }
+        }
+        for (cid, ipld) in self.fetch_all(batch, parallelism).await? {
+            fetched.insert(cid, ipld);
+        }
+        Ok(fetched.remove(cid).unwrap())
+    }
+
+    async fn walk_pre_order<V: Visitor>(
+        &self,
+        root: &Cid,
+        options: WalkOptions,
+        mut visitor: V,
+    ) -> Result<()> {
+        let traversal = options.traversal;
+        let mut visited = HashSet::new();
+        let mut fetched = HashMap::new();
+        let mut queue = VecDeque::new();
+        queue.push_back((0, root.clone()));
+        while let Some((depth, cid)) = match traversal {
+            Traversal::DepthFirst => queue.pop_back(),
+            Traversal::BreadthFirst => queue.pop_front(),
+        } {
+            if !visited.insert(cid.clone()) {
+                continue;
+            }
+            let ipld = match traversal {
+                Traversal::DepthFirst => {
+                    let pending = queue.iter().rev().map(|(_, cid)| cid);
+                    self.fetch_ahead(&cid, pending, &visited, &mut fetched, options.parallelism)
+                        .await?
+                }
+                Traversal::BreadthFirst => {
+                    let pending = queue.iter().map(|(_, cid)| cid);
+                    self.fetch_ahead(&cid, pending, &visited, &mut fetched, options.parallelism)
+                        .await?
+                }
+            };
+            match visitor.visit(depth, &cid, &ipld) {
+                WalkControl::Descend => {
+                    let links = links(&ipld).into_iter().map(|link| (depth + 1, link));
+                    match traversal {
+                        Traversal::DepthFirst => queue.extend(links.rev()),
+                        Traversal::BreadthFirst => queue.extend(links),
+                    }
+                }
+                WalkControl::Skip => {}
+                WalkControl::Stop => break,
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the blocks of the dag ordered so that every block comes after
+    /// all of its links.
+    ///
+    /// Depth first returns them in depth first post-order, breadth first
+    /// orders them by their height above the leaves.
+    async fn post_order(&self, root: &Cid, options: WalkOptions) -> Result<Vec<Node>> {
+        let mut visited = HashSet::new();
+        let mut fetched = HashMap::new();
+        let mut heights = HashMap::new();
+        let mut nodes = vec![];
+        let ipld = self.get_ipld(root).await?;
+        visited.insert(root.clone());
+        let mut stack = vec![(
+            Node {
+                depth: 0,
+                cid: root.clone(),
+                links: links(&ipld),
+                ipld,
+            },
+            0,
+        )];
+        while let Some((node, next)) = stack.last_mut() {
+            if let Some(link) = node.links.get(*next).cloned() {
+                *next += 1;
+                if visited.insert(link.clone()) {
+                    let depth = node.depth + 1;
+                    let pending = node.links[*next..].iter();
+                    let ipld = self
+                        .fetch_ahead(&link, pending, &visited, &mut fetched, options.parallelism)
+                        .await?;
+                    let links = links(&ipld);
+                    stack.push((
+                        Node {
+                            depth,
+                            cid: link,
+                            ipld,
+                            links,
+                        },
+                        0,
+                    ));
+                }
+            } else {
+                let (node, _) = stack.pop().unwrap();
+                let height = node
+                    .links
+                    .iter()
+                    .filter_map(|link| heights.get(link))
+                    .map(|height| height + 1)
+                    .max()
+                    .unwrap_or(0);
+                heights.insert(node.cid.clone(), height);
+                nodes.push(node);
+            }
+        }
+        if options.traversal == Traversal::BreadthFirst {
+            nodes.sort_by_key(|node| heights[&node.cid]);
+        }
+        Ok(nodes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Codec;
+    use libipld::ipld;
+    use libipld::mem::MemStore;
+    use libipld::store::StoreResult;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[async_std::test]
+    async fn test_walk() {
+        let builder = BlockBuilder::new(MemStore::default(), Codec::new());
+        let leaf = builder.insert(&ipld!({"leaf": true})).await.unwrap();
+        let a = builder.insert(&ipld!({"a": leaf.clone()})).await.unwrap();
+        let b = builder.insert(&ipld!({"b": 1})).await.unwrap();
+        let root = builder
+            .insert(&ipld!([a.clone(), b.clone(), leaf.clone()]))
+            .await
+            .unwrap();
+
+        let mut visited = vec![];
+        builder
+            .walk(&root, |depth, cid: &Cid, _: &Ipld| {
+                visited.push((depth, cid.clone()));
+                WalkControl::Descend
+            })
+            .await
+            .unwrap();
+        assert_eq!(
+            visited,
+            vec![(0, root.clone()), (1, a.clone()), (2, leaf), (1, b.clone())]
+        );
+
+        let mut visited = vec![];
+        builder
+            .walk(&root, |_, cid: &Cid, _: &Ipld| {
+                visited.push(cid.clone());
+                if *cid == a {
+                    WalkControl::Skip
+                } else {
+                    WalkControl::Descend
+                }
+            })
+            .await
+            .unwrap();
+        assert_eq!(visited.len(), 4);
+
+        let mut visited = vec![];
+        builder
+            .walk(&root, |_, cid: &Cid, _: &Ipld| {
+                visited.push(cid.clone());
+                WalkControl::Stop
+            })
+            .await
+            .unwrap();
+        assert_eq!(visited, vec![root]);
+    }
+
+    #[async_std::test]
+    async fn test_walk_order() {
+        let builder = BlockBuilder::new(MemStore::default(), Codec::new());
+        let leaf = builder.insert(&ipld!({"leaf": true})).await.unwrap();
+        let b = builder.insert(&ipld!({"b": 1})).await.unwrap();
+        let a = builder.insert(&ipld!({"a": leaf.clone()})).await.unwrap();
+        let root = builder
+            .insert(&ipld!([leaf.clone(), a.clone(), b.clone()]))
+            .await
+            .unwrap();
+
+        let walk = |traversal, emission| {
+            let builder = &builder;
+            let root = &root;
+            async move {
+                let mut visited = vec![];
+                let options = WalkOptions {
+                    traversal,
+                    emission,
+                    ..Default::default()
+                };
+                builder
+                    .walk_with(root, options, |_, cid: &Cid, _: &Ipld| {
+                        visited.push(cid.clone());
+                        WalkControl::Descend
+                    })
+                    .await
+                    .unwrap();
+                visited
+            }
+        };
+        assert_eq!(
+            walk(Traversal::DepthFirst, Emission::PreOrder).await,
+            vec![root.clone(), leaf.clone(), a.clone(), b.clone()]
+        );
+        assert_eq!(
+            walk(Traversal::BreadthFirst, Emission::PreOrder).await,
+            vec![root.clone(), leaf.clone(), a.clone(), b.clone()]
+        );
+        assert_eq!(
+            walk(Traversal::DepthFirst, Emission::PostOrder).await,
+            vec![leaf.clone(), a.clone(), b.clone(), root.clone()]
+        );
+        assert_eq!(
+            walk(Traversal::BreadthFirst, Emission::PostOrder).await,
+            vec![leaf, b, a, root]
+        );
+    }
+
+    #[derive(Clone)]
+    struct SlowStore {
+        store: MemStore,
+        in_flight: Arc<AtomicUsize>,
+        max_in_flight: Arc<AtomicUsize>,
+    }
+
+    impl ReadonlyStore for SlowStore {
+        fn get<'a>(&'a self, cid: &'a Cid) -> StoreResult<'a, Box<[u8]>> {
+            Box::pin(async move {
+                let in_flight = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                self.max_in_flight.fetch_max(in_flight, Ordering::SeqCst);
+                futures_timer::Delay::new(Duration::from_millis(10)).await;
+                self.in_flight.fetch_sub(1, Ordering::SeqCst);
+                self.store.get(cid).await
+            })
+        }
+    }
+
+    #[async_std::test]
+    async fn test_walk_parallel() {
+        let builder = BlockBuilder::new(MemStore::default(), Codec::new());
+        let mut leaves = vec![];
+        for i in 0..8 {
+            leaves.push(Ipld::Link(
+                builder.insert(&ipld!({ "leaf": i })).await.unwrap(),
+            ));
+        }
+        let root = builder.insert(&Ipld::List(leaves)).await.unwrap();
+
+        let store = SlowStore {
+            store: builder.store().clone(),
+            in_flight: Default::default(),
+            max_in_flight: Default::default(),
+        };
+        let builder = BlockBuilder::new(store.clone(), Codec::new());
+        for emission in &[Emission::PreOrder, Emission::PostOrder] {
+            let options = WalkOptions {
+                emission: *emission,
+                parallelism: 4,
+                ..Default::default()
+            };
+            let mut visited = 0;
+            builder
+                .walk_with(&root, options, |_, _: &Cid, _: &Ipld| {
+                    visited += 1;
+                    WalkControl::Descend
+                })
+                .await
+                .unwrap();
+            assert_eq!(visited, 9);
+            assert_eq!(store.max_in_flight.swap(0, Ordering::SeqCst), 4);
+        }
+    }
+}